@@ -0,0 +1,169 @@
+//! Admin authentication for node-control fields
+//!
+//! Fields that steer the node rather than read or write user data
+//! (`dialPeer`, `requestSync`, `fsck`, inference jobs, MQTT publishing, IPFS
+//! pinning and the network resilience queries) require the admin role. A
+//! request proves it with an `X-Admin-Token` header holding either:
+//!
+//! - one of the API tokens from `ADMIN_API_TOKENS`, or
+//! - a short-lived token signed by a node-operator key (`ADMIN_PUBLIC_KEYS`,
+//!   plus the key of `NODE_PRIV_KEY`): the read token format from
+//!   `read_access.rs`, signed under `cyberfly-admin-token-v1` instead so read
+//!   tokens can't be replayed as admin tokens
+//!
+//! With neither configured the privileged fields are refused to everyone.
+//! Every privileged call, allowed or not, is written to the audit log: the
+//! `cyberfly::audit` tracing target and an in-memory window served by the
+//! `adminAuditLog` query.
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::crypto;
+use crate::metrics;
+use crate::read_access::ReadTokenClaims;
+
+/// Domain separator for admin tokens signed by an operator key
+pub const ADMIN_TOKEN_DOMAIN: &str = "cyberfly-admin-token-v1";
+
+/// Header carrying the admin credential
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+/// Audit entries kept in memory
+pub const AUDIT_LOG_CAPACITY: usize = 1000;
+
+/// The admin credential a request presents, if any
+#[derive(Debug, Clone, Default)]
+pub struct AdminCredential {
+    token: Option<String>,
+}
+
+impl AdminCredential {
+    /// Credential from an `X-Admin-Token` header value
+    pub fn from_header(header: Option<&str>) -> Self {
+        let token = header
+            .map(|value| value.trim().to_string())
+            .filter(|token| !token.is_empty());
+        Self { token }
+    }
+
+    /// Credential holding `token`
+    pub fn token(token: impl Into<String>) -> Self {
+        Self { token: Some(token.into()) }
+    }
+}
+
+/// One privileged call
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    /// Unix milliseconds
+    pub timestamp: i64,
+    /// `token:<hash prefix>`, `key:<public key>`, or `anonymous`
+    pub actor: String,
+    /// GraphQL field that was called
+    pub action: String,
+    pub allowed: bool,
+    /// Why the call was refused
+    pub reason: Option<String>,
+}
+
+/// Admin credentials accepted by this node, and the audit log
+#[derive(Clone, Default)]
+pub struct AdminAuth {
+    /// SHA-256 hex of each API token, so the tokens themselves aren't kept
+    token_hashes: Arc<Vec<String>>,
+    operator_keys: Arc<HashSet<String>>,
+    audit: Arc<Mutex<VecDeque<AuditEntry>>>,
+}
+
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+impl AdminAuth {
+    /// Accept the given API tokens and operator public keys (hex)
+    pub fn new(api_tokens: &[String], operator_keys: &[String]) -> Self {
+        Self {
+            token_hashes: Arc::new(api_tokens.iter().map(|token| token_hash(token)).collect()),
+            operator_keys: Arc::new(operator_keys.iter().map(|key| key.to_lowercase()).collect()),
+            audit: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Whether any admin credential is configured
+    pub fn is_enabled(&self) -> bool {
+        !self.token_hashes.is_empty() || !self.operator_keys.is_empty()
+    }
+
+    /// Check a credential, returning the actor it identifies
+    pub fn authenticate(&self, credential: &AdminCredential) -> Result<String> {
+        if !self.is_enabled() {
+            bail!("Admin access is not configured on this node");
+        }
+        let token = credential
+            .token
+            .as_deref()
+            .ok_or_else(|| anyhow!("Admin credentials are required"))?;
+
+        let hash = token_hash(token);
+        if self.token_hashes.iter().any(|known| crypto::constant_time_eq(known, &hash)) {
+            return Ok(format!("token:{}", &hash[..8]));
+        }
+
+        let claims = ReadTokenClaims::verify_for(ADMIN_TOKEN_DOMAIN, token)
+            .map_err(|_| anyhow!("Invalid admin credentials"))?;
+        claims.check_expiry(chrono::Utc::now().timestamp_millis())?;
+        if !self.operator_keys.contains(&claims.public_key.to_lowercase()) {
+            bail!("Key {} is not a node operator", claims.public_key);
+        }
+        Ok(format!("key:{}", claims.public_key))
+    }
+
+    /// Authenticate a call to `action` and record it in the audit log
+    pub fn authorize(&self, action: &str, credential: &AdminCredential) -> Result<String> {
+        let result = self.authenticate(credential);
+        let (actor, reason) = match &result {
+            Ok(actor) => (actor.clone(), None),
+            Err(e) if credential.token.is_none() => ("anonymous".to_string(), Some(e.to_string())),
+            Err(e) => ("invalid".to_string(), Some(e.to_string())),
+        };
+        self.record(AuditEntry {
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            actor,
+            action: action.to_string(),
+            allowed: result.is_ok(),
+            reason,
+        });
+        result
+    }
+
+    fn record(&self, entry: AuditEntry) {
+        let outcome = if entry.allowed { "allowed" } else { "denied" };
+        metrics::ADMIN_ACTIONS.with_label_values(&[&entry.action, outcome]).inc();
+        tracing::info!(
+            target: "cyberfly::audit",
+            actor = %entry.actor,
+            action = %entry.action,
+            allowed = entry.allowed,
+            reason = entry.reason.as_deref().unwrap_or(""),
+            "admin action"
+        );
+        if let Ok(mut audit) = self.audit.lock() {
+            if audit.len() == AUDIT_LOG_CAPACITY {
+                audit.pop_front();
+            }
+            audit.push_back(entry);
+        }
+    }
+
+    /// The most recent audit entries, newest first
+    pub fn audit_log(&self, limit: usize) -> Vec<AuditEntry> {
+        self.audit
+            .lock()
+            .map(|audit| audit.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,506 @@
+//! Aggregation pipelines over a database's JSON documents
+//!
+//! A pipeline is a MongoDB-style array of stages:
+//!
+//! ```json
+//! [
+//!   { "$match": { "status": "shipped" } },
+//!   { "$unwind": "$items" },
+//!   { "$group": { "_id": "$items.sku", "units": { "$sum": "$items.qty" }, "orders": { "$count": {} } } },
+//!   { "$sort": { "units": -1 } },
+//!   { "$limit": 10 }
+//! ]
+//! ```
+//!
+//! Supported stages are `$match` (filter documents, see `filters::FilterExpr`),
+//! `$project`, `$group` (accumulators `$sum`, `$avg`, `$min`, `$max`, `$count`),
+//! `$sort`, `$skip`, `$limit` and `$unwind`. Expressions are `"$path"` field
+//! references or literals. Leading `$match` stages are answered through
+//! `JsonFilter`, so they use secondary indexes where possible; without one the
+//! pipeline starts from `get_all_jsons`. Every input document carries its
+//! storage key as `_key`.
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Value as JsonValue};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::filters::{resolve_path, FilterExpr, FilterOptions, JsonFilter, JsonFilterConditions};
+use crate::storage::BlobStorage;
+
+/// One pipeline stage
+#[derive(Debug, Clone)]
+pub enum Stage {
+    Match(FilterExpr),
+    Project(Vec<(String, Projection)>),
+    Group {
+        id: JsonValue,
+        accumulators: Vec<(String, Accumulator)>,
+    },
+    /// Fields with `true` for ascending
+    Sort(Vec<(String, bool)>),
+    Skip(usize),
+    Limit(usize),
+    Unwind {
+        path: String,
+        preserve_empty: bool,
+    },
+}
+
+/// Output of one `$project` field
+#[derive(Debug, Clone)]
+pub enum Projection {
+    Include,
+    Exclude,
+    /// Computed from an expression
+    Value(JsonValue),
+}
+
+/// `$group` accumulator over an expression
+#[derive(Debug, Clone)]
+pub enum Accumulator {
+    Sum(JsonValue),
+    Avg(JsonValue),
+    Min(JsonValue),
+    Max(JsonValue),
+    Count,
+}
+
+/// Parsed aggregation pipeline
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    pub stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Parse a pipeline from a JSON array of stage documents
+    pub fn parse(pipeline: &JsonValue) -> Result<Self> {
+        let stages = pipeline
+            .as_array()
+            .ok_or_else(|| anyhow!("Pipeline must be an array of stages"))?;
+        let stages = stages.iter().map(parse_stage).collect::<Result<Vec<_>>>()?;
+        Ok(Self { stages })
+    }
+
+    /// Filter made of the leading `$match` stages, and the remaining stages
+    fn split_leading_match(&self) -> (Option<FilterExpr>, &[Stage]) {
+        let count = self
+            .stages
+            .iter()
+            .take_while(|stage| matches!(stage, Stage::Match(_)))
+            .count();
+        let mut filters: Vec<FilterExpr> = self.stages[..count]
+            .iter()
+            .filter_map(|stage| match stage {
+                Stage::Match(filter) => Some(filter.clone()),
+                _ => None,
+            })
+            .collect();
+        let filter = match filters.len() {
+            0 => None,
+            1 => filters.pop(),
+            _ => Some(FilterExpr::And(filters)),
+        };
+        (filter, &self.stages[count..])
+    }
+}
+
+/// Runs pipelines against a storage instance
+pub struct Aggregator<'a> {
+    storage: &'a BlobStorage,
+}
+
+impl<'a> Aggregator<'a> {
+    pub fn new(storage: &'a BlobStorage) -> Self {
+        Self { storage }
+    }
+
+    /// Aggregate the JSON documents of `db_name`
+    pub async fn aggregate(&self, db_name: &str, pipeline: &Pipeline) -> Result<Vec<JsonValue>> {
+        let (filter, rest) = pipeline.split_leading_match();
+        let docs = match filter {
+            Some(filter) => {
+                let conditions = JsonFilterConditions::from_filter(filter);
+                JsonFilter::new(self.storage)
+                    .filter_across_keys(&format!("{}:*", db_name), &conditions, &FilterOptions::default())
+                    .await?
+            }
+            None => self
+                .storage
+                .get_all_jsons(db_name)
+                .await?
+                .into_iter()
+                .map(|(key, mut doc, _)| {
+                    if let Some(obj) = doc.as_object_mut() {
+                        obj.insert("_key".to_string(), JsonValue::String(key));
+                    }
+                    doc
+                })
+                .collect(),
+        };
+        rest.iter().try_fold(docs, |docs, stage| run_stage(stage, docs))
+    }
+}
+
+fn parse_stage(stage: &JsonValue) -> Result<Stage> {
+    let (name, spec) = match stage.as_object() {
+        Some(map) if map.len() == 1 => map.iter().next().expect("one entry"),
+        _ => bail!("Each stage must be an object with a single operator, got {}", stage),
+    };
+    Ok(match name.as_str() {
+        "$match" => Stage::Match(FilterExpr::parse(spec)?),
+        "$project" => {
+            let fields = object_arg(name, spec)?;
+            let mut projection = Vec::new();
+            for (field, value) in fields {
+                projection.push((
+                    field.clone(),
+                    match value {
+                        JsonValue::Bool(true) => Projection::Include,
+                        JsonValue::Bool(false) => Projection::Exclude,
+                        JsonValue::Number(n) if n.as_f64() == Some(1.0) => Projection::Include,
+                        JsonValue::Number(n) if n.as_f64() == Some(0.0) => Projection::Exclude,
+                        other => Projection::Value(other.clone()),
+                    },
+                ));
+            }
+            let excludes = projection
+                .iter()
+                .any(|(field, p)| matches!(p, Projection::Exclude) && field != "_key");
+            let includes = projection.iter().any(|(_, p)| !matches!(p, Projection::Exclude));
+            if excludes && includes {
+                bail!("$project cannot mix inclusion and exclusion");
+            }
+            Stage::Project(projection)
+        }
+        "$group" => {
+            let fields = object_arg(name, spec)?;
+            let id = fields
+                .get("_id")
+                .cloned()
+                .ok_or_else(|| anyhow!("$group needs an _id"))?;
+            let mut accumulators = Vec::new();
+            for (field, accumulator) in fields.iter().filter(|(field, _)| *field != "_id") {
+                accumulators.push((field.clone(), parse_accumulator(field, accumulator)?));
+            }
+            Stage::Group { id, accumulators }
+        }
+        "$sort" => {
+            let fields = object_arg(name, spec)?;
+            if fields.is_empty() {
+                bail!("$sort needs at least one field");
+            }
+            let mut keys = Vec::new();
+            for (field, direction) in fields {
+                keys.push((
+                    field.clone(),
+                    match direction.as_i64() {
+                        Some(1) => true,
+                        Some(-1) => false,
+                        _ => bail!("$sort direction for '{}' must be 1 or -1", field),
+                    },
+                ));
+            }
+            Stage::Sort(keys)
+        }
+        "$skip" => Stage::Skip(count_arg(name, spec)?),
+        "$limit" => Stage::Limit(count_arg(name, spec)?),
+        "$unwind" => {
+            let (path, preserve_empty) = match spec {
+                JsonValue::String(path) => (path.as_str(), false),
+                JsonValue::Object(options) => (
+                    options
+                        .get("path")
+                        .and_then(JsonValue::as_str)
+                        .ok_or_else(|| anyhow!("$unwind needs a path"))?,
+                    options
+                        .get("preserveNullAndEmptyArrays")
+                        .and_then(JsonValue::as_bool)
+                        .unwrap_or(false),
+                ),
+                _ => bail!("$unwind needs a field path"),
+            };
+            let path = path
+                .strip_prefix('$')
+                .ok_or_else(|| anyhow!("$unwind path must start with '$'"))?;
+            Stage::Unwind {
+                path: path.to_string(),
+                preserve_empty,
+            }
+        }
+        other => bail!("Unknown pipeline stage {}", other),
+    })
+}
+
+fn parse_accumulator(field: &str, spec: &JsonValue) -> Result<Accumulator> {
+    let (op, arg) = match spec.as_object() {
+        Some(map) if map.len() == 1 => map.iter().next().expect("one entry"),
+        _ => bail!("Accumulator '{}' must be an object with a single operator", field),
+    };
+    Ok(match op.as_str() {
+        "$sum" => Accumulator::Sum(arg.clone()),
+        "$avg" => Accumulator::Avg(arg.clone()),
+        "$min" => Accumulator::Min(arg.clone()),
+        "$max" => Accumulator::Max(arg.clone()),
+        "$count" => Accumulator::Count,
+        other => bail!("Unknown accumulator {} for '{}'", other, field),
+    })
+}
+
+fn object_arg<'v>(stage: &str, spec: &'v JsonValue) -> Result<&'v Map<String, JsonValue>> {
+    spec.as_object().ok_or_else(|| anyhow!("{} needs an object", stage))
+}
+
+fn count_arg(stage: &str, spec: &JsonValue) -> Result<usize> {
+    spec.as_u64()
+        .map(|n| n as usize)
+        .ok_or_else(|| anyhow!("{} needs a non-negative integer", stage))
+}
+
+fn run_stage(stage: &Stage, docs: Vec<JsonValue>) -> Result<Vec<JsonValue>> {
+    Ok(match stage {
+        Stage::Match(filter) => docs.into_iter().filter(|doc| filter.matches(doc)).collect(),
+        Stage::Project(fields) => docs.iter().map(|doc| project(doc, fields)).collect(),
+        Stage::Group { id, accumulators } => group(&docs, id, accumulators),
+        Stage::Sort(keys) => {
+            let mut docs = docs;
+            docs.sort_by(|a, b| {
+                keys.iter()
+                    .map(|(field, ascending)| {
+                        let ordering = compare(&field_value(a, field), &field_value(b, field));
+                        if *ascending {
+                            ordering
+                        } else {
+                            ordering.reverse()
+                        }
+                    })
+                    .find(|ordering| *ordering != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            });
+            docs
+        }
+        Stage::Skip(n) => docs.into_iter().skip(*n).collect(),
+        Stage::Limit(n) => docs.into_iter().take(*n).collect(),
+        Stage::Unwind { path, preserve_empty } => docs
+            .into_iter()
+            .flat_map(|doc| unwind(doc, path, *preserve_empty))
+            .collect(),
+    })
+}
+
+/// Value of a `"$path"` reference or a literal
+fn evaluate(doc: &JsonValue, expr: &JsonValue) -> JsonValue {
+    match expr {
+        JsonValue::String(s) if s.starts_with('$') => field_value(doc, &s[1..]),
+        JsonValue::Object(map) => JsonValue::Object(
+            map.iter()
+                .map(|(key, value)| (key.clone(), evaluate(doc, value)))
+                .collect(),
+        ),
+        literal => literal.clone(),
+    }
+}
+
+/// Value at a dotted path; several values (through arrays) become an array
+fn field_value(doc: &JsonValue, path: &str) -> JsonValue {
+    let mut found = Vec::new();
+    resolve_path(doc, path, &mut found);
+    match found.len() {
+        0 => JsonValue::Null,
+        1 => found[0].clone(),
+        _ => JsonValue::Array(found.into_iter().cloned().collect()),
+    }
+}
+
+fn project(doc: &JsonValue, fields: &[(String, Projection)]) -> JsonValue {
+    let exclusion = fields.iter().all(|(_, p)| matches!(p, Projection::Exclude));
+    let mut out = if exclusion {
+        doc.as_object().cloned().unwrap_or_default()
+    } else {
+        let mut out = Map::new();
+        // `_key` is kept unless excluded, like MongoDB's `_id`
+        if let Some(key) = doc.get("_key") {
+            out.insert("_key".to_string(), key.clone());
+        }
+        out
+    };
+    for (field, projection) in fields {
+        match projection {
+            Projection::Exclude => {
+                out.remove(field);
+            }
+            Projection::Include => {
+                let value = field_value(doc, field);
+                if !value.is_null() || doc.get(field).is_some() {
+                    out.insert(field.clone(), value);
+                }
+            }
+            Projection::Value(expr) => {
+                out.insert(field.clone(), evaluate(doc, expr));
+            }
+        }
+    }
+    JsonValue::Object(out)
+}
+
+/// Running state of one accumulator
+enum AccumulatorState {
+    Sum(f64),
+    Avg(f64, usize),
+    Extreme(Option<JsonValue>),
+    Count(usize),
+}
+
+fn group(docs: &[JsonValue], id: &JsonValue, accumulators: &[(String, Accumulator)]) -> Vec<JsonValue> {
+    // Groups in order of first appearance
+    let mut order: Vec<(JsonValue, Vec<AccumulatorState>)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    for doc in docs {
+        let group_id = evaluate(doc, id);
+        let position = *positions.entry(group_id.to_string()).or_insert_with(|| {
+            let states = accumulators
+                .iter()
+                .map(|(_, accumulator)| match accumulator {
+                    Accumulator::Sum(_) => AccumulatorState::Sum(0.0),
+                    Accumulator::Avg(_) => AccumulatorState::Avg(0.0, 0),
+                    Accumulator::Min(_) | Accumulator::Max(_) => AccumulatorState::Extreme(None),
+                    Accumulator::Count => AccumulatorState::Count(0),
+                })
+                .collect();
+            order.push((group_id.clone(), states));
+            order.len() - 1
+        });
+
+        for ((_, accumulator), state) in accumulators.iter().zip(order[position].1.iter_mut()) {
+            match (accumulator, state) {
+                (Accumulator::Sum(expr), AccumulatorState::Sum(total)) => {
+                    *total += numbers(&evaluate(doc, expr)).sum::<f64>();
+                }
+                (Accumulator::Avg(expr), AccumulatorState::Avg(total, count)) => {
+                    for n in numbers(&evaluate(doc, expr)) {
+                        *total += n;
+                        *count += 1;
+                    }
+                }
+                (Accumulator::Min(expr), AccumulatorState::Extreme(current)) => {
+                    let value = evaluate(doc, expr);
+                    if !value.is_null() && current.as_ref().is_none_or(|c| compare(&value, c) == Ordering::Less) {
+                        *current = Some(value);
+                    }
+                }
+                (Accumulator::Max(expr), AccumulatorState::Extreme(current)) => {
+                    let value = evaluate(doc, expr);
+                    if !value.is_null() && current.as_ref().is_none_or(|c| compare(&value, c) == Ordering::Greater) {
+                        *current = Some(value);
+                    }
+                }
+                (Accumulator::Count, AccumulatorState::Count(count)) => *count += 1,
+                _ => unreachable!("states are created from their accumulators"),
+            }
+        }
+    }
+
+    order
+        .into_iter()
+        .map(|(group_id, states)| {
+            let mut out = Map::new();
+            out.insert("_id".to_string(), group_id);
+            for ((field, _), state) in accumulators.iter().zip(states) {
+                let value = match state {
+                    AccumulatorState::Sum(total) => number(total),
+                    AccumulatorState::Avg(_, 0) => JsonValue::Null,
+                    AccumulatorState::Avg(total, count) => number(total / count as f64),
+                    AccumulatorState::Extreme(value) => value.unwrap_or(JsonValue::Null),
+                    AccumulatorState::Count(count) => JsonValue::from(count),
+                };
+                out.insert(field.clone(), value);
+            }
+            JsonValue::Object(out)
+        })
+        .collect()
+}
+
+/// Numbers in a value; arrays contribute their numeric elements
+fn numbers(value: &JsonValue) -> Box<dyn Iterator<Item = f64> + '_> {
+    match value {
+        JsonValue::Number(n) => Box::new(n.as_f64().into_iter()),
+        JsonValue::Array(items) => Box::new(items.iter().filter_map(JsonValue::as_f64)),
+        _ => Box::new(std::iter::empty()),
+    }
+}
+
+/// Integral results stay integers
+fn number(value: f64) -> JsonValue {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        JsonValue::from(value as i64)
+    } else {
+        serde_json::Number::from_f64(value).map(JsonValue::Number).unwrap_or(JsonValue::Null)
+    }
+}
+
+fn unwind(doc: JsonValue, path: &str, preserve_empty: bool) -> Vec<JsonValue> {
+    let value = field_value(&doc, path);
+    let elements = match value {
+        JsonValue::Array(items) if !items.is_empty() => items,
+        JsonValue::Array(_) | JsonValue::Null => {
+            return if preserve_empty { vec![doc] } else { Vec::new() };
+        }
+        other => vec![other],
+    };
+    elements
+        .into_iter()
+        .map(|element| {
+            let mut copy = doc.clone();
+            set_path(&mut copy, path, element);
+            copy
+        })
+        .collect()
+}
+
+fn set_path(doc: &mut JsonValue, path: &str, value: JsonValue) {
+    let mut current = doc;
+    let mut parts = path.split('.').peekable();
+    while let Some(part) = parts.next() {
+        let Some(obj) = current.as_object_mut() else {
+            return;
+        };
+        if parts.peek().is_none() {
+            obj.insert(part.to_string(), value);
+            return;
+        }
+        current = obj.entry(part.to_string()).or_insert_with(|| JsonValue::Object(Map::new()));
+    }
+}
+
+/// Total order across JSON types: null < numbers < strings < objects < arrays < booleans
+pub fn compare(a: &JsonValue, b: &JsonValue) -> Ordering {
+    fn rank(value: &JsonValue) -> u8 {
+        match value {
+            JsonValue::Null => 0,
+            JsonValue::Number(_) => 1,
+            JsonValue::String(_) => 2,
+            JsonValue::Object(_) => 3,
+            JsonValue::Array(_) => 4,
+            JsonValue::Bool(_) => 5,
+        }
+    }
+    match (a, b) {
+        (JsonValue::Number(x), JsonValue::Number(y)) => x
+            .as_f64()
+            .unwrap_or(0.0)
+            .partial_cmp(&y.as_f64().unwrap_or(0.0))
+            .unwrap_or(Ordering::Equal),
+        (JsonValue::String(x), JsonValue::String(y)) => x.cmp(y),
+        (JsonValue::Bool(x), JsonValue::Bool(y)) => x.cmp(y),
+        (JsonValue::Array(x), JsonValue::Array(y)) => x
+            .iter()
+            .zip(y)
+            .map(|(x, y)| compare(x, y))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or_else(|| x.len().cmp(&y.len())),
+        // Objects compare by their serialized form, which is stable for equal objects
+        (JsonValue::Object(_), JsonValue::Object(_)) => a.to_string().cmp(&b.to_string()),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
@@ -0,0 +1,323 @@
+//! Batched signed writes
+//!
+//! `submitBatch` takes many operations in one request. Each operation either
+//! carries its own signature, or the client signs once over a Merkle root of
+//! the whole batch:
+//!
+//! - leaf `i` is `SHA-256(0x00 || canonical_message(op_i))`
+//! - an inner node is `SHA-256(0x01 || left || right)`; an odd node at the
+//!   end of a level is carried up unchanged
+//! - the client signs `cyberfly-batch-root-v1`, a newline, then the root in hex
+//!
+//! The node then gives every operation a signature of its own holding the root
+//! signature and the operation's inclusion proof:
+//!
+//! `merkle:<root signature hex>:<proof>`
+//!
+//! where the proof lists sibling hashes bottom up, comma separated, each
+//! prefixed `L` or `R` for the side it sits on. An operation signed this way
+//! verifies on its own wherever it travels afterwards (the op log, fsck, sync
+//! responses), so it is logged like any other.
+//!
+//! Replicating the batch itself (`SyncMessage::MerkleBatch`) sends the signer
+//! and root signature once, as a `MerkleBatch`; each operation only carries
+//! its leaf index and the sibling hashes of its proof, and the receiving node
+//! rebuilds the `merkle:` signatures before verifying.
+//!
+//! Every operation of a batch checks the same root signature; verified roots
+//! are remembered per thread, so that costs one Ed25519 verification per
+//! worker rather than one per operation.
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use crate::crypto;
+use crate::sync::SignedOperation;
+
+/// Domain separator for the message a batch root signature covers
+pub const BATCH_ROOT_DOMAIN: &str = "cyberfly-batch-root-v1";
+
+/// Prefix of a signature that is a root signature plus inclusion proof
+pub const MERKLE_SIGNATURE_PREFIX: &str = "merkle:";
+
+/// Most operations accepted in one batch
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+/// Longest inclusion proof accepted (far beyond any batch size)
+const MAX_PROOF_STEPS: usize = 32;
+
+/// Verified roots remembered per thread
+const VERIFIED_ROOT_CACHE: usize = 8;
+
+thread_local! {
+    static VERIFIED_ROOTS: RefCell<VecDeque<[u8; 32]>> = const { RefCell::new(VecDeque::new()) };
+}
+
+/// One step of an inclusion proof: the sibling hash and which side it is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofStep {
+    Left([u8; 32]),
+    Right([u8; 32]),
+}
+
+/// A batch signed over its Merkle root, as replicated to peers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MerkleBatch {
+    /// Key that signed the root, shared by every operation
+    pub public_key: String,
+    /// Root signature (hex)
+    pub root_signature: String,
+    /// Number of operations the root was computed over
+    pub leaf_count: usize,
+    /// The replicated operations, possibly only some of the batch
+    pub leaves: Vec<MerkleLeaf>,
+}
+
+/// One operation of a `MerkleBatch`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MerkleLeaf {
+    /// Position of the operation in the batch
+    pub index: usize,
+    /// Sibling hashes (hex) from the leaf up to the root
+    pub proof: Vec<String>,
+    /// The operation, with `public_key` and `signature` left empty
+    pub operation: SignedOperation,
+}
+
+impl MerkleBatch {
+    /// Pack operations carrying `merkle:` signatures from one batch of
+    /// `leaf_count`, each with its index in the batch
+    pub fn pack(leaf_count: usize, ops: Vec<(usize, SignedOperation)>) -> Result<Self> {
+        let mut batch: Option<Self> = None;
+        for (index, mut op) in ops {
+            let (root_signature, proof) = decode_signature(&op.signature)?;
+            let root_signature = hex::encode(root_signature);
+            let batch = batch.get_or_insert_with(|| Self {
+                public_key: op.public_key.clone(),
+                root_signature: root_signature.clone(),
+                leaf_count,
+                leaves: Vec::new(),
+            });
+            if op.public_key != batch.public_key || root_signature != batch.root_signature {
+                bail!("Operation {} was not signed over the same batch root", op.op_id);
+            }
+            op.public_key.clear();
+            op.signature.clear();
+            batch.leaves.push(MerkleLeaf {
+                index,
+                proof: proof
+                    .iter()
+                    .map(|step| match step {
+                        ProofStep::Left(hash) | ProofStep::Right(hash) => hex::encode(hash),
+                    })
+                    .collect(),
+                operation: op,
+            });
+        }
+        batch.ok_or_else(|| anyhow!("Empty batch"))
+    }
+
+    /// The operations with their public key and `merkle:` signature restored.
+    /// Signatures are not verified here.
+    pub fn unpack(self) -> Result<Vec<SignedOperation>> {
+        self.leaves
+            .into_iter()
+            .map(|leaf| {
+                if leaf.index >= self.leaf_count {
+                    bail!("Leaf index {} is outside a batch of {}", leaf.index, self.leaf_count);
+                }
+                let sides = proof_sides(leaf.index, self.leaf_count);
+                if leaf.proof.len() != sides.len() {
+                    bail!("Malformed batch inclusion proof");
+                }
+                let proof = sides
+                    .into_iter()
+                    .zip(&leaf.proof)
+                    .map(|(left, hex)| {
+                        let hash = decode_hash(hex)?;
+                        Ok(if left { ProofStep::Left(hash) } else { ProofStep::Right(hash) })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let mut op = leaf.operation;
+                op.public_key = self.public_key.clone();
+                op.signature = encode_signature(&self.root_signature, &proof);
+                Ok(op)
+            })
+            .collect()
+    }
+}
+
+/// For each step of the proof for leaf `index` of `leaf_count`, whether the
+/// sibling is on the left
+fn proof_sides(mut index: usize, mut leaf_count: usize) -> Vec<bool> {
+    let mut sides = Vec::new();
+    while leaf_count > 1 {
+        if index % 2 == 1 {
+            sides.push(true);
+        } else if index + 1 < leaf_count {
+            sides.push(false);
+        }
+        index /= 2;
+        leaf_count = leaf_count.div_ceil(2);
+    }
+    sides
+}
+
+fn decode_hash(hex: &str) -> Result<[u8; 32]> {
+    crypto::secure_hex_decode(hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow!("Malformed batch inclusion proof"))
+}
+
+/// Merkle leaf of an operation
+pub fn leaf_hash(op: &SignedOperation) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update(op.canonical_message().as_bytes());
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [odd] => *odd,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Root over `leaves`, or `None` for an empty batch
+pub fn merkle_root(leaves: &[[u8; 32]]) -> Option<[u8; 32]> {
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.first().copied()
+}
+
+/// Inclusion proof for the leaf at `index`
+pub fn merkle_proof(leaves: &[[u8; 32]], mut index: usize) -> Vec<ProofStep> {
+    let mut proof = Vec::new();
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        if index % 2 == 1 {
+            proof.push(ProofStep::Left(level[index - 1]));
+        } else if let Some(sibling) = level.get(index + 1) {
+            proof.push(ProofStep::Right(*sibling));
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+    proof
+}
+
+/// Root a proof leads to from `leaf`
+pub fn fold_proof(leaf: [u8; 32], proof: &[ProofStep]) -> [u8; 32] {
+    proof.iter().fold(leaf, |hash, step| match step {
+        ProofStep::Left(sibling) => node_hash(sibling, &hash),
+        ProofStep::Right(sibling) => node_hash(&hash, sibling),
+    })
+}
+
+/// The message a client signs for a batch root
+pub fn root_message(root: &[u8; 32]) -> String {
+    format!("{}\n{}", BATCH_ROOT_DOMAIN, hex::encode(root))
+}
+
+/// Whether a signature is a batch root signature with inclusion proof
+pub fn is_merkle_signature(signature: &str) -> bool {
+    signature.starts_with(MERKLE_SIGNATURE_PREFIX)
+}
+
+/// Give each operation of a batch signed over its Merkle root its own
+/// `merkle:` signature. Returns the root.
+pub fn attach_root_signature(ops: &mut [SignedOperation], root_signature: &str) -> Result<[u8; 32]> {
+    let leaves: Vec<[u8; 32]> = ops.iter().map(leaf_hash).collect();
+    let root = merkle_root(&leaves).ok_or_else(|| anyhow!("Empty batch"))?;
+    for (index, op) in ops.iter_mut().enumerate() {
+        op.signature = encode_signature(root_signature, &merkle_proof(&leaves, index));
+    }
+    Ok(root)
+}
+
+fn encode_signature(root_signature: &str, proof: &[ProofStep]) -> String {
+    let steps: Vec<String> = proof
+        .iter()
+        .map(|step| match step {
+            ProofStep::Left(hash) => format!("L{}", hex::encode(hash)),
+            ProofStep::Right(hash) => format!("R{}", hex::encode(hash)),
+        })
+        .collect();
+    format!("{}{}:{}", MERKLE_SIGNATURE_PREFIX, root_signature.to_lowercase(), steps.join(","))
+}
+
+fn decode_signature(signature: &str) -> Result<(Vec<u8>, Vec<ProofStep>)> {
+    let body = signature
+        .strip_prefix(MERKLE_SIGNATURE_PREFIX)
+        .ok_or_else(|| anyhow!("Not a batch signature"))?;
+    let (root_signature, proof) = body
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Malformed batch signature"))?;
+    let root_signature = crypto::secure_hex_decode(root_signature)
+        .map_err(|e| anyhow!("Invalid root signature hex: {}", e))?;
+
+    let steps: Vec<&str> = proof.split(',').filter(|step| !step.is_empty()).collect();
+    if steps.len() > MAX_PROOF_STEPS {
+        bail!("Batch inclusion proof is too long");
+    }
+    let proof = steps
+        .into_iter()
+        .map(|step| {
+            if let Some(hex) = step.strip_prefix('L') {
+                Ok(ProofStep::Left(decode_hash(hex)?))
+            } else if let Some(hex) = step.strip_prefix('R') {
+                Ok(ProofStep::Right(decode_hash(hex)?))
+            } else {
+                Err(anyhow!("Malformed batch inclusion proof"))
+            }
+        })
+        .collect::<Result<_>>()?;
+    Ok((root_signature, proof))
+}
+
+/// Verify an operation's `merkle:` signature: its inclusion proof must lead
+/// to a root signed by the operation's public key
+pub fn verify_merkle_signature(op: &SignedOperation) -> Result<()> {
+    let (root_signature, proof) = decode_signature(&op.signature)?;
+    let public_key = crypto::secure_hex_decode(&op.public_key)
+        .map_err(|e| anyhow!("Invalid public key hex: {}", e))?;
+    let root = fold_proof(leaf_hash(op), &proof);
+
+    let mut hasher = Sha256::new();
+    hasher.update(&public_key);
+    hasher.update(root);
+    hasher.update(&root_signature);
+    let verified: [u8; 32] = hasher.finalize().into();
+    if VERIFIED_ROOTS.with(|roots| roots.borrow().contains(&verified)) {
+        return Ok(());
+    }
+
+    crypto::verify_signature(&public_key, root_message(&root).as_bytes(), &root_signature)?;
+    VERIFIED_ROOTS.with(|roots| {
+        let mut roots = roots.borrow_mut();
+        if roots.len() >= VERIFIED_ROOT_CACHE {
+            roots.pop_front();
+        }
+        roots.push_back(verified);
+    });
+    Ok(())
+}
@@ -0,0 +1,152 @@
+//! Blob Encoding Module
+//!
+//! Framing for value blobs written by `BlobStorage`. Each blob starts with a
+//! small header naming its encoding, so values above a size threshold can be
+//! zstd-compressed and decoded transparently on read.
+//!
+//! ## Layout
+//! `[0xCF, 0xB1, encoding, payload...]`
+//!
+//! Blobs written before the header existed are plain bincode `StoredValue`s,
+//! which always start with a little-endian `u32` variant index (`0..=8`), so
+//! their first byte can never be `0xCF`. Those are decoded as raw payloads.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use crate::metrics;
+
+const MAGIC: [u8; 2] = [0xCF, 0xB1];
+const HEADER_LEN: usize = 3;
+
+/// Payload encoding recorded in the blob header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BlobEncoding {
+    Raw = 0,
+    Zstd = 1,
+}
+
+impl BlobEncoding {
+    fn from_byte(b: u8) -> Result<Self> {
+        match b {
+            0 => Ok(BlobEncoding::Raw),
+            1 => Ok(BlobEncoding::Zstd),
+            other => Err(anyhow!("Unknown blob encoding: {}", other)),
+        }
+    }
+}
+
+/// Compression settings for value blobs
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// Payloads smaller than this (bytes) are stored uncompressed
+    pub threshold: usize,
+    /// zstd compression level (1-22)
+    pub level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 1024,
+            level: 3,
+        }
+    }
+}
+
+/// Frame a serialized value, compressing it when worthwhile
+pub fn encode(payload: Vec<u8>, config: &CompressionConfig) -> Result<Vec<u8>> {
+    let original_len = payload.len();
+    metrics::STORAGE_VALUE_BYTES_RAW.inc_by(original_len as u64);
+
+    if config.enabled && original_len >= config.threshold {
+        let compressed = zstd::stream::encode_all(payload.as_slice(), config.level)?;
+        // Keep the raw payload if compression doesn't pay for itself
+        if compressed.len() < original_len {
+            metrics::STORAGE_COMPRESSION_RATIO.observe(compressed.len() as f64 / original_len as f64);
+            metrics::STORAGE_VALUE_BYTES_STORED.inc_by((HEADER_LEN + compressed.len()) as u64);
+            return Ok(frame(BlobEncoding::Zstd, &compressed));
+        }
+    }
+
+    metrics::STORAGE_VALUE_BYTES_STORED.inc_by((HEADER_LEN + original_len) as u64);
+    Ok(frame(BlobEncoding::Raw, &payload))
+}
+
+/// Strip the header and decompress; headerless (legacy) blobs pass through
+pub fn decode(blob: &[u8]) -> Result<Cow<'_, [u8]>> {
+    if blob.len() < HEADER_LEN || blob[..2] != MAGIC {
+        return Ok(Cow::Borrowed(blob));
+    }
+    let payload = &blob[HEADER_LEN..];
+    match BlobEncoding::from_byte(blob[2])? {
+        BlobEncoding::Raw => Ok(Cow::Borrowed(payload)),
+        BlobEncoding::Zstd => Ok(Cow::Owned(zstd::stream::decode_all(payload)?)),
+    }
+}
+
+/// Encoding of a stored blob (`Raw` for legacy headerless blobs)
+pub fn encoding_of(blob: &[u8]) -> Result<BlobEncoding> {
+    if blob.len() < HEADER_LEN || blob[..2] != MAGIC {
+        return Ok(BlobEncoding::Raw);
+    }
+    BlobEncoding::from_byte(blob[2])
+}
+
+fn frame(encoding: BlobEncoding, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(&MAGIC);
+    out.push(encoding as u8);
+    out.extend_from_slice(payload);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_payload_stored_raw() {
+        let blob = encode(b"tiny".to_vec(), &CompressionConfig::default()).unwrap();
+        assert_eq!(encoding_of(&blob).unwrap(), BlobEncoding::Raw);
+        assert_eq!(decode(&blob).unwrap().as_ref(), b"tiny");
+    }
+
+    #[test]
+    fn test_large_payload_compressed() {
+        let payload = b"abcdefgh".repeat(1000);
+        let blob = encode(payload.clone(), &CompressionConfig::default()).unwrap();
+        assert_eq!(encoding_of(&blob).unwrap(), BlobEncoding::Zstd);
+        assert!(blob.len() < payload.len() / 10);
+        assert_eq!(decode(&blob).unwrap().as_ref(), payload.as_slice());
+    }
+
+    #[test]
+    fn test_disabled_and_incompressible_stay_raw() {
+        let disabled = CompressionConfig { enabled: false, ..Default::default() };
+        let blob = encode(vec![1u8; 4096], &disabled).unwrap();
+        assert_eq!(encoding_of(&blob).unwrap(), BlobEncoding::Raw);
+
+        // Pseudo-random bytes barely shrink; whichever encoding wins must round-trip
+        let noise: Vec<u8> = (0..4096u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let blob = encode(noise.clone(), &CompressionConfig::default()).unwrap();
+        assert_eq!(decode(&blob).unwrap().as_ref(), noise.as_slice());
+    }
+
+    #[test]
+    fn test_legacy_headerless_blob_passthrough() {
+        // bincode StoredValue::String starts with variant index 0u32
+        let legacy = vec![0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, b'h', b'e', b'l', b'l', b'o', 0, 0];
+        assert_eq!(encoding_of(&legacy).unwrap(), BlobEncoding::Raw);
+        assert_eq!(decode(&legacy).unwrap().as_ref(), legacy.as_slice());
+    }
+
+    #[test]
+    fn test_unknown_encoding_rejected() {
+        assert!(decode(&[0xCF, 0xB1, 9, 1, 2, 3]).is_err());
+    }
+}
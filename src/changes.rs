@@ -0,0 +1,213 @@
+//! Change feed for live data subscriptions
+//!
+//! `BlobStorage` publishes every change it makes to a stored value as a
+//! `ChangeEvent` on a broadcast channel it owns: writes, deletes, TTL changes
+//! and expiry, value migrations. The changes made while applying a signed
+//! operation, whether submitted to this node or replicated by `SyncManager`,
+//! are published as one event for that operation once it is applied (see
+//! `attribute`), including operations that change no stored value, such as
+//! grants.
+//! The `watchKey`, `watchDatabase` and `watchQuery` subscriptions each hold a
+//! receiver and filter it.
+//!
+//! ## Backpressure
+//! The channel is bounded (`CHANGE_FEED_CAPACITY` events) and publishing
+//! never waits for subscribers. A client that falls behind by more than the
+//! buffer loses the oldest events it hasn't read; instead of silently
+//! skipping them, its stream yields `ChangeNotice::Missed` with the number
+//! dropped, so it can re-read the keys it cares about and carry on.
+
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::filters::FilterExpr;
+use crate::sync::SignedOperation;
+
+/// Events buffered for each subscriber before the slowest start missing them
+pub const CHANGE_FEED_CAPACITY: usize = 1024;
+
+/// Operation type of a change that removed a key
+pub const DELETE: &str = "delete";
+
+/// Operation type of a change that removed an expired key
+pub const EXPIRE: &str = "expire";
+
+tokio::task_local! {
+    /// Set while an operation is applied; its changes are published with it
+    static APPLYING: ();
+}
+
+/// One change to stored data
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeEvent {
+    /// The operation that made the change; `None` for changes storage makes
+    /// on its own (deletes, expiry, migrations)
+    pub op_id: Option<String>,
+    pub db_name: String,
+    pub key: String,
+    /// Lowercase store type of the operation or value (`string`, `hash`,
+    /// `grant`, ...), or `delete` / `expire` for a removed key
+    pub op_type: String,
+    pub field: Option<String>,
+    /// Value as written by the operation; without one, the new value of a
+    /// string, or the document of a JSON or hash key (empty otherwise)
+    pub value: String,
+    /// Public key that signed the operation
+    pub signer: Option<String>,
+    /// Peer this node received the operation from. Operations don't record
+    /// the node they were first submitted to, so this is the relaying peer.
+    pub received_from: Option<String>,
+    /// Whether it was made on this node rather than replicated
+    pub local: bool,
+    /// Operation timestamp, or when storage made the change (Unix ms)
+    pub timestamp: i64,
+}
+
+impl ChangeEvent {
+    /// The change an operation makes; `received_from` is `None` for
+    /// operations submitted to this node
+    pub fn from_op(op: &SignedOperation, received_from: Option<&str>) -> Self {
+        Self {
+            op_id: Some(op.op_id.clone()),
+            db_name: op.db_name.clone(),
+            key: op.key.clone(),
+            op_type: op.store_type.to_lowercase(),
+            field: op.field.clone(),
+            value: op.value.clone(),
+            signer: Some(op.public_key.clone()),
+            received_from: received_from.map(str::to_string),
+            local: received_from.is_none(),
+            timestamp: op.timestamp,
+        }
+    }
+
+    /// A change storage makes to `<db_name>:<key>` outside any operation
+    pub fn stored(full_key: &str, op_type: &str, value: String) -> Self {
+        let (db_name, key) = full_key.split_once(':').unwrap_or((full_key, ""));
+        Self {
+            op_id: None,
+            db_name: db_name.to_string(),
+            key: key.to_string(),
+            op_type: op_type.to_string(),
+            field: None,
+            value,
+            signer: None,
+            received_from: None,
+            local: true,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+}
+
+/// Run `write`, which applies the operation `event` describes, and publish
+/// `event` in place of the storage changes it makes if it succeeds
+pub async fn attribute<T, E>(
+    feed: &ChangeFeed,
+    event: ChangeEvent,
+    write: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let result = APPLYING.scope((), write).await;
+    if result.is_ok() {
+        feed.publish(event);
+    }
+    result
+}
+
+/// What a subscriber receives
+#[derive(Debug, Clone)]
+pub enum ChangeNotice {
+    Change(Arc<ChangeEvent>),
+    /// This many events were dropped because the subscriber fell behind
+    Missed(u64),
+}
+
+/// Which changes a subscriber wants
+#[derive(Debug, Clone, Default)]
+pub struct ChangeFilter {
+    pub db_name: String,
+    pub key: Option<String>,
+    pub key_prefix: Option<String>,
+    /// Lowercase store types; empty matches all
+    pub op_types: Vec<String>,
+    pub signer: Option<String>,
+    /// Document filter on values that parse as JSON
+    pub value_filter: Option<FilterExpr>,
+}
+
+impl ChangeFilter {
+    pub fn database(db_name: &str) -> Self {
+        Self {
+            db_name: db_name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn matches(&self, event: &ChangeEvent) -> bool {
+        event.db_name == self.db_name
+            && self.key.as_ref().is_none_or(|key| &event.key == key)
+            && self.key_prefix.as_ref().is_none_or(|prefix| event.key.starts_with(prefix.as_str()))
+            && (self.op_types.is_empty() || self.op_types.contains(&event.op_type))
+            && self.signer.as_ref().is_none_or(|signer| {
+                event.signer.as_ref().is_some_and(|event_signer| signer.eq_ignore_ascii_case(event_signer))
+            })
+            && self.value_filter.as_ref().is_none_or(|filter| {
+                serde_json::from_str(&event.value).is_ok_and(|doc| filter.matches(&doc))
+            })
+    }
+}
+
+/// Broadcast channel of applied operations
+#[derive(Clone)]
+pub struct ChangeFeed {
+    tx: broadcast::Sender<Arc<ChangeEvent>>,
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        Self::new(CHANGE_FEED_CAPACITY)
+    }
+}
+
+impl ChangeFeed {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+        Self { tx }
+    }
+
+    /// Publish a change; a no-op without subscribers
+    pub fn publish(&self, event: ChangeEvent) {
+        let _ = self.tx.send(Arc::new(event));
+    }
+
+    /// Publish a change storage made to `full_key`, unless it is part of an
+    /// operation being applied. `value` is only rendered when someone is
+    /// listening.
+    pub fn publish_stored(&self, full_key: &str, op_type: &str, value: impl FnOnce() -> String) {
+        if APPLYING.try_with(|_| ()).is_ok() || self.subscriber_count() == 0 {
+            return;
+        }
+        self.publish(ChangeEvent::stored(full_key, op_type, value()));
+    }
+
+    /// Number of live subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.tx.receiver_count()
+    }
+
+    /// Changes matching `filter`, from now on
+    pub fn watch(&self, filter: ChangeFilter) -> impl Stream<Item = ChangeNotice> + Send + 'static {
+        BroadcastStream::new(self.tx.subscribe()).filter_map(move |item| {
+            match item {
+                Ok(event) if filter.matches(&event) => Some(ChangeNotice::Change(event)),
+                Ok(_) => None,
+                Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                    crate::metrics::CHANGE_FEED_MISSED.inc_by(missed);
+                    Some(ChangeNotice::Missed(missed))
+                }
+            }
+        })
+    }
+}
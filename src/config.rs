@@ -2,6 +2,11 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::env;
 
+use crate::blob_encoding::CompressionConfig;
+use crate::rate_limit::RateLimitConfig;
+use crate::storage::Durability;
+use crate::storage_backend::BackendKind;
+
 /// TTL tiers for user plans (in seconds)
 /// These will be fetched from smart contract in the future
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -47,6 +52,30 @@ pub struct Config {
     pub relay_config: RelayConfig,
     pub kadena_config: Option<KadenaConfig>,
     pub ttl_tiers: TtlTiers,
+    pub storage_config: StorageConfig,
+    pub security_config: SecurityConfig,
+    pub api_config: ApiConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApiConfig {
+    pub cors_allowed_origins: Vec<String>, // "*" allows any origin, empty only the same origin
+    pub admin_api_tokens: Vec<String>, // tokens granting the admin role
+    pub admin_public_keys: Vec<String>, // node-operator keys that may sign admin tokens
+    pub rate_limit: RateLimitConfig, // per-IP/per-key token buckets and query limits
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityConfig {
+    pub legacy_signatures_until: i64, // Unix ms after which legacy signature formats are rejected
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageConfig {
+    pub backend: BackendKind, // sled (default), redb, or memory
+    pub durability: Durability, // fast (default), flush, or fsync
+    pub durability_overrides: Vec<(String, Durability)>, // per-database levels
+    pub compression: CompressionConfig, // zstd for value blobs above a size threshold
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -217,6 +246,138 @@ impl Config {
             .and_then(|v| v.parse().ok())
             .unwrap_or(31_536_000); // 365 days default
 
+        // Storage backend selection (sled | redb | memory)
+        let storage_backend = env::var("STORAGE_BACKEND")
+            .ok()
+            .and_then(|v| match v.parse::<BackendKind>() {
+                Ok(kind) => Some(kind),
+                Err(e) => {
+                    tracing::warn!("{}, falling back to sled", e);
+                    None
+                }
+            })
+            .unwrap_or(BackendKind::SledIroh);
+
+        // Write durability (fast | flush | fsync), with per-database overrides
+        // in the form "db1=fsync,db2=flush"
+        let storage_durability = env::var("STORAGE_DURABILITY")
+            .ok()
+            .and_then(|v| match v.parse::<Durability>() {
+                Ok(level) => Some(level),
+                Err(e) => {
+                    tracing::warn!("{}, falling back to fast", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        let durability_overrides = env::var("STORAGE_DURABILITY_OVERRIDES")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .filter_map(|entry| {
+                let (db, level) = entry.split_once('=')?;
+                match level.parse::<Durability>() {
+                    Ok(level) => Some((db.trim().to_string(), level)),
+                    Err(e) => {
+                        tracing::warn!("Ignoring durability override '{}': {}", entry, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        // Value blob compression
+        let compression_defaults = CompressionConfig::default();
+        let compression = CompressionConfig {
+            enabled: env::var("STORAGE_COMPRESSION")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(compression_defaults.enabled),
+            threshold: env::var("STORAGE_COMPRESSION_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(compression_defaults.threshold),
+            level: env::var("STORAGE_COMPRESSION_LEVEL")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(compression_defaults.level),
+        };
+
+        // End of the legacy signature deprecation window, as Unix milliseconds
+        // or an RFC 3339 date
+        let legacy_signatures_until = env::var("LEGACY_SIGNATURES_UNTIL").ok().and_then(|v| {
+            let parsed = v.parse::<i64>().ok().or_else(|| {
+                chrono::DateTime::parse_from_rfc3339(&v)
+                    .ok()
+                    .map(|date| date.timestamp_millis())
+            });
+            if parsed.is_none() {
+                tracing::warn!("Ignoring invalid LEGACY_SIGNATURES_UNTIL '{}'", v);
+            }
+            parsed
+        })
+        .unwrap_or(crate::crypto::DEFAULT_LEGACY_SIGNATURES_UNTIL);
+
+        // GraphQL API: CORS allowlist ("*" allows any origin, unset only the same one) and
+        // admin credentials for node-control fields
+        let list = |name: &str| -> Vec<String> {
+            env::var(name)
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        };
+        let cors_allowed_origins = list("CORS_ALLOWED_ORIGINS");
+        let mut admin_public_keys = list("ADMIN_PUBLIC_KEYS");
+        // The node's own key is always an operator key
+        if let Ok(secret) = env::var("NODE_PRIV_KEY") {
+            match hex::decode(secret.trim()).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) {
+                Some(bytes) => admin_public_keys.push(hex::encode(
+                    ed25519_dalek::SigningKey::from_bytes(&bytes).verifying_key().as_bytes(),
+                )),
+                None => tracing::warn!("NODE_PRIV_KEY is not a 32-byte hex key, not using it for admin access"),
+            }
+        }
+
+        // Rate limits and query limits
+        let rate_defaults = RateLimitConfig::default();
+        let rate_limit = RateLimitConfig {
+            enabled: env::var("RATE_LIMIT_ENABLED")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(rate_defaults.enabled),
+            ip_per_second: env::var("RATE_LIMIT_IP_PER_SECOND")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(rate_defaults.ip_per_second),
+            ip_burst: env::var("RATE_LIMIT_IP_BURST")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(rate_defaults.ip_burst),
+            key_per_second: env::var("RATE_LIMIT_KEY_PER_SECOND")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(rate_defaults.key_per_second),
+            key_burst: env::var("RATE_LIMIT_KEY_BURST")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(rate_defaults.key_burst),
+            trust_forwarded_for: env::var("RATE_LIMIT_TRUST_FORWARDED_FOR")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(rate_defaults.trust_forwarded_for),
+            max_query_depth: env::var("GRAPHQL_MAX_DEPTH")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(rate_defaults.max_query_depth),
+            max_query_complexity: env::var("GRAPHQL_MAX_COMPLEXITY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(rate_defaults.max_query_complexity),
+        };
+
         Ok(Self {
             api_host,
             api_port,
@@ -240,6 +401,21 @@ impl Config {
                 pro: ttl_pro,
                 enterprise: ttl_enterprise,
             },
+            storage_config: StorageConfig {
+                backend: storage_backend,
+                durability: storage_durability,
+                durability_overrides,
+                compression,
+            },
+            security_config: SecurityConfig {
+                legacy_signatures_until,
+            },
+            api_config: ApiConfig {
+                cors_allowed_origins,
+                admin_api_tokens: list("ADMIN_API_TOKENS"),
+                admin_public_keys,
+                rate_limit,
+            },
         })
     }
 }
@@ -1,5 +1,7 @@
 use anyhow::{anyhow, Result};
 use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde_json::Value;
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 // Security constants
@@ -9,6 +11,17 @@ pub const MAX_MESSAGE_LENGTH: usize = 1024 * 1024; // 1MB max message size
 pub const MIN_TIMESTAMP_TOLERANCE: u64 = 300; // 5 minutes in seconds
 pub const MAX_TIMESTAMP_TOLERANCE: u64 = 3600; // 1 hour in seconds
 
+/// Domain separator prefixed to every canonical signed-operation message, so a
+/// signature made for this envelope can't be reused in another protocol
+pub const SIGNED_OPERATION_DOMAIN: &str = "cyberfly-signed-operation-v1";
+
+/// Default end of the legacy signature deprecation window:
+/// 2027-01-01T00:00:00Z (Unix ms)
+pub const DEFAULT_LEGACY_SIGNATURES_UNTIL: i64 = 1_798_761_600_000;
+
+/// Legacy (pre-envelope) signatures are accepted until this time (Unix ms)
+static LEGACY_SIGNATURES_UNTIL: AtomicI64 = AtomicI64::new(DEFAULT_LEGACY_SIGNATURES_UNTIL);
+
 // Security error messages
 pub const INVALID_PUBLIC_KEY_LENGTH: &str = "Invalid public key length - must be 32 bytes";
 pub const INVALID_SIGNATURE_LENGTH: &str = "Invalid signature length - must be 64 bytes";
@@ -78,6 +91,11 @@ pub fn extract_name_from_db(db_name: &str) -> Option<String> {
     db_name.rfind('-').map(|pos| db_name[..pos].to_string())
 }
 
+/// Extract the owner's public key from a database name
+pub fn db_owner_key(db_name: &str) -> Option<&str> {
+    db_name.rsplit_once('-').map(|(_, key)| key)
+}
+
 /// Validate timestamp against current time with configurable tolerance
 pub fn validate_timestamp(timestamp: i64, tolerance_seconds: Option<u64>) -> Result<()> {
     let tolerance = tolerance_seconds.unwrap_or(MAX_TIMESTAMP_TOLERANCE);
@@ -162,3 +180,111 @@ pub fn constant_time_eq(a: &str, b: &str) -> bool {
     
     result == 0
 }
+
+/// Set the end of the deprecation window for legacy signature formats
+/// (Unix milliseconds); `DEFAULT_LEGACY_SIGNATURES_UNTIL` until called
+pub fn set_legacy_signature_cutoff(until_ms: i64) {
+    LEGACY_SIGNATURES_UNTIL.store(until_ms, Ordering::Relaxed);
+}
+
+/// End of the deprecation window for legacy signature formats (Unix ms)
+pub fn legacy_signature_cutoff() -> i64 {
+    LEGACY_SIGNATURES_UNTIL.load(Ordering::Relaxed)
+}
+
+/// Whether legacy signature formats are still inside their deprecation window
+pub fn legacy_signatures_accepted() -> bool {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(i64::MAX);
+    now_ms < legacy_signature_cutoff()
+}
+
+/// Serialize JSON deterministically following RFC 8785 (JCS): no whitespace,
+/// object keys sorted by UTF-16 code units, and numbers in ECMAScript form.
+/// Integers that fit in 64 bits are written exactly.
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                out.push_str(&i.to_string());
+            } else if let Some(u) = n.as_u64() {
+                out.push_str(&u.to_string());
+            } else {
+                out.push_str(&ecmascript_number(n.as_f64().unwrap_or_default()));
+            }
+        }
+        // serde_json escapes exactly what JCS requires (quote, backslash and
+        // control characters, with lowercase \u00xx)
+        Value::String(s) => out.push_str(&Value::String(s.clone()).to_string()),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// Format a finite f64 like ECMAScript's Number.prototype.toString
+fn ecmascript_number(x: f64) -> String {
+    if x == 0.0 {
+        return "0".to_string();
+    }
+
+    // Shortest round-trip digits and decimal exponent, e.g. "1.5e-7"
+    let scientific = format!("{:e}", x.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>().unwrap_or(0) + 1;
+
+    let body = if k <= n && n <= 21 {
+        format!("{}{}", digits, "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", "0".repeat((-n) as usize), digits)
+    } else {
+        let e = n - 1;
+        let sign = if e < 0 { '-' } else { '+' };
+        if k == 1 {
+            format!("{}e{}{}", digits, sign, e.abs())
+        } else {
+            format!("{}.{}e{}{}", &digits[..1], &digits[1..], sign, e.abs())
+        }
+    };
+
+    if x < 0.0 {
+        format!("-{}", body)
+    } else {
+        body
+    }
+}
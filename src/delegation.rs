@@ -0,0 +1,282 @@
+//! Delegated write access to databases
+//!
+//! A database `<name>-<owner_key>` belongs to `owner_key`, but the owner can
+//! let other keys write to it with capability grants, in the spirit of UCAN:
+//! the owner (issuer) signs a `Grant` operation naming the grantee (audience),
+//! the store types it may write, the key prefixes it may touch and an expiry.
+//! Grants are ordinary signed operations, so they are verified, logged and
+//! replicated like any other write:
+//!
+//! - `store_type: "Grant"`, `key: "__grants"`, `field: <grantee key>`, and
+//!   `value: {"store_types": [...], "key_prefixes": [...], "expires_at": <ms>}`
+//!   (every member optional; an empty value grants everything)
+//! - `store_type: "Revoke"` with the same key and field withdraws it
+//!
+//! Grant and revoke for one grantee share a CRDT key, so the later of the two
+//! wins on every node. Only the owner may grant or revoke; delegates can't
+//! re-delegate. `SignedOperation::verify_with_delegations` accepts a write
+//! from a delegate holding a grant that covers the operation. A revocation
+//! only refuses writes signed at or after it, so every node accepts the same
+//! writes whatever order grants, revocations and writes reach it in. Writes
+//! submitted through the API (`SignedOperation::verify_submission`) also need
+//! the grant to be live by the node's own clock, since the signer picks the
+//! timestamp.
+//!
+//! ## Persistence
+//! Grants live in the storage backend's `delegations` keyspace (a dedicated
+//! sled tree on the default backend), cached in memory for verification, as
+//! JSON under `<db_name>\0<grantee_key>`.
+
+use anyhow::{anyhow, bail, Result};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+use crate::storage_backend::{MemoryBackend, StorageBackend};
+use crate::sync::SignedOperation;
+
+/// Reserved key that grant and revoke operations are written to
+pub const GRANTS_KEY: &str = "__grants";
+
+/// Whether an operation grants or revokes access rather than writing data
+pub fn is_delegation_op(op: &SignedOperation) -> bool {
+    op.store_type.eq_ignore_ascii_case("grant") || op.store_type.eq_ignore_ascii_case("revoke")
+}
+
+/// What a grant allows its holder to write
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Capability {
+    /// Store types the grantee may write (any when empty)
+    #[serde(default)]
+    pub store_types: Vec<String>,
+    /// Key prefixes the grantee may write under (any when empty)
+    #[serde(default)]
+    pub key_prefixes: Vec<String>,
+    /// Unix milliseconds after which the grant no longer applies
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+}
+
+impl Capability {
+    /// Parse a grant operation's value; an empty value grants everything
+    pub fn parse(value: &str) -> Result<Self> {
+        if value.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(value).map_err(|e| anyhow!("Invalid grant: {}", e))
+    }
+
+    /// Check that an operation signed at its timestamp is within this capability
+    pub fn allows(&self, op: &SignedOperation) -> Result<()> {
+        if let Some(expires_at) = self.expires_at {
+            if op.timestamp >= expires_at {
+                bail!("Grant expired at {}", expires_at);
+            }
+        }
+        if !self.store_types.is_empty()
+            && !self.store_types.iter().any(|t| t.eq_ignore_ascii_case(&op.store_type))
+        {
+            bail!("Grant does not allow {} writes", op.store_type);
+        }
+        if !self.key_prefixes.is_empty()
+            && !self.key_prefixes.iter().any(|prefix| op.key.starts_with(prefix.as_str()))
+        {
+            bail!("Grant does not cover key {}", op.key);
+        }
+        Ok(())
+    }
+}
+
+/// The latest grant or revocation for one grantee on one database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Grant {
+    pub db_name: String,
+    pub grantee: String,
+    /// What the latest grant allowed, kept after a revocation for writes
+    /// signed before it
+    pub capability: Capability,
+    pub revoked: bool,
+    /// Timestamp and ID of the operation that set this state (for LWW)
+    pub issued_at: i64,
+    pub op_id: String,
+    /// Timestamp of the grant `capability` comes from; `None` when only a
+    /// revocation has been seen
+    #[serde(default)]
+    pub granted_at: Option<i64>,
+}
+
+/// Grant registry over a storage backend
+#[derive(Clone)]
+pub struct Delegations {
+    grants: Arc<DashMap<(String, String), Grant>>,
+    backend: Arc<dyn StorageBackend>,
+    /// Serializes LWW compare-and-persist
+    lock: Arc<Mutex<()>>,
+}
+
+impl Default for Delegations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Delegations {
+    /// Create a registry with non-persistent storage
+    pub fn new() -> Self {
+        Self::with_backend(Arc::new(MemoryBackend::new()))
+    }
+
+    /// Create a registry persisting to `backend`; call `load` to pick up
+    /// grants recorded by earlier runs
+    pub fn with_backend(backend: Arc<dyn StorageBackend>) -> Self {
+        Self {
+            grants: Arc::new(DashMap::new()),
+            backend,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Load persisted grants into memory
+    pub async fn load(&self) -> Result<usize> {
+        let backend = Arc::clone(&self.backend);
+        let grants = Arc::clone(&self.grants);
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut count = 0;
+            for entry in backend.delegations().keys_with_prefix("")? {
+                let Some(bytes) = backend.delegations().get(&entry)? else {
+                    continue;
+                };
+                match serde_json::from_slice::<Grant>(&bytes) {
+                    Ok(grant) => {
+                        grants.insert((grant.db_name.clone(), grant.grantee.clone()), grant);
+                        count += 1;
+                    }
+                    Err(e) => tracing::warn!("Skipping unreadable grant {:?}: {}", entry, e),
+                }
+            }
+            Ok(count)
+        })
+        .await
+        .map_err(|e| anyhow!("Thread join error: {}", e))?
+    }
+
+    /// Record a verified grant or revoke operation. Returns false when a
+    /// later operation for the same grantee is already recorded.
+    pub async fn apply(&self, op: &SignedOperation) -> Result<bool> {
+        if op.key != GRANTS_KEY {
+            bail!("Grant and revoke operations must use key {}", GRANTS_KEY);
+        }
+        let grantee = op
+            .field
+            .clone()
+            .ok_or_else(|| anyhow!("field (grantee public key) required for grants"))?;
+        if grantee.len() != 64 || !grantee.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("Grantee must be a hex encoded Ed25519 public key");
+        }
+        let revoked = op.store_type.eq_ignore_ascii_case("revoke");
+        let capability = if revoked {
+            Capability::default()
+        } else {
+            Capability::parse(&op.value)?
+        };
+        let grant = Grant {
+            db_name: op.db_name.clone(),
+            grantee,
+            capability,
+            revoked,
+            issued_at: op.timestamp,
+            op_id: op.op_id.clone(),
+            granted_at: (!revoked).then_some(op.timestamp),
+        };
+
+        let backend = Arc::clone(&self.backend);
+        let grants = Arc::clone(&self.grants);
+        let lock = Arc::clone(&self.lock);
+        tokio::task::spawn_blocking(move || -> Result<bool> {
+            let _guard = lock.lock().map_err(|_| anyhow!("Delegation lock poisoned"))?;
+            let id = (grant.db_name.clone(), grant.grantee.clone());
+            let mut grant = grant;
+            let mut applied = true;
+            if let Some(existing) = grants.get(&id).map(|existing| existing.clone()) {
+                if (existing.issued_at, &existing.op_id) >= (grant.issued_at, &grant.op_id) {
+                    // An older grant reaching us after a revocation still
+                    // covers the writes signed before that revocation
+                    let newer_grant = grant.granted_at > existing.granted_at;
+                    if !existing.revoked || !newer_grant {
+                        return Ok(false);
+                    }
+                    grant = Grant {
+                        capability: grant.capability,
+                        granted_at: grant.granted_at,
+                        ..existing
+                    };
+                    applied = false;
+                } else if grant.revoked {
+                    grant.capability = existing.capability;
+                    grant.granted_at = existing.granted_at;
+                }
+            }
+            let entry = format!("{}\0{}", id.0, id.1);
+            backend.delegations().insert(&entry, serde_json::to_vec(&grant)?)?;
+            grants.insert(id, grant);
+            Ok(applied)
+        })
+        .await
+        .map_err(|e| anyhow!("Thread join error: {}", e))?
+    }
+
+    /// Check that a non-owner signer holds a live grant covering `op`
+    pub fn authorize(&self, op: &SignedOperation) -> Result<()> {
+        if is_delegation_op(op) {
+            bail!("Only the database owner can grant or revoke access");
+        }
+        if crate::encryption::is_encryption_op(op) {
+            bail!("Only the database owner can change its encryption");
+        }
+        if crate::read_access::is_privacy_op(op) {
+            bail!("Only the database owner can change who may read it");
+        }
+        let grant = self
+            .grants
+            .get(&(op.db_name.clone(), op.public_key.clone()))
+            .ok_or_else(|| anyhow!("Key {} has no grant on {}", op.public_key, op.db_name))?;
+        if grant.revoked && (op.timestamp >= grant.issued_at || grant.granted_at.is_none()) {
+            bail!("Grant for {} on {} was revoked at {}", op.public_key, op.db_name, grant.issued_at);
+        }
+        grant.capability.allows(op)
+    }
+
+    /// Like `authorize`, but the grant must also be live by the node's clock
+    /// at `now_ms`, not just at the timestamp the signer chose, so a delegate
+    /// can't backdate a write past a revocation or expiry within the
+    /// timestamp tolerance
+    pub fn authorize_at(&self, op: &SignedOperation, now_ms: i64) -> Result<()> {
+        self.authorize(op)?;
+        if !self.is_active(&op.db_name, &op.public_key, now_ms) {
+            bail!("Grant for {} on {} is no longer active", op.public_key, op.db_name);
+        }
+        Ok(())
+    }
+
+    /// Whether `grantee` holds a grant on `db_name` that is neither revoked
+    /// nor expired at `now_ms`
+    pub fn is_active(&self, db_name: &str, grantee: &str, now_ms: i64) -> bool {
+        self.grants
+            .get(&(db_name.to_string(), grantee.to_string()))
+            .is_some_and(|grant| {
+                !grant.revoked && grant.capability.expires_at.is_none_or(|expires_at| now_ms < expires_at)
+            })
+    }
+
+    /// Current grants and revocations on a database, by grantee
+    pub fn grants(&self, db_name: &str) -> Vec<Grant> {
+        let mut grants: Vec<Grant> = self
+            .grants
+            .iter()
+            .filter(|entry| entry.key().0 == db_name)
+            .map(|entry| entry.value().clone())
+            .collect();
+        grants.sort_by(|a, b| a.grantee.cmp(&b.grantee));
+        grants
+    }
+}
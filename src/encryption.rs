@@ -0,0 +1,438 @@
+//! Encrypted databases with client-side keys
+//!
+//! The owner of a database can mark it encrypted so that nodes only ever see
+//! ciphertext. Values are sealed by clients with XChaCha20-Poly1305 under a
+//! random 32-byte database key; nodes never hold that key. The owner publishes
+//! the key wrapped to each member's Ed25519 public key (converted to X25519)
+//! in an ordinary signed operation, so it is verified, logged and replicated
+//! like any other write:
+//!
+//! - `store_type: "Encryption"`, `key: "__encryption"`, and `value:`
+//!   `{"algorithm": "xchacha20poly1305", "key_id": "...", "members": {<member key>: <wrapped key>}}`
+//!
+//! The latest metadata operation wins (LWW), which is how the key is rotated
+//! or members are added and removed. Only the owner can publish it, and a
+//! database can't be switched back to plaintext.
+//!
+//! ## Envelope format
+//! All binary fields are standard base64; public keys are hex like everywhere
+//! else. A sealed value is the JSON string
+//! `{"v": 1, "alg": "xchacha20poly1305", "kid": <key id>, "nonce": <24 bytes>, "ct": <ciphertext + tag>}`
+//! with associated data `cyberfly-encrypted-value-v1\0<db_name>\0<key>\0<field>`
+//! (empty field when the store type has none), so a value can't be moved to
+//! another key. A wrapped key is `{"epk": <ephemeral X25519 key>, "nonce": <24 bytes>, "ct": <sealed db key>}`:
+//! the sealing key is HKDF-SHA256 over the X25519 shared secret with salt
+//! `epk || member X25519 key` and info `cyberfly-db-key-wrap-v1`, and the
+//! associated data is the database name.
+//!
+//! ## What nodes can still do
+//! Keys, hash fields and sorted-set scores stay in plaintext, so key-level
+//! operations keep working: `get*`, `getAll`, field and score filters, TTLs
+//! and sync. Encrypted databases accept only String, Hash, List, Set and
+//! SortedSet writes whose value is a sealed envelope; queries that need to
+//! read values (JSON filters, aggregation, SQL, value patterns, secondary and
+//! full-text indexes) are refused.
+//!
+//! ## Persistence
+//! Metadata lives in the storage backend's `encryption` keyspace (a dedicated
+//! sled tree on the default backend), cached in memory, as JSON under
+//! `<db_name>`.
+
+use anyhow::{anyhow, bail, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use curve25519_dalek::MontgomeryPoint;
+use dashmap::DashMap;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::crypto;
+use crate::storage_backend::{MemoryBackend, StorageBackend};
+use crate::sync::SignedOperation;
+
+/// Reserved key that encryption metadata operations are written to
+pub const ENCRYPTION_KEY: &str = "__encryption";
+
+/// The only supported cipher
+pub const ALGORITHM: &str = "xchacha20poly1305";
+
+/// Version of the sealed value envelope
+pub const ENVELOPE_VERSION: u8 = 1;
+
+/// Store types whose values can be sealed without nodes needing to read them
+pub const ENCRYPTED_STORE_TYPES: &[&str] = &["string", "hash", "list", "set", "sortedset"];
+
+const VALUE_AAD_DOMAIN: &str = "cyberfly-encrypted-value-v1";
+const KEY_WRAP_INFO: &[u8] = b"cyberfly-db-key-wrap-v1";
+const NONCE_LENGTH: usize = 24;
+const KEY_LENGTH: usize = 32;
+const TAG_LENGTH: usize = 16;
+
+/// Whether an operation publishes encryption metadata rather than writing data
+pub fn is_encryption_op(op: &SignedOperation) -> bool {
+    op.store_type.eq_ignore_ascii_case("encryption")
+}
+
+/// Generate a random database key
+pub fn generate_db_key() -> [u8; KEY_LENGTH] {
+    rand::random()
+}
+
+fn decode_fixed<const N: usize>(name: &str, encoded: &str) -> Result<[u8; N]> {
+    BASE64
+        .decode(encoded)
+        .map_err(|e| anyhow!("{} is not valid base64: {}", name, e))?
+        .try_into()
+        .map_err(|_| anyhow!("{} must be {} bytes", name, N))
+}
+
+fn cipher(key: &[u8; KEY_LENGTH]) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(key.into())
+}
+
+/// A value sealed under a database key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SealedValue {
+    pub v: u8,
+    pub alg: String,
+    pub kid: String,
+    pub nonce: String,
+    pub ct: String,
+}
+
+impl SealedValue {
+    /// Parse and check the shape of an envelope, without decrypting it
+    pub fn parse(value: &str) -> Result<Self> {
+        let sealed: Self = serde_json::from_str(value)
+            .map_err(|e| anyhow!("Value is not an encrypted envelope: {}", e))?;
+        if sealed.v != ENVELOPE_VERSION {
+            bail!("Unsupported envelope version {}", sealed.v);
+        }
+        if sealed.alg != ALGORITHM {
+            bail!("Unsupported encryption algorithm {}", sealed.alg);
+        }
+        if sealed.kid.is_empty() {
+            bail!("Envelope key id is empty");
+        }
+        decode_fixed::<NONCE_LENGTH>("nonce", &sealed.nonce)?;
+        let ct = BASE64
+            .decode(&sealed.ct)
+            .map_err(|e| anyhow!("ct is not valid base64: {}", e))?;
+        if ct.len() < TAG_LENGTH {
+            bail!("Ciphertext is shorter than its authentication tag");
+        }
+        Ok(sealed)
+    }
+
+    /// Seal `plaintext` for `key` (and hash `field`) of `db_name`
+    pub fn seal(
+        db_key: &[u8; KEY_LENGTH],
+        key_id: &str,
+        db_name: &str,
+        key: &str,
+        field: Option<&str>,
+        plaintext: &[u8],
+    ) -> Result<Self> {
+        let nonce: [u8; NONCE_LENGTH] = rand::random();
+        let aad = value_aad(db_name, key, field);
+        let ct = cipher(db_key)
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad: &aad })
+            .map_err(|_| anyhow!("Encryption failed"))?;
+        Ok(Self {
+            v: ENVELOPE_VERSION,
+            alg: ALGORITHM.to_string(),
+            kid: key_id.to_string(),
+            nonce: BASE64.encode(nonce),
+            ct: BASE64.encode(ct),
+        })
+    }
+
+    /// Decrypt an envelope read from `key` (and hash `field`) of `db_name`
+    pub fn open(&self, db_key: &[u8; KEY_LENGTH], db_name: &str, key: &str, field: Option<&str>) -> Result<Vec<u8>> {
+        let nonce = decode_fixed::<NONCE_LENGTH>("nonce", &self.nonce)?;
+        let ct = BASE64.decode(&self.ct).map_err(|e| anyhow!("ct is not valid base64: {}", e))?;
+        let aad = value_aad(db_name, key, field);
+        cipher(db_key)
+            .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ct, aad: &aad })
+            .map_err(|_| anyhow!("Decryption failed: wrong key or tampered value"))
+    }
+
+    /// The envelope as stored
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+fn value_aad(db_name: &str, key: &str, field: Option<&str>) -> Vec<u8> {
+    format!("{}\0{}\0{}\0{}", VALUE_AAD_DOMAIN, db_name, key, field.unwrap_or("")).into_bytes()
+}
+
+/// A database key wrapped to one member
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WrappedKey {
+    pub epk: String,
+    pub nonce: String,
+    pub ct: String,
+}
+
+impl WrappedKey {
+    /// Wrap `db_key` to the member with hex Ed25519 public key `member`
+    pub fn wrap(db_key: &[u8; KEY_LENGTH], db_name: &str, member: &str) -> Result<Self> {
+        let member_x = member_x25519(member)?;
+        let ephemeral: [u8; KEY_LENGTH] = rand::random();
+        let epk = MontgomeryPoint::mul_base_clamped(ephemeral);
+        let shared = member_x.mul_clamped(ephemeral);
+        let kek = wrapping_key(&shared, &epk, &member_x)?;
+
+        let nonce: [u8; NONCE_LENGTH] = rand::random();
+        let ct = cipher(&kek)
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: db_key, aad: db_name.as_bytes() })
+            .map_err(|_| anyhow!("Key wrapping failed"))?;
+        Ok(Self {
+            epk: BASE64.encode(epk.to_bytes()),
+            nonce: BASE64.encode(nonce),
+            ct: BASE64.encode(ct),
+        })
+    }
+
+    /// Recover the database key with the member's Ed25519 signing key
+    pub fn unwrap(&self, db_name: &str, member: &SigningKey) -> Result<[u8; KEY_LENGTH]> {
+        let epk = MontgomeryPoint(decode_fixed::<KEY_LENGTH>("epk", &self.epk)?);
+        let member_x = member.verifying_key().to_montgomery();
+        let shared = epk.mul_clamped(member.to_scalar_bytes());
+        let kek = wrapping_key(&shared, &epk, &member_x)?;
+
+        let nonce = decode_fixed::<NONCE_LENGTH>("nonce", &self.nonce)?;
+        let ct = BASE64.decode(&self.ct).map_err(|e| anyhow!("ct is not valid base64: {}", e))?;
+        cipher(&kek)
+            .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ct, aad: db_name.as_bytes() })
+            .map_err(|_| anyhow!("Key unwrapping failed: not a member or tampered key"))?
+            .try_into()
+            .map_err(|_| anyhow!("Unwrapped database key must be {} bytes", KEY_LENGTH))
+    }
+
+    fn check(&self) -> Result<()> {
+        decode_fixed::<KEY_LENGTH>("epk", &self.epk)?;
+        decode_fixed::<NONCE_LENGTH>("nonce", &self.nonce)?;
+        let ct = BASE64.decode(&self.ct).map_err(|e| anyhow!("ct is not valid base64: {}", e))?;
+        if ct.len() != KEY_LENGTH + TAG_LENGTH {
+            bail!("Wrapped key must be {} bytes", KEY_LENGTH + TAG_LENGTH);
+        }
+        Ok(())
+    }
+}
+
+/// X25519 public key of a hex encoded Ed25519 public key
+fn member_x25519(member: &str) -> Result<MontgomeryPoint> {
+    let bytes: [u8; KEY_LENGTH] = crypto::secure_hex_decode(member)?
+        .try_into()
+        .map_err(|_| anyhow!(crypto::INVALID_PUBLIC_KEY_LENGTH))?;
+    let key = VerifyingKey::from_bytes(&bytes).map_err(|e| anyhow!("Invalid member key {}: {}", member, e))?;
+    Ok(key.to_montgomery())
+}
+
+fn wrapping_key(shared: &MontgomeryPoint, epk: &MontgomeryPoint, member_x: &MontgomeryPoint) -> Result<[u8; KEY_LENGTH]> {
+    let mut salt = Vec::with_capacity(2 * KEY_LENGTH);
+    salt.extend_from_slice(epk.as_bytes());
+    salt.extend_from_slice(member_x.as_bytes());
+    let mut kek = [0u8; KEY_LENGTH];
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(KEY_WRAP_INFO, &mut kek)
+        .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+    Ok(kek)
+}
+
+/// Encryption metadata of a database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    pub algorithm: String,
+    pub key_id: String,
+    /// Database key wrapped to each member, by hex Ed25519 public key
+    pub members: BTreeMap<String, WrappedKey>,
+}
+
+impl EncryptionConfig {
+    /// Wrap a fresh or rotated database key to every member
+    pub fn new(db_key: &[u8; KEY_LENGTH], key_id: &str, db_name: &str, members: &[&str]) -> Result<Self> {
+        let members = members
+            .iter()
+            .map(|member| Ok((member.to_string(), WrappedKey::wrap(db_key, db_name, member)?)))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            algorithm: ALGORITHM.to_string(),
+            key_id: key_id.to_string(),
+            members,
+        })
+    }
+
+    /// Parse and validate a metadata operation's value
+    pub fn parse(value: &str) -> Result<Self> {
+        let config: Self = serde_json::from_str(value).map_err(|e| anyhow!("Invalid encryption metadata: {}", e))?;
+        if config.algorithm != ALGORITHM {
+            bail!("Unsupported encryption algorithm {}", config.algorithm);
+        }
+        if config.key_id.is_empty() {
+            bail!("Encryption key_id is empty");
+        }
+        if config.members.is_empty() {
+            bail!("Encryption metadata must wrap the key to at least one member");
+        }
+        for (member, wrapped) in &config.members {
+            member_x25519(member)?;
+            wrapped.check().map_err(|e| anyhow!("Wrapped key for {}: {}", member, e))?;
+        }
+        Ok(config)
+    }
+
+    /// The database key wrapped to `member`, if they are one
+    pub fn wrapped_key(&self, member: &str) -> Option<&WrappedKey> {
+        self.members.get(member)
+    }
+}
+
+/// The latest encryption metadata of one database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DbEncryption {
+    pub db_name: String,
+    pub config: EncryptionConfig,
+    /// Timestamp and ID of the operation that set it (for LWW)
+    pub issued_at: i64,
+    pub op_id: String,
+}
+
+/// Registry of encrypted databases over a storage backend
+#[derive(Clone)]
+pub struct EncryptedDatabases {
+    databases: Arc<DashMap<String, DbEncryption>>,
+    backend: Arc<dyn StorageBackend>,
+    /// Serializes LWW compare-and-persist
+    lock: Arc<Mutex<()>>,
+}
+
+impl Default for EncryptedDatabases {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EncryptedDatabases {
+    /// Create a registry with non-persistent storage
+    pub fn new() -> Self {
+        Self::with_backend(Arc::new(MemoryBackend::new()))
+    }
+
+    /// Create a registry persisting to `backend`; call `load` to pick up
+    /// metadata recorded by earlier runs
+    pub fn with_backend(backend: Arc<dyn StorageBackend>) -> Self {
+        Self {
+            databases: Arc::new(DashMap::new()),
+            backend,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Load persisted metadata into memory
+    pub async fn load(&self) -> Result<usize> {
+        let backend = Arc::clone(&self.backend);
+        let databases = Arc::clone(&self.databases);
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut count = 0;
+            for entry in backend.encryption().keys_with_prefix("")? {
+                let Some(bytes) = backend.encryption().get(&entry)? else {
+                    continue;
+                };
+                match serde_json::from_slice::<DbEncryption>(&bytes) {
+                    Ok(encryption) => {
+                        databases.insert(encryption.db_name.clone(), encryption);
+                        count += 1;
+                    }
+                    Err(e) => tracing::warn!("Skipping unreadable encryption metadata {:?}: {}", entry, e),
+                }
+            }
+            Ok(count)
+        })
+        .await
+        .map_err(|e| anyhow!("Thread join error: {}", e))?
+    }
+
+    /// Record a verified metadata operation. Returns false when later
+    /// metadata for the database is already recorded.
+    pub async fn apply(&self, op: &SignedOperation) -> Result<bool> {
+        if op.key != ENCRYPTION_KEY {
+            bail!("Encryption operations must use key {}", ENCRYPTION_KEY);
+        }
+        let encryption = DbEncryption {
+            db_name: op.db_name.clone(),
+            config: EncryptionConfig::parse(&op.value)?,
+            issued_at: op.timestamp,
+            op_id: op.op_id.clone(),
+        };
+
+        let backend = Arc::clone(&self.backend);
+        let databases = Arc::clone(&self.databases);
+        let lock = Arc::clone(&self.lock);
+        tokio::task::spawn_blocking(move || -> Result<bool> {
+            let _guard = lock.lock().map_err(|_| anyhow!("Encryption lock poisoned"))?;
+            if let Some(existing) = databases.get(&encryption.db_name) {
+                if (existing.issued_at, &existing.op_id) >= (encryption.issued_at, &encryption.op_id) {
+                    return Ok(false);
+                }
+            }
+            backend.encryption().insert(&encryption.db_name, serde_json::to_vec(&encryption)?)?;
+            databases.insert(encryption.db_name.clone(), encryption);
+            Ok(true)
+        })
+        .await
+        .map_err(|e| anyhow!("Thread join error: {}", e))?
+    }
+
+    /// Whether `db_name` has been marked encrypted
+    pub fn is_encrypted(&self, db_name: &str) -> bool {
+        self.databases.contains_key(db_name)
+    }
+
+    /// Current encryption metadata of a database
+    pub fn get(&self, db_name: &str) -> Option<DbEncryption> {
+        self.databases.get(db_name).map(|entry| entry.value().clone())
+    }
+
+    /// Check that a data write to an encrypted database carries a sealed
+    /// value in a store type that nodes don't need to read
+    pub fn check_write(&self, op: &SignedOperation) -> Result<()> {
+        if is_encryption_op(op)
+            || crate::delegation::is_delegation_op(op)
+            || crate::read_access::is_privacy_op(op)
+            || !self.is_encrypted(&op.db_name)
+        {
+            return Ok(());
+        }
+        let store_type = op.store_type.to_lowercase();
+        if !ENCRYPTED_STORE_TYPES.contains(&store_type.as_str()) {
+            bail!(
+                "Database {} is encrypted; {} writes are not supported (use String, Hash, List, Set or SortedSet)",
+                op.db_name,
+                op.store_type
+            );
+        }
+        SealedValue::parse(&op.value)
+            .map_err(|e| anyhow!("Database {} is encrypted: {}", op.db_name, e))?;
+        Ok(())
+    }
+
+    /// Refuse a query that has to read values of an encrypted database
+    pub fn check_value_query(&self, db_name: &str) -> Result<()> {
+        if self.is_encrypted(db_name) {
+            bail!(
+                "Database {} is encrypted; only key-level operations are available",
+                db_name
+            );
+        }
+        Ok(())
+    }
+}
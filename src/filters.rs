@@ -1,8 +1,13 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
 use serde_json::Value as JsonValue;
+use futures::Stream;
 use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
 
-use crate::storage::BlobStorage;
+use crate::query_planner::{self, QueryPlan};
+use crate::storage::{BlobStorage, StoreType};
 
 /// JSON filter for advanced querying with conditions, sorting, and pagination
 pub struct JsonFilter<'a> {
@@ -22,29 +27,81 @@ impl<'a> JsonFilter<'a> {
         conditions: &JsonFilterConditions,
         options: &FilterOptions,
     ) -> Result<Vec<JsonValue>> {
-        // Scan keys matching pattern
-        let keys = self.storage.scan_keys(pattern).await?;
+        let plan = self.explain(pattern, conditions, options).await;
+        self.execute(pattern, &plan, conditions, options).await
+    }
+
+    /// Plan a filter without running it; see `query_planner`
+    pub async fn explain(
+        &self,
+        pattern: &str,
+        conditions: &JsonFilterConditions,
+        options: &FilterOptions,
+    ) -> QueryPlan {
+        let db_name = query_planner::pattern_db(pattern);
+        let indexes = match db_name {
+            Some(db_name) => self.storage.index_manager().indexes_for(db_name).await,
+            None => Vec::new(),
+        };
+        query_planner::plan(&indexes, db_name, conditions, options)
+    }
+
+    /// Run a filter with a plan from `explain`
+    pub async fn execute(
+        &self,
+        pattern: &str,
+        plan: &QueryPlan,
+        conditions: &JsonFilterConditions,
+        options: &FilterOptions,
+    ) -> Result<Vec<JsonValue>> {
+        // Candidates from the indexes, or every key matching the pattern
+        let keys = match (plan.candidate_keys(self.storage.index_manager()).await?, &plan.db_name) {
+            (Some(candidates), Some(db_name)) => {
+                let re = regex::Regex::new(&pattern.replace("*", ".*").replace("?", "."))?;
+                candidates
+                    .into_iter()
+                    .map(|key| format!("{}:{}", db_name, key))
+                    .filter(|key| re.is_match(key))
+                    .collect()
+            }
+            _ => self.storage.scan_keys(pattern).await?,
+        };
 
         if keys.is_empty() {
             return Ok(Vec::new());
         }
 
-        let mut collected = Vec::new();
+        let offset = options.offset.unwrap_or(0);
+        let limit = options.limit.unwrap_or(usize::MAX);
 
-        // Process keys and collect matching documents
-        for key in keys {
-            if let Ok(Some(json_str)) = self.storage.get_json(&key, None).await {
-                if let Ok(mut doc) = serde_json::from_str::<JsonValue>(&json_str) {
-                    // Apply conditions
-                    if self.matches_conditions(&doc, conditions) {
-                        // Add _key field for reference
-                        if let Some(obj) = doc.as_object_mut() {
-                            obj.insert("_key".to_string(), JsonValue::String(key.clone()));
-                        }
-                        collected.push(doc);
+        // Keys already in sort order: stop once the page is full
+        if plan.index_order {
+            let ordered: Box<dyn Iterator<Item = String> + Send> = match options.sort_order {
+                SortOrder::Asc => Box::new(keys.into_iter()),
+                SortOrder::Desc => Box::new(keys.into_iter().rev()),
+            };
+            let mut page = Vec::new();
+            let mut skipped = 0;
+            for key in ordered {
+                if page.len() >= limit {
+                    break;
+                }
+                if let Some(doc) = self.matching_doc(&key, conditions).await {
+                    if skipped < offset {
+                        skipped += 1;
+                    } else {
+                        page.push(doc);
                     }
                 }
             }
+            return Ok(page);
+        }
+
+        let mut collected = Vec::new();
+        for key in keys {
+            if let Some(doc) = self.matching_doc(&key, conditions).await {
+                collected.push(doc);
+            }
         }
 
         // Sort if required
@@ -54,18 +111,35 @@ impl<'a> JsonFilter<'a> {
         }
 
         // Apply pagination
-        let offset = options.offset.unwrap_or(0);
-        let limit = options.limit.unwrap_or(usize::MAX);
-
         Ok(collected.into_iter().skip(offset).take(limit).collect())
     }
 
+    /// The JSON document at `key` with a `_key` field, if it matches
+    async fn matching_doc(&self, key: &str, conditions: &JsonFilterConditions) -> Option<JsonValue> {
+        let json_str = self.storage.get_json(key, None).await.ok()??;
+        let mut doc = serde_json::from_str::<JsonValue>(&json_str).ok()?;
+        if !self.matches_conditions(&doc, conditions) {
+            return None;
+        }
+        // Add _key field for reference
+        if let Some(obj) = doc.as_object_mut() {
+            obj.insert("_key".to_string(), JsonValue::String(key.to_string()));
+        }
+        Some(doc)
+    }
+
     /// Check if a JSON document matches the filter conditions
     fn matches_conditions(&self, doc: &JsonValue, conditions: &JsonFilterConditions) -> bool {
         if conditions.is_empty() {
             return true;
         }
 
+        if let Some(filter) = &conditions.filter {
+            if !filter.matches(doc) {
+                return false;
+            }
+        }
+
         for (field, condition) in &conditions.conditions {
             let field_value = self.get_nested_field(doc, field);
 
@@ -263,7 +337,9 @@ impl<'a> TimeSeriesFilter<'a> {
         Self { storage }
     }
 
-    /// Query time series data with optional filtering and aggregation
+    /// Query time series data with optional filtering and aggregation.
+    /// Buckets without a value are left out; see `query_buckets` to keep
+    /// them as nulls.
     pub async fn query(
         &self,
         key: &str,
@@ -271,6 +347,24 @@ impl<'a> TimeSeriesFilter<'a> {
         to_timestamp: i64,
         options: &TimeSeriesOptions,
     ) -> Result<Vec<(i64, f64)>> {
+        Ok(self
+            .query_buckets(key, from_timestamp, to_timestamp, options)
+            .await?
+            .into_iter()
+            .filter_map(|(ts, val)| val.map(|val| (ts, val)))
+            .collect())
+    }
+
+    /// Like `query`, but keeps the buckets that have no value: with
+    /// `GapFill::Null`, missing buckets and buckets whose aggregate is
+    /// undefined (such as a rate over a single point) come back as `None`
+    pub async fn query_buckets(
+        &self,
+        key: &str,
+        from_timestamp: i64,
+        to_timestamp: i64,
+        options: &TimeSeriesOptions,
+    ) -> Result<Vec<(i64, Option<f64>)>> {
         let mut points = self
             .storage
             .ts_range(key, from_timestamp, to_timestamp)
@@ -295,53 +389,211 @@ impl<'a> TimeSeriesFilter<'a> {
         }
 
         // Apply aggregation
-        if let Some(ref agg) = options.aggregation {
-            points = self.aggregate_points(&points, agg)?;
+        let Some(ref agg) = options.aggregation else {
+            return Ok(points.into_iter().map(|(ts, val)| (ts, Some(val))).collect());
+        };
+        let origin = options.alignment.origin(from_timestamp, to_timestamp);
+        let buckets = self.aggregate_points(&points, agg, origin)?;
+        match options.fill {
+            Some(fill) => fill_gaps(buckets, agg.time_bucket, fill),
+            None => Ok(buckets.into_iter().filter(|(_, val)| val.is_some()).collect()),
         }
-
-        Ok(points)
     }
 
-    /// Aggregate time series points
+    /// Aggregate time series points into buckets that start at `origin` plus
+    /// a multiple of the bucket size
     fn aggregate_points(
         &self,
         points: &[(i64, f64)],
         agg: &Aggregation,
-    ) -> Result<Vec<(i64, f64)>> {
-        if points.is_empty() {
-            return Ok(Vec::new());
+        origin: i64,
+    ) -> Result<Vec<(i64, Option<f64>)>> {
+        if agg.time_bucket <= 0 {
+            bail!("Aggregation needs a positive time bucket");
         }
 
-        let mut buckets: std::collections::HashMap<i64, Vec<f64>> =
-            std::collections::HashMap::new();
-
-        // Group points into time buckets
+        // Group points into time buckets, keeping them in time order
+        let mut buckets: BTreeMap<i64, Vec<(i64, f64)>> = BTreeMap::new();
         for (ts, val) in points {
-            let bucket_ts = (ts / agg.time_bucket) * agg.time_bucket;
-            buckets.entry(bucket_ts).or_default().push(*val);
+            let bucket_ts = bucket_start(*ts, origin, agg.time_bucket);
+            buckets.entry(bucket_ts).or_default().push((*ts, *val));
         }
 
         // Aggregate each bucket
-        let mut result: Vec<(i64, f64)> = buckets
+        Ok(buckets
             .into_iter()
-            .map(|(ts, values)| {
-                let agg_value = match agg.agg_type {
-                    AggregationType::Avg => values.iter().sum::<f64>() / values.len() as f64,
-                    AggregationType::Sum => values.iter().sum::<f64>(),
-                    AggregationType::Min => values.iter().cloned().fold(f64::INFINITY, f64::min),
-                    AggregationType::Max => {
-                        values.iter().cloned().fold(f64::NEG_INFINITY, f64::max)
+            .map(|(ts, points)| (ts, agg.agg_type.apply(&points)))
+            .collect())
+    }
+
+    /// Time series of a db matching a selector, with their labels
+    pub async fn select_series(
+        &self,
+        db_name: &str,
+        selector: &SeriesSelector,
+    ) -> Result<Vec<LabeledSeries>> {
+        let pattern = selector.key_pattern.as_deref().unwrap_or("*");
+        let re = Regex::new(&format!(
+            "^{}:{}$",
+            regex::escape(db_name),
+            regex::escape(pattern).replace(r"\*", ".*").replace(r"\?", ".")
+        ))?;
+
+        let mut series = Vec::new();
+        for key in self.storage.get_keys_by_type(db_name, StoreType::TimeSeries).await? {
+            if !re.is_match(&key) {
+                continue;
+            }
+            let labels = self.storage.ts_labels(&key).await?;
+            if selector
+                .labels
+                .iter()
+                .all(|(name, value)| labels.get(name) == Some(value))
+            {
+                series.push((key, labels));
+            }
+        }
+        Ok(series)
+    }
+
+    /// Query many series at once (like TS.MRANGE): select them by key pattern
+    /// and labels, aggregate each into buckets aligned on multiples of the
+    /// bucket size, and optionally reduce across series per bucket
+    pub async fn mrange(
+        &self,
+        db_name: &str,
+        selector: &SeriesSelector,
+        options: &MRangeOptions,
+    ) -> Result<Vec<SeriesRange>> {
+        let series = self.select_series(db_name, selector).await?;
+        self.range_series(&series, options.from_timestamp, options.to_timestamp, options)
+            .await
+    }
+
+    /// `mrange` over consecutive windows of about `chunk` milliseconds, so a
+    /// large range is read and returned piece by piece. Windows start on bucket
+    /// boundaries, so no bucket is split; windows without points are skipped.
+    pub fn mrange_stream(
+        &self,
+        db_name: String,
+        selector: SeriesSelector,
+        options: MRangeOptions,
+        chunk: i64,
+    ) -> impl Stream<Item = Result<MRangeChunk>> + 'a {
+        let storage = self.storage;
+        let bucket = options.aggregation.as_ref().map_or(1, |agg| agg.time_bucket.max(1));
+        // Round up to whole buckets
+        let chunk = chunk.max(1).saturating_add(bucket - 1) / bucket * bucket;
+        let params = Arc::new((db_name, selector, options));
+
+        futures::stream::try_unfold(
+            (None, Some(params.2.from_timestamp)),
+            move |(series, mut next): (Option<Vec<LabeledSeries>>, Option<i64>)| {
+                let params = Arc::clone(&params);
+                async move {
+                    let (db_name, selector, options) = &*params;
+                    let filter = TimeSeriesFilter::new(storage);
+                    let series = match series {
+                        Some(series) => series,
+                        None => filter.select_series(db_name, selector).await?,
+                    };
+                    while let Some(start) = next.filter(|start| *start <= options.to_timestamp) {
+                        let end = start
+                            .div_euclid(chunk)
+                            .saturating_mul(chunk)
+                            .saturating_add(chunk - 1)
+                            .min(options.to_timestamp);
+                        next = end.checked_add(1);
+                        let ranges = filter.range_series(&series, start, end, options).await?;
+                        if ranges.iter().any(|range| !range.points.is_empty()) {
+                            let chunk = MRangeChunk {
+                                from_timestamp: start,
+                                to_timestamp: end,
+                                series: ranges,
+                            };
+                            return Ok(Some((chunk, (Some(series), next))));
+                        }
                     }
-                    AggregationType::Count => values.len() as f64,
-                    AggregationType::First => values.first().cloned().unwrap_or(0.0),
-                    AggregationType::Last => values.last().cloned().unwrap_or(0.0),
-                };
-                (ts, agg_value)
-            })
-            .collect();
+                    Ok(None)
+                }
+            },
+        )
+    }
 
-        result.sort_by_key(|(ts, _)| *ts);
-        Ok(result)
+    /// Points of the selected series in `[from, to]`, aggregated and reduced
+    async fn range_series(
+        &self,
+        series: &[LabeledSeries],
+        from_timestamp: i64,
+        to_timestamp: i64,
+        options: &MRangeOptions,
+    ) -> Result<Vec<SeriesRange>> {
+        let mut ranges = Vec::with_capacity(series.len());
+        for (key, labels) in series {
+            let mut points = self.storage.ts_range(key, from_timestamp, to_timestamp).await?;
+            if let Some(ref agg) = options.aggregation {
+                points = self
+                    .aggregate_points(&points, agg, 0)?
+                    .into_iter()
+                    .filter_map(|(ts, val)| val.map(|val| (ts, val)))
+                    .collect();
+            }
+            ranges.push(SeriesRange {
+                key: key.clone(),
+                labels: labels.clone(),
+                points,
+            });
+        }
+
+        let Some(ref reducer) = options.reduce else {
+            return Ok(ranges);
+        };
+        if reducer.is_time_based() {
+            bail!("'{}' cannot reduce across series", reducer);
+        }
+        // Groups by label value (series without the label are left out), or one group
+        let mut groups: BTreeMap<Option<&String>, Vec<&SeriesRange>> = BTreeMap::new();
+        for range in &ranges {
+            match &options.group_by {
+                Some(label) => {
+                    if let Some(value) = range.labels.get(label) {
+                        groups.entry(Some(value)).or_default().push(range);
+                    }
+                }
+                None => groups.entry(None).or_default().push(range),
+            }
+        }
+
+        Ok(groups
+            .into_iter()
+            .map(|(value, members)| {
+                let mut buckets: BTreeMap<i64, Vec<(i64, f64)>> = BTreeMap::new();
+                for range in &members {
+                    for (ts, val) in &range.points {
+                        buckets.entry(*ts).or_default().push((*ts, *val));
+                    }
+                }
+                // Labels describe the group, as TS.MRANGE GROUPBY does
+                let mut labels = BTreeMap::new();
+                if let (Some(label), Some(value)) = (&options.group_by, value) {
+                    labels.insert(label.clone(), value.clone());
+                }
+                labels.insert("__reducer__".to_string(), reducer.to_string());
+                let sources: Vec<&str> = members.iter().map(|range| range.key.as_str()).collect();
+                labels.insert("__source__".to_string(), sources.join(","));
+                SeriesRange {
+                    key: match (&options.group_by, value) {
+                        (Some(label), Some(value)) => format!("{}={}", label, value),
+                        _ => "*".to_string(),
+                    },
+                    labels,
+                    points: buckets
+                        .into_iter()
+                        .filter_map(|(ts, points)| reducer.apply(&points).map(|val| (ts, val)))
+                        .collect(),
+                }
+            })
+            .collect())
     }
 }
 
@@ -440,12 +692,23 @@ pub enum FilterCondition {
 #[derive(Debug, Clone, Default)]
 pub struct JsonFilterConditions {
     conditions: Vec<(String, FilterCondition)>,
+    /// Filter document, AND-ed with the flat conditions
+    filter: Option<FilterExpr>,
 }
 
 impl JsonFilterConditions {
     pub fn new() -> Self {
         Self {
             conditions: Vec::new(),
+            filter: None,
+        }
+    }
+
+    /// Conditions from a MongoDB-style filter document
+    pub fn from_filter(filter: FilterExpr) -> Self {
+        Self {
+            conditions: Vec::new(),
+            filter: Some(filter),
         }
     }
 
@@ -453,8 +716,319 @@ impl JsonFilterConditions {
         self.conditions.push((field, condition));
     }
 
+    pub fn set_filter(&mut self, filter: FilterExpr) {
+        self.filter = Some(filter);
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.conditions.is_empty()
+        self.conditions.is_empty() && self.filter.is_none()
+    }
+
+    pub fn conditions(&self) -> &[(String, FilterCondition)] {
+        &self.conditions
+    }
+
+    /// Number of field conditions checked per document
+    pub fn len(&self) -> usize {
+        self.conditions.len() + self.filter.as_ref().map_or(0, FilterExpr::clause_count)
+    }
+
+    /// Conditions every match must satisfy, for index selection: the flat
+    /// conditions plus those the filter document requires at its top level
+    pub fn index_conditions(&self) -> Vec<(String, FilterCondition)> {
+        let mut conditions = self.conditions.clone();
+        if let Some(filter) = &self.filter {
+            let mut ranged = HashSet::new();
+            filter.required_conditions(&mut conditions, &mut ranged);
+        }
+        conditions
+    }
+}
+
+/// MongoDB-style filter document.
+///
+/// Top-level keys are dotted field paths or `$and`/`$or`/`$not`. A field maps
+/// either to a value (equality) or to an operator document using `$eq`, `$ne`,
+/// `$gt`, `$gte`, `$lt`, `$lte`, `$in`, `$nin`, `$contains`, `$exists`,
+/// `$regex` (with `$options`), `$size`, `$elemMatch` and `$not`.
+///
+/// As in MongoDB, paths descend into arrays of objects, and a comparison
+/// against an array field matches when any element does. Equality is JSON
+/// equality; ordering comparisons only match values of the same type.
+#[derive(Debug, Clone)]
+pub enum FilterExpr {
+    And(Vec<FilterExpr>),
+    Or(Vec<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Field(String, FieldPredicate),
+}
+
+/// Operators on a single field
+#[derive(Debug, Clone)]
+pub enum FieldPredicate {
+    /// `$eq`, `$ne`, `$gt`, `$gte`, `$lt`, `$lte`, `$in` and `$contains`
+    Condition(FilterCondition),
+    NotIn(Vec<JsonValue>),
+    Exists(bool),
+    Regex(Regex),
+    Size(usize),
+    /// Some array element matches every predicate (operator form)
+    ElemMatchValue(Vec<FieldPredicate>),
+    /// Some array element matches the filter document
+    ElemMatch(Box<FilterExpr>),
+    Not(Box<FieldPredicate>),
+    /// Several operators on the same field
+    All(Vec<FieldPredicate>),
+}
+
+impl FilterExpr {
+    /// Parse a filter document
+    pub fn parse(doc: &JsonValue) -> Result<Self> {
+        let map = doc
+            .as_object()
+            .ok_or_else(|| anyhow!("Filter must be an object, got {}", doc))?;
+        let mut clauses = Vec::new();
+        for (key, value) in map {
+            clauses.push(match key.as_str() {
+                "$and" | "$or" => {
+                    let items = value
+                        .as_array()
+                        .filter(|items| !items.is_empty())
+                        .ok_or_else(|| anyhow!("{} needs a non-empty array of filters", key))?;
+                    let parsed = items.iter().map(Self::parse).collect::<Result<Vec<_>>>()?;
+                    if key == "$and" {
+                        FilterExpr::And(parsed)
+                    } else {
+                        FilterExpr::Or(parsed)
+                    }
+                }
+                "$not" => FilterExpr::Not(Box::new(Self::parse(value)?)),
+                op if op.starts_with('$') => bail!("Unknown top-level operator {}", op),
+                path => FilterExpr::Field(path.to_string(), FieldPredicate::parse(value)?),
+            });
+        }
+        Ok(match clauses.len() {
+            1 => clauses.remove(0),
+            _ => FilterExpr::And(clauses),
+        })
+    }
+
+    /// Whether `doc` matches this filter
+    pub fn matches(&self, doc: &JsonValue) -> bool {
+        match self {
+            FilterExpr::And(items) => items.iter().all(|item| item.matches(doc)),
+            FilterExpr::Or(items) => items.iter().any(|item| item.matches(doc)),
+            FilterExpr::Not(inner) => !inner.matches(doc),
+            FilterExpr::Field(path, predicate) => {
+                let mut values = Vec::new();
+                resolve_path(doc, path, &mut values);
+                predicate.matches(&values)
+            }
+        }
+    }
+
+    /// Number of field predicates
+    pub fn clause_count(&self) -> usize {
+        match self {
+            FilterExpr::And(items) | FilterExpr::Or(items) => items.iter().map(Self::clause_count).sum(),
+            FilterExpr::Not(inner) => inner.clause_count(),
+            FilterExpr::Field(..) => 1,
+        }
+    }
+
+    /// Collect conditions implied by this filter that an index can answer.
+    /// Only one range per path is taken: with array fields, `$gt` and `$lt`
+    /// may be met by different elements, so their bounds cannot be combined.
+    fn required_conditions(&self, out: &mut Vec<(String, FilterCondition)>, ranged: &mut HashSet<String>) {
+        match self {
+            FilterExpr::And(items) => items.iter().for_each(|item| item.required_conditions(out, ranged)),
+            FilterExpr::Field(path, predicate) => predicate.required_conditions(path, out, ranged),
+            FilterExpr::Or(_) | FilterExpr::Not(_) => {}
+        }
+    }
+}
+
+impl FieldPredicate {
+    /// Parse the value of a field in a filter document
+    pub fn parse(value: &JsonValue) -> Result<Self> {
+        let Some(map) = value.as_object().filter(|m| !m.is_empty() && m.keys().all(|k| k.starts_with('$'))) else {
+            return Ok(FieldPredicate::Condition(FilterCondition::Eq(value.clone())));
+        };
+        let mut predicates = Vec::new();
+        for (op, arg) in map {
+            predicates.push(match op.as_str() {
+                "$eq" => FieldPredicate::Condition(FilterCondition::Eq(arg.clone())),
+                "$ne" => FieldPredicate::Condition(FilterCondition::Ne(arg.clone())),
+                "$gt" => FieldPredicate::Condition(FilterCondition::Gt(arg.clone())),
+                "$gte" => FieldPredicate::Condition(FilterCondition::Gte(arg.clone())),
+                "$lt" => FieldPredicate::Condition(FilterCondition::Lt(arg.clone())),
+                "$lte" => FieldPredicate::Condition(FilterCondition::Lte(arg.clone())),
+                "$in" => FieldPredicate::Condition(FilterCondition::In(array_arg(op, arg)?)),
+                "$nin" => FieldPredicate::NotIn(array_arg(op, arg)?),
+                "$contains" => FieldPredicate::Condition(FilterCondition::Contains(
+                    arg.as_str().ok_or_else(|| anyhow!("$contains needs a string"))?.to_string(),
+                )),
+                "$exists" => FieldPredicate::Exists(arg.as_bool().ok_or_else(|| anyhow!("$exists needs a boolean"))?),
+                "$regex" => {
+                    let options = map.get("$options").and_then(JsonValue::as_str).unwrap_or("");
+                    FieldPredicate::Regex(parse_regex(arg, options)?)
+                }
+                "$options" if map.contains_key("$regex") => continue,
+                "$size" => FieldPredicate::Size(
+                    arg.as_u64().ok_or_else(|| anyhow!("$size needs a non-negative integer"))? as usize,
+                ),
+                "$elemMatch" => {
+                    let is_operator_doc = arg
+                        .as_object()
+                        .ok_or_else(|| anyhow!("$elemMatch needs an object"))?
+                        .keys()
+                        .all(|k| k.starts_with('$') && !matches!(k.as_str(), "$and" | "$or" | "$not"));
+                    if is_operator_doc {
+                        match FieldPredicate::parse(arg)? {
+                            FieldPredicate::All(items) => FieldPredicate::ElemMatchValue(items),
+                            single => FieldPredicate::ElemMatchValue(vec![single]),
+                        }
+                    } else {
+                        FieldPredicate::ElemMatch(Box::new(FilterExpr::parse(arg)?))
+                    }
+                }
+                "$not" => match arg {
+                    JsonValue::String(_) => FieldPredicate::Not(Box::new(FieldPredicate::Regex(parse_regex(arg, "")?))),
+                    JsonValue::Object(_) => FieldPredicate::Not(Box::new(FieldPredicate::parse(arg)?)),
+                    _ => bail!("$not needs an operator document or a regex"),
+                },
+                other => bail!("Unknown operator {}", other),
+            });
+        }
+        Ok(match predicates.len() {
+            1 => predicates.remove(0),
+            _ => FieldPredicate::All(predicates),
+        })
+    }
+
+    /// Whether the values found at a path match; empty means the path is missing
+    pub fn matches(&self, values: &[&JsonValue]) -> bool {
+        match self {
+            FieldPredicate::Condition(FilterCondition::Ne(target)) => {
+                !FieldPredicate::Condition(FilterCondition::Eq(target.clone())).matches(values)
+            }
+            FieldPredicate::Condition(condition) => {
+                candidates(values).any(|value| condition_matches(value, condition))
+            }
+            FieldPredicate::NotIn(targets) => !candidates(values).any(|value| targets.contains(value)),
+            FieldPredicate::Exists(expected) => values.is_empty() != *expected,
+            FieldPredicate::Regex(re) => {
+                candidates(values).any(|value| value.as_str().is_some_and(|s| re.is_match(s)))
+            }
+            FieldPredicate::Size(size) => values
+                .iter()
+                .any(|value| value.as_array().is_some_and(|items| items.len() == *size)),
+            FieldPredicate::ElemMatchValue(predicates) => array_elements(values)
+                .any(|element| predicates.iter().all(|p| p.matches(&[element]))),
+            FieldPredicate::ElemMatch(filter) => array_elements(values).any(|element| filter.matches(element)),
+            FieldPredicate::Not(inner) => !inner.matches(values),
+            FieldPredicate::All(items) => items.iter().all(|item| item.matches(values)),
+        }
+    }
+
+    fn required_conditions(&self, path: &str, out: &mut Vec<(String, FilterCondition)>, ranged: &mut HashSet<String>) {
+        match self {
+            FieldPredicate::Condition(condition) => match condition {
+                FilterCondition::Eq(_) | FilterCondition::In(_) | FilterCondition::Contains(_) => {
+                    out.push((path.to_string(), condition.clone()));
+                }
+                FilterCondition::Gt(_) | FilterCondition::Gte(_) | FilterCondition::Lt(_) | FilterCondition::Lte(_) => {
+                    if ranged.insert(path.to_string()) {
+                        out.push((path.to_string(), condition.clone()));
+                    }
+                }
+                FilterCondition::Ne(_) => {}
+            },
+            FieldPredicate::All(items) => items.iter().for_each(|item| item.required_conditions(path, out, ranged)),
+            _ => {}
+        }
+    }
+}
+
+fn array_arg(op: &str, arg: &JsonValue) -> Result<Vec<JsonValue>> {
+    arg.as_array()
+        .cloned()
+        .ok_or_else(|| anyhow!("{} needs an array", op))
+}
+
+/// Compile `$regex` with MongoDB-style `$options` flags (`i`, `m`, `s`, `x`)
+fn parse_regex(pattern: &JsonValue, options: &str) -> Result<Regex> {
+    let pattern = pattern.as_str().ok_or_else(|| anyhow!("$regex needs a string"))?;
+    if let Some(flag) = options.chars().find(|c| !"imsx".contains(*c)) {
+        bail!("Unsupported $options flag '{}'", flag);
+    }
+    let pattern = if options.is_empty() {
+        pattern.to_string()
+    } else {
+        format!("(?{}){}", options, pattern)
+    };
+    Ok(Regex::new(&pattern)?)
+}
+
+/// Values at a path plus the elements of array values
+fn candidates<'a>(values: &'a [&'a JsonValue]) -> impl Iterator<Item = &'a JsonValue> {
+    values.iter().flat_map(|value| {
+        let elements = value.as_array().map(|items| items.iter()).into_iter().flatten();
+        std::iter::once(*value).chain(elements)
+    })
+}
+
+fn array_elements<'a>(values: &'a [&'a JsonValue]) -> impl Iterator<Item = &'a JsonValue> {
+    values.iter().filter_map(|value| value.as_array()).flatten()
+}
+
+/// One value against one condition; ordering needs matching types
+fn condition_matches(value: &JsonValue, condition: &FilterCondition) -> bool {
+    let ordering = |target: &JsonValue| match (value, target) {
+        (JsonValue::Number(a), JsonValue::Number(b)) => a.as_f64().zip(b.as_f64()).and_then(|(a, b)| a.partial_cmp(&b)),
+        (JsonValue::String(a), JsonValue::String(b)) => Some(a.cmp(b)),
+        (JsonValue::Bool(a), JsonValue::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    match condition {
+        FilterCondition::Eq(target) => value == target,
+        FilterCondition::Ne(target) => value != target,
+        FilterCondition::Gt(target) => ordering(target) == Some(Ordering::Greater),
+        FilterCondition::Gte(target) => matches!(ordering(target), Some(Ordering::Greater | Ordering::Equal)),
+        FilterCondition::Lt(target) => ordering(target) == Some(Ordering::Less),
+        FilterCondition::Lte(target) => matches!(ordering(target), Some(Ordering::Less | Ordering::Equal)),
+        FilterCondition::Contains(text) => value.as_str().is_some_and(|s| s.contains(text.as_str())),
+        FilterCondition::In(targets) => targets.contains(value),
+    }
+}
+
+/// Values at a dotted path. Numeric segments index arrays; other segments
+/// descend into every object element of an array, as in MongoDB.
+pub fn resolve_path<'v>(value: &'v JsonValue, path: &str, out: &mut Vec<&'v JsonValue>) {
+    let parts: Vec<&str> = path.split('.').collect();
+    resolve_parts(value, &parts, out);
+}
+
+fn resolve_parts<'v>(value: &'v JsonValue, parts: &[&str], out: &mut Vec<&'v JsonValue>) {
+    let Some((first, rest)) = parts.split_first() else {
+        out.push(value);
+        return;
+    };
+    match value {
+        JsonValue::Object(map) => {
+            if let Some(next) = map.get(*first) {
+                resolve_parts(next, rest, out);
+            }
+        }
+        JsonValue::Array(items) => {
+            if let Some(item) = first.parse::<usize>().ok().and_then(|i| items.get(i)) {
+                resolve_parts(item, rest, out);
+            }
+            for item in items.iter().filter(|item| item.is_object()) {
+                resolve_parts(item, parts, out);
+            }
+        }
+        _ => {}
     }
 }
 
@@ -484,6 +1058,10 @@ pub struct TimeSeriesOptions {
     pub filter_by_ts: Option<Vec<i64>>,
     pub min_value: Option<f64>,
     pub max_value: Option<f64>,
+    /// How buckets without a value are filled (default: left out)
+    pub fill: Option<GapFill>,
+    /// Where aggregation buckets start
+    pub alignment: BucketAlignment,
 }
 
 #[derive(Debug, Clone)]
@@ -492,6 +1070,8 @@ pub struct Aggregation {
     pub time_bucket: i64,
 }
 
+/// Bucket aggregations. Timestamps are in milliseconds; rates are per second
+/// and integrals in value-seconds.
 #[derive(Debug, Clone)]
 pub enum AggregationType {
     Avg,
@@ -501,4 +1081,336 @@ pub enum AggregationType {
     Count,
     First,
     Last,
+    /// Max minus min
+    Range,
+    /// Population standard deviation
+    StdDevP,
+    /// Sample standard deviation
+    StdDevS,
+    /// Population variance
+    VarP,
+    /// Sample variance
+    VarS,
+    /// Percentile between 0 and 100, interpolated between closest ranks
+    Percentile(f64),
+    /// Change per second from the first to the last point
+    Rate,
+    /// Area under the line through the points
+    Integral,
+    /// Integral divided by the time span of the points
+    TimeWeightedAvg,
+}
+
+impl AggregationType {
+    /// Aggregate the points of one bucket, in time order. `None` when the
+    /// aggregate is undefined, such as a rate over a single point.
+    pub fn apply(&self, points: &[(i64, f64)]) -> Option<f64> {
+        let (&(first_ts, first), &(last_ts, last)) = (points.first()?, points.last()?);
+        let values = || points.iter().map(|(_, val)| *val);
+        let count = points.len() as f64;
+        let mean = values().sum::<f64>() / count;
+        let squares = || values().map(|val| (val - mean).powi(2)).sum::<f64>();
+        let seconds = |from: i64, to: i64| (to as f64 - from as f64) / 1000.0;
+        let integral = || {
+            points
+                .windows(2)
+                .map(|pair| seconds(pair[0].0, pair[1].0) * (pair[0].1 + pair[1].1) / 2.0)
+                .sum::<f64>()
+        };
+        let min = || values().fold(f64::INFINITY, f64::min);
+        let max = || values().fold(f64::NEG_INFINITY, f64::max);
+
+        Some(match self {
+            AggregationType::Avg => mean,
+            AggregationType::Sum => values().sum::<f64>(),
+            AggregationType::Min => min(),
+            AggregationType::Max => max(),
+            AggregationType::Count => count,
+            AggregationType::First => first,
+            AggregationType::Last => last,
+            AggregationType::Range => max() - min(),
+            AggregationType::StdDevP => (squares() / count).sqrt(),
+            AggregationType::VarP => squares() / count,
+            AggregationType::StdDevS | AggregationType::VarS if points.len() < 2 => return None,
+            AggregationType::StdDevS => (squares() / (count - 1.0)).sqrt(),
+            AggregationType::VarS => squares() / (count - 1.0),
+            AggregationType::Percentile(p) => {
+                let mut sorted: Vec<f64> = values().collect();
+                sorted.sort_by(f64::total_cmp);
+                let rank = p / 100.0 * (sorted.len() - 1) as f64;
+                let (low, high) = (sorted[rank.floor() as usize], sorted[rank.ceil() as usize]);
+                low + (high - low) * rank.fract()
+            }
+            AggregationType::Rate if first_ts == last_ts => return None,
+            AggregationType::Rate => (last - first) / seconds(first_ts, last_ts),
+            AggregationType::Integral => integral(),
+            AggregationType::TimeWeightedAvg if first_ts == last_ts => mean,
+            AggregationType::TimeWeightedAvg => integral() / seconds(first_ts, last_ts),
+        })
+    }
+
+    /// Whether the aggregate depends on the spacing of the points, so it
+    /// makes no sense across series sharing one timestamp
+    pub fn is_time_based(&self) -> bool {
+        matches!(
+            self,
+            AggregationType::Rate | AggregationType::Integral | AggregationType::TimeWeightedAvg
+        )
+    }
+}
+
+impl std::fmt::Display for AggregationType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AggregationType::Avg => "avg",
+            AggregationType::Sum => "sum",
+            AggregationType::Min => "min",
+            AggregationType::Max => "max",
+            AggregationType::Count => "count",
+            AggregationType::First => "first",
+            AggregationType::Last => "last",
+            AggregationType::Range => "range",
+            AggregationType::StdDevP => "std.p",
+            AggregationType::StdDevS => "std.s",
+            AggregationType::VarP => "var.p",
+            AggregationType::VarS => "var.s",
+            AggregationType::Percentile(p) => return write!(f, "p{}", p),
+            AggregationType::Rate => "rate",
+            AggregationType::Integral => "integral",
+            AggregationType::TimeWeightedAvg => "twa",
+        };
+        f.write_str(name)
+    }
+}
+
+impl std::str::FromStr for AggregationType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.to_lowercase();
+        Ok(match s.as_str() {
+            "avg" => AggregationType::Avg,
+            "sum" => AggregationType::Sum,
+            "min" => AggregationType::Min,
+            "max" => AggregationType::Max,
+            "count" => AggregationType::Count,
+            "first" => AggregationType::First,
+            "last" => AggregationType::Last,
+            "range" => AggregationType::Range,
+            "std.p" => AggregationType::StdDevP,
+            "std.s" => AggregationType::StdDevS,
+            "var.p" => AggregationType::VarP,
+            "var.s" => AggregationType::VarS,
+            "rate" | "derivative" => AggregationType::Rate,
+            "integral" => AggregationType::Integral,
+            "twa" => AggregationType::TimeWeightedAvg,
+            other => match other.strip_prefix('p').and_then(|p| p.parse::<f64>().ok()) {
+                Some(p) if (0.0..=100.0).contains(&p) => AggregationType::Percentile(p),
+                _ => bail!("Unknown aggregation: {}", other),
+            },
+        })
+    }
+}
+
+/// How buckets without a value are filled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapFill {
+    /// Keep them with no value
+    Null,
+    /// Repeat the last value before them
+    Previous,
+    /// Interpolate between the values around them
+    Linear,
+}
+
+impl std::str::FromStr for GapFill {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_lowercase().as_str() {
+            "null" => GapFill::Null,
+            "previous" => GapFill::Previous,
+            "linear" => GapFill::Linear,
+            other => bail!("Unknown gap fill: {}", other),
+        })
+    }
+}
+
+/// Where aggregation buckets start
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BucketAlignment {
+    /// Multiples of the bucket size
+    #[default]
+    Epoch,
+    /// The start of the queried range
+    Start,
+    /// So the last bucket ends at the end of the queried range
+    End,
+    /// A given timestamp
+    At(i64),
+}
+
+impl BucketAlignment {
+    fn origin(&self, from_timestamp: i64, to_timestamp: i64) -> i64 {
+        match self {
+            BucketAlignment::Epoch => 0,
+            BucketAlignment::Start => from_timestamp,
+            BucketAlignment::End => to_timestamp.saturating_add(1),
+            BucketAlignment::At(ts) => *ts,
+        }
+    }
+}
+
+impl std::str::FromStr for BucketAlignment {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_lowercase().as_str() {
+            "epoch" => BucketAlignment::Epoch,
+            "start" => BucketAlignment::Start,
+            "end" => BucketAlignment::End,
+            other => BucketAlignment::At(
+                other
+                    .parse()
+                    .map_err(|_| anyhow!("Unknown bucket alignment: {}", other))?,
+            ),
+        })
+    }
+}
+
+/// Most buckets gap filling may produce for one query
+const MAX_FILLED_BUCKETS: i128 = 100_000;
+
+/// Start of the bucket holding `ts`, for buckets starting at `origin`
+fn bucket_start(ts: i64, origin: i64, size: i64) -> i64 {
+    let offset = (ts as i128 - origin as i128).div_euclid(size as i128) * size as i128;
+    (origin as i128 + offset).clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+/// Add the missing buckets between the first and the last one and fill
+/// every bucket without a value
+fn fill_gaps(
+    buckets: Vec<(i64, Option<f64>)>,
+    size: i64,
+    fill: GapFill,
+) -> Result<Vec<(i64, Option<f64>)>> {
+    let (Some(&(first, _)), Some(&(last, _))) = (buckets.first(), buckets.last()) else {
+        return Ok(buckets);
+    };
+    let total = (last as i128 - first as i128) / size as i128 + 1;
+    if total > MAX_FILLED_BUCKETS {
+        bail!(
+            "Gap filling would produce {} buckets (limit {}); use a larger time bucket",
+            total,
+            MAX_FILLED_BUCKETS
+        );
+    }
+
+    let known: BTreeMap<i64, Option<f64>> = buckets.into_iter().collect();
+    let mut filled: Vec<(i64, Option<f64>)> = (0..total as i64)
+        .map(|i| {
+            let ts = first + i * size;
+            (ts, known.get(&ts).copied().flatten())
+        })
+        .collect();
+
+    match fill {
+        GapFill::Null => {}
+        GapFill::Previous => {
+            let mut previous = None;
+            for (_, val) in filled.iter_mut() {
+                match val {
+                    Some(known) => previous = Some(*known),
+                    None => *val = previous,
+                }
+            }
+        }
+        GapFill::Linear => {
+            let known: Vec<(usize, f64)> = filled
+                .iter()
+                .enumerate()
+                .filter_map(|(i, (_, val))| val.map(|val| (i, val)))
+                .collect();
+            for pair in known.windows(2) {
+                let ((from, from_val), (to, to_val)) = (pair[0], pair[1]);
+                let step = (to_val - from_val) / (to - from) as f64;
+                for (i, (_, val)) in filled.iter_mut().enumerate().take(to).skip(from + 1) {
+                    *val = Some(from_val + step * (i - from) as f64);
+                }
+            }
+        }
+    }
+    Ok(filled)
+}
+
+/// Full key of a time series and its labels
+pub type LabeledSeries = (String, BTreeMap<String, String>);
+
+/// Which series a multi-series query reads
+#[derive(Debug, Clone, Default)]
+pub struct SeriesSelector {
+    /// Key pattern within the db, with `*` and `?` wildcards (default: all)
+    pub key_pattern: Option<String>,
+    /// Labels every selected series must carry with these values
+    pub labels: Vec<(String, String)>,
+}
+
+// Multi-series query options
+#[derive(Debug, Clone, Default)]
+pub struct MRangeOptions {
+    pub from_timestamp: i64,
+    pub to_timestamp: i64,
+    /// Per-series aggregation into aligned buckets
+    pub aggregation: Option<Aggregation>,
+    /// Reducer applied across series for each timestamp or bucket
+    pub reduce: Option<AggregationType>,
+    /// Label whose values group series before reducing
+    pub group_by: Option<String>,
+}
+
+/// Points of one series, or of one reduced group of series
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeriesRange {
+    pub key: String,
+    pub labels: BTreeMap<String, String>,
+    pub points: Vec<(i64, f64)>,
+}
+
+/// One window of a streamed multi-series query
+#[derive(Debug, Clone, PartialEq)]
+pub struct MRangeChunk {
+    pub from_timestamp: i64,
+    pub to_timestamp: i64,
+    pub series: Vec<SeriesRange>,
+}
+
+/// Time series labels from JSON: an object of names to values, or a
+/// `[{"key": .., "value": ..}]` list as used for stream fields
+pub fn parse_labels(json: &str) -> Result<BTreeMap<String, String>> {
+    let text = |value: &JsonValue| match value {
+        JsonValue::String(s) => Some(s.clone()),
+        JsonValue::Number(_) | JsonValue::Bool(_) => Some(value.to_string()),
+        _ => None,
+    };
+    let mut labels = BTreeMap::new();
+    match serde_json::from_str::<JsonValue>(json)? {
+        JsonValue::Object(map) => {
+            for (name, value) in &map {
+                let value = text(value).ok_or_else(|| anyhow!("Label '{}' must be a scalar", name))?;
+                labels.insert(name.clone(), value);
+            }
+        }
+        JsonValue::Array(items) => {
+            for item in &items {
+                match (item.get("key").and_then(JsonValue::as_str), item.get("value").and_then(text)) {
+                    (Some(name), Some(value)) => {
+                        labels.insert(name.to_string(), value);
+                    }
+                    _ => bail!("Labels must be {{\"key\", \"value\"}} objects"),
+                }
+            }
+        }
+        _ => bail!("Labels must be a JSON object or list"),
+    }
+    Ok(labels)
 }
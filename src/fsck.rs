@@ -0,0 +1,336 @@
+//! Storage Consistency Checker (fsck)
+//!
+//! Walks the storage index and the sync op log and reports anything that
+//! doesn't line up:
+//! - index entries whose value is missing, undecodable or of the wrong type
+//! - stored `SignatureMetadata` that no longer verifies
+//! - op log entries with invalid signatures, or that were never applied
+//!
+//! With `repair` enabled, broken keys are rebuilt by replaying their operations
+//! from the op log. Keys with no local operation are dropped from the index and,
+//! when a sync channel is available, re-requested from peers.
+//!
+//! Available as the `fsck` CLI subcommand (node stopped) and the `fsck`
+//! GraphQL mutation (node running).
+
+use anyhow::Result;
+use async_graphql::{Enum, SimpleObject};
+use std::collections::HashMap;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::crypto;
+use crate::delegation::{self, Delegations};
+use crate::encryption;
+use crate::read_access;
+use crate::storage::{BlobStorage, KeyCheck, SignatureMetadata};
+use crate::sync::{SignedOperation, SyncManager, SyncMessage};
+
+/// Options for a consistency check run
+#[derive(Debug, Clone, Default)]
+pub struct FsckOptions {
+    /// Attempt to repair problems instead of only reporting them
+    pub repair: bool,
+    /// Restrict the check to a single database
+    pub db_name: Option<String>,
+}
+
+/// Category of a detected problem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+pub enum FsckIssueKind {
+    /// Index entry could not be decoded
+    CorruptIndex,
+    /// Index entry points at a value that doesn't exist
+    MissingValue,
+    /// Value exists but doesn't decode
+    CorruptValue,
+    /// Value type disagrees with the index entry
+    TypeMismatch,
+    /// Stored signature metadata doesn't verify
+    InvalidSignature,
+    /// Operation in the op log has an invalid signature
+    InvalidOperation,
+    /// Operation in the op log was never applied to storage
+    OrphanOperation,
+}
+
+/// A single problem found by fsck
+#[derive(Debug, Clone, SimpleObject)]
+pub struct FsckIssue {
+    pub key: String,
+    pub kind: FsckIssueKind,
+    pub detail: String,
+    pub repaired: bool,
+}
+
+/// Summary of a consistency check run
+#[derive(Debug, Clone, Default, SimpleObject)]
+pub struct FsckReport {
+    pub keys_scanned: usize,
+    pub expired_keys: usize,
+    pub signatures_verified: usize,
+    /// Values with no signature, or with no string value or logged operation to check it against
+    pub signatures_unverifiable: usize,
+    pub operations_scanned: usize,
+    pub issues: Vec<FsckIssue>,
+    pub repaired: usize,
+    /// Databases re-requested from peers during repair
+    pub peer_sync_requested: Vec<String>,
+    pub duration_ms: u64,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.iter().all(|issue| issue.repaired)
+    }
+}
+
+/// Consistency checker over a storage instance and (optionally) its op log
+pub struct Fsck<'a> {
+    storage: &'a BlobStorage,
+    sync_manager: Option<&'a SyncManager>,
+    sync_outbound: Option<&'a UnboundedSender<SyncMessage>>,
+}
+
+impl<'a> Fsck<'a> {
+    pub fn new(storage: &'a BlobStorage) -> Self {
+        Self {
+            storage,
+            sync_manager: None,
+            sync_outbound: None,
+        }
+    }
+
+    /// Cross-check against the op log and use it for repairs
+    pub fn with_sync_manager(mut self, sync_manager: &'a SyncManager) -> Self {
+        self.sync_manager = Some(sync_manager);
+        self
+    }
+
+    /// Allow repairs to re-request data from connected peers
+    pub fn with_sync_outbound(mut self, sync_outbound: &'a UnboundedSender<SyncMessage>) -> Self {
+        self.sync_outbound = Some(sync_outbound);
+        self
+    }
+
+    pub async fn run(&self, options: &FsckOptions) -> Result<FsckReport> {
+        let started = std::time::Instant::now();
+        let mut report = FsckReport::default();
+
+        // Op log, grouped by storage key and by signature
+        let mut operations = match self.sync_manager {
+            Some(manager) => manager.sync_store().get_all_operations().await,
+            None => Vec::new(),
+        };
+        if let Some(ref db) = options.db_name {
+            operations.retain(|op| &op.db_name == db);
+        }
+        operations.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.op_id.cmp(&b.op_id)));
+        let mut ops_by_key: HashMap<String, Vec<&SignedOperation>> = HashMap::new();
+        let mut ops_by_signature: HashMap<&str, &SignedOperation> = HashMap::new();
+        for op in &operations {
+            ops_by_key
+                .entry(format!("{}:{}", op.db_name, op.key))
+                .or_default()
+                .push(op);
+            ops_by_signature.insert(op.signature.as_str(), op);
+        }
+
+        // Pass 1: every index entry
+        let prefix = options
+            .db_name
+            .as_ref()
+            .map(|db| format!("{}:", db))
+            .unwrap_or_default();
+        let keys = self.storage.index_keys(&prefix).await?;
+        report.keys_scanned = keys.len();
+
+        let mut peer_dbs: Vec<String> = Vec::new();
+        for key in keys {
+            let (kind, detail) = match self.storage.check_key(&key).await? {
+                KeyCheck::Missing => continue,
+                KeyCheck::Expired => {
+                    report.expired_keys += 1;
+                    continue;
+                }
+                KeyCheck::Valid { metadata, string_value, .. } => {
+                    if let Some(meta) = metadata {
+                        match verify_metadata(&key, &meta, string_value.as_deref(), &ops_by_signature, self.storage.delegations()) {
+                            Some(Ok(())) => report.signatures_verified += 1,
+                            Some(Err(e)) => {
+                                let repaired = options.repair
+                                    && self.replay_key(&key, &ops_by_key).await;
+                                report.issues.push(FsckIssue {
+                                    key,
+                                    kind: FsckIssueKind::InvalidSignature,
+                                    detail: e.to_string(),
+                                    repaired,
+                                });
+                            }
+                            None => report.signatures_unverifiable += 1,
+                        }
+                    }
+                    continue;
+                }
+                KeyCheck::CorruptIndex { error } => (FsckIssueKind::CorruptIndex, error),
+                KeyCheck::MissingValue { hash } => {
+                    (FsckIssueKind::MissingValue, format!("value {} not found", hash))
+                }
+                KeyCheck::CorruptValue { hash, error } => {
+                    (FsckIssueKind::CorruptValue, format!("value {}: {}", hash, error))
+                }
+                KeyCheck::TypeMismatch { indexed, actual } => (
+                    FsckIssueKind::TypeMismatch,
+                    format!("indexed as {:?}, stored as {:?}", indexed, actual),
+                ),
+            };
+
+            let mut repaired = false;
+            if options.repair {
+                // Drop the broken entry first so replays start from a clean key
+                self.storage.delete(&key).await?;
+                repaired = self.replay_key(&key, &ops_by_key).await;
+                if !repaired && self.sync_outbound.is_some() {
+                    let db = key.split(':').next().unwrap_or_default().to_string();
+                    if !peer_dbs.contains(&db) {
+                        peer_dbs.push(db);
+                    }
+                }
+            }
+            report.issues.push(FsckIssue { key, kind, detail, repaired });
+        }
+
+        // Pass 2: op log entries
+        report.operations_scanned = operations.len();
+        if let Some(manager) = self.sync_manager {
+            for op in &operations {
+                let key = format!("{}:{}", op.db_name, op.key);
+                if let Err(e) = op.verify_signature() {
+                    report.issues.push(FsckIssue {
+                        key,
+                        kind: FsckIssueKind::InvalidOperation,
+                        detail: format!("op {}: {}", op.op_id, e),
+                        repaired: false,
+                    });
+                    continue;
+                }
+                // An applied op whose key is gone was deleted or expired; only
+                // ops that never reached storage (e.g. crash mid-apply) are orphans.
+                // Grants and encryption metadata live outside the data keyspace
+                if manager.sync_store().is_applied(&op.op_id).await
+                    || delegation::is_delegation_op(op)
+                    || encryption::is_encryption_op(op)
+                    || read_access::is_privacy_op(op)
+                {
+                    continue;
+                }
+                if !matches!(self.storage.check_key(&key).await?, KeyCheck::Missing) {
+                    continue;
+                }
+                let repaired = options.repair && manager.replay_operation(op).await.is_ok();
+                report.issues.push(FsckIssue {
+                    key,
+                    kind: FsckIssueKind::OrphanOperation,
+                    detail: format!("op {} was never applied", op.op_id),
+                    repaired,
+                });
+            }
+        }
+
+        if !peer_dbs.is_empty() {
+            self.request_from_peers().await;
+            report.peer_sync_requested = peer_dbs;
+        }
+
+        report.repaired = report.issues.iter().filter(|i| i.repaired).count();
+        report.duration_ms = started.elapsed().as_millis() as u64;
+        tracing::info!(
+            "fsck: scanned {} keys and {} operations, {} issues ({} repaired) in {}ms",
+            report.keys_scanned,
+            report.operations_scanned,
+            report.issues.len(),
+            report.repaired,
+            report.duration_ms
+        );
+        Ok(report)
+    }
+
+    /// Rebuild a key from its logged operations. Returns true if anything was replayed.
+    async fn replay_key(&self, key: &str, ops_by_key: &HashMap<String, Vec<&SignedOperation>>) -> bool {
+        let (Some(manager), Some(ops)) = (self.sync_manager, ops_by_key.get(key)) else {
+            return false;
+        };
+        let mut replayed = false;
+        for op in ops {
+            if op.verify_signature().is_err() {
+                continue;
+            }
+            match manager.replay_operation(op).await {
+                Ok(()) => replayed = true,
+                Err(e) => tracing::warn!(op_id = %op.op_id, "fsck replay failed: {}", e),
+            }
+        }
+        replayed
+    }
+
+    /// Ask peers for a full sync; missing keys come back through normal sync apply
+    async fn request_from_peers(&self) {
+        let (Some(tx), Some(manager)) = (self.sync_outbound, self.sync_manager) else {
+            return;
+        };
+        let requester = manager.get_stats().await.local_node_id;
+        if let Err(e) = tx.send(SyncMessage::SyncRequest {
+            requester,
+            since_timestamp: None,
+        }) {
+            tracing::warn!("fsck: failed to request sync from peers: {}", e);
+        }
+    }
+}
+
+/// Re-verify stored signature metadata for `key` (`<db_name>:<key>`).
+///
+/// String values signed in the short `db_name:key:value` form are checked
+/// directly. Other values only keep the last signer's metadata, so they're
+/// checked via the matching operation in the op log. A string value whose
+/// short form fails is invalid unless its logged operation verifies. Returns
+/// `None` when there is no signature to check, or no string value or logged
+/// operation to check it against.
+fn verify_metadata(
+    key: &str,
+    meta: &SignatureMetadata,
+    string_value: Option<&str>,
+    ops_by_signature: &HashMap<&str, &SignedOperation>,
+    delegations: &Delegations,
+) -> Option<Result<()>> {
+    let (db_name, user_key) = key.split_once(':')?;
+    if meta.signature.is_empty() {
+        return None;
+    }
+    if let Err(e) = crypto::verify_db_name_secure(db_name, &meta.public_key) {
+        // Delegates sign with their own key; a later revocation doesn't
+        // invalidate what they wrote while the grant was live
+        if !delegations.grants(db_name).iter().any(|grant| grant.grantee == meta.public_key) {
+            return Some(Err(e));
+        }
+    }
+
+    let short_form = string_value.map(|value| {
+        let message = format!("{}:{}:{}", db_name, user_key, value);
+        crypto::secure_hex_decode(&meta.public_key).and_then(|pk| {
+            let sig = crypto::secure_hex_decode(&meta.signature)?;
+            crypto::verify_signature(&pk, message.as_bytes(), &sig)
+        })
+    });
+    if let Some(Ok(())) = short_form {
+        return short_form;
+    }
+
+    // Canonically or batch signed values are checked via their operation
+    let Some(op) = ops_by_signature.get(meta.signature.as_str()) else {
+        return short_form;
+    };
+    if op.public_key != meta.public_key {
+        return Some(Err(anyhow::anyhow!("Signature belongs to a different public key")));
+    }
+    Some(op.verify_signature())
+}
@@ -0,0 +1,529 @@
+//! Full-text search for `IndexType::FullText` secondary indexes
+//!
+//! An inverted index over the text of one document field (or every string
+//! field with `*`), stored in the same keyspace as other secondary index
+//! entries, under the index's `<db_name>\0<index_name>\0` prefix:
+//! - `t<term>\0<key>` -> bincode `Vec<u32>` token positions (postings)
+//! - `d<key>` -> bincode `DocRecord` (indexed texts and token count)
+//!
+//! ## Analysis
+//! Text is split on non-alphanumeric characters, lowercased and stemmed
+//! (Snowball English), so "Computers" and "computing" both index as `comput`.
+//!
+//! ## Queries
+//! - `laptop computer`: documents containing every term
+//! - `"desktop computer"`: terms at consecutive positions
+//! - `comp*`: any term starting with the prefix
+//!
+//! Matches are ranked with BM25 and returned with highlighted snippets.
+
+use anyhow::Result;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use crate::storage_backend::IndexStore;
+
+/// BM25 term frequency saturation
+const BM25_K1: f64 = 1.2;
+/// BM25 document length normalization
+const BM25_B: f64 = 0.75;
+/// Tokens of context kept on each side of the first match in a snippet
+const SNIPPET_CONTEXT: usize = 8;
+/// Position gap between texts of one document, so phrases can't span them
+const TEXT_GAP: u32 = 1;
+
+const POSTING_TAG: char = 't';
+const DOC_TAG: char = 'd';
+const SEP: char = '\0';
+
+/// A token with its normalized term and byte span in the original text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub term: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Indexed texts of one document
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct DocRecord {
+    texts: Vec<String>,
+    length: u32,
+}
+
+/// Change in index totals caused by one document update
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DocDelta {
+    pub docs: isize,
+    pub tokens: isize,
+    pub bytes: isize,
+}
+
+/// Totals for one full-text index
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FullTextStats {
+    pub docs: usize,
+    pub tokens: usize,
+    pub bytes: usize,
+}
+
+/// One ranked search result
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub key: String,
+    pub score: f64,
+    /// Matching snippets with terms wrapped in `<em>`
+    pub highlights: Vec<String>,
+}
+
+/// Parsed query clause; a document must match every clause
+#[derive(Debug, Clone, PartialEq)]
+pub enum Clause {
+    Term(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+}
+
+fn stemmer() -> &'static Stemmer {
+    static STEMMER: OnceLock<Stemmer> = OnceLock::new();
+    STEMMER.get_or_init(|| Stemmer::create(Algorithm::English))
+}
+
+/// Lowercase and stem a single word
+pub fn normalize(word: &str) -> String {
+    stemmer().stem(&word.to_lowercase()).into_owned()
+}
+
+/// Split text into normalized tokens
+pub fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (start, c.is_alphanumeric()) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                tokens.push(Token { term: normalize(&text[s..i]), start: s, end: i });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+/// Parse a query string into clauses
+pub fn parse_query(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    for (i, part) in query.split('"').enumerate() {
+        if i % 2 == 1 {
+            // Inside quotes
+            let terms: Vec<String> = tokenize(part).into_iter().map(|t| t.term).collect();
+            match terms.len() {
+                0 => {}
+                1 => clauses.push(Clause::Term(terms[0].clone())),
+                _ => clauses.push(Clause::Phrase(terms)),
+            }
+            continue;
+        }
+        for word in part.split_whitespace() {
+            if let Some(prefix) = word.strip_suffix('*') {
+                // Matched against stemmed terms, so only lowercased ("comput*" matches "computer")
+                let prefix: String = prefix.chars().filter(|c| c.is_alphanumeric()).collect();
+                if !prefix.is_empty() {
+                    clauses.push(Clause::Prefix(prefix.to_lowercase()));
+                }
+                continue;
+            }
+            let terms: Vec<String> = tokenize(word).into_iter().map(|t| t.term).collect();
+            match terms.len() {
+                0 => {}
+                1 => clauses.push(Clause::Term(terms[0].clone())),
+                // "e-mail" style words tokenize to several terms; keep them together
+                _ => clauses.push(Clause::Phrase(terms)),
+            }
+        }
+    }
+    clauses
+}
+
+fn posting_key(prefix: &str, term: &str, key: &str) -> String {
+    format!("{}{}{}{}{}", prefix, POSTING_TAG, term, SEP, key)
+}
+
+fn doc_key(prefix: &str, key: &str) -> String {
+    format!("{}{}{}", prefix, DOC_TAG, key)
+}
+
+fn read_doc(store: &dyn IndexStore, prefix: &str, key: &str) -> Result<Option<DocRecord>> {
+    match store.get(&doc_key(prefix, key))? {
+        Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Texts currently indexed for `key`
+pub fn doc_texts(store: &dyn IndexStore, prefix: &str, key: &str) -> Result<Vec<String>> {
+    Ok(read_doc(store, prefix, key)?.map(|doc| doc.texts).unwrap_or_default())
+}
+
+/// Term positions across all texts of a document
+fn positions(texts: &[String]) -> (HashMap<String, Vec<u32>>, u32) {
+    let mut postings: HashMap<String, Vec<u32>> = HashMap::new();
+    let mut position = 0u32;
+    for text in texts {
+        for token in tokenize(text) {
+            postings.entry(token.term).or_default().push(position);
+            position += 1;
+        }
+        position += TEXT_GAP;
+    }
+    let length = postings.values().map(|p| p.len() as u32).sum();
+    (postings, length)
+}
+
+/// Replace the indexed texts of `key` (an empty list removes the document)
+pub fn set_document(store: &dyn IndexStore, prefix: &str, key: &str, texts: Vec<String>) -> Result<DocDelta> {
+    let mut delta = DocDelta::default();
+
+    if let Some(old) = read_doc(store, prefix, key)? {
+        let (old_postings, old_length) = positions(&old.texts);
+        for term in old_postings.keys() {
+            let posting = posting_key(prefix, term, key);
+            store.remove(&posting)?;
+            delta.bytes -= posting.len() as isize;
+        }
+        let record = doc_key(prefix, key);
+        store.remove(&record)?;
+        delta.bytes -= record.len() as isize;
+        delta.docs -= 1;
+        delta.tokens -= old_length as isize;
+    }
+
+    if texts.is_empty() {
+        return Ok(delta);
+    }
+
+    let (postings, length) = positions(&texts);
+    for (term, term_positions) in postings {
+        let posting = posting_key(prefix, &term, key);
+        delta.bytes += posting.len() as isize;
+        store.insert(&posting, bincode::serialize(&term_positions)?)?;
+    }
+    let record = doc_key(prefix, key);
+    delta.bytes += record.len() as isize;
+    store.insert(&record, bincode::serialize(&DocRecord { texts, length })?)?;
+    delta.docs += 1;
+    delta.tokens += length as isize;
+    Ok(delta)
+}
+
+/// Recompute index totals from the store
+pub fn stats(store: &dyn IndexStore, prefix: &str) -> Result<FullTextStats> {
+    let mut stats = FullTextStats::default();
+    let doc_prefix = format!("{}{}", prefix, DOC_TAG);
+    for record in store.keys_with_prefix(&doc_prefix)? {
+        if let Some(bytes) = store.get(&record)? {
+            let doc: DocRecord = bincode::deserialize(&bytes)?;
+            stats.docs += 1;
+            stats.tokens += doc.length as usize;
+        }
+        stats.bytes += record.len();
+    }
+    let posting_prefix = format!("{}{}", prefix, POSTING_TAG);
+    stats.bytes += store
+        .keys_with_prefix(&posting_prefix)?
+        .iter()
+        .map(|k| k.len())
+        .sum::<usize>();
+    Ok(stats)
+}
+
+/// Distinct terms in the index
+pub fn terms(store: &dyn IndexStore, prefix: &str) -> Result<Vec<String>> {
+    let posting_prefix = format!("{}{}", prefix, POSTING_TAG);
+    let mut terms: Vec<String> = store
+        .keys_with_prefix(&posting_prefix)?
+        .iter()
+        .filter_map(|k| k[posting_prefix.len()..].split_once(SEP).map(|(term, _)| term.to_string()))
+        .collect();
+    terms.dedup();
+    Ok(terms)
+}
+
+/// Postings for one term: key -> positions
+fn postings(store: &dyn IndexStore, prefix: &str, term: &str) -> Result<HashMap<String, Vec<u32>>> {
+    let term_prefix = format!("{}{}{}{}", prefix, POSTING_TAG, term, SEP);
+    let mut result = HashMap::new();
+    for posting in store.keys_with_prefix(&term_prefix)? {
+        if let Some(bytes) = store.get(&posting)? {
+            result.insert(posting[term_prefix.len()..].to_string(), bincode::deserialize(&bytes)?);
+        }
+    }
+    Ok(result)
+}
+
+/// Terms starting with `partial`
+fn expand_prefix(store: &dyn IndexStore, prefix: &str, partial: &str) -> Result<Vec<String>> {
+    let scan = format!("{}{}{}", prefix, POSTING_TAG, partial);
+    let term_start = prefix.len() + 1;
+    let mut expanded: Vec<String> = store
+        .keys_with_prefix(&scan)?
+        .iter()
+        .filter_map(|k| k[term_start..].split_once(SEP).map(|(term, _)| term.to_string()))
+        .collect();
+    expanded.dedup();
+    Ok(expanded)
+}
+
+/// Scoring context for one search
+struct Bm25<'a> {
+    store: &'a dyn IndexStore,
+    prefix: &'a str,
+    docs: f64,
+    avg_length: f64,
+    lengths: HashMap<String, f64>,
+}
+
+impl Bm25<'_> {
+    fn score(&mut self, key: &str, tf: usize, df: usize) -> Result<f64> {
+        let length = match self.lengths.get(key) {
+            Some(length) => *length,
+            None => {
+                let length = read_doc(self.store, self.prefix, key)?.map(|d| d.length).unwrap_or(0) as f64;
+                self.lengths.insert(key.to_string(), length);
+                length
+            }
+        };
+        let df = df as f64;
+        let tf = tf as f64;
+        let idf = (1.0 + (self.docs - df + 0.5) / (df + 0.5)).ln();
+        let norm = 1.0 - BM25_B + BM25_B * length / self.avg_length.max(1.0);
+        Ok(idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * norm))
+    }
+}
+
+/// Matches of one clause: key -> (score, matched terms)
+type ClauseMatches = HashMap<String, (f64, HashSet<String>)>;
+
+fn match_clause(bm25: &mut Bm25<'_>, clause: &Clause) -> Result<ClauseMatches> {
+    let mut matches: ClauseMatches = HashMap::new();
+    match clause {
+        Clause::Term(term) => add_term(bm25, term, &mut matches)?,
+        Clause::Prefix(partial) => {
+            for term in expand_prefix(bm25.store, bm25.prefix, partial)? {
+                add_term(bm25, &term, &mut matches)?;
+            }
+        }
+        Clause::Phrase(terms) => {
+            let lists: Vec<HashMap<String, Vec<u32>>> = terms
+                .iter()
+                .map(|term| postings(bm25.store, bm25.prefix, term))
+                .collect::<Result<_>>()?;
+            let Some((first, rest)) = lists.split_first() else {
+                return Ok(matches);
+            };
+            for (key, starts) in first {
+                let occurrences = starts
+                    .iter()
+                    .filter(|&&start| {
+                        rest.iter().enumerate().all(|(i, list)| {
+                            list.get(key)
+                                .is_some_and(|p| p.contains(&(start + i as u32 + 1)))
+                        })
+                    })
+                    .count();
+                if occurrences == 0 {
+                    continue;
+                }
+                let mut score = 0.0;
+                for list in &lists {
+                    score += bm25.score(key, occurrences, list.len())?;
+                }
+                matches.insert(key.clone(), (score, terms.iter().cloned().collect()));
+            }
+        }
+    }
+    Ok(matches)
+}
+
+fn add_term(bm25: &mut Bm25<'_>, term: &str, matches: &mut ClauseMatches) -> Result<()> {
+    let list = postings(bm25.store, bm25.prefix, term)?;
+    let df = list.len();
+    for (key, term_positions) in list {
+        let score = bm25.score(&key, term_positions.len(), df)?;
+        let entry = matches.entry(key).or_default();
+        entry.0 += score;
+        entry.1.insert(term.to_string());
+    }
+    Ok(())
+}
+
+/// Run a parsed query against one index. `stats` supplies the corpus totals.
+pub fn search(store: &dyn IndexStore, prefix: &str, clauses: &[Clause], stats: FullTextStats) -> Result<Vec<SearchHit>> {
+    if clauses.is_empty() || stats.docs == 0 {
+        return Ok(Vec::new());
+    }
+    let mut bm25 = Bm25 {
+        store,
+        prefix,
+        docs: stats.docs as f64,
+        avg_length: stats.tokens as f64 / stats.docs as f64,
+        lengths: HashMap::new(),
+    };
+
+    let mut combined: Option<ClauseMatches> = None;
+    for clause in clauses {
+        let matches = match_clause(&mut bm25, clause)?;
+        combined = Some(match combined {
+            None => matches,
+            Some(mut acc) => {
+                acc.retain(|key, _| matches.contains_key(key));
+                for (key, (score, terms)) in matches {
+                    if let Some(entry) = acc.get_mut(&key) {
+                        entry.0 += score;
+                        entry.1.extend(terms);
+                    }
+                }
+                acc
+            }
+        });
+    }
+
+    let mut hits = Vec::new();
+    for (key, (score, terms)) in combined.unwrap_or_default() {
+        let texts = doc_texts(store, prefix, &key)?;
+        hits.push(SearchHit {
+            highlights: highlight(&texts, &terms),
+            key,
+            score,
+        });
+    }
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.key.cmp(&b.key)));
+    Ok(hits)
+}
+
+/// Snippets of the texts containing any of `terms`, with matches wrapped in `<em>`
+pub fn highlight(texts: &[String], terms: &HashSet<String>) -> Vec<String> {
+    let mut snippets = Vec::new();
+    for text in texts {
+        let tokens = tokenize(text);
+        let Some(first) = tokens.iter().position(|t| terms.contains(&t.term)) else {
+            continue;
+        };
+        let from = first.saturating_sub(SNIPPET_CONTEXT);
+        let to = (first + SNIPPET_CONTEXT * 2).min(tokens.len() - 1);
+        let (start, end) = (tokens[from].start, tokens[to].end);
+
+        let mut snippet = String::new();
+        if start > 0 {
+            snippet.push('…');
+        }
+        let mut cursor = start;
+        for token in &tokens[from..=to] {
+            if terms.contains(&token.term) {
+                snippet.push_str(&text[cursor..token.start]);
+                snippet.push_str("<em>");
+                snippet.push_str(&text[token.start..token.end]);
+                snippet.push_str("</em>");
+                cursor = token.end;
+            }
+        }
+        snippet.push_str(&text[cursor..end]);
+        if end < text.len() {
+            snippet.push('…');
+        }
+        snippets.push(snippet);
+    }
+    snippets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage_backend::MemoryIndex;
+
+    const PREFIX: &str = "db\0ft\0";
+
+    fn index(docs: &[(&str, &str)]) -> (MemoryIndex, FullTextStats) {
+        let store = MemoryIndex::default();
+        for (key, text) in docs {
+            set_document(&store, PREFIX, key, vec![text.to_string()]).unwrap();
+        }
+        let stats = stats(&store, PREFIX).unwrap();
+        (store, stats)
+    }
+
+    fn keys(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|h| h.key.as_str()).collect()
+    }
+
+    #[test]
+    fn test_tokenize_and_stem() {
+        let tokens = tokenize("Running, runs; RUNNER!");
+        let terms: Vec<&str> = tokens.iter().map(|t| t.term.as_str()).collect();
+        assert_eq!(terms, vec!["run", "run", "runner"]);
+        assert_eq!((tokens[1].start, tokens[1].end), (9, 13));
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            parse_query(r#"fast "red cars" comp*"#),
+            vec![
+                Clause::Term("fast".to_string()),
+                Clause::Phrase(vec!["red".to_string(), "car".to_string()]),
+                Clause::Prefix("comp".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bm25_ranking() {
+        let (store, stats) = index(&[
+            ("a", "rust rust rust programming"),
+            ("b", "rust is a language for systems programming and more words here"),
+            ("c", "python programming"),
+        ]);
+        let hits = search(&store, PREFIX, &parse_query("rust"), stats).unwrap();
+        assert_eq!(keys(&hits), vec!["a", "b"]);
+        assert!(hits[0].score > hits[1].score);
+
+        // Every clause must match
+        let hits = search(&store, PREFIX, &parse_query("rust python"), stats).unwrap();
+        assert!(hits.is_empty());
+    }
+
+    #[test]
+    fn test_phrase_and_prefix() {
+        let (store, stats) = index(&[
+            ("a", "new york city"),
+            ("b", "york is new"),
+        ]);
+        let hits = search(&store, PREFIX, &parse_query(r#""new york""#), stats).unwrap();
+        assert_eq!(keys(&hits), vec!["a"]);
+
+        let hits = search(&store, PREFIX, &parse_query("yo*"), stats).unwrap();
+        assert_eq!(hits.len(), 2);
+    }
+
+    #[test]
+    fn test_reindex_and_remove() {
+        let (store, _) = index(&[("a", "alpha beta")]);
+        set_document(&store, PREFIX, "a", vec!["gamma".to_string()]).unwrap();
+        let stats = stats(&store, PREFIX).unwrap();
+        assert_eq!((stats.docs, stats.tokens), (1, 1));
+        assert!(search(&store, PREFIX, &parse_query("alpha"), stats).unwrap().is_empty());
+
+        set_document(&store, PREFIX, "a", Vec::new()).unwrap();
+        assert!(store.keys_with_prefix(PREFIX).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_highlight() {
+        let terms: HashSet<String> = ["quick".to_string()].into_iter().collect();
+        let snippets = highlight(&["The Quick brown fox".to_string(), "nothing".to_string()], &terms);
+        assert_eq!(snippets, vec!["The <em>Quick</em> brown fox"]);
+    }
+}
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use async_graphql::http::ALL_WEBSOCKET_PROTOCOLS;
-use async_graphql::{Context, InputObject, Object, Schema, SimpleObject, Subscription};
+use async_graphql::{Context, InputObject, MergedObject, Object, OneofObject, Schema, SimpleObject, Subscription, Union};
 use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
 use axum::{
     body::Bytes,
@@ -17,9 +17,12 @@ use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 
 use crate::{
-    crypto, 
+    graphql_indexing::{IndexMutation, IndexQuery},
     error::{DbError, STORAGE_NOT_FOUND, SYNC_MANAGER_NOT_FOUND, IPFS_STORAGE_NOT_FOUND, ENDPOINT_NOT_FOUND, MQTT_STORE_NOT_FOUND, MQTT_BRIDGE_NOT_AVAILABLE, INVALID_TIMESTAMP, INVALID_TIMESTAMP_FORMAT, MESSAGE_BROADCAST_NOT_FOUND, SYNC_OUTBOUND_NOT_FOUND, DISCOVERED_PEERS_NOT_FOUND}, 
+    admin::{AdminAuth, AdminCredential, ADMIN_TOKEN_HEADER},
+    rate_limit::{RateLimiter, SCAN_COST},
     ipfs::IpfsStorage, 
+    read_access::ReadCredential,
     iroh_network::IrohNetwork,
     peer_registry::PeerRegistry,
     storage::RedisStorage, 
@@ -45,6 +48,312 @@ fn format_key(db_name: &str, key: &str) -> String {
     full_key
 }
 
+/// Field guard serving a private database only to readers it admits (see
+/// `read_access.rs`); public databases are open to everyone
+pub struct ReadGuard {
+    db_name: String,
+}
+
+impl ReadGuard {
+    pub fn new(db_name: &str) -> Self {
+        Self {
+            db_name: db_name.to_string(),
+        }
+    }
+}
+
+impl async_graphql::Guard for ReadGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+        let credential = ctx.data_opt::<ReadCredential>().cloned().unwrap_or_default();
+        storage
+            .read_access()
+            .authorize(&self.db_name, &credential, storage.delegations())
+            .map_err(|e| DbError::AuthError(e.to_string()).into())
+    }
+}
+
+/// Field guard admitting only a database's owner, who proves their key with
+/// a signed bearer token (see `read_access.rs`)
+pub struct OwnerGuard {
+    db_name: String,
+}
+
+impl OwnerGuard {
+    pub fn new(db_name: &str) -> Self {
+        Self {
+            db_name: db_name.to_string(),
+        }
+    }
+}
+
+impl async_graphql::Guard for OwnerGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+        let credential = ctx.data_opt::<ReadCredential>().cloned().unwrap_or_default();
+        let caller = storage
+            .read_access()
+            .verify(&credential)
+            .map_err(|e| DbError::AuthError(e.to_string()))?;
+        crate::crypto::verify_db_name_secure(&self.db_name, &caller)
+            .map_err(|_| DbError::AuthError(format!("Only the owner of {} may do this", self.db_name)).into())
+    }
+}
+
+/// Field guard admitting only requests with admin credentials (see
+/// `admin.rs`); every call is audited
+pub struct AdminGuard;
+
+impl async_graphql::Guard for AdminGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        let admin = ctx.data_opt::<AdminAuth>().cloned().unwrap_or_default();
+        let credential = ctx.data_opt::<AdminCredential>().cloned().unwrap_or_default();
+        admin
+            .authorize(ctx.item.node.name.node.as_str(), &credential)
+            .map(|_| ())
+            .map_err(|e| DbError::AuthError(e.to_string()).into())
+    }
+}
+
+/// Whether the request may see a message on `topic`; `graphql/<db>/<key>`
+/// topics of private databases carry their values
+fn can_read_topic(storage: Option<&RedisStorage>, credential: &ReadCredential, topic: &str) -> bool {
+    let (Some(storage), Some(db_name)) = (storage, topic.strip_prefix("graphql/").and_then(|rest| rest.split('/').next())) else {
+        return true;
+    };
+    storage.read_access().can_read(db_name, credential, storage.delegations())
+}
+
+/// Refuse a query that has to read the values of an encrypted database
+fn ensure_plaintext(storage: &RedisStorage, db_name: &str) -> Result<(), DbError> {
+    storage
+        .encryption()
+        .check_value_query(db_name)
+        .map_err(|e| DbError::InvalidData(e.to_string()))
+}
+
+/// TTL of signed writes, by the user's plan tier.
+/// TODO: Query smart contract to get user's plan based on public_key.
+/// For now, all users are on free tier (24 hours).
+const FREE_TIER_TTL: u64 = 86_400;
+
+/// Checks a verified operation must pass before it is written
+async fn admit_operation(
+    storage: &RedisStorage,
+    op: &crate::sync::SignedOperation,
+    format: crate::sync::SignatureFormat,
+) -> Result<(), DbError> {
+    // Encrypted databases only take sealed values
+    storage
+        .encryption()
+        .check_write(op)
+        .map_err(|e| DbError::InvalidData(e.to_string()))?;
+
+    // Reject resubmissions of an already accepted nonce (or legacy signature)
+    storage
+        .replay_guard()
+        .check(op, format)
+        .await
+        .map_err(|e| DbError::SignatureError(e.to_string()))
+}
+
+/// After a signed write: announce it to topic subscribers and add it to the
+/// sync log
+async fn record_write(ctx: &Context<'_>, op: &crate::sync::SignedOperation) {
+    // Add operation to SyncManager (stores in blob storage)
+    if let Ok(sync_manager) = ctx.data::<SyncManager>() {
+        match sync_manager.sync_store().add_operation(op.clone()).await {
+            Ok(added) => {
+                if added {
+                    tracing::debug!("Operation added to blob storage: {}", op.op_id);
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to add operation to blob storage: {}", e);
+                // Continue - data is still in Redis
+            }
+        }
+    }
+
+    // Broadcast message event to subscribers if broadcast channel is available
+    if let Ok(broadcast_tx) = ctx.data::<broadcast::Sender<MessageEvent>>() {
+        let event = MessageEvent {
+            topic: format!("graphql/{}/{}", op.db_name, op.key),
+            payload: op.value.as_bytes().to_vec(),
+            timestamp: op.timestamp,
+        };
+
+        // Ignore send errors (no active subscribers)
+        let _ = broadcast_tx.send(event);
+    }
+}
+
+/// Verify a batch's operations on the blocking pool, one chunk per core,
+/// returning each with the format it was signed in
+async fn verify_batch(
+    operations: Vec<Result<crate::sync::SignedOperation, DbError>>,
+    delegations: &crate::delegation::Delegations,
+) -> Vec<Result<(crate::sync::SignedOperation, crate::sync::SignatureFormat), DbError>> {
+    let workers = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let chunk_size = operations.len().div_ceil(workers).max(1);
+
+    let mut operations = operations.into_iter().peekable();
+    let mut handles = Vec::new();
+    while operations.peek().is_some() {
+        let chunk: Vec<_> = operations.by_ref().take(chunk_size).collect();
+        let delegations = delegations.clone();
+        let len = chunk.len();
+        let handle = tokio::task::spawn_blocking(move || {
+            chunk
+                .into_iter()
+                .map(|op| {
+                    let op = op?;
+                    let format = op
+                        .verify_submission(&delegations)
+                        .map_err(|e| DbError::SignatureError(e.to_string()))?;
+                    Ok((op, format))
+                })
+                .collect::<Vec<_>>()
+        });
+        handles.push((len, handle));
+    }
+
+    let mut verified = Vec::new();
+    for (len, handle) in handles {
+        match handle.await {
+            Ok(chunk) => verified.extend(chunk),
+            Err(e) => verified.extend((0..len).map(|_| Err(DbError::InternalError(format!("Thread join error: {}", e))))),
+        }
+    }
+    verified
+}
+
+/// Verify, write, record and replicate one signed operation for a write
+/// mutation; `label` names the mutation in metrics
+async fn submit_operation(
+    ctx: &Context<'_>,
+    label: &str,
+    signed_operation: crate::sync::SignedOperation,
+    durability: Option<crate::storage::Durability>,
+) -> Result<StorageResult, DbError> {
+    use crate::metrics;
+
+    // Track GraphQL request
+    metrics::GRAPHQL_REQUESTS.with_label_values(&[label]).inc();
+    let timer = std::time::Instant::now();
+    
+    let storage = ctx
+        .data::<RedisStorage>()
+        .map_err(|_| {
+            metrics::GRAPHQL_ERRORS.with_label_values(&[label]).inc();
+            DbError::StaticError(STORAGE_NOT_FOUND)
+        })?;
+
+    // Verify signature (by the owner or a delegate), freshness and the
+    // legacy format deprecation window
+    let format = signed_operation.verify_submission(storage.delegations()).map_err(|e| {
+        metrics::GRAPHQL_ERRORS.with_label_values(&[label]).inc();
+        DbError::SignatureError(e.to_string())
+    })?;
+
+    // Per-key write budget, charged only once the signature proves the key
+    if let Some(limiter) = ctx.data_opt::<RateLimiter>() {
+        if let Err(wait) = limiter.check_key(&signed_operation.public_key) {
+            metrics::GRAPHQL_ERRORS.with_label_values(&[label]).inc();
+            ctx.insert_http_header(axum::http::header::RETRY_AFTER, crate::rate_limit::retry_after_header(wait));
+            return Err(DbError::RateLimitError(format!(
+                "too many writes from key {}",
+                signed_operation.public_key
+            )));
+        }
+    }
+
+    admit_operation(storage, &signed_operation, format).await.inspect_err(|_| {
+        metrics::GRAPHQL_ERRORS.with_label_values(&[label]).inc();
+    })?;
+
+    tracing::info!(
+        "Signature verified for db: {}, key: {}",
+        signed_operation.db_name,
+        signed_operation.key
+    );
+
+    // Apply the requested durability to this write only
+    let request_storage = durability.map(|level| storage.with_durability(level));
+    let storage = request_storage.as_ref().unwrap_or(storage);
+
+    // Create signature metadata to store alongside values when possible
+    let metadata_ts: i64 = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+
+    let sig_meta = Some(crate::storage::SignatureMetadata {
+        public_key: signed_operation.public_key.clone(),
+        signature: signed_operation.signature.clone(),
+        timestamp: metadata_ts,
+    });
+
+    // Apply TTL based on user's plan tier
+    let ttl_seconds = Some(FREE_TIER_TTL);
+
+    // Store data based on type, announcing it to watch subscriptions
+    let written = crate::changes::attribute(
+        storage.changes(),
+        crate::changes::ChangeEvent::from_op(&signed_operation, None),
+        storage.write_signed_operation(&signed_operation, sig_meta, ttl_seconds),
+    )
+    .await;
+    if let Err(e) = written {
+        release_nonce(storage, &signed_operation, format).await;
+        return Err(write_error(e));
+    }
+
+    record_write(ctx, &signed_operation).await;
+
+    // If an outbound sync sender is available in the GraphQL context, broadcast the operation
+    if let Ok(sync_out_tx) = ctx.data::<tokio::sync::mpsc::UnboundedSender<crate::sync::SyncMessage>>() {
+        tracing::info!("GraphQL: sending outbound SyncMessage::Operation: {}", signed_operation.op_id);
+        let res = sync_out_tx.send(crate::sync::SyncMessage::Operation { operation: signed_operation.clone() });
+        if res.is_err() {
+            tracing::warn!("GraphQL: failed to send outbound sync message (receiver gone)");
+        }
+    }
+
+    // Track successful completion
+    let duration = timer.elapsed().as_secs_f64();
+    metrics::GRAPHQL_LATENCY.with_label_values(&[label]).observe(duration);
+
+    Ok(StorageResult {
+        success: true,
+        message: format!(
+            "Data stored successfully in db: {}, key: {}",
+            signed_operation.db_name, signed_operation.key
+        ),
+    })
+}
+
+/// Give back the nonce `admit_operation` recorded for a write that failed
+async fn release_nonce(
+    storage: &RedisStorage,
+    op: &crate::sync::SignedOperation,
+    format: crate::sync::SignatureFormat,
+) {
+    if let Err(e) = storage.replay_guard().release(op, format).await {
+        tracing::warn!(op_id = %op.op_id, "Failed to release nonce of a failed write: {}", e);
+    }
+}
+
+/// Error for a failed `write_signed_operation`: its own `DbError` when the
+/// operation was invalid, an internal error otherwise
+fn write_error(e: anyhow::Error) -> DbError {
+    e.downcast::<DbError>().unwrap_or_else(DbError::from)
+}
+
 // Combined state for API routes
 #[derive(Clone)]
 struct AppState {
@@ -77,7 +386,8 @@ pub struct StreamEntry {
     pub fields: Vec<StreamField>,
 }
 
-#[derive(SimpleObject, Clone)]
+#[derive(SimpleObject, InputObject, Clone)]
+#[graphql(input_name = "StreamFieldInput")]
 pub struct StreamField {
     pub key: String,
     pub value: String,
@@ -102,6 +412,152 @@ pub struct TimeSeriesPoint {
     pub value: f64,
 }
 
+/// Label of a time series
+#[derive(SimpleObject, InputObject, Clone)]
+#[graphql(input_name = "SeriesLabelInput")]
+pub struct SeriesLabel {
+    pub name: String,
+    pub value: String,
+}
+
+/// Multi-series time series query (like TS.MRANGE)
+#[derive(InputObject, Clone)]
+pub struct TimeSeriesMRangeInput {
+    pub db_name: String,
+    /// Key pattern with `*` and `?` wildcards (default: every series)
+    pub key_pattern: Option<String>,
+    /// Labels every selected series must carry
+    pub labels: Option<Vec<SeriesLabel>>,
+    pub from_timestamp: String,
+    pub to_timestamp: String,
+    /// Per-series aggregation, as in `TimeSeriesAggregateInput`
+    pub aggregation: Option<String>,
+    /// Bucket size for `aggregation`; buckets start at multiples of it
+    pub time_bucket: Option<i64>,
+    /// Reducer across series per timestamp or bucket; rate, integral and
+    /// twa depend on point spacing and cannot reduce
+    pub reduce: Option<String>,
+    /// Label to group series by before reducing
+    pub group_by: Option<String>,
+}
+
+impl TimeSeriesMRangeInput {
+    fn to_query(&self) -> Result<(crate::filters::SeriesSelector, crate::filters::MRangeOptions), DbError> {
+        use crate::filters::{Aggregation, AggregationType, MRangeOptions, SeriesSelector};
+
+        let parse_type = |name: &str| {
+            name.parse::<AggregationType>()
+                .map_err(|e| DbError::InvalidData(e.to_string()))
+        };
+        let aggregation = match (&self.aggregation, self.time_bucket) {
+            (Some(agg), Some(time_bucket)) if time_bucket > 0 => Some(Aggregation {
+                agg_type: parse_type(agg)?,
+                time_bucket,
+            }),
+            (None, None) => None,
+            _ => {
+                return Err(DbError::InvalidData(
+                    "aggregation needs a positive time_bucket".to_string(),
+                ))
+            }
+        };
+        let options = MRangeOptions {
+            from_timestamp: self
+                .from_timestamp
+                .parse::<i64>()
+                .map_err(|_| DbError::StaticError("Invalid from_timestamp"))?,
+            to_timestamp: self
+                .to_timestamp
+                .parse::<i64>()
+                .map_err(|_| DbError::StaticError("Invalid to_timestamp"))?,
+            aggregation,
+            reduce: self.reduce.as_deref().map(parse_type).transpose()?,
+            group_by: self.group_by.clone(),
+        };
+        if options.group_by.is_some() && options.reduce.is_none() {
+            return Err(DbError::InvalidData("group_by needs a reducer".to_string()));
+        }
+        let selector = SeriesSelector {
+            key_pattern: self.key_pattern.clone(),
+            labels: self
+                .labels
+                .iter()
+                .flatten()
+                .map(|label| (label.name.clone(), label.value.clone()))
+                .collect(),
+        };
+        Ok((selector, options))
+    }
+}
+
+/// Points of one series, or of one reduced group of series
+#[derive(SimpleObject, Clone)]
+pub struct SeriesRangeGql {
+    pub key: String,
+    pub labels: Vec<SeriesLabel>,
+    pub points: Vec<TimeSeriesPoint>,
+}
+
+impl From<crate::filters::SeriesRange> for SeriesRangeGql {
+    fn from(range: crate::filters::SeriesRange) -> Self {
+        Self {
+            key: range.key,
+            labels: range
+                .labels
+                .into_iter()
+                .map(|(name, value)| SeriesLabel { name, value })
+                .collect(),
+            points: range
+                .points
+                .into_iter()
+                .map(|(ts, val)| TimeSeriesPoint {
+                    timestamp: ts.to_string(),
+                    value: val,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// One window of a streamed multi-series query
+#[derive(SimpleObject, Clone)]
+pub struct TimeSeriesMRangeChunk {
+    pub from_timestamp: String,
+    pub to_timestamp: String,
+    pub series: Vec<SeriesRangeGql>,
+}
+
+/// Bucketed aggregation of one time series
+#[derive(InputObject, Clone)]
+pub struct TimeSeriesAggregateInput {
+    pub db_name: String,
+    pub key: String,
+    pub from_timestamp: String,
+    pub to_timestamp: String,
+    /// avg, sum, min, max, count, first, last, range, std.p, std.s, var.p,
+    /// var.s, p50/p95/p99 (any pN), rate, integral or twa
+    pub aggregation: String,
+    pub time_bucket: i64,
+    /// Fill for buckets without a value: null, previous or linear
+    pub fill: Option<String>,
+    /// Bucket start: epoch (default), start, end or a timestamp
+    pub align: Option<String>,
+}
+
+/// Aggregated bucket; `value` is null for gaps kept by the null fill
+#[derive(SimpleObject, Clone)]
+pub struct TimeSeriesBucket {
+    pub timestamp: String,
+    pub value: Option<f64>,
+}
+
+/// Result set of a SQL query; each row is a JSON array aligned with `columns`
+#[derive(SimpleObject, Clone)]
+pub struct SqlQueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<async_graphql::Json<Vec<serde_json::Value>>>,
+}
+
 #[derive(SimpleObject, Clone)]
 pub struct GeoLocation {
     pub member: String,
@@ -109,6 +565,119 @@ pub struct GeoLocation {
     pub latitude: Option<f64>,
 }
 
+/// Member of a vector key
+#[derive(SimpleObject, Clone)]
+pub struct VectorEntryGql {
+    pub member: String,
+    pub vector: Vec<f32>,
+    pub metadata: async_graphql::Json<serde_json::Value>,
+}
+
+/// Nearest-neighbour hit; a lower distance is closer
+#[derive(SimpleObject, Clone)]
+pub struct VectorMatchGql {
+    pub member: String,
+    pub distance: f32,
+    pub metadata: async_graphql::Json<serde_json::Value>,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct VectorInfoGql {
+    pub dimension: usize,
+    pub metric: crate::vector::DistanceMetric,
+    pub count: usize,
+}
+
+/// Write access the owner of a database has granted (or revoked) for a key
+#[derive(SimpleObject, Clone)]
+pub struct GrantGql {
+    pub grantee: String,
+    pub store_types: Vec<String>,
+    pub key_prefixes: Vec<String>,
+    /// Unix milliseconds
+    pub expires_at: Option<String>,
+    pub revoked: bool,
+    /// Unix milliseconds of the grant or revoke operation
+    pub issued_at: String,
+}
+
+impl From<crate::delegation::Grant> for GrantGql {
+    fn from(grant: crate::delegation::Grant) -> Self {
+        Self {
+            grantee: grant.grantee,
+            store_types: grant.capability.store_types,
+            key_prefixes: grant.capability.key_prefixes,
+            expires_at: grant.capability.expires_at.map(|ms| ms.to_string()),
+            revoked: grant.revoked,
+            issued_at: grant.issued_at.to_string(),
+        }
+    }
+}
+
+/// A database key sealed to one member (see `encryption.rs` for the format)
+#[derive(SimpleObject, Clone)]
+pub struct WrappedKeyGql {
+    /// Member's Ed25519 public key (hex)
+    pub member: String,
+    /// Ephemeral X25519 public key (base64)
+    pub epk: String,
+    pub nonce: String,
+    pub ct: String,
+}
+
+/// Encryption metadata of a database
+#[derive(SimpleObject, Clone)]
+pub struct EncryptionGql {
+    pub algorithm: String,
+    pub key_id: String,
+    pub members: Vec<WrappedKeyGql>,
+    /// Unix milliseconds of the metadata operation
+    pub issued_at: String,
+}
+
+impl From<crate::encryption::DbEncryption> for EncryptionGql {
+    fn from(encryption: crate::encryption::DbEncryption) -> Self {
+        Self {
+            algorithm: encryption.config.algorithm,
+            key_id: encryption.config.key_id,
+            members: encryption
+                .config
+                .members
+                .into_iter()
+                .map(|(member, wrapped)| WrappedKeyGql {
+                    member,
+                    epk: wrapped.epk,
+                    nonce: wrapped.nonce,
+                    ct: wrapped.ct,
+                })
+                .collect(),
+            issued_at: encryption.issued_at.to_string(),
+        }
+    }
+}
+
+/// Challenge for a read token (see `read_access.rs`)
+#[derive(SimpleObject, Clone)]
+pub struct ReadChallengeGql {
+    pub challenge: String,
+    /// Unix milliseconds
+    pub expires_at: String,
+}
+
+/// Top-k similarity search over one vector key
+#[derive(InputObject, Clone)]
+pub struct VectorSearchInput {
+    pub db_name: String,
+    pub key: String,
+    pub vector: Vec<f32>,
+    /// Number of hits (default 10)
+    pub k: Option<usize>,
+    /// Candidates explored; higher is slower but more accurate (default 64)
+    pub ef: Option<usize>,
+    /// Filter document over the members' metadata, e.g. `{"label": "cat"}`
+    pub filter: Option<async_graphql::Json<serde_json::Value>>,
+}
+
 // ============================================================================
 // TTL (Time-To-Live) Types
 // ============================================================================
@@ -427,6 +996,7 @@ pub struct BlobOperation {
     pub json_path: Option<String>,
     pub stream_fields: Option<String>,
     pub ts_timestamp: Option<String>,
+    pub labels: Option<String>,
     pub longitude: Option<f64>,
     pub latitude: Option<f64>,
     pub public_key: String,
@@ -564,6 +1134,17 @@ pub struct BandwidthStatsGql {
     pub download_limit_bps: i64,
 }
 
+/// A call to an admin-only field (see `admin.rs`)
+#[derive(SimpleObject, Clone)]
+pub struct AuditEntryGql {
+    /// Unix milliseconds
+    pub timestamp: String,
+    pub actor: String,
+    pub action: String,
+    pub allowed: bool,
+    pub reason: Option<String>,
+}
+
 /// Combined network resilience summary
 #[derive(SimpleObject, Clone)]
 pub struct NetworkResilienceSummaryGql {
@@ -588,6 +1169,98 @@ pub struct MessageEvent {
     pub timestamp: i64,
 }
 
+/// An operation applied to storage (see `changes.rs`)
+#[derive(Clone)]
+pub struct DataChange(Arc<crate::changes::ChangeEvent>);
+
+#[Object]
+impl DataChange {
+    /// Null for changes storage makes on its own (deletes, expiry, migrations)
+    async fn op_id(&self) -> Option<&str> {
+        self.0.op_id.as_deref()
+    }
+
+    async fn db_name(&self) -> &str {
+        &self.0.db_name
+    }
+
+    async fn key(&self) -> &str {
+        &self.0.key
+    }
+
+    /// Lowercase store type of the operation (`string`, `hash`, `grant`, ...),
+    /// or `delete` / `expire` for a removed key
+    async fn op_type(&self) -> &str {
+        &self.0.op_type
+    }
+
+    async fn field(&self) -> Option<&str> {
+        self.0.field.as_deref()
+    }
+
+    async fn value(&self) -> &str {
+        &self.0.value
+    }
+
+    /// Public key that signed the operation
+    async fn signer(&self) -> Option<&str> {
+        self.0.signer.as_deref()
+    }
+
+    /// Peer this node received the operation from; null for changes made
+    /// on this node
+    async fn received_from(&self) -> Option<&str> {
+        self.0.received_from.as_deref()
+    }
+
+    /// Whether it was made on this node rather than replicated
+    async fn local(&self) -> bool {
+        self.0.local
+    }
+
+    /// Unix milliseconds
+    async fn timestamp(&self) -> String {
+        self.0.timestamp.to_string()
+    }
+}
+
+/// Changes dropped because the subscriber fell behind; re-read what you need
+#[derive(SimpleObject, Clone)]
+pub struct ChangesMissed {
+    pub missed: i64,
+}
+
+#[derive(Union, Clone)]
+pub enum ChangeNotification {
+    Change(DataChange),
+    Missed(ChangesMissed),
+}
+
+impl From<crate::changes::ChangeNotice> for ChangeNotification {
+    fn from(notice: crate::changes::ChangeNotice) -> Self {
+        match notice {
+            crate::changes::ChangeNotice::Change(event) => ChangeNotification::Change(DataChange(event)),
+            crate::changes::ChangeNotice::Missed(missed) => ChangeNotification::Missed(ChangesMissed {
+                missed: missed as i64,
+            }),
+        }
+    }
+}
+
+/// Which changes `watchQuery` emits; all given conditions must hold
+#[derive(InputObject)]
+pub struct WatchFilter {
+    pub db_name: String,
+    pub key_prefix: Option<String>,
+    /// Store types such as `String` or `Json`; all when omitted
+    pub store_types: Option<Vec<String>>,
+    /// Only operations signed by this public key
+    pub signer: Option<String>,
+    /// MongoDB-style filter document (`$and`, `$gt`, ...) on values that parse
+    /// as JSON
+    pub value_filter: Option<async_graphql::Json<serde_json::Value>>,
+}
+
 #[derive(SimpleObject, Clone)]
 pub struct MessageUpdate {
     pub topic: String,
@@ -605,11 +1278,26 @@ pub struct SignedData {
     pub value: String,
     /// Ed25519 public key (hex encoded)
     pub public_key: String,
-    /// Ed25519 signature (hex encoded)
+    /// Ed25519 signature (hex encoded) over the canonical operation envelope:
+    /// `cyberfly-signed-operation-v1`, a newline, then the RFC 8785 JSON of
+    /// `db_name, field, json_path, key, latitude, longitude, op_id (= nonce),
+    /// public_key, score, store_type, stream_fields, timestamp (= signedAt),
+    /// ts_timestamp (= timestamp), value`, plus `labels` when given. Without
+    /// nonce and signedAt the deprecated `db_name:key:value` form is checked instead.
     pub signature: String,
-    /// Store type: String, Hash, List, Set, SortedSet
+    /// Client-chosen operation ID covered by the signature; each may be used once
+    pub nonce: Option<String>,
+    /// Signing time in Unix milliseconds, covered by the signature
+    pub signed_at: Option<String>,
+    /// Store type: String, Hash, List, Set, SortedSet, Json, Stream, TimeSeries, Geo, Vector,
+    /// Grant/Revoke to share write access (owner only, key `__grants`), or
+    /// Encryption to publish the wrapped database key (owner only, key `__encryption`),
+    /// or Privacy with value `{"private": true}` to require read tokens (owner only, key `__privacy`)
     pub store_type: String,
-    /// Optional field name for Hash store type
+    /// Optional field name for Hash store type; the member for Vector, whose
+    /// value is `[...]` or `{"vector": [...], "metadata": {...}, "metric": "cosine"}`;
+    /// the grantee's public key for Grant/Revoke, whose value is
+    /// `{"store_types": [...], "key_prefixes": [...], "expires_at": <ms>}`
     pub field: Option<String>,
     /// Optional score for SortedSet store type
     pub score: Option<f64>,
@@ -619,17 +1307,335 @@ pub struct SignedData {
     pub stream_fields: Option<String>,
     /// Optional timestamp for TimeSeries store type (Unix timestamp in seconds)
     pub timestamp: Option<String>,
+    /// Optional labels for TimeSeries store type (JSON object of names to
+    /// values); replaces the series labels
+    pub labels: Option<String>,
     /// Optional longitude for Geo store type
     pub longitude: Option<f64>,
     /// Optional latitude for Geo store type
     pub latitude: Option<f64>,
+    /// Optional write durability; can raise but not lower the database's level
+    pub durability: Option<crate::storage::Durability>,
+}
+
+impl SignedData {
+    /// The operation as signed: with a nonce and signing time the client
+    /// signs the canonical envelope, otherwise the deprecated short form
+    fn to_operation(&self) -> Result<crate::sync::SignedOperation, DbError> {
+        let (op_id, timestamp) = match (self.nonce.as_deref(), self.signed_at.as_deref()) {
+            (Some(nonce), Some(signed_at)) => {
+                if nonce.is_empty() || nonce.len() > 128 {
+                    return Err(DbError::InvalidData("nonce must be 1-128 characters".to_string()));
+                }
+                let signed_at = signed_at.parse::<i64>().map_err(|_| {
+                    DbError::InvalidData("signedAt must be Unix milliseconds".to_string())
+                })?;
+                (nonce.to_string(), signed_at)
+            }
+            (None, None) => (
+                uuid::Uuid::new_v4().to_string(),
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_millis() as i64,
+            ),
+            _ => {
+                return Err(DbError::InvalidData(
+                    "nonce and signedAt must be provided together".to_string(),
+                ))
+            }
+        };
+
+        Ok(crate::sync::SignedOperation {
+            op_id,
+            timestamp,
+            db_name: self.db_name.clone(),
+            key: self.key.clone(),
+            value: self.value.clone(),
+            store_type: self.store_type.clone(),
+            field: self.field.clone(),
+            score: self.score,
+            json_path: self.json_path.clone(),
+            stream_fields: self.stream_fields.clone(),
+            ts_timestamp: self.timestamp.clone(),
+            labels: self.labels.clone(),
+            longitude: self.longitude,
+            latitude: self.latitude,
+            public_key: self.public_key.clone(),
+            signature: self.signature.clone(),
+        })
+    }
+}
+
+/// Signed write with typed arguments per store type, for `submitWrite`. The
+/// signature covers the same canonical envelope as `SignedData`'s, with
+/// `op_id` = nonce and `timestamp` = signedAt; the store type and remaining
+/// envelope fields come from `write` (each write type says how). Envelope
+/// fields a write type doesn't mention are `null`.
+#[derive(InputObject)]
+pub struct SignedWrite {
+    /// Database name (must be in format: <name>-<public_key_hex>)
+    pub db_name: String,
+    pub key: String,
+    /// Ed25519 public key (hex encoded)
+    pub public_key: String,
+    /// Ed25519 signature (hex encoded) over the canonical envelope
+    pub signature: String,
+    /// Client-chosen operation ID; each may be used once
+    pub nonce: String,
+    /// Signing time in Unix milliseconds
+    pub signed_at: i64,
+    pub write: StoreWrite,
+    /// Optional write durability; can raise but not lower the database's level
+    pub durability: Option<crate::storage::Durability>,
+}
+
+/// The write itself; exactly one store type is given
+#[derive(OneofObject)]
+pub enum StoreWrite {
+    String(StringWrite),
+    Hash(HashWrite),
+    List(ListWrite),
+    Set(SetWrite),
+    SortedSet(SortedSetWrite),
+    Json(JsonWrite),
+    Stream(StreamWrite),
+    TimeSeries(TimeSeriesWrite),
+    Geo(GeoWrite),
+    Vector(VectorWrite),
+}
+
+/// Envelope: `store_type` "String", `value`
+#[derive(InputObject)]
+pub struct StringWrite {
+    pub value: String,
+}
+
+/// Envelope: `store_type` "Hash", `field`, `value`
+#[derive(InputObject)]
+pub struct HashWrite {
+    pub field: String,
+    pub value: String,
+}
+
+/// Appends `value`. Envelope: `store_type` "List", `value`
+#[derive(InputObject)]
+pub struct ListWrite {
+    pub value: String,
+}
+
+/// Envelope: `store_type` "Set", `value` = member
+#[derive(InputObject)]
+pub struct SetWrite {
+    pub member: String,
+}
+
+/// Envelope: `store_type` "SortedSet", `value` = member, `score`
+#[derive(InputObject)]
+pub struct SortedSetWrite {
+    pub member: String,
+    pub score: f64,
+}
+
+/// Envelope: `store_type` "Json", `value` (JSON text), `json_path` = path
+#[derive(InputObject)]
+pub struct JsonWrite {
+    /// JSON text to set
+    pub value: String,
+    /// JSON path to set it at (default: "$")
+    pub path: Option<String>,
+}
+
+/// Appends an entry. Envelope: `store_type` "Stream", `value` "", and
+/// `stream_fields` = the canonical JSON of `[{"key": ..., "value": ...}, ...]`
+#[derive(InputObject)]
+pub struct StreamWrite {
+    pub fields: Vec<StreamField>,
+}
+
+/// Envelope: `store_type` "TimeSeries", `ts_timestamp` = timestamp as a
+/// decimal string, `value` = value in ECMAScript number form (what
+/// `String(value)` gives), and with labels `labels` = the canonical JSON of
+/// `{name: value, ...}`
+#[derive(InputObject)]
+pub struct TimeSeriesWrite {
+    /// Sample timestamp
+    pub timestamp: i64,
+    pub value: f64,
+    /// Replaces the series labels
+    pub labels: Option<Vec<SeriesLabel>>,
+}
+
+/// Envelope: `store_type` "Geo", `value` = member, `longitude`, `latitude`
+#[derive(InputObject)]
+pub struct GeoWrite {
+    pub member: String,
+    pub longitude: f64,
+    pub latitude: f64,
+}
+
+/// Envelope: `store_type` "Vector", `field` = member, and `value` = the
+/// canonical JSON of `{"vector": [...], "metadata": ..., "metric": ...}`
+/// without the members not given (metric as `cosine`, `l2` or `dot`)
+#[derive(InputObject)]
+pub struct VectorWrite {
+    pub member: String,
+    pub vector: Vec<f64>,
+    pub metadata: Option<async_graphql::Json<serde_json::Value>>,
+    pub metric: Option<crate::vector::DistanceMetric>,
+}
+
+impl SignedWrite {
+    /// The operation the client signed
+    fn to_operation(&self) -> Result<crate::sync::SignedOperation, DbError> {
+        use crate::crypto::canonical_json;
+
+        if self.nonce.is_empty() || self.nonce.len() > 128 {
+            return Err(DbError::InvalidData("nonce must be 1-128 characters".to_string()));
+        }
+        let mut op = crate::sync::SignedOperation {
+            op_id: self.nonce.clone(),
+            timestamp: self.signed_at,
+            db_name: self.db_name.clone(),
+            key: self.key.clone(),
+            value: String::new(),
+            store_type: String::new(),
+            field: None,
+            score: None,
+            json_path: None,
+            stream_fields: None,
+            ts_timestamp: None,
+            labels: None,
+            longitude: None,
+            latitude: None,
+            public_key: self.public_key.clone(),
+            signature: self.signature.clone(),
+        };
+
+        let store_type = match &self.write {
+            StoreWrite::String(write) => {
+                op.value = write.value.clone();
+                "String"
+            }
+            StoreWrite::Hash(write) => {
+                op.field = Some(write.field.clone());
+                op.value = write.value.clone();
+                "Hash"
+            }
+            StoreWrite::List(write) => {
+                op.value = write.value.clone();
+                "List"
+            }
+            StoreWrite::Set(write) => {
+                op.value = write.member.clone();
+                "Set"
+            }
+            StoreWrite::SortedSet(write) => {
+                op.value = write.member.clone();
+                op.score = Some(write.score);
+                "SortedSet"
+            }
+            StoreWrite::Json(write) => {
+                op.value = write.value.clone();
+                op.json_path = write.path.clone();
+                "Json"
+            }
+            StoreWrite::Stream(write) => {
+                let fields: Vec<serde_json::Value> = write
+                    .fields
+                    .iter()
+                    .map(|field| serde_json::json!({ "key": field.key, "value": field.value }))
+                    .collect();
+                op.stream_fields = Some(canonical_json(&serde_json::Value::Array(fields)));
+                "Stream"
+            }
+            StoreWrite::TimeSeries(write) => {
+                if !write.value.is_finite() {
+                    return Err(DbError::InvalidData("TimeSeries value must be finite".to_string()));
+                }
+                op.ts_timestamp = Some(write.timestamp.to_string());
+                op.value = canonical_json(&serde_json::json!(write.value));
+                op.labels = write.labels.as_ref().map(|labels| {
+                    let labels: serde_json::Map<String, serde_json::Value> = labels
+                        .iter()
+                        .map(|label| (label.name.clone(), serde_json::Value::String(label.value.clone())))
+                        .collect();
+                    canonical_json(&serde_json::Value::Object(labels))
+                });
+                "TimeSeries"
+            }
+            StoreWrite::Geo(write) => {
+                op.value = write.member.clone();
+                op.longitude = Some(write.longitude);
+                op.latitude = Some(write.latitude);
+                "Geo"
+            }
+            StoreWrite::Vector(write) => {
+                let mut value = serde_json::json!({ "vector": write.vector });
+                if let Some(metadata) = &write.metadata {
+                    value["metadata"] = metadata.0.clone();
+                }
+                if let Some(metric) = write.metric {
+                    value["metric"] = serde_json::Value::from(match metric {
+                        crate::vector::DistanceMetric::Cosine => "cosine",
+                        crate::vector::DistanceMetric::L2 => "l2",
+                        crate::vector::DistanceMetric::Dot => "dot",
+                    });
+                }
+                op.field = Some(write.member.clone());
+                op.value = canonical_json(&value);
+                "Vector"
+            }
+        };
+        op.store_type = store_type.to_string();
+        Ok(op)
+    }
+}
+
+/// Operations for `submitBatch`
+#[derive(InputObject)]
+pub struct BatchInput {
+    /// Operations to write, in order; at most 1000. Their `durability` must
+    /// be unset (use the batch's).
+    pub operations: Vec<SignedData>,
+    /// Signature (hex) over the batch's Merkle root instead of one per
+    /// operation: `cyberfly-batch-root-v1`, a newline, then the root in hex.
+    /// Leaf `i` is SHA-256 of `0x00` and operation `i`'s canonical message, an
+    /// inner node SHA-256 of `0x01`, left and right, and an odd node at the end
+    /// of a level is carried up unchanged. Every operation needs a nonce and
+    /// signedAt and the same public key; their `signature` is ignored.
+    pub root_signature: Option<String>,
+    /// Optional write durability for every operation of the batch
+    pub durability: Option<crate::storage::Durability>,
 }
 
+/// Outcome of one operation of a batch
+#[derive(SimpleObject, Clone)]
+pub struct BatchItemResult {
+    /// Position of the operation in the batch
+    pub index: i32,
+    pub success: bool,
+    pub op_id: String,
+    pub message: String,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct BatchResult {
+    /// Whether every operation was written
+    pub success: bool,
+    pub accepted: i32,
+    pub rejected: i32,
+    /// One result per operation, in input order
+    pub results: Vec<BatchItemResult>,
+}
+
+#[derive(Default)]
 pub struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
     /// Get a string value from storage
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_string(
         &self,
         ctx: &Context<'_>,
@@ -657,6 +1663,7 @@ impl QueryRoot {
     }
 
     /// Get all string entries for a database
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
     async fn get_all_strings(
         &self,
         ctx: &Context<'_>,
@@ -679,6 +1686,7 @@ impl QueryRoot {
     }
 
     /// Get all hashes for a database
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
     async fn get_all_hashes(
         &self,
         ctx: &Context<'_>,
@@ -701,6 +1709,7 @@ impl QueryRoot {
     }
 
     /// Get all lists for a database
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
     async fn get_all_lists(
         &self,
         ctx: &Context<'_>,
@@ -723,6 +1732,7 @@ impl QueryRoot {
     }
 
     /// Get all sets for a database
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
     async fn get_all_sets(
         &self,
         ctx: &Context<'_>,
@@ -745,6 +1755,7 @@ impl QueryRoot {
     }
 
     /// Get all sorted sets for a database
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
     async fn get_all_sorted_sets(
         &self,
         ctx: &Context<'_>,
@@ -767,6 +1778,7 @@ impl QueryRoot {
     }
 
     /// Get all JSON docs for a database (alias uses existing get_all_json)
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
     async fn get_all_jsons(
         &self,
         ctx: &Context<'_>,
@@ -777,6 +1789,7 @@ impl QueryRoot {
     }
 
     /// Get all stream entries for a database
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
     async fn get_all_streams(
         &self,
         ctx: &Context<'_>,
@@ -799,6 +1812,7 @@ impl QueryRoot {
     }
 
     /// Get all timeseries for a database
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
     async fn get_all_timeseries(
         &self,
         ctx: &Context<'_>,
@@ -821,6 +1835,7 @@ impl QueryRoot {
     }
 
     /// Get all geo entries for a database
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
     async fn get_all_geo(
         &self,
         ctx: &Context<'_>,
@@ -843,6 +1858,7 @@ impl QueryRoot {
     }
 
     /// Get a hash field from storage
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_hash(
         &self,
         ctx: &Context<'_>,
@@ -867,6 +1883,7 @@ impl QueryRoot {
     }
 
     /// Get all hash fields from storage
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_all_hash(
         &self,
         ctx: &Context<'_>,
@@ -893,6 +1910,7 @@ impl QueryRoot {
     }
 
     /// Get list items from storage
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_list(
         &self,
         ctx: &Context<'_>,
@@ -916,6 +1934,7 @@ impl QueryRoot {
     }
 
     /// Get set members from storage
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_set(
         &self,
         ctx: &Context<'_>,
@@ -931,6 +1950,7 @@ impl QueryRoot {
     }
 
     /// Get sorted set range from storage
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_sorted_set(
         &self,
         ctx: &Context<'_>,
@@ -989,6 +2009,7 @@ impl QueryRoot {
     // ============ JSON Queries ============
 
     /// Get JSON document or specific path
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_json(
         &self,
         ctx: &Context<'_>,
@@ -1013,6 +2034,7 @@ impl QueryRoot {
     }
 
     /// Filter JSON by JSONPath
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
     async fn filter_json(
         &self,
         ctx: &Context<'_>,
@@ -1023,6 +2045,7 @@ impl QueryRoot {
         let storage = ctx
             .data::<RedisStorage>()
             .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+        ensure_plaintext(storage, &db_name)?;
 
         let full_key = format_key(&db_name, &key);
         let value = storage
@@ -1037,6 +2060,7 @@ impl QueryRoot {
     }
 
     /// Get all JSON documents for a database prefix (with signature metadata)
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
     async fn get_all_json(
         &self,
         ctx: &Context<'_>,
@@ -1069,7 +2093,63 @@ impl QueryRoot {
         Ok(out)
     }
 
+    /// Run an aggregation pipeline ($match, $project, $group, $sort, $skip,
+    /// $limit, $unwind) over the JSON documents of a database
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
+    async fn aggregate(
+        &self,
+        ctx: &Context<'_>,
+        db_name: String,
+        pipeline: async_graphql::Json<serde_json::Value>,
+    ) -> Result<Vec<async_graphql::Json<serde_json::Value>>, DbError> {
+        use crate::metrics;
+
+        metrics::GRAPHQL_REQUESTS.with_label_values(&["aggregate"]).inc();
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+        ensure_plaintext(storage, &db_name)?;
+
+        let pipeline = crate::aggregation::Pipeline::parse(&pipeline.0)
+            .map_err(|e| DbError::InvalidData(e.to_string()))?;
+        let docs = crate::aggregation::Aggregator::new(storage)
+            .aggregate(&db_name, &pipeline)
+            .await
+            .map_err(DbError::from)?;
+
+        Ok(docs.into_iter().map(async_graphql::Json).collect())
+    }
+
+    /// Run a read-only SQL query over the virtual tables `json_docs`,
+    /// `timeseries`, `stream_entries` and `geo` of a database
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
+    async fn sql(
+        &self,
+        ctx: &Context<'_>,
+        db_name: String,
+        query: String,
+    ) -> Result<SqlQueryResult, DbError> {
+        use crate::metrics;
+
+        metrics::GRAPHQL_REQUESTS.with_label_values(&["sql"]).inc();
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+        ensure_plaintext(storage, &db_name)?;
+
+        let result = crate::sql::SqlEngine::new(storage)
+            .query(&db_name, &query)
+            .await
+            .map_err(|e| DbError::InvalidData(e.to_string()))?;
+
+        Ok(SqlQueryResult {
+            columns: result.columns,
+            rows: result.rows.into_iter().map(async_graphql::Json).collect(),
+        })
+    }
+
     /// Get all entries across all store types for a database prefix
+    #[graphql(guard = "ReadGuard::new(&db_name)", complexity = "SCAN_COST + child_complexity")]
     async fn get_all(
         &self,
         ctx: &Context<'_>,
@@ -1098,6 +2178,7 @@ impl QueryRoot {
     // ============ Stream Queries ============
 
     /// Get stream entries by range
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_stream(
         &self,
         ctx: &Context<'_>,
@@ -1134,6 +2215,7 @@ impl QueryRoot {
     }
 
     /// Filter stream entries by pattern
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn filter_stream(
         &self,
         ctx: &Context<'_>,
@@ -1169,6 +2251,7 @@ impl QueryRoot {
     }
 
     /// Get stream length
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_stream_length(
         &self,
         ctx: &Context<'_>,
@@ -1188,6 +2271,7 @@ impl QueryRoot {
     // ============ TimeSeries Queries ============
 
     /// Get time series data by time range
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_timeseries(
         &self,
         ctx: &Context<'_>,
@@ -1223,6 +2307,7 @@ impl QueryRoot {
     }
 
     /// Filter time series by value range
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn filter_timeseries(
         &self,
         ctx: &Context<'_>,
@@ -1260,6 +2345,7 @@ impl QueryRoot {
     }
 
     /// Get latest time series value
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_latest_timeseries(
         &self,
         ctx: &Context<'_>,
@@ -1270,18 +2356,93 @@ impl QueryRoot {
             .data::<RedisStorage>()
             .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
 
-        let full_key = format_key(&db_name, &key);
-        let data = storage.ts_get(&full_key).await.map_err(DbError::from)?;
+        let full_key = format_key(&db_name, &key);
+        let data = storage.ts_get(&full_key).await.map_err(DbError::from)?;
+
+        Ok(data.map(|(ts, val)| TimeSeriesPoint {
+            timestamp: ts.to_string(),
+            value: val,
+        }))
+    }
+
+    /// Aggregate one time series into buckets, with optional gap filling
+    #[graphql(guard = "ReadGuard::new(&input.db_name)")]
+    async fn timeseries_aggregate(
+        &self,
+        ctx: &Context<'_>,
+        input: TimeSeriesAggregateInput,
+    ) -> Result<Vec<TimeSeriesBucket>, DbError> {
+        use crate::filters::{Aggregation, TimeSeriesFilter, TimeSeriesOptions};
+
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        let invalid = |e: anyhow::Error| DbError::InvalidData(e.to_string());
+        let options = TimeSeriesOptions {
+            aggregation: Some(Aggregation {
+                agg_type: input.aggregation.parse().map_err(invalid)?,
+                time_bucket: input.time_bucket,
+            }),
+            fill: input.fill.as_deref().map(str::parse).transpose().map_err(invalid)?,
+            alignment: input
+                .align
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .map_err(invalid)?
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        let full_key = format_key(&input.db_name, &input.key);
+        let from_ts = input
+            .from_timestamp
+            .parse::<i64>()
+            .map_err(|_| DbError::StaticError("Invalid from_timestamp"))?;
+        let to_ts = input
+            .to_timestamp
+            .parse::<i64>()
+            .map_err(|_| DbError::StaticError("Invalid to_timestamp"))?;
+
+        let buckets = TimeSeriesFilter::new(storage)
+            .query_buckets(&full_key, from_ts, to_ts, &options)
+            .await
+            .map_err(invalid)?;
+
+        Ok(buckets
+            .into_iter()
+            .map(|(ts, value)| TimeSeriesBucket {
+                timestamp: ts.to_string(),
+                value,
+            })
+            .collect())
+    }
+
+    /// Query many time series at once by key pattern and labels, with
+    /// aligned buckets and optional reduction across series
+    #[graphql(guard = "ReadGuard::new(&input.db_name)", complexity = "SCAN_COST + child_complexity")]
+    async fn timeseries_mrange(
+        &self,
+        ctx: &Context<'_>,
+        input: TimeSeriesMRangeInput,
+    ) -> Result<Vec<SeriesRangeGql>, DbError> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        let (selector, options) = input.to_query()?;
+        let ranges = crate::filters::TimeSeriesFilter::new(storage)
+            .mrange(&input.db_name, &selector, &options)
+            .await
+            .map_err(DbError::from)?;
 
-        Ok(data.map(|(ts, val)| TimeSeriesPoint {
-            timestamp: ts.to_string(),
-            value: val,
-        }))
+        Ok(ranges.into_iter().map(SeriesRangeGql::from).collect())
     }
 
     // ============ Geospatial Queries ============
 
     /// Get location of a member
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_geo_location(
         &self,
         ctx: &Context<'_>,
@@ -1307,6 +2468,7 @@ impl QueryRoot {
     }
 
     /// Search locations within radius
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn search_geo_radius(
         &self,
         ctx: &Context<'_>,
@@ -1339,6 +2501,7 @@ impl QueryRoot {
     }
 
     /// Search locations within radius from member
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn search_geo_radius_by_member(
         &self,
         ctx: &Context<'_>,
@@ -1370,6 +2533,7 @@ impl QueryRoot {
     }
 
     /// Calculate distance between two members
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_geo_distance(
         &self,
         ctx: &Context<'_>,
@@ -1391,9 +2555,138 @@ impl QueryRoot {
             .map_err(DbError::from)
     }
 
+    // ============ Vector Queries ============
+
+    /// Nearest members of a vector key to a query vector
+    #[graphql(guard = "ReadGuard::new(&input.db_name)")]
+    async fn vector_search(
+        &self,
+        ctx: &Context<'_>,
+        input: VectorSearchInput,
+    ) -> Result<Vec<VectorMatchGql>, DbError> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        let filter = input
+            .filter
+            .map(|filter| crate::filters::FilterExpr::parse(&filter.0))
+            .transpose()
+            .map_err(|e| DbError::InvalidData(format!("Invalid filter: {}", e)))?;
+        let full_key = format_key(&input.db_name, &input.key);
+        let matches = storage
+            .vsearch(&full_key, &input.vector, input.k.unwrap_or(10), input.ef, filter.as_ref())
+            .await
+            .map_err(|e| DbError::InvalidData(e.to_string()))?;
+
+        Ok(matches
+            .into_iter()
+            .map(|m| VectorMatchGql {
+                member: m.member,
+                distance: m.distance,
+                metadata: async_graphql::Json(m.metadata),
+            })
+            .collect())
+    }
+
+    /// Get one member of a vector key
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
+    async fn get_vector(
+        &self,
+        ctx: &Context<'_>,
+        db_name: String,
+        key: String,
+        member: String,
+    ) -> Result<Option<VectorEntryGql>, DbError> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        let full_key = format_key(&db_name, &key);
+        let entry = storage.vget(&full_key, &member).await.map_err(DbError::from)?;
+
+        Ok(entry.map(|e| VectorEntryGql {
+            member: e.member,
+            vector: e.vector,
+            metadata: async_graphql::Json(e.metadata),
+        }))
+    }
+
+    /// Dimension, metric and size of a vector key
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
+    async fn vector_info(
+        &self,
+        ctx: &Context<'_>,
+        db_name: String,
+        key: String,
+    ) -> Result<Option<VectorInfoGql>, DbError> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        let full_key = format_key(&db_name, &key);
+        let info = storage.vinfo(&full_key).await.map_err(DbError::from)?;
+
+        Ok(info.map(|(dimension, metric, count)| VectorInfoGql {
+            dimension,
+            metric,
+            count,
+        }))
+    }
+
+    /// Encryption metadata of a database, or null when it stores plaintext.
+    /// Members unwrap the database key with their own signing key.
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
+    async fn encryption(&self, ctx: &Context<'_>, db_name: String) -> Result<Option<EncryptionGql>, DbError> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        Ok(storage.encryption().get(&db_name).map(EncryptionGql::from))
+    }
+
+    /// Whether a database requires a read token
+    async fn is_private(&self, ctx: &Context<'_>, db_name: String) -> Result<bool, DbError> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        Ok(storage.read_access().is_private(&db_name))
+    }
+
+    /// A fresh challenge to embed in a read token, binding it to this node
+    /// for the next five minutes
+    async fn read_challenge(&self, ctx: &Context<'_>) -> Result<ReadChallengeGql, DbError> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        let (challenge, expires_at) = storage.read_access().issue_challenge();
+        Ok(ReadChallengeGql {
+            challenge,
+            expires_at: expires_at.to_string(),
+        })
+    }
+
+    /// Keys the owner has granted write access to a database, including revocations
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
+    async fn grants(&self, ctx: &Context<'_>, db_name: String) -> Result<Vec<GrantGql>, DbError> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        Ok(storage
+            .delegations()
+            .grants(&db_name)
+            .into_iter()
+            .map(GrantGql::from)
+            .collect())
+    }
+
     // ============ Filter Queries for Basic Types ============
 
     /// Filter hash fields by pattern
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn filter_hash(
         &self,
         ctx: &Context<'_>,
@@ -1421,6 +2714,7 @@ impl QueryRoot {
     }
 
     /// Filter list by value pattern
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn filter_list(
         &self,
         ctx: &Context<'_>,
@@ -1431,6 +2725,7 @@ impl QueryRoot {
         let storage = ctx
             .data::<RedisStorage>()
             .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+        ensure_plaintext(storage, &db_name)?;
 
         let full_key = format_key(&db_name, &key);
         storage
@@ -1440,6 +2735,7 @@ impl QueryRoot {
     }
 
     /// Filter set by member pattern
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn filter_set(
         &self,
         ctx: &Context<'_>,
@@ -1450,6 +2746,7 @@ impl QueryRoot {
         let storage = ctx
             .data::<RedisStorage>()
             .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+        ensure_plaintext(storage, &db_name)?;
 
         let full_key = format_key(&db_name, &key);
         storage
@@ -1459,6 +2756,7 @@ impl QueryRoot {
     }
 
     /// Filter sorted set by score range
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn filter_sorted_set(
         &self,
         ctx: &Context<'_>,
@@ -1514,6 +2812,7 @@ impl QueryRoot {
     // ============ Blob Operation Queries ============
 
     /// Get all blob operations for a specific database
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_blob_operations(
         &self,
         ctx: &Context<'_>,
@@ -1544,6 +2843,7 @@ impl QueryRoot {
                 json_path: op.json_path,
                 stream_fields: op.stream_fields,
                 ts_timestamp: op.ts_timestamp,
+                labels: op.labels,
                 longitude: op.longitude,
                 latitude: op.latitude,
                 public_key: op.public_key,
@@ -1555,6 +2855,7 @@ impl QueryRoot {
     }
 
     /// Get all blob operations (across all databases)
+    #[graphql(complexity = "SCAN_COST + child_complexity")]
     async fn get_all_blob_operations(
         &self,
         ctx: &Context<'_>,
@@ -1569,9 +2870,19 @@ impl QueryRoot {
         // Get all operations but apply limit efficiently
         let all_operations = sync_manager.sync_store().get_all_operations().await;
 
+        // Leave out private databases the caller may not read
+        let storage = ctx.data_opt::<RedisStorage>();
+        let credential = ctx.data_opt::<ReadCredential>().cloned().unwrap_or_default();
+        let readable = |op: &crate::sync::SignedOperation| {
+            storage.is_none_or(|storage| {
+                storage.read_access().can_read(&op.db_name, &credential, storage.delegations())
+            })
+        };
+
         // Apply limit efficiently
         let limited_ops: Vec<BlobOperation> = all_operations
             .into_iter()
+            .filter(|op| readable(op))
             .take(limit)
             .map(|op| BlobOperation {
                 op_id: op.op_id,
@@ -1585,6 +2896,7 @@ impl QueryRoot {
                 json_path: op.json_path,
                 stream_fields: op.stream_fields,
                 ts_timestamp: op.ts_timestamp,
+                labels: op.labels,
                 longitude: op.longitude,
                 latitude: op.latitude,
                 public_key: op.public_key,
@@ -1596,6 +2908,7 @@ impl QueryRoot {
     }
 
     /// Get blob operations by database name since a specific timestamp
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_blob_operations_since(
         &self,
         ctx: &Context<'_>,
@@ -1631,6 +2944,7 @@ impl QueryRoot {
                 json_path: op.json_path,
                 stream_fields: op.stream_fields,
                 ts_timestamp: op.ts_timestamp,
+                labels: op.labels,
                 longitude: op.longitude,
                 latitude: op.latitude,
                 public_key: op.public_key,
@@ -1642,6 +2956,7 @@ impl QueryRoot {
     }
 
     /// Get count of blob operations for a database
+    #[graphql(guard = "ReadGuard::new(db_name.as_deref().unwrap_or_default())")]
     async fn get_blob_operation_count(
         &self,
         ctx: &Context<'_>,
@@ -1665,6 +2980,7 @@ impl QueryRoot {
     // ============ TTL (Time-To-Live) Queries ============
 
     /// Get TTL information for a key
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
     async fn get_ttl(
         &self,
         ctx: &Context<'_>,
@@ -2030,6 +3346,7 @@ impl QueryRoot {
     // ============================================================================
 
     /// Get circuit breaker summary
+    #[graphql(guard = "AdminGuard")]
     async fn get_circuit_breaker_summary(&self, ctx: &Context<'_>) -> Result<CircuitBreakerSummaryGql, DbError> {
         use crate::metrics;
         
@@ -2053,6 +3370,7 @@ impl QueryRoot {
     }
 
     /// Get circuit breaker state for a specific peer
+    #[graphql(guard = "AdminGuard")]
     async fn get_circuit_breaker_state(&self, ctx: &Context<'_>, peer_id: String) -> Result<CircuitBreakerStatus, DbError> {
         use crate::metrics;
         use crate::network_resilience::CircuitState;
@@ -2083,6 +3401,7 @@ impl QueryRoot {
     }
 
     /// Get reputation summary
+    #[graphql(guard = "AdminGuard")]
     async fn get_reputation_summary(&self, ctx: &Context<'_>) -> Result<ReputationSummaryGql, DbError> {
         use crate::metrics;
         
@@ -2106,6 +3425,7 @@ impl QueryRoot {
     }
 
     /// Get reputation for a specific peer
+    #[graphql(guard = "AdminGuard")]
     async fn get_peer_reputation(&self, ctx: &Context<'_>, peer_id: String) -> Result<Option<PeerReputationGql>, DbError> {
         use crate::metrics;
         
@@ -2140,6 +3460,7 @@ impl QueryRoot {
     }
 
     /// Get top peers by reputation
+    #[graphql(guard = "AdminGuard")]
     async fn get_top_peers_by_reputation(&self, ctx: &Context<'_>, limit: Option<i32>) -> Result<Vec<PeerReputationGql>, DbError> {
         use crate::metrics;
         
@@ -2177,6 +3498,7 @@ impl QueryRoot {
     }
 
     /// Get bandwidth statistics
+    #[graphql(guard = "AdminGuard")]
     async fn get_bandwidth_stats(&self, ctx: &Context<'_>) -> Result<BandwidthStatsGql, DbError> {
         use crate::metrics;
         
@@ -2202,6 +3524,7 @@ impl QueryRoot {
     }
 
     /// Get combined network resilience summary
+    #[graphql(guard = "AdminGuard")]
     async fn get_network_resilience_summary(&self, ctx: &Context<'_>) -> Result<NetworkResilienceSummaryGql, DbError> {
         use crate::metrics;
         
@@ -2242,6 +3565,27 @@ impl QueryRoot {
         })
     }
 
+    /// Recent calls to admin-only fields, newest first (admin)
+    #[graphql(guard = "AdminGuard")]
+    async fn admin_audit_log(&self, ctx: &Context<'_>, limit: Option<i32>) -> Result<Vec<AuditEntryGql>, DbError> {
+        let admin = ctx
+            .data::<AdminAuth>()
+            .map_err(|_| DbError::InternalError("Admin auth not found".to_string()))?;
+
+        let limit = limit.unwrap_or(100).max(0) as usize;
+        Ok(admin
+            .audit_log(limit)
+            .into_iter()
+            .map(|entry| AuditEntryGql {
+                timestamp: entry.timestamp.to_string(),
+                actor: entry.actor,
+                action: entry.action,
+                allowed: entry.allowed,
+                reason: entry.reason,
+            })
+            .collect())
+    }
+
     /// Get performance metrics from Prometheus
     async fn get_metrics(&self, _ctx: &Context<'_>) -> Result<PerformanceMetrics, DbError> {
         use crate::metrics::*;
@@ -2457,283 +3801,211 @@ impl QueryRoot {
     }
 }
 
+#[derive(Default)]
 pub struct MutationRoot;
 
 #[Object]
 impl MutationRoot {
-    /// Submit signed data to the database
+    /// Submit signed data to the database, with the store type and its
+    /// arguments as loosely typed fields (see `submitWrite` for typed ones)
     async fn submit_data(
         &self,
         ctx: &Context<'_>,
         input: SignedData,
     ) -> Result<StorageResult, DbError> {
+        let signed_operation = input.to_operation()?;
+        submit_operation(ctx, "submit_data", signed_operation, input.durability).await
+    }
+
+    /// Submit a signed write whose arguments are typed per store type, so
+    /// invalid combinations are rejected by the schema
+    async fn submit_write(
+        &self,
+        ctx: &Context<'_>,
+        input: SignedWrite,
+    ) -> Result<StorageResult, DbError> {
+        let signed_operation = input.to_operation()?;
+        submit_operation(ctx, "submit_write", signed_operation, input.durability).await
+    }
+
+    /// Submit many signed operations at once. Signatures are verified in
+    /// parallel, the writes go through a `BatchWriter` and peers receive the
+    /// batch as one sync message. Each operation succeeds or fails on its own;
+    /// see `BatchInput` for signing the whole batch once.
+    async fn submit_batch(
+        &self,
+        ctx: &Context<'_>,
+        input: BatchInput,
+    ) -> Result<BatchResult, DbError> {
         use crate::metrics;
-        
-        // Track GraphQL request
-        metrics::GRAPHQL_REQUESTS.with_label_values(&["submit_data"]).inc();
+
+        metrics::GRAPHQL_REQUESTS.with_label_values(&["submit_batch"]).inc();
         let timer = std::time::Instant::now();
-        
+
         let storage = ctx
             .data::<RedisStorage>()
             .map_err(|_| {
-                metrics::GRAPHQL_ERRORS.with_label_values(&["submit_data"]).inc();
+                metrics::GRAPHQL_ERRORS.with_label_values(&["submit_batch"]).inc();
                 DbError::StaticError(STORAGE_NOT_FOUND)
             })?;
 
-        // Enhanced database name verification with security checks
-        crypto::verify_db_name_secure(&input.db_name, &input.public_key).map_err(|e| {
-            metrics::GRAPHQL_ERRORS.with_label_values(&["submit_data"]).inc();
-            DbError::SignatureError(format!("Database name verification failed: {}", e))
-        })?;
-
-        // Securely decode public key and signature from hex with validation
-        let public_key_bytes = crypto::secure_hex_decode(&input.public_key)
-            .map_err(|e| {
-                metrics::GRAPHQL_ERRORS.with_label_values(&["submit_data"]).inc();
-                DbError::InvalidData(format!("Invalid public key hex: {}", e))
-            })?;
-        let signature_bytes = crypto::secure_hex_decode(&input.signature)
-            .map_err(|e| {
-                metrics::GRAPHQL_ERRORS.with_label_values(&["submit_data"]).inc();
-                DbError::InvalidData(format!("Invalid signature hex: {}", e))
-            })?;
-
-        // Create message to verify (db_name:key:value)
-        let message = format!("{}:{}:{}", input.db_name, input.key, input.value);
-
-        // Verify signature
-        crypto::verify_signature(&public_key_bytes, message.as_bytes(), &signature_bytes)
-            .map_err(|e| {
-                metrics::GRAPHQL_ERRORS.with_label_values(&["submit_data"]).inc();
-                DbError::SignatureError(e.to_string())
-            })?;
-
-        tracing::info!(
-            "Signature verified for db: {}, key: {}",
-            input.db_name,
-            input.key
-        );
-
-        // Create full key with database namespace
-        let full_key = format!("{}:{}", input.db_name, input.key);
-
-        // Create signature metadata to store alongside values when possible
-        let metadata_ts: i64 = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as i64;
+        if input.operations.is_empty() || input.operations.len() > crate::batch::MAX_BATCH_SIZE {
+            metrics::GRAPHQL_ERRORS.with_label_values(&["submit_batch"]).inc();
+            return Err(DbError::InvalidData(format!(
+                "A batch holds 1 to {} operations",
+                crate::batch::MAX_BATCH_SIZE
+            )));
+        }
 
-        let sig_meta = Some(crate::storage::SignatureMetadata {
-            public_key: input.public_key.clone(),
-            signature: input.signature.clone(),
-            timestamp: metadata_ts,
-        });
+        let mut operations: Vec<Result<crate::sync::SignedOperation, DbError>> = input
+            .operations
+            .iter()
+            .map(|data| match data.durability {
+                Some(_) => Err(DbError::InvalidData(
+                    "durability is set on the batch, not its operations".to_string(),
+                )),
+                None => data.to_operation(),
+            })
+            .collect();
 
-        // Clone fields we'll need later for SignedOperation (before they're moved)
-        let field_clone = input.field.clone();
-        let score_clone = input.score;
-        let json_path_clone = input.json_path.clone();
-        let stream_fields_clone = input.stream_fields.clone();
-        let ts_timestamp_clone = input.timestamp.clone();
-        let longitude_clone = input.longitude;
-        let latitude_clone = input.latitude;
-        
-        // Apply TTL based on user's plan tier
-        // TODO: Query smart contract to get user's plan based on public_key
-        // For now, all users are on free tier (24 hours)
-        const FREE_TIER_TTL: u64 = 86_400; // 24 hours
-        let ttl_seconds = Some(FREE_TIER_TTL);
-
-        // Store data based on type
-        match input.store_type.to_lowercase().as_str() {
-            "string" => {
-                storage
-                    .set_string_with_ttl(&full_key, &input.value, sig_meta.clone(), ttl_seconds)
-                    .await
-                    .map_err(DbError::from)?;
+        // One signature over the Merkle root covers every operation, so the
+        // batch must be whole and have a single signer
+        if let Some(root_signature) = input.root_signature.as_deref() {
+            let mut ops = operations
+                .into_iter()
+                .enumerate()
+                .map(|(index, op)| op.map_err(|e| DbError::InvalidData(format!("operation {}: {}", index, e))))
+                .collect::<Result<Vec<_>, _>>()?;
+            if input.operations.iter().any(|data| data.nonce.is_none()) {
+                return Err(DbError::InvalidData(
+                    "every operation of a batch signed over its Merkle root needs a nonce and signedAt".to_string(),
+                ));
             }
-            "hash" => {
-                let field = input.field.ok_or_else(|| {
-                    DbError::InvalidData("Field required for Hash type".to_string())
-                })?;
-                storage
-                    .set_hash_with_ttl(&full_key, &field, &input.value, sig_meta.clone(), ttl_seconds)
-                    .await
-                    .map_err(DbError::from)?;
-            }
-            "list" => {
-                storage
-                    .push_list_with_ttl(&full_key, &input.value, sig_meta.clone(), ttl_seconds)
-                    .await
-                    .map_err(DbError::from)?;
-            }
-            "set" => {
-                storage
-                    .add_set_with_ttl(&full_key, &input.value, sig_meta.clone(), ttl_seconds)
-                    .await
-                    .map_err(DbError::from)?;
-            }
-            "sortedset" => {
-                let score = input.score.ok_or_else(|| {
-                    DbError::InvalidData("Score required for SortedSet type".to_string())
-                })?;
-                storage
-                    .add_sorted_set_with_ttl(&full_key, score, &input.value, sig_meta.clone(), ttl_seconds)
-                    .await
-                    .map_err(DbError::from)?;
-            }
-            "json" => {
-                let path = input.json_path.as_deref().unwrap_or("$");
-                storage
-                    .set_json_with_ttl(&full_key, path, &input.value, sig_meta.clone(), ttl_seconds)
-                    .await
-                    .map_err(DbError::from)?;
+            if ops.iter().any(|op| op.public_key != ops[0].public_key) {
+                return Err(DbError::InvalidData(
+                    "every operation of a batch signed over its Merkle root needs the same public key".to_string(),
+                ));
             }
-            "stream" => {
-                // Parse stream_fields JSON array: [{"key": "field1", "value": "val1"}, ...]
-                let fields_json = input.stream_fields.ok_or_else(|| {
-                    DbError::InvalidData("stream_fields required for Stream type".to_string())
+            crate::batch::attach_root_signature(&mut ops, root_signature)
+                .and_then(|_| crate::batch::verify_merkle_signature(&ops[0]))
+                .map_err(|e| {
+                    metrics::GRAPHQL_ERRORS.with_label_values(&["submit_batch"]).inc();
+                    DbError::SignatureError(format!("batch root: {}", e))
                 })?;
-                let fields: Vec<serde_json::Value> =
-                    serde_json::from_str(&fields_json).map_err(|e| {
-                        DbError::InvalidData(format!("Invalid stream_fields JSON: {}", e))
-                    })?;
-
-                let mut owned_fields: Vec<(String, String)> = Vec::new();
-
-                for field_obj in fields {
-                    if let (Some(k), Some(v)) = (
-                        field_obj.get("key").and_then(|k| k.as_str()),
-                        field_obj.get("value").and_then(|v| v.as_str()),
-                    ) {
-                        owned_fields.push((k.to_string(), v.to_string()));
+            operations = ops.into_iter().map(Ok).collect();
+        }
+
+        let verified = verify_batch(operations, storage.delegations()).await;
+
+        // Per-key write budget, one token per operation
+        let limiter = ctx.data_opt::<RateLimiter>();
+        let mut retry_after = None;
+        let mut admitted = Vec::with_capacity(verified.len());
+        for item in verified {
+            admitted.push(match item {
+                Ok((op, format)) => match limiter.and_then(|limiter| limiter.check_key(&op.public_key).err()) {
+                    Some(wait) => {
+                        retry_after = retry_after.max(Some(wait));
+                        Err(DbError::RateLimitError(format!("too many writes from key {}", op.public_key)))
                     }
-                }
-                let mut field_pairs: Vec<(String, String)> = Vec::new();
+                    None => admit_operation(storage, &op, format).await.map(|()| (op, format)),
+                },
+                Err(e) => Err(e),
+            });
+        }
 
-                for (k, v) in &owned_fields {
-                    field_pairs.push((k.clone(), v.clone()));
-                }
+        // Apply the requested durability to this batch only
+        let request_storage = input.durability.map(|level| storage.with_durability(level));
+        let storage = request_storage.as_ref().unwrap_or(storage);
 
-                storage
-                    .xadd_with_ttl(&full_key, "*", &owned_fields, sig_meta.clone(), ttl_seconds)
-                    .await
-                    .map_err(DbError::from)?;
+        let (indices, ops): (Vec<usize>, Vec<crate::sync::SignedOperation>) = admitted
+            .iter()
+            .enumerate()
+            .filter_map(|(index, op)| op.as_ref().ok().map(|(op, _)| (index, op.clone())))
+            .unzip();
+        let written = storage
+            .batch_writer(None)
+            .write_operations(&ops, Some(FREE_TIER_TTL))
+            .await;
+        for (index, result) in indices.into_iter().zip(written) {
+            if let Err(e) = result {
+                if let Ok((op, format)) = &admitted[index] {
+                    release_nonce(storage, op, *format).await;
+                }
+                admitted[index] = Err(write_error(e));
             }
-            "timeseries" => {
-                let timestamp_str = input.timestamp.ok_or_else(|| {
-                    DbError::InvalidData("timestamp required for TimeSeries type".to_string())
-                })?;
-                let timestamp = timestamp_str
-                    .parse::<i64>()
-                    .map_err(|_| DbError::InvalidData("Invalid timestamp format".to_string()))?;
-                let value = input.value.parse::<f64>().map_err(|_| {
-                    DbError::InvalidData("Value must be a number for TimeSeries type".to_string())
-                })?;
+        }
 
-                storage
-                    .ts_add_with_ttl(&full_key, timestamp, value, sig_meta.clone(), ttl_seconds)
-                    .await
-                    .map_err(DbError::from)?;
-            }
-            "geo" => {
-                let longitude = input.longitude.ok_or_else(|| {
-                    DbError::InvalidData("longitude required for Geo type".to_string())
-                })?;
-                let latitude = input.latitude.ok_or_else(|| {
-                    DbError::InvalidData("latitude required for Geo type".to_string())
-                })?;
+        let mut replicate = Vec::new();
+        let mut results = Vec::with_capacity(admitted.len());
+        for (index, (item, data)) in admitted.into_iter().zip(&input.operations).enumerate() {
+            let op_id = data.nonce.clone().unwrap_or_default();
+            results.push(match item {
+                Ok((op, _)) => {
+                    record_write(ctx, &op).await;
+                    let result = BatchItemResult {
+                        index: index as i32,
+                        success: true,
+                        op_id: op.op_id.clone(),
+                        message: format!("Data stored successfully in db: {}, key: {}", op.db_name, op.key),
+                    };
+                    replicate.push((index, op));
+                    result
+                }
+                Err(e) => BatchItemResult {
+                    index: index as i32,
+                    success: false,
+                    op_id,
+                    message: e.to_string(),
+                },
+            });
+        }
 
-                storage
-                    .geoadd_with_ttl(&full_key, longitude, latitude, &input.value, sig_meta.clone(), ttl_seconds)
-                    .await
-                    .map_err(DbError::from)?;
-            }
-            _ => {
-                return Err(DbError::InvalidData(format!(
-                    "Unknown store type: {}",
-                    input.store_type
-                )));
+        // A client whose whole batch was over budget is told when to retry
+        if replicate.is_empty() {
+            if let Some(wait) = retry_after {
+                ctx.insert_http_header(axum::http::header::RETRY_AFTER, crate::rate_limit::retry_after_header(wait));
             }
         }
 
-        // Create SignedOperation for the sync system
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as i64;
-
-        let signed_operation = crate::sync::SignedOperation {
-            op_id: uuid::Uuid::new_v4().to_string(),
-            timestamp,
-            db_name: input.db_name.clone(),
-            key: input.key.clone(),
-            value: input.value.clone(),
-            store_type: input.store_type.clone(),
-            field: field_clone,
-            score: score_clone,
-            json_path: json_path_clone,
-            stream_fields: stream_fields_clone,
-            ts_timestamp: ts_timestamp_clone,
-            longitude: longitude_clone,
-            latitude: latitude_clone,
-            public_key: input.public_key.clone(),
-            signature: input.signature.clone(),
-        };
-
-        // Add operation to SyncManager (stores in blob storage)
-        if let Ok(sync_manager) = ctx.data::<SyncManager>() {
-            match sync_manager
-                .sync_store()
-                .add_operation(signed_operation.clone())
-                .await
-            {
-                Ok(added) => {
-                    if added {
-                        tracing::debug!(
-                            "Operation added to blob storage: {}",
-                            signed_operation.op_id
-                        );
+        // Replicate the written operations in one sync message, sending a
+        // root signature once rather than with every operation
+        if !replicate.is_empty() {
+            if let Ok(sync_out_tx) = ctx.data::<tokio::sync::mpsc::UnboundedSender<crate::sync::SyncMessage>>() {
+                tracing::info!("GraphQL: sending outbound batch sync message of {} operations", replicate.len());
+                let message = if input.root_signature.is_some() {
+                    crate::batch::MerkleBatch::pack(input.operations.len(), replicate)
+                        .map(|batch| crate::sync::SyncMessage::MerkleBatch { batch })
+                } else {
+                    Ok(crate::sync::SyncMessage::Batch {
+                        operations: replicate.into_iter().map(|(_, op)| op).collect(),
+                    })
+                };
+                match message {
+                    Ok(message) => {
+                        if sync_out_tx.send(message).is_err() {
+                            tracing::warn!("GraphQL: failed to send outbound sync message (receiver gone)");
+                        }
                     }
+                    Err(e) => tracing::warn!("GraphQL: failed to pack batch sync message: {}", e),
                 }
-                Err(e) => {
-                    tracing::warn!("Failed to add operation to blob storage: {}", e);
-                    // Continue - data is still in Redis
-                }
-            }
-        }
-
-        // If an outbound sync sender is available in the GraphQL context, broadcast the operation
-        if let Ok(sync_out_tx) = ctx.data::<tokio::sync::mpsc::UnboundedSender<crate::sync::SyncMessage>>() {
-            tracing::info!("GraphQL: sending outbound SyncMessage::Operation: {}", signed_operation.op_id);
-            let res = sync_out_tx.send(crate::sync::SyncMessage::Operation { operation: signed_operation.clone() });
-            if res.is_err() {
-                tracing::warn!("GraphQL: failed to send outbound sync message (receiver gone)");
             }
         }
 
-        // Broadcast message event to subscribers if broadcast channel is available
-        if let Ok(broadcast_tx) = ctx.data::<broadcast::Sender<MessageEvent>>() {
-            let event = MessageEvent {
-                topic: format!("graphql/{}/{}", input.db_name, input.key),
-                payload: input.value.as_bytes().to_vec(),
-                timestamp,
-            };
-
-            // Ignore send errors (no active subscribers)
-            let _ = broadcast_tx.send(event);
+        let accepted = results.iter().filter(|result| result.success).count() as i32;
+        let rejected = results.len() as i32 - accepted;
+        if rejected > 0 {
+            metrics::GRAPHQL_ERRORS.with_label_values(&["submit_batch"]).inc();
         }
-
-        // Track successful completion
-        let duration = timer.elapsed().as_secs_f64();
-        metrics::GRAPHQL_LATENCY.with_label_values(&["submit_data"]).observe(duration);
-
-        Ok(StorageResult {
-            success: true,
-            message: format!(
-                "Data stored successfully in db: {}, key: {}",
-                input.db_name, input.key
-            ),
+        metrics::GRAPHQL_LATENCY
+            .with_label_values(&["submit_batch"])
+            .observe(timer.elapsed().as_secs_f64());
+
+        Ok(BatchResult {
+            success: rejected == 0,
+            accepted,
+            rejected,
+            results,
         })
     }
 
@@ -2758,6 +4030,7 @@ impl MutationRoot {
 
     /// Pin a CID in IPFS
     /// Note: Iroh doesn't have traditional "pinning" - all added content is persistent by default
+    #[graphql(guard = "AdminGuard")]
     async fn pin_ipfs(&self, ctx: &Context<'_>, cid: String) -> Result<IpfsResult, DbError> {
         let _ipfs = ctx
             .data::<IpfsStorage>()
@@ -2776,6 +4049,7 @@ impl MutationRoot {
 
     /// Unpin a CID in IPFS
     /// Note: Iroh doesn't have traditional "unpinning" - garbage collection is handled differently
+    #[graphql(guard = "AdminGuard")]
     async fn unpin_ipfs(&self, ctx: &Context<'_>, cid: String) -> Result<IpfsResult, DbError> {
         let _ipfs = ctx
             .data::<IpfsStorage>()
@@ -2875,6 +4149,7 @@ impl MutationRoot {
     // ============ IoT Mutations ============
 
     /// Publish message to IoT devices via MQTT
+    #[graphql(guard = "AdminGuard")]
     async fn publish_iot_message(
         &self,
         ctx: &Context<'_>,
@@ -2943,7 +4218,48 @@ impl MutationRoot {
         })
     }
 
+    /// Check storage consistency (admin)
+    ///
+    /// Scans the storage index and op log for missing/corrupt values and bad
+    /// signatures. With `repair`, rebuilds broken keys from the op log and
+    /// re-requests anything else from peers.
+    #[graphql(guard = "AdminGuard")]
+    async fn fsck(
+        &self,
+        ctx: &Context<'_>,
+        repair: Option<bool>,
+        db_name: Option<String>,
+    ) -> Result<crate::fsck::FsckReport, DbError> {
+        use crate::metrics;
+
+        metrics::GRAPHQL_REQUESTS.with_label_values(&["fsck"]).inc();
+
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        let mut fsck = crate::fsck::Fsck::new(storage);
+        if let Ok(sync_manager) = ctx.data::<SyncManager>() {
+            fsck = fsck.with_sync_manager(sync_manager);
+        }
+        if let Ok(sync_out_tx) =
+            ctx.data::<tokio::sync::mpsc::UnboundedSender<crate::sync::SyncMessage>>()
+        {
+            fsck = fsck.with_sync_outbound(sync_out_tx);
+        }
+
+        let options = crate::fsck::FsckOptions {
+            repair: repair.unwrap_or(false),
+            db_name,
+        };
+        fsck.run(&options).await.map_err(|e| {
+            metrics::GRAPHQL_ERRORS.with_label_values(&["fsck"]).inc();
+            DbError::InternalError(format!("fsck failed: {}", e))
+        })
+    }
+
     /// Request sync from connected peers
+    #[graphql(guard = "AdminGuard")]
     async fn request_sync(
         &self,
         ctx: &Context<'_>,
@@ -3000,6 +4316,7 @@ impl MutationRoot {
     /// 
     /// Note: This establishes a direct connection using the gossip ALPN protocol.
     /// Peers already connected via gossip network don't need manual dialing.
+    #[graphql(guard = "AdminGuard")]
     async fn dial_peer(
         &self,
         ctx: &Context<'_>,
@@ -3096,6 +4413,7 @@ impl MutationRoot {
     ///
     /// The job will be broadcast to the network and picked up by a capable node.
     /// Returns the job ID which can be used to track status.
+    #[graphql(guard = "AdminGuard")]
     async fn submit_inference_job(
         &self,
         ctx: &Context<'_>,
@@ -3169,6 +4487,7 @@ impl MutationRoot {
     /// Cancel a pending inference job
     ///
     /// Broadcasts a cancellation message to prevent nodes from picking up the job.
+    #[graphql(guard = "AdminGuard")]
     async fn cancel_inference_job(
         &self,
         ctx: &Context<'_>,
@@ -3252,6 +4571,109 @@ pub struct SubscriptionRoot;
 
 #[Subscription]
 impl SubscriptionRoot {
+    /// Changes to one key, as they are applied locally or by sync
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
+    async fn watch_key(
+        &self,
+        ctx: &Context<'_>,
+        db_name: String,
+        key: String,
+    ) -> Result<impl Stream<Item = ChangeNotification>, DbError> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        let filter = crate::changes::ChangeFilter {
+            key: Some(key),
+            ..crate::changes::ChangeFilter::database(&db_name)
+        };
+        Ok(storage.changes().watch(filter).map(ChangeNotification::from))
+    }
+
+    /// Changes to a database, optionally only of one store type
+    #[graphql(guard = "ReadGuard::new(&db_name)")]
+    async fn watch_database(
+        &self,
+        ctx: &Context<'_>,
+        db_name: String,
+        store_type: Option<String>,
+    ) -> Result<impl Stream<Item = ChangeNotification>, DbError> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        let filter = crate::changes::ChangeFilter {
+            op_types: store_type.into_iter().map(|t| t.to_lowercase()).collect(),
+            ..crate::changes::ChangeFilter::database(&db_name)
+        };
+        Ok(storage.changes().watch(filter).map(ChangeNotification::from))
+    }
+
+    /// Changes to a database matching a filter
+    #[graphql(guard = "ReadGuard::new(&filter.db_name)")]
+    async fn watch_query(
+        &self,
+        ctx: &Context<'_>,
+        filter: WatchFilter,
+    ) -> Result<impl Stream<Item = ChangeNotification>, DbError> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        let value_filter = match filter.value_filter {
+            Some(doc) => {
+                ensure_plaintext(storage, &filter.db_name)?;
+                Some(crate::filters::FilterExpr::parse(&doc.0).map_err(|e| DbError::InvalidData(e.to_string()))?)
+            }
+            None => None,
+        };
+        let filter = crate::changes::ChangeFilter {
+            db_name: filter.db_name,
+            key: None,
+            key_prefix: filter.key_prefix,
+            op_types: filter
+                .store_types
+                .unwrap_or_default()
+                .iter()
+                .map(|t| t.to_lowercase())
+                .collect(),
+            signer: filter.signer,
+            value_filter,
+        };
+        Ok(storage.changes().watch(filter).map(ChangeNotification::from))
+    }
+
+    /// Stream a multi-series time series query window by window (default
+    /// windows of one hour, rounded up to whole buckets)
+    #[graphql(guard = "ReadGuard::new(&input.db_name)")]
+    async fn timeseries_mrange<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        input: TimeSeriesMRangeInput,
+        chunk_size: Option<i64>,
+    ) -> Result<impl Stream<Item = Result<TimeSeriesMRangeChunk, DbError>> + 'ctx, DbError> {
+        let storage = ctx
+            .data::<RedisStorage>()
+            .map_err(|_| DbError::StaticError(STORAGE_NOT_FOUND))?;
+
+        let (selector, options) = input.to_query()?;
+        let chunks = crate::filters::TimeSeriesFilter::new(storage).mrange_stream(
+            input.db_name,
+            selector,
+            options,
+            chunk_size.unwrap_or(3_600_000),
+        );
+        Ok(chunks.map(|chunk| {
+            chunk
+                .map(|chunk| TimeSeriesMRangeChunk {
+                    from_timestamp: chunk.from_timestamp.to_string(),
+                    to_timestamp: chunk.to_timestamp.to_string(),
+                    series: chunk.series.into_iter().map(SeriesRangeGql::from).collect(),
+                })
+                .map_err(DbError::from)
+        }))
+    }
+
     /// Subscribe to messages on a specific topic (supports wildcards)
     async fn subscribe_topic<'ctx>(
         &self,
@@ -3262,13 +4684,17 @@ impl SubscriptionRoot {
             .data::<broadcast::Sender<MessageEvent>>()
             .map_err(|_| DbError::InternalError("Message broadcast channel not found".to_string()))?
             .subscribe();
+        let storage = ctx.data_opt::<RedisStorage>().cloned();
+        let credential = ctx.data_opt::<ReadCredential>().cloned().unwrap_or_default();
 
         Ok(
             tokio_stream::wrappers::BroadcastStream::new(rx).filter_map(move |result| {
                 let topic_filter = topic_filter.clone();
                 match result {
                     Ok(event) => {
-                        if topic_matches(&event.topic, &topic_filter) {
+                        if topic_matches(&event.topic, &topic_filter)
+                            && can_read_topic(storage.as_ref(), &credential, &event.topic)
+                        {
                             Some(MessageUpdate {
                                 topic: event.topic,
                                 payload: String::from_utf8_lossy(&event.payload).to_string(),
@@ -3293,15 +4719,17 @@ impl SubscriptionRoot {
             .data::<broadcast::Sender<MessageEvent>>()
             .map_err(|_| DbError::InternalError("Message broadcast channel not found".to_string()))?
             .subscribe();
+        let storage = ctx.data_opt::<RedisStorage>().cloned();
+        let credential = ctx.data_opt::<ReadCredential>().cloned().unwrap_or_default();
 
         Ok(
-            tokio_stream::wrappers::BroadcastStream::new(rx).filter_map(|result| match result {
-                Ok(event) => Some(MessageUpdate {
+            tokio_stream::wrappers::BroadcastStream::new(rx).filter_map(move |result| match result {
+                Ok(event) if can_read_topic(storage.as_ref(), &credential, &event.topic) => Some(MessageUpdate {
                     topic: event.topic,
                     payload: String::from_utf8_lossy(&event.payload).to_string(),
                     timestamp: event.timestamp.to_string(),
                 }),
-                Err(_) => None,
+                _ => None,
             }),
         )
     }
@@ -3388,7 +4816,34 @@ fn topic_matches(topic: &str, filter: &str) -> bool {
     topic_idx == topic_parts.len() && filter_idx == filter_parts.len()
 }
 
-pub type ApiSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+/// Root query: core data API plus secondary index queries
+#[derive(MergedObject, Default)]
+pub struct Query(QueryRoot, IndexQuery);
+
+/// Root mutation: core data API plus secondary index management
+#[derive(MergedObject, Default)]
+pub struct Mutation(MutationRoot, IndexMutation);
+
+pub type ApiSchema = Schema<Query, Mutation, SubscriptionRoot>;
+
+/// Origins allowed by CORS. `*` allows any origin; an empty allowlist sends
+/// no CORS headers, leaving browsers to same-origin requests
+fn cors_origins(allowlist: &[String]) -> tower_http::cors::AllowOrigin {
+    if allowlist.iter().any(|origin| origin == "*") {
+        return Any.into();
+    }
+    let origins: Vec<axum::http::HeaderValue> = allowlist
+        .iter()
+        .filter_map(|origin| match origin.parse() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                tracing::warn!("Ignoring invalid CORS origin '{}'", origin);
+                None
+            }
+        })
+        .collect();
+    origins.into()
+}
 
 pub async fn create_server(
     storage: RedisStorage,
@@ -3408,6 +4863,7 @@ pub async fn create_server(
     message_broadcast: Option<broadcast::Sender<MessageEvent>>,
     sync_outbound: Option<tokio::sync::mpsc::UnboundedSender<crate::sync::SyncMessage>>,
     inference_scheduler: Option<Arc<crate::inference::InferenceScheduler>>,
+    api_config: crate::config::ApiConfig,
 ) -> Result<Router> {
     // Initialize start time when server is created (not on first request)
     let _ = get_start_time();
@@ -3418,12 +4874,23 @@ pub async fn create_server(
         tx
     });
 
-    let mut schema_builder = Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+    // Secondary indexes are owned (and kept current) by storage
+    let index_manager = storage.index_manager().clone();
+
+    // Shared by the per-IP layer and the per-key check in submitData
+    let rate_limiter = RateLimiter::new(api_config.rate_limit);
+
+    let mut schema_builder = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
         .enable_federation() // Enable GraphQL Federation
         .enable_subscription_in_federation() // Enable subscriptions in federation
         .data(storage)
+        .data(index_manager)
         .data(ipfs.clone()) // Clone ipfs so we can use it for AppState later
-        .data(broadcast_tx.clone());
+        .data(broadcast_tx.clone())
+        .data(AdminAuth::new(&api_config.admin_api_tokens, &api_config.admin_public_keys))
+        .data(rate_limiter.clone())
+        .limit_depth(api_config.rate_limit.max_query_depth)
+        .limit_complexity(api_config.rate_limit.max_query_complexity);
 
     // Add SyncManager if available
     if let Some(sync_mgr) = sync_manager {
@@ -3485,7 +4952,7 @@ pub async fn create_server(
 
     // Configure CORS to allow requests from the frontend
     let cors = CorsLayer::new()
-        .allow_origin(Any)
+        .allow_origin(cors_origins(&api_config.cors_allowed_origins))
         .allow_methods(Any)
         .allow_headers(Any)
         .expose_headers(Any);
@@ -3509,6 +4976,7 @@ pub async fn create_server(
         .route("/blobs/upload", post(blob_upload_handler))
         .route("/blobs/upload/", post(blob_upload_handler))
         .route("/blobs/{hash}", get(blob_download_handler))
+        .layer(axum::middleware::from_fn_with_state(rate_limiter, crate::rate_limit::rate_limit_layer))
         .layer(cors)
         .layer(TraceLayer::new_for_http())  // Add request tracing
         .with_state(app_state);
@@ -3528,20 +4996,63 @@ async fn readiness_handler() -> impl IntoResponse {
     (axum::http::StatusCode::OK, "READY")
 }
 
+/// Start of the message of a `DbError::RateLimitError`
+const RATE_LIMITED_MESSAGE: &str = "Rate limit exceeded";
+
+/// HTTP response for an executed GraphQL request. A request refused by a
+/// per-key rate limit gets 429 Too Many Requests, like one refused by the
+/// per-IP layer.
+pub fn graphql_http_response(response: async_graphql::Response) -> axum::response::Response {
+    let rate_limited = response.http_headers.contains_key(axum::http::header::RETRY_AFTER)
+        || response
+            .errors
+            .iter()
+            .any(|error| error.message.starts_with(RATE_LIMITED_MESSAGE));
+    let mut response = GraphQLResponse::from(response).into_response();
+    if rate_limited {
+        *response.status_mut() = axum::http::StatusCode::TOO_MANY_REQUESTS;
+    }
+    response
+}
+
+/// Read token from an `Authorization: Bearer` header (see `read_access.rs`)
+fn read_credential(headers: &axum::http::HeaderMap) -> ReadCredential {
+    ReadCredential::from_authorization(
+        headers
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok()),
+    )
+}
+
 async fn graphql_handler(
     axum::extract::State(state): axum::extract::State<AppState>,
+    headers: axum::http::HeaderMap,
     req: GraphQLRequest,
 ) -> impl IntoResponse {
     tracing::debug!("GraphQL request received");
+    let request = req
+        .into_inner()
+        .data(read_credential(&headers))
+        .data(AdminCredential::from_header(
+            headers.get(ADMIN_TOKEN_HEADER).and_then(|value| value.to_str().ok()),
+        ));
     
     // Add timeout to prevent hanging forever on blocked queries
     match tokio::time::timeout(
         std::time::Duration::from_secs(30),
-        state.schema.execute(req.into_inner())
+        state.schema.execute(request)
     ).await {
         Ok(response) => {
             tracing::debug!("GraphQL request completed");
-            GraphQLResponse::from(response)
+            for error in &response.errors {
+                let limit = match error.message.as_str() {
+                    "Query is nested too deep." => "depth",
+                    "Query is too complex." => "complexity",
+                    _ => continue,
+                };
+                crate::metrics::GRAPHQL_QUERIES_REJECTED.with_label_values(&[limit]).inc();
+            }
+            graphql_http_response(response)
         },
         Err(_) => {
             tracing::error!("GraphQL query timed out after 30 seconds");
@@ -3549,18 +5060,36 @@ async fn graphql_handler(
             let error_response = async_graphql::Response::from_errors(vec![
                 async_graphql::ServerError::new("Query timed out after 30 seconds", None)
             ]);
-            GraphQLResponse::from(error_response)
+            GraphQLResponse::from(error_response).into_response()
         }
     }
 }
 
 async fn graphql_subscription_handler(
     axum::extract::State(state): axum::extract::State<AppState>,
+    headers: axum::http::HeaderMap,
     protocol: GraphQLProtocol,
     ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
-    ws.protocols(ALL_WEBSOCKET_PROTOCOLS)
-        .on_upgrade(move |socket| GraphQLWebSocket::new(socket, state.schema.clone(), protocol).serve())
+    // Browsers can't set headers on websockets, so the connection_init
+    // payload may carry the token instead
+    let upgrade_credential = read_credential(&headers);
+    ws.protocols(ALL_WEBSOCKET_PROTOCOLS).on_upgrade(move |socket| {
+        GraphQLWebSocket::new(socket, state.schema.clone(), protocol)
+            .on_connection_init(move |payload| async move {
+                let authorization = ["authorization", "Authorization"]
+                    .iter()
+                    .find_map(|name| payload.get(name).and_then(|value| value.as_str()));
+                let credential = match authorization {
+                    Some(value) => ReadCredential::from_authorization(Some(value)),
+                    None => upgrade_credential,
+                };
+                let mut data = async_graphql::Data::default();
+                data.insert(credential);
+                Ok(data)
+            })
+            .serve()
+    })
 }
 
 async fn graphql_playground() -> impl axum::response::IntoResponse {
@@ -1,7 +1,11 @@
 //! GraphQL schema extensions for secondary indexing
+//!
+//! Merged into the root `Query`/`Mutation` in `graphql.rs`.
 
-use async_graphql::{Context, Object, Result, SimpleObject};
-use crate::indexing::{IndexManager, IndexType, QueryOperator, IndexStats};
+use async_graphql::{Context, InputObject, Object, Result, SimpleObject};
+use crate::filters::{FilterCondition, FilterExpr, FilterOptions, JsonFilter, JsonFilterConditions, SortOrder};
+use crate::indexing::{IndexManager, IndexType, QueryOperator};
+use crate::storage::RedisStorage;
 
 /// Input for creating a new index
 #[derive(Debug, Clone)]
@@ -9,7 +13,7 @@ pub struct CreateIndexInput {
     pub db_name: String,
     pub index_name: String,
     pub field: String,
-    pub index_type: String, // "exact", "range", "fulltext", "geo"
+    pub index_type: String, // "exact", "range", "fulltext", "geo", "compound"
 }
 
 #[Object]
@@ -76,6 +80,85 @@ pub struct IndexStatsResult {
     pub unique_values: i32,
 }
 
+/// Ranked full-text search hit for GraphQL
+#[derive(SimpleObject)]
+pub struct SearchHitResult {
+    pub key: String,
+    pub score: f64,
+    /// Matching snippets with terms wrapped in `<em>`
+    pub highlights: Vec<String>,
+}
+
+/// One condition of a document filter
+#[derive(InputObject)]
+pub struct FilterConditionInput {
+    /// Dotted field path
+    pub field: String,
+    /// "eq", "ne", "gt", "gte", "lt", "lte", "contains" or "in"
+    pub op: String,
+    pub value: async_graphql::Json<serde_json::Value>,
+}
+
+/// Document filter for `filterDocuments`
+#[derive(InputObject)]
+pub struct FilterDocumentsInput {
+    pub db_name: String,
+    /// Conditions every document must meet
+    pub conditions: Option<Vec<FilterConditionInput>>,
+    /// MongoDB-style filter document (`$and`, `$or`, `$elemMatch`, ...),
+    /// AND-ed with `conditions`
+    pub filter: Option<async_graphql::Json<serde_json::Value>>,
+    /// Dotted field path to sort by
+    pub sort_by: Option<String>,
+    pub sort_desc: Option<bool>,
+    pub limit: Option<i32>,
+    pub offset: Option<i32>,
+    /// Return only the query plan
+    pub explain: Option<bool>,
+}
+
+/// Documents matching a filter, or only its plan when `explain` is set
+#[derive(SimpleObject)]
+pub struct FilterDocumentsResult {
+    pub documents: async_graphql::Json<Vec<serde_json::Value>>,
+    pub count: i32,
+    /// Whether indexes narrowed the keys instead of a full scan
+    pub index_used: bool,
+    pub plan: Vec<String>,
+}
+
+/// Convert condition inputs to JsonFilterConditions
+fn parse_conditions(inputs: Vec<FilterConditionInput>) -> Result<JsonFilterConditions> {
+    let mut conditions = JsonFilterConditions::new();
+    for input in inputs {
+        let value = input.value.0;
+        let condition = match input.op.to_lowercase().as_str() {
+            "eq" => FilterCondition::Eq(value),
+            "ne" => FilterCondition::Ne(value),
+            "gt" => FilterCondition::Gt(value),
+            "gte" => FilterCondition::Gte(value),
+            "lt" => FilterCondition::Lt(value),
+            "lte" => FilterCondition::Lte(value),
+            "contains" => match value {
+                serde_json::Value::String(text) => FilterCondition::Contains(text),
+                _ => return Err(async_graphql::Error::new("'contains' needs a string value")),
+            },
+            "in" => match value {
+                serde_json::Value::Array(values) => FilterCondition::In(values),
+                _ => return Err(async_graphql::Error::new("'in' needs an array value")),
+            },
+            other => {
+                return Err(async_graphql::Error::new(format!(
+                    "Invalid operator: {}. Use 'eq', 'ne', 'gt', 'gte', 'lt', 'lte', 'contains' or 'in'",
+                    other
+                )))
+            }
+        };
+        conditions.add_condition(input.field, condition);
+    }
+    Ok(conditions)
+}
+
 /// Convert string to IndexType
 fn parse_index_type(type_str: &str) -> Result<IndexType> {
     match type_str.to_lowercase().as_str() {
@@ -83,8 +166,9 @@ fn parse_index_type(type_str: &str) -> Result<IndexType> {
         "range" => Ok(IndexType::Range),
         "fulltext" | "full_text" => Ok(IndexType::FullText),
         "geo" => Ok(IndexType::Geo),
+        "compound" => Ok(IndexType::Compound),
         _ => Err(async_graphql::Error::new(format!(
-            "Invalid index type: {}. Use 'exact', 'range', 'fulltext', or 'geo'",
+            "Invalid index type: {}. Use 'exact', 'range', 'fulltext', 'geo' or 'compound'",
             type_str
         ))),
     }
@@ -152,6 +236,7 @@ pub struct IndexQuery;
 #[Object]
 impl IndexQuery {
     /// Query an index
+    #[graphql(guard = "crate::graphql::ReadGuard::new(&db_name)")]
     async fn query_index(
         &self,
         ctx: &Context<'_>,
@@ -164,6 +249,7 @@ impl IndexQuery {
         max: Option<f64>,
     ) -> Result<IndexQueryResult> {
         let index_manager = ctx.data::<IndexManager>()?;
+        ctx.data::<RedisStorage>()?.encryption().check_value_query(&db_name)?;
         
         let input = QueryIndexInput {
             db_name: db_name.clone(),
@@ -185,13 +271,75 @@ impl IndexQuery {
         })
     }
 
+    /// Full-text search across the database's full-text indexes.
+    /// Quoted text matches a phrase and a trailing `*` matches a word prefix.
+    #[graphql(guard = "crate::graphql::ReadGuard::new(&db_name)")]
+    async fn search(
+        &self,
+        ctx: &Context<'_>,
+        db_name: String,
+        query: String,
+        limit: Option<i32>,
+    ) -> Result<Vec<SearchHitResult>> {
+        let index_manager = ctx.data::<IndexManager>()?;
+        ctx.data::<RedisStorage>()?.encryption().check_value_query(&db_name)?;
+        let limit = limit.unwrap_or(20).clamp(1, 1000) as usize;
+        let hits = index_manager.search(&db_name, &query, limit).await?;
+
+        Ok(hits
+            .into_iter()
+            .map(|hit| SearchHitResult {
+                key: hit.key,
+                score: hit.score,
+                highlights: hit.highlights,
+            })
+            .collect())
+    }
+
+    /// Filter JSON documents of a database, using indexes where possible.
+    /// With `explain: true` only the query plan is returned.
+    #[graphql(guard = "crate::graphql::ReadGuard::new(&input.db_name)")]
+    async fn filter_documents(&self, ctx: &Context<'_>, input: FilterDocumentsInput) -> Result<FilterDocumentsResult> {
+        let storage = ctx.data::<RedisStorage>()?;
+        storage.encryption().check_value_query(&input.db_name)?;
+        let mut conditions = parse_conditions(input.conditions.unwrap_or_default())?;
+        if let Some(filter) = input.filter {
+            let expr = FilterExpr::parse(&filter.0).map_err(|e| async_graphql::Error::new(e.to_string()))?;
+            conditions.set_filter(expr);
+        }
+        let options = FilterOptions {
+            limit: input.limit.map(|l| l.max(0) as usize),
+            offset: input.offset.map(|o| o.max(0) as usize),
+            sort_by: input.sort_by,
+            sort_order: if input.sort_desc.unwrap_or(false) { SortOrder::Desc } else { SortOrder::Asc },
+        };
+
+        let pattern = format!("{}:*", input.db_name);
+        let filter = JsonFilter::new(storage);
+        let plan = filter.explain(&pattern, &conditions, &options).await;
+        let documents = if input.explain.unwrap_or(false) {
+            Vec::new()
+        } else {
+            filter.execute(&pattern, &plan, &conditions, &options).await?
+        };
+
+        Ok(FilterDocumentsResult {
+            count: documents.len() as i32,
+            documents: async_graphql::Json(documents),
+            index_used: !plan.is_full_scan(),
+            plan: plan.explain(&pattern, &options),
+        })
+    }
+
     /// List all indexes in a database
+    #[graphql(guard = "crate::graphql::ReadGuard::new(&db_name)")]
     async fn list_indexes(&self, ctx: &Context<'_>, db_name: String) -> Result<Vec<String>> {
         let index_manager = ctx.data::<IndexManager>()?;
         Ok(index_manager.list_indexes(&db_name).await)
     }
 
     /// Get index statistics
+    #[graphql(guard = "crate::graphql::ReadGuard::new(&db_name)")]
     async fn get_index_stats(
         &self,
         ctx: &Context<'_>,
@@ -206,6 +354,7 @@ impl IndexQuery {
             IndexType::Range => "range",
             IndexType::FullText => "fulltext",
             IndexType::Geo => "geo",
+            IndexType::Compound => "compound",
         };
         
         Ok(IndexStatsResult {
@@ -224,7 +373,12 @@ pub struct IndexMutation;
 
 #[Object]
 impl IndexMutation {
-    /// Create a new index
+    /// Create a new index and backfill it from existing JSON/Hash keys.
+    /// Only the database owner may call it (see `OwnerGuard`).
+    #[graphql(
+        guard = "crate::graphql::OwnerGuard::new(&db_name)",
+        complexity = "crate::rate_limit::SCAN_COST + child_complexity"
+    )]
     async fn create_index(
         &self,
         ctx: &Context<'_>,
@@ -233,17 +387,18 @@ impl IndexMutation {
         field: String,
         index_type: String,
     ) -> Result<bool> {
-        let index_manager = ctx.data::<IndexManager>()?;
+        let storage = ctx.data::<RedisStorage>()?;
         let idx_type = parse_index_type(&index_type)?;
         
-        index_manager
-            .create_index(db_name, index_name, field, idx_type)
+        storage
+            .create_index(&db_name, &index_name, &field, idx_type)
             .await?;
         
         Ok(true)
     }
 
-    /// Drop an index
+    /// Drop an index. Only the database owner may call it.
+    #[graphql(guard = "crate::graphql::OwnerGuard::new(&db_name)")]
     async fn drop_index(
         &self,
         ctx: &Context<'_>,
@@ -1,13 +1,58 @@
 //! Secondary indexing system for Redis-style data types
 //! Provides MongoDB-like query capabilities on top of the existing storage layer
+//!
+//! `BlobStorage` owns an `IndexManager` and keeps it current: every JSON or Hash
+//! write, delete and expiry calls `index_document` with the old and new document,
+//! and `BlobStorage::create_index` backfills existing keys. Indexed keys are the
+//! user keys within a database (without the `<db_name>:` prefix).
+//!
+//! ## Persistence
+//! Definitions and entries live in the storage backend's `index_definitions` and
+//! `secondary_index` keyspaces (dedicated sled trees on the default backend), so
+//! indexes survive restarts without a rebuild. Each entry is a key with an empty
+//! value:
+//!
+//! `<db_name>\0<index_name>\0<encoded value>\0<key>`
+//!
+//! Values are encoded so that byte order matches value order: numbers in `Range`
+//! indexes become `n` + 16 hex digits of an order-preserving `f64` encoding, and
+//! everything else is `s` + the raw text. `Equals`/`In` are prefix scans and
+//! `GreaterThan`/`LessThan`/`Between` are range scans over the numeric entries.
+//!
+//! `Compound` indexes cover several fields (`field` is a comma-separated list,
+//! e.g. `status,createdAt`). Their encoded value is one component per field,
+//! each encoded like a `Range` value and joined with `\x01`; a missing field is
+//! `-`. A compound scan fixes equality values for the leading fields and may
+//! bound the next one, returning keys in that field's order. `query_planner.rs`
+//! chooses between compound and single-field indexes for JSON filters.
+//!
+//! `FullText` indexes keep an inverted index under the same prefix instead (see
+//! `fulltext.rs`) and answer `IndexManager::search`.
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-/// Secondary index for fast lookups by field values
+use crate::fulltext::{self, SearchHit};
+use crate::storage_backend::{IndexStore, MemoryBackend, StorageBackend};
+
+/// Separator between the parts of an entry key
+const SEP: char = '\0';
+/// Encoded value tag for numbers (sorts numerically)
+const NUMBER_TAG: char = 'n';
+/// Encoded value tag for text
+const TEXT_TAG: char = 's';
+/// Encoded component for a field missing from a compound entry
+const MISSING_TAG: char = '-';
+/// Separator between the components of a compound value
+pub const COMPONENT_SEP: char = '\u{1}';
+
+/// Secondary index definition and entry counters.
+///
+/// Entries are kept in an `IndexStore`; the counters are recomputed from it
+/// when indexes are loaded.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecondaryIndex {
     /// Index name
@@ -18,12 +63,19 @@ pub struct SecondaryIndex {
     pub field: String,
     /// Index type
     pub index_type: IndexType,
-    /// Index data: field_value -> set of keys
-    pub data: HashMap<String, HashSet<String>>,
     /// Maximum number of keys to index (0 = unlimited)
     pub max_keys: usize,
-    /// Memory limit in bytes (0 = unlimited)
+    /// Storage limit for entries in bytes (0 = unlimited)
     pub memory_limit_bytes: usize,
+    /// Number of (value, key) entries
+    #[serde(skip)]
+    entry_count: usize,
+    /// Total size of entry keys in bytes
+    #[serde(skip)]
+    entry_bytes: usize,
+    /// Total tokens across documents (full-text indexes only)
+    #[serde(skip)]
+    total_tokens: usize,
 }
 
 /// Types of indexes supported
@@ -37,6 +89,8 @@ pub enum IndexType {
     FullText,
     /// Geospatial index (for location-based queries)
     Geo,
+    /// Multi-field index over a comma-separated field list
+    Compound,
 }
 
 /// Query operators for indexed lookups
@@ -58,6 +112,43 @@ pub enum QueryOperator {
     StartsWith(String),
 }
 
+/// Bounds on a numeric field; each bound is `(value, inclusive)`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct NumberBounds {
+    pub min: Option<(f64, bool)>,
+    pub max: Option<(f64, bool)>,
+}
+
+impl NumberBounds {
+    pub fn is_unbounded(&self) -> bool {
+        self.min.is_none() && self.max.is_none()
+    }
+
+    /// Tighten the lower bound
+    pub fn with_min(mut self, value: f64, inclusive: bool) -> Self {
+        let tighter = match self.min {
+            Some((current, current_inclusive)) => value > current || (value == current && !inclusive && current_inclusive),
+            None => true,
+        };
+        if tighter {
+            self.min = Some((value, inclusive));
+        }
+        self
+    }
+
+    /// Tighten the upper bound
+    pub fn with_max(mut self, value: f64, inclusive: bool) -> Self {
+        let tighter = match self.max {
+            Some((current, current_inclusive)) => value < current || (value == current && !inclusive && current_inclusive),
+            None => true,
+        };
+        if tighter {
+            self.max = Some((value, inclusive));
+        }
+        self
+    }
+}
+
 /// Index query result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryResult {
@@ -77,12 +168,14 @@ impl SecondaryIndex {
             db_name,
             field,
             index_type,
-            data: HashMap::new(),
             max_keys: 0,  // unlimited by default
             memory_limit_bytes: 0,  // unlimited by default
+            entry_count: 0,
+            entry_bytes: 0,
+            total_tokens: 0,
         }
     }
-    
+
     /// Create a new index with limits
     pub fn new_with_limits(
         name: String,
@@ -93,49 +186,79 @@ impl SecondaryIndex {
         memory_limit_mb: usize,
     ) -> Self {
         Self {
-            name,
-            db_name,
-            field,
-            index_type,
-            data: HashMap::new(),
             max_keys,
             memory_limit_bytes: memory_limit_mb * 1024 * 1024,
+            ..Self::new(name, db_name, field, index_type)
         }
     }
 
-    /// Estimate current memory usage in bytes
-    pub fn memory_usage_bytes(&self) -> usize {
-        let mut total = std::mem::size_of::<Self>();
-        
-        for (key, values) in &self.data {
-            // String overhead + capacity
-            total += std::mem::size_of::<String>() + key.capacity();
-            // HashSet overhead
-            total += std::mem::size_of::<HashSet<String>>();
-            
-            for value in values {
-                total += std::mem::size_of::<String>() + value.capacity();
-            }
+    /// Indexed fields; a single entry unless this is a compound index
+    pub fn fields(&self) -> Vec<&str> {
+        if self.index_type == IndexType::Compound {
+            self.field.split(',').map(str::trim).collect()
+        } else {
+            vec![self.field.as_str()]
         }
-        
-        total
     }
-    
+
+    /// Key prefix shared by all entries of this index
+    fn prefix(&self) -> String {
+        index_prefix(&self.db_name, &self.name)
+    }
+
+    /// Approximate size of this index's entries in bytes
+    pub fn memory_usage_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.entry_bytes
+    }
+
     /// Check if adding a key would exceed limits
     fn would_exceed_limits(&self) -> bool {
         if self.max_keys > 0 && self.total_keys() >= self.max_keys {
             return true;
         }
-        
+
         if self.memory_limit_bytes > 0 && self.memory_usage_bytes() >= self.memory_limit_bytes {
             return true;
         }
-        
+
         false
     }
 
+    /// Recompute the entry counters from the store
+    fn recount(&mut self, store: &dyn IndexStore) -> Result<()> {
+        if self.index_type == IndexType::FullText {
+            let stats = fulltext::stats(store, &self.prefix())?;
+            self.entry_count = stats.docs;
+            self.entry_bytes = stats.bytes;
+            self.total_tokens = stats.tokens;
+            return Ok(());
+        }
+        let entries = store.keys_with_prefix(&self.prefix())?;
+        self.entry_count = entries.len();
+        self.entry_bytes = entries.iter().map(|k| k.len()).sum();
+        Ok(())
+    }
+
     /// Add a key to the index with the given field value
-    pub fn insert(&mut self, field_value: String, key: String) -> Result<()> {
+    pub fn insert(&mut self, store: &dyn IndexStore, field_value: String, key: String) -> Result<()> {
+        if field_value.contains(SEP) {
+            anyhow::bail!("Index values may not contain NUL characters");
+        }
+        if self.index_type == IndexType::FullText {
+            let mut texts = fulltext::doc_texts(store, &self.prefix(), &key)?;
+            if texts.contains(&field_value) {
+                return Ok(());
+            }
+            if texts.is_empty() && self.would_exceed_limits() {
+                anyhow::bail!("Index '{}' would exceed limits", self.name);
+            }
+            texts.push(field_value);
+            return self.set_texts(store, &key, texts);
+        }
+        let entry = entry_key(&self.prefix(), &encode_value(&self.index_type, &field_value), &key);
+        if store.contains(&entry)? {
+            return Ok(());
+        }
         if self.would_exceed_limits() {
             anyhow::bail!(
                 "Index '{}' would exceed limits (max_keys: {}, memory: {} MB)",
@@ -144,65 +267,109 @@ impl SecondaryIndex {
                 self.memory_limit_bytes / 1024 / 1024
             );
         }
-        
-        self.data
-            .entry(field_value)
-            .or_insert_with(HashSet::new)
-            .insert(key);
-        
+
+        store.insert(&entry, Vec::new())?;
+        self.entry_count += 1;
+        self.entry_bytes += entry.len();
+
         Ok(())
     }
 
     /// Remove a key from the index
-    pub fn remove(&mut self, field_value: &str, key: &str) {
-        if let Some(keys) = self.data.get_mut(field_value) {
-            keys.remove(key);
-            if keys.is_empty() {
-                self.data.remove(field_value);
+    pub fn remove(&mut self, store: &dyn IndexStore, field_value: &str, key: &str) -> Result<()> {
+        if self.index_type == IndexType::FullText {
+            let mut texts = fulltext::doc_texts(store, &self.prefix(), key)?;
+            let before = texts.len();
+            texts.retain(|text| text != field_value);
+            if texts.len() != before {
+                self.set_texts(store, key, texts)?;
             }
+            return Ok(());
+        }
+        let entry = entry_key(&self.prefix(), &encode_value(&self.index_type, field_value), key);
+        if store.contains(&entry)? {
+            store.remove(&entry)?;
+            self.entry_count = self.entry_count.saturating_sub(1);
+            self.entry_bytes = self.entry_bytes.saturating_sub(entry.len());
         }
+        Ok(())
+    }
+
+    /// Re-index the texts of one full-text document and update the counters
+    fn set_texts(&mut self, store: &dyn IndexStore, key: &str, texts: Vec<String>) -> Result<()> {
+        let delta = fulltext::set_document(store, &self.prefix(), key, texts)?;
+        self.entry_count = self.entry_count.saturating_add_signed(delta.docs);
+        self.entry_bytes = self.entry_bytes.saturating_add_signed(delta.bytes);
+        self.total_tokens = self.total_tokens.saturating_add_signed(delta.tokens);
+        Ok(())
+    }
+
+    /// Ranked full-text search (full-text indexes only)
+    pub fn search(&self, store: &dyn IndexStore, clauses: &[fulltext::Clause]) -> Result<Vec<SearchHit>> {
+        if self.index_type != IndexType::FullText {
+            anyhow::bail!("Index '{}' is not a full-text index", self.name);
+        }
+        let stats = fulltext::FullTextStats {
+            docs: self.entry_count,
+            tokens: self.total_tokens,
+            bytes: self.entry_bytes,
+        };
+        fulltext::search(store, &self.prefix(), clauses, stats)
     }
 
     /// Update index when a field value changes
-    pub fn update(&mut self, old_value: &str, new_value: String, key: String) -> Result<()> {
-        self.remove(old_value, &key);
-        self.insert(new_value, key)
+    pub fn update(&mut self, store: &dyn IndexStore, old_value: &str, new_value: String, key: String) -> Result<()> {
+        self.remove(store, old_value, &key)?;
+        self.insert(store, new_value, key)
     }
 
     /// Query the index with the given operator
-    pub fn query(&self, operator: &QueryOperator) -> Vec<String> {
+    pub fn query(&self, store: &dyn IndexStore, operator: &QueryOperator) -> Result<Vec<String>> {
         let start = std::time::Instant::now();
-        
-        let keys = match operator {
-            QueryOperator::Equals(value) => {
-                self.data.get(value).cloned().unwrap_or_default()
-            }
+        let prefix = self.prefix();
+
+        if self.index_type == IndexType::FullText {
+            return self.query_fulltext(store, operator);
+        }
+
+        let entries = match operator {
+            QueryOperator::Equals(value) => self.value_entries(store, &prefix, value)?,
             QueryOperator::In(values) => {
-                let mut result = HashSet::new();
+                let mut result = Vec::new();
                 for value in values {
-                    if let Some(keys) = self.data.get(value) {
-                        result.extend(keys.iter().cloned());
-                    }
+                    result.extend(self.value_entries(store, &prefix, value)?);
                 }
                 result
             }
             QueryOperator::GreaterThan(threshold) => {
-                self.range_query(|val| val > *threshold)
+                // Entries equal to the threshold end in `\0<key>`, which sorts below `\x01`
+                self.number_range(store, &prefix, Some(format!("{}{}", encode_number(*threshold), '\u{1}')), None)?
             }
             QueryOperator::LessThan(threshold) => {
-                self.range_query(|val| val < *threshold)
-            }
-            QueryOperator::Between(min, max) => {
-                self.range_query(|val| val >= *min && val <= *max)
+                self.number_range(store, &prefix, None, Some(encode_number(*threshold)))?
             }
+            QueryOperator::Between(min, max) => self.number_range(
+                store,
+                &prefix,
+                Some(encode_number(*min)),
+                Some(format!("{}{}", encode_number(*max), '\u{1}')),
+            )?,
             QueryOperator::Contains(substring) => {
-                self.text_query(|val| val.to_lowercase().contains(&substring.to_lowercase()))
+                let needle = substring.to_lowercase();
+                self.text_query(store, &prefix, |val| val.to_lowercase().contains(&needle))?
             }
-            QueryOperator::StartsWith(prefix) => {
-                self.text_query(|val| val.to_lowercase().starts_with(&prefix.to_lowercase()))
+            QueryOperator::StartsWith(text) => {
+                let needle = text.to_lowercase();
+                self.text_query(store, &prefix, |val| val.to_lowercase().starts_with(&needle))?
             }
         };
 
+        let keys: HashSet<String> = entries
+            .iter()
+            .filter_map(|entry| split_entry(&entry[prefix.len()..]))
+            .map(|(_, key)| key.to_string())
+            .collect();
+
         let elapsed = start.elapsed();
         tracing::debug!(
             "Index query on '{}' returned {} keys in {:?}",
@@ -211,65 +378,282 @@ impl SecondaryIndex {
             elapsed
         );
 
-        keys.into_iter().collect()
+        Ok(keys.into_iter().collect())
     }
 
-    /// Range query helper for numeric comparisons
-    fn range_query<F>(&self, predicate: F) -> HashSet<String>
-    where
-        F: Fn(f64) -> bool,
-    {
-        let mut result = HashSet::new();
-        for (value_str, keys) in &self.data {
-            if let Ok(value) = value_str.parse::<f64>() {
-                if predicate(value) {
-                    result.extend(keys.iter().cloned());
+    /// Ordered scan of a compound index: `equals` fixes the leading fields and
+    /// `range` bounds the field after them. Keys come back in index order.
+    pub fn scan_compound(&self, store: &dyn IndexStore, equals: &[String], range: &NumberBounds) -> Result<Vec<String>> {
+        if self.index_type != IndexType::Compound {
+            anyhow::bail!("Index '{}' is not a compound index", self.name);
+        }
+        let field_count = self.fields().len();
+        if equals.len() > field_count || (equals.len() == field_count && !range.is_unbounded()) {
+            anyhow::bail!("Index '{}' covers only {} fields", self.name, field_count);
+        }
+
+        let prefix = self.prefix();
+        let mut base = prefix.clone();
+        for (i, value) in equals.iter().enumerate() {
+            base.push_str(&encode_component(value));
+            base.push(if i + 1 == field_count { SEP } else { COMPONENT_SEP });
+        }
+        let entries = if range.is_unbounded() {
+            store.keys_with_prefix(&base)?
+        } else {
+            // The bounded component is followed by `\x01` or `\0`, both below `\x02`
+            let start = match range.min {
+                Some((min, true)) => format!("{}{}", base, encode_number(min)),
+                Some((min, false)) => format!("{}{}{}", base, encode_number(min), '\u{2}'),
+                None => format!("{}{}", base, NUMBER_TAG),
+            };
+            let end = match range.max {
+                Some((max, true)) => format!("{}{}{}", base, encode_number(max), '\u{2}'),
+                Some((max, false)) => format!("{}{}", base, encode_number(max)),
+                None => format!("{}{}", base, char::from(NUMBER_TAG as u8 + 1)),
+            };
+            store.keys_in_range(&start, &end)?
+        };
+
+        let mut seen = HashSet::new();
+        Ok(entries
+            .iter()
+            .filter_map(|entry| split_entry(&entry[prefix.len()..]))
+            .map(|(_, key)| key.to_string())
+            .filter(|key| seen.insert(key.clone()))
+            .collect())
+    }
+
+    /// Operators on a full-text index: `Contains` needs every word, `StartsWith`
+    /// matches word prefixes, `Equals`/`In` match phrases
+    fn query_fulltext(&self, store: &dyn IndexStore, operator: &QueryOperator) -> Result<Vec<String>> {
+        let phrase = |text: &str| format!("\"{}\"", text.replace('"', " "));
+        let queries: Vec<String> = match operator {
+            QueryOperator::Contains(text) => vec![text.replace('"', " ")],
+            QueryOperator::StartsWith(text) => vec![text
+                .split_whitespace()
+                .map(|word| format!("{}*", word))
+                .collect::<Vec<_>>()
+                .join(" ")],
+            QueryOperator::Equals(text) => vec![phrase(text)],
+            QueryOperator::In(texts) => texts.iter().map(|t| phrase(t)).collect(),
+            _ => Vec::new(),
+        };
+        let mut keys = Vec::new();
+        for query in queries {
+            for hit in self.search(store, &fulltext::parse_query(&query))? {
+                if !keys.contains(&hit.key) {
+                    keys.push(hit.key);
                 }
             }
         }
-        result
+        Ok(keys)
+    }
+
+    /// Entries for one exact value
+    fn value_entries(&self, store: &dyn IndexStore, prefix: &str, value: &str) -> Result<Vec<String>> {
+        let value_prefix = format!("{}{}{}", prefix, encode_value(&self.index_type, value), SEP);
+        store.keys_with_prefix(&value_prefix)
     }
 
-    /// Text query helper for string matching
-    fn text_query<F>(&self, predicate: F) -> HashSet<String>
+    /// Range scan over numeric entries; bounds are encoded values, `None` is open
+    fn number_range(
+        &self,
+        store: &dyn IndexStore,
+        prefix: &str,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Vec<String>> {
+        let start = format!("{}{}", prefix, from.unwrap_or_else(|| NUMBER_TAG.to_string()));
+        // Tags are single ASCII letters; the next letter bounds all numeric entries
+        let end = format!(
+            "{}{}",
+            prefix,
+            to.unwrap_or_else(|| char::from(NUMBER_TAG as u8 + 1).to_string())
+        );
+        store.keys_in_range(&start, &end)
+    }
+
+    /// Text query helper for string matching (scans the whole index)
+    fn text_query<F>(&self, store: &dyn IndexStore, prefix: &str, predicate: F) -> Result<Vec<String>>
     where
         F: Fn(&str) -> bool,
     {
-        let mut result = HashSet::new();
-        for (value_str, keys) in &self.data {
-            if predicate(value_str) {
-                result.extend(keys.iter().cloned());
-            }
-        }
-        result
+        Ok(store
+            .keys_with_prefix(prefix)?
+            .into_iter()
+            .filter(|entry| {
+                split_entry(&entry[prefix.len()..])
+                    .and_then(|(value, _)| decode_value(&self.index_type, value))
+                    .is_some_and(|value| predicate(&value))
+            })
+            .collect())
     }
 
     /// Get all unique field values in the index
-    pub fn get_all_values(&self) -> Vec<String> {
-        self.data.keys().cloned().collect()
+    pub fn get_all_values(&self, store: &dyn IndexStore) -> Result<Vec<String>> {
+        let prefix = self.prefix();
+        if self.index_type == IndexType::FullText {
+            return fulltext::terms(store, &prefix);
+        }
+        let values: BTreeSet<String> = store
+            .keys_with_prefix(&prefix)?
+            .iter()
+            .filter_map(|entry| {
+                split_entry(&entry[prefix.len()..]).and_then(|(value, _)| decode_value(&self.index_type, value))
+            })
+            .collect();
+        Ok(values.into_iter().collect())
     }
 
     /// Get total number of indexed keys
     pub fn total_keys(&self) -> usize {
-        self.data.values().map(|keys| keys.len()).sum()
+        self.entry_count
+    }
+}
+
+fn index_prefix(db_name: &str, index_name: &str) -> String {
+    format!("{}{}{}{}", db_name, SEP, index_name, SEP)
+}
+
+fn definition_key(db_name: &str, index_name: &str) -> String {
+    format!("{}{}{}", db_name, SEP, index_name)
+}
+
+fn entry_key(prefix: &str, encoded_value: &str, key: &str) -> String {
+    format!("{}{}{}{}", prefix, encoded_value, SEP, key)
+}
+
+/// Split the part of an entry key after the index prefix into (encoded value, key)
+fn split_entry(rest: &str) -> Option<(&str, &str)> {
+    rest.split_once(SEP)
+}
+
+/// Encode a field value for an index of `index_type`
+fn encode_value(index_type: &IndexType, value: &str) -> String {
+    match index_type {
+        IndexType::Range => encode_component(value),
+        IndexType::Compound => value
+            .split(COMPONENT_SEP)
+            .map(|component| match component {
+                "" => MISSING_TAG.to_string(),
+                text => encode_component(text),
+            })
+            .collect::<Vec<_>>()
+            .join(&COMPONENT_SEP.to_string()),
+        _ => format!("{}{}", TEXT_TAG, value),
+    }
+}
+
+/// Numbers sort numerically, everything else as text
+fn encode_component(value: &str) -> String {
+    if let Ok(number) = value.trim().parse::<f64>() {
+        if !number.is_nan() {
+            return encode_number(number);
+        }
+    }
+    format!("{}{}", TEXT_TAG, value)
+}
+
+/// Order-preserving encoding of an `f64`: flip the sign bit of positives and
+/// every bit of negatives, so unsigned byte order equals numeric order
+fn encode_number(number: f64) -> String {
+    // -0.0 and 0.0 must share an encoding
+    let number = if number == 0.0 { 0.0 } else { number };
+    let bits = number.to_bits();
+    let ordered = if bits >> 63 == 1 { !bits } else { bits | (1 << 63) };
+    format!("{}{:016x}", NUMBER_TAG, ordered)
+}
+
+fn decode_number(hex: &str) -> Option<f64> {
+    let ordered = u64::from_str_radix(hex, 16).ok()?;
+    let bits = if ordered >> 63 == 1 { ordered & !(1 << 63) } else { !ordered };
+    Some(f64::from_bits(bits))
+}
+
+/// Original text of an encoded value; compound values list their components
+fn decode_value(index_type: &IndexType, encoded: &str) -> Option<String> {
+    if *index_type == IndexType::Compound {
+        let components: Option<Vec<String>> = encoded.split(COMPONENT_SEP).map(decode_component).collect();
+        return components.map(|c| c.join(","));
+    }
+    decode_component(encoded)
+}
+
+fn decode_component(encoded: &str) -> Option<String> {
+    let mut chars = encoded.chars();
+    match chars.next()? {
+        NUMBER_TAG => decode_number(chars.as_str()).map(|n| n.to_string()),
+        TEXT_TAG => Some(chars.as_str().to_string()),
+        MISSING_TAG => Some(String::new()),
+        _ => None,
     }
 }
 
 /// Index manager for managing multiple indexes
 #[derive(Clone)]
 pub struct IndexManager {
-    /// All indexes: (db_name, index_name) -> Index
+    /// All index definitions: (db_name, index_name) -> Index
     indexes: Arc<RwLock<HashMap<(String, String), SecondaryIndex>>>,
+    /// Backend holding definitions and entries
+    backend: Arc<dyn StorageBackend>,
 }
 
 impl IndexManager {
-    /// Create a new index manager
+    /// Create a new index manager with non-persistent storage
     pub fn new() -> Self {
+        Self::with_backend(Arc::new(MemoryBackend::new()))
+    }
+
+    /// Create an index manager persisting to `backend`; call `load` to pick up
+    /// indexes created by earlier runs
+    pub fn with_backend(backend: Arc<dyn StorageBackend>) -> Self {
         Self {
             indexes: Arc::new(RwLock::new(HashMap::new())),
+            backend,
         }
     }
 
+    /// Load persisted index definitions. Returns the number of indexes loaded.
+    pub async fn load(&self) -> Result<usize> {
+        let loaded = self
+            .blocking(|backend| {
+                let mut loaded = Vec::new();
+                for def_key in backend.index_definitions().keys_with_prefix("")? {
+                    let Some(bytes) = backend.index_definitions().get(&def_key)? else {
+                        continue;
+                    };
+                    let mut index: SecondaryIndex = bincode::deserialize(&bytes)?;
+                    index.recount(backend.secondary_index())?;
+                    loaded.push(index);
+                }
+                Ok(loaded)
+            })
+            .await?;
+
+        let mut indexes = self.indexes.write().await;
+        let count = loaded.len();
+        for index in loaded {
+            indexes.insert((index.db_name.clone(), index.name.clone()), index);
+        }
+        if count > 0 {
+            tracing::info!("Loaded {} secondary indexes", count);
+        }
+        Ok(count)
+    }
+
+    /// Run blocking store operations on the blocking thread pool
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn StorageBackend) -> Result<T> + Send + 'static,
+    {
+        let backend = Arc::clone(&self.backend);
+        tokio::task::spawn_blocking(move || f(backend.as_ref()))
+            .await
+            .map_err(|e| anyhow!("Thread join error: {}", e))?
+    }
+
     /// Create a new index
     pub async fn create_index(
         &self,
@@ -280,15 +664,20 @@ impl IndexManager {
     ) -> Result<()> {
         let mut indexes = self.indexes.write().await;
         let key = (db_name.clone(), index_name.clone());
-        
+
         if indexes.contains_key(&key) {
             anyhow::bail!("Index '{}' already exists in database '{}'", index_name, db_name);
         }
 
         let index = SecondaryIndex::new(index_name.clone(), db_name.clone(), field.clone(), index_type);
+        let definition = bincode::serialize(&index)?;
+        let def_key = definition_key(&db_name, &index_name);
+        self.blocking(move |backend| backend.index_definitions().insert(&def_key, definition))
+            .await?;
+
         let field_name = index.field.clone();
         indexes.insert(key, index);
-        
+
         tracing::info!("Created index '{}' for field '{}'", index_name, field_name);
         Ok(())
     }
@@ -297,8 +686,18 @@ impl IndexManager {
     pub async fn drop_index(&self, db_name: &str, index_name: &str) -> Result<()> {
         let mut indexes = self.indexes.write().await;
         let key = (db_name.to_string(), index_name.to_string());
-        
+
         if indexes.remove(&key).is_some() {
+            let def_key = definition_key(db_name, index_name);
+            let prefix = index_prefix(db_name, index_name);
+            self.blocking(move |backend| {
+                backend.index_definitions().remove(&def_key)?;
+                for entry in backend.secondary_index().keys_with_prefix(&prefix)? {
+                    backend.secondary_index().remove(&entry)?;
+                }
+                Ok(())
+            })
+            .await?;
             tracing::info!("Dropped index '{}' from database '{}'", index_name, db_name);
             Ok(())
         } else {
@@ -316,9 +715,16 @@ impl IndexManager {
     ) -> Result<()> {
         let mut indexes = self.indexes.write().await;
         let index_key = (db_name.to_string(), index_name.to_string());
-        
+
         if let Some(index) = indexes.get_mut(&index_key) {
-            index.insert(field_value, key)?;
+            let mut updated = index.clone();
+            let updated = self
+                .blocking(move |backend| {
+                    updated.insert(backend.secondary_index(), field_value, key)?;
+                    Ok(updated)
+                })
+                .await?;
+            *index = updated;
             Ok(())
         } else {
             anyhow::bail!("Index '{}' not found in database '{}'", index_name, db_name)
@@ -335,15 +741,95 @@ impl IndexManager {
     ) -> Result<()> {
         let mut indexes = self.indexes.write().await;
         let index_key = (db_name.to_string(), index_name.to_string());
-        
+
         if let Some(index) = indexes.get_mut(&index_key) {
-            index.remove(field_value, key);
+            let mut updated = index.clone();
+            let field_value = field_value.to_string();
+            let key = key.to_string();
+            let updated = self
+                .blocking(move |backend| {
+                    updated.remove(backend.secondary_index(), &field_value, &key)?;
+                    Ok(updated)
+                })
+                .await?;
+            *index = updated;
             Ok(())
         } else {
             anyhow::bail!("Index '{}' not found in database '{}'", index_name, db_name)
         }
     }
 
+    /// Definitions of every index in a database
+    pub async fn indexes_for(&self, db_name: &str) -> Vec<SecondaryIndex> {
+        let indexes = self.indexes.read().await;
+        let mut found: Vec<SecondaryIndex> = indexes
+            .iter()
+            .filter(|((db, _), _)| db == db_name)
+            .map(|(_, index)| index.clone())
+            .collect();
+        found.sort_by(|a, b| a.name.cmp(&b.name));
+        found
+    }
+
+    /// Whether any index exists for a database
+    pub async fn has_indexes(&self, db_name: &str) -> bool {
+        let indexes = self.indexes.read().await;
+        indexes.keys().any(|(db, _)| db == db_name)
+    }
+
+    /// Move `key` from the entries for its `old` document to those for `new`
+    /// in every index of `db_name`. Either document may be absent (insert/delete).
+    pub async fn index_document(
+        &self,
+        db_name: &str,
+        key: &str,
+        old: Option<&serde_json::Value>,
+        new: Option<&serde_json::Value>,
+    ) {
+        let mut indexes = self.indexes.write().await;
+        let mut work = Vec::new();
+        for ((db, name), index) in indexes.iter() {
+            if db != db_name {
+                continue;
+            }
+            let old_values = old.map(|doc| extract_field_values(doc, &index.field)).unwrap_or_default();
+            let new_values = new.map(|doc| extract_field_values(doc, &index.field)).unwrap_or_default();
+            if old_values != new_values {
+                work.push((name.clone(), index.clone(), old_values, new_values));
+            }
+        }
+        if work.is_empty() {
+            return;
+        }
+
+        let key = key.to_string();
+        let result = self
+            .blocking(move |backend| {
+                let store = backend.secondary_index();
+                for (_, index, old_values, new_values) in work.iter_mut() {
+                    for value in old_values.iter().filter(|v| !new_values.contains(*v)) {
+                        index.remove(store, value, &key)?;
+                    }
+                    for value in new_values.iter() {
+                        if let Err(e) = index.insert(store, value.clone(), key.clone()) {
+                            tracing::warn!(key = %key, "Skipping index update: {}", e);
+                        }
+                    }
+                }
+                Ok(work)
+            })
+            .await;
+
+        match result {
+            Ok(work) => {
+                for (name, index, _, _) in work {
+                    indexes.insert((db_name.to_string(), name), index);
+                }
+            }
+            Err(e) => tracing::warn!("Secondary index update failed: {}", e),
+        }
+    }
+
     /// Query an index
     pub async fn query_index(
         &self,
@@ -354,12 +840,15 @@ impl IndexManager {
         let start = std::time::Instant::now();
         let indexes = self.indexes.read().await;
         let index_key = (db_name.to_string(), index_name.to_string());
-        
+
         if let Some(index) = indexes.get(&index_key) {
-            let keys = index.query(&operator);
+            let index = index.clone();
+            let keys = self
+                .blocking(move |backend| index.query(backend.secondary_index(), &operator))
+                .await?;
             let count = keys.len();
             let execution_time_ms = start.elapsed().as_millis() as u64;
-            
+
             Ok(QueryResult {
                 keys,
                 count,
@@ -370,6 +859,71 @@ impl IndexManager {
         }
     }
 
+    /// Ordered scan of a compound index (see `SecondaryIndex::scan_compound`)
+    pub async fn scan_compound(
+        &self,
+        db_name: &str,
+        index_name: &str,
+        equals: Vec<String>,
+        range: NumberBounds,
+    ) -> Result<Vec<String>> {
+        let indexes = self.indexes.read().await;
+        let index_key = (db_name.to_string(), index_name.to_string());
+
+        if let Some(index) = indexes.get(&index_key) {
+            let index = index.clone();
+            self.blocking(move |backend| index.scan_compound(backend.secondary_index(), &equals, &range))
+                .await
+        } else {
+            anyhow::bail!("Index '{}' not found in database '{}'", index_name, db_name)
+        }
+    }
+
+    /// Ranked full-text search across every full-text index of a database.
+    /// A key matched by several indexes keeps its best score.
+    pub async fn search(&self, db_name: &str, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let indexes = self.indexes.read().await;
+        let fulltext_indexes: Vec<SecondaryIndex> = indexes
+            .iter()
+            .filter(|((db, _), index)| db == db_name && index.index_type == IndexType::FullText)
+            .map(|(_, index)| index.clone())
+            .collect();
+        if fulltext_indexes.is_empty() {
+            anyhow::bail!("No full-text index in database '{}'", db_name);
+        }
+
+        let clauses = fulltext::parse_query(query);
+        let timer = std::time::Instant::now();
+        let hits = self
+            .blocking(move |backend| {
+                let mut best: HashMap<String, SearchHit> = HashMap::new();
+                for index in &fulltext_indexes {
+                    for hit in index.search(backend.secondary_index(), &clauses)? {
+                        match best.get_mut(&hit.key) {
+                            Some(existing) if existing.score >= hit.score => {
+                                existing.highlights.extend(hit.highlights);
+                            }
+                            Some(existing) => {
+                                let mut hit = hit;
+                                hit.highlights.append(&mut existing.highlights);
+                                *existing = hit;
+                            }
+                            None => {
+                                best.insert(hit.key.clone(), hit);
+                            }
+                        }
+                    }
+                }
+                let mut hits: Vec<SearchHit> = best.into_values().collect();
+                hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.key.cmp(&b.key)));
+                hits.truncate(limit);
+                Ok(hits)
+            })
+            .await?;
+        tracing::debug!("Search '{}' in '{}' returned {} hits in {:?}", query, db_name, hits.len(), timer.elapsed());
+        Ok(hits)
+    }
+
     /// List all indexes in a database
     pub async fn list_indexes(&self, db_name: &str) -> Vec<String> {
         let indexes = self.indexes.read().await;
@@ -384,14 +938,18 @@ impl IndexManager {
     pub async fn get_index_stats(&self, db_name: &str, index_name: &str) -> Result<IndexStats> {
         let indexes = self.indexes.read().await;
         let index_key = (db_name.to_string(), index_name.to_string());
-        
+
         if let Some(index) = indexes.get(&index_key) {
+            let scanned = index.clone();
+            let unique_values = self
+                .blocking(move |backend| Ok(scanned.get_all_values(backend.secondary_index())?.len()))
+                .await?;
             Ok(IndexStats {
                 name: index.name.clone(),
                 field: index.field.clone(),
                 index_type: index.index_type.clone(),
                 total_keys: index.total_keys(),
-                unique_values: index.data.len(),
+                unique_values,
                 memory_usage_bytes: index.memory_usage_bytes(),
                 memory_usage_mb: (index.memory_usage_bytes() as f64) / (1024.0 * 1024.0),
                 max_keys: index.max_keys,
@@ -405,13 +963,13 @@ impl IndexManager {
             anyhow::bail!("Index '{}' not found in database '{}'", index_name, db_name)
         }
     }
-    
+
     /// Get total memory usage across all indexes
     pub async fn total_memory_usage(&self) -> usize {
         let indexes = self.indexes.read().await;
         indexes.values().map(|idx| idx.memory_usage_bytes()).sum()
     }
-    
+
     /// Get total memory usage in megabytes
     pub async fn total_memory_usage_mb(&self) -> f64 {
         let bytes = self.total_memory_usage().await;
@@ -419,6 +977,76 @@ impl IndexManager {
     }
 }
 
+/// Values of `field` in a document, as index keys.
+///
+/// `field` is a dotted path (`address.city`) resolved like a filter path, so it
+/// descends into arrays of objects (`items.sku`). Strings, numbers and booleans
+/// are indexed by their text form; arrays index each scalar element. `*` selects
+/// every string in the document (for full-text indexes over whole documents).
+///
+/// A comma-separated list of paths (a compound index) yields every combination
+/// of the fields' values joined with `COMPONENT_SEP`. Documents without the
+/// first field are skipped; other missing fields become empty components.
+pub fn extract_field_values(doc: &serde_json::Value, field: &str) -> Vec<String> {
+    if field.contains(',') {
+        let mut tuples = vec![String::new()];
+        for (i, path) in field.split(',').map(str::trim).enumerate() {
+            let mut values: Vec<String> = extract_field_values(doc, path)
+                .into_iter()
+                .filter(|value| !value.is_empty() && !value.contains(COMPONENT_SEP))
+                .collect();
+            if values.is_empty() {
+                if i == 0 {
+                    return Vec::new();
+                }
+                values.push(String::new());
+            }
+            tuples = tuples
+                .iter()
+                .flat_map(|prefix| {
+                    values.iter().map(move |value| match i {
+                        0 => value.clone(),
+                        _ => format!("{}{}{}", prefix, COMPONENT_SEP, value),
+                    })
+                })
+                .collect();
+        }
+        return tuples;
+    }
+    if field == "*" {
+        let mut strings = Vec::new();
+        collect_strings(doc, &mut strings);
+        return strings;
+    }
+    let mut found = Vec::new();
+    crate::filters::resolve_path(doc, field, &mut found);
+    found
+        .into_iter()
+        .flat_map(|value| match value {
+            serde_json::Value::Array(items) => items.iter().filter_map(scalar_text).collect(),
+            other => scalar_text(other).into_iter().collect::<Vec<_>>(),
+        })
+        .collect()
+}
+
+fn collect_strings(value: &serde_json::Value, out: &mut Vec<String>) {
+    match value {
+        serde_json::Value::String(s) => out.push(s.clone()),
+        serde_json::Value::Array(items) => items.iter().for_each(|item| collect_strings(item, out)),
+        serde_json::Value::Object(map) => map.values().for_each(|item| collect_strings(item, out)),
+        _ => {}
+    }
+}
+
+/// Text form of a scalar JSON value, as stored in index entries
+pub fn scalar_text(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
 
 impl Default for IndexManager {
     fn default() -> Self {
@@ -447,7 +1075,7 @@ mod tests {
     #[tokio::test]
     async fn test_exact_index() {
         let manager = IndexManager::new();
-        
+
         // Create index on email field
         manager
             .create_index(
@@ -482,7 +1110,7 @@ mod tests {
     #[tokio::test]
     async fn test_range_index() {
         let manager = IndexManager::new();
-        
+
         manager
             .create_index(
                 "users".to_string(),
@@ -510,7 +1138,7 @@ mod tests {
     #[tokio::test]
     async fn test_text_search() {
         let manager = IndexManager::new();
-        
+
         manager
             .create_index(
                 "products".to_string(),
@@ -533,4 +1161,84 @@ mod tests {
 
         assert_eq!(result.count, 2);
     }
+
+    #[tokio::test]
+    async fn test_index_document_moves_key() {
+        let manager = IndexManager::new();
+        manager
+            .create_index("users".to_string(), "city_idx".to_string(), "address.city".to_string(), IndexType::Exact)
+            .await
+            .unwrap();
+
+        let old = serde_json::json!({ "address": { "city": "Oslo" } });
+        let new = serde_json::json!({ "address": { "city": "Bergen" } });
+        manager.index_document("users", "user:1", None, Some(&old)).await;
+        manager.index_document("users", "user:1", Some(&old), Some(&new)).await;
+
+        let oslo = manager
+            .query_index("users", "city_idx", QueryOperator::Equals("Oslo".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(oslo.count, 0);
+        let bergen = manager
+            .query_index("users", "city_idx", QueryOperator::Equals("Bergen".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(bergen.keys, vec!["user:1".to_string()]);
+
+        manager.index_document("users", "user:1", Some(&new), None).await;
+        assert_eq!(manager.get_index_stats("users", "city_idx").await.unwrap().total_keys, 0);
+    }
+
+    #[test]
+    fn test_extract_field_values() {
+        let doc = serde_json::json!({ "age": 30, "tags": ["a", "b", { "c": 1 }], "ok": true, "none": null });
+        assert_eq!(extract_field_values(&doc, "age"), vec!["30"]);
+        assert_eq!(extract_field_values(&doc, "tags"), vec!["a", "b"]);
+        assert_eq!(extract_field_values(&doc, "ok"), vec!["true"]);
+        assert!(extract_field_values(&doc, "none").is_empty());
+        assert!(extract_field_values(&doc, "missing.path").is_empty());
+        assert_eq!(extract_field_values(&doc, "tags.c"), vec!["1"]);
+    }
+
+    #[test]
+    fn test_number_encoding_preserves_order() {
+        let numbers = [-1e300, -42.5, -1.0, -0.0, 0.0, 1e-9, 1.0, 2.0, 10.0, 1e300];
+        let encoded: Vec<String> = numbers.iter().map(|n| encode_number(*n)).collect();
+        for pair in encoded.windows(2) {
+            assert!(pair[0] <= pair[1], "{:?}", pair);
+        }
+        assert_eq!(encode_number(-0.0), encode_number(0.0));
+        for n in numbers {
+            assert_eq!(decode_number(&encode_number(n)[1..]), Some(if n == 0.0 { 0.0 } else { n }));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_range_bounds() {
+        let manager = IndexManager::new();
+        manager
+            .create_index("db".to_string(), "n".to_string(), "n".to_string(), IndexType::Range)
+            .await
+            .unwrap();
+        for (value, key) in [("-5", "a"), ("0", "b"), ("10", "c"), ("10.0", "d"), ("99", "e"), ("abc", "f")] {
+            manager.insert_into_index("db", "n", value.to_string(), key.to_string()).await.unwrap();
+        }
+
+        let sorted = |mut keys: Vec<String>| {
+            keys.sort();
+            keys
+        };
+        let gt = manager.query_index("db", "n", QueryOperator::GreaterThan(10.0)).await.unwrap();
+        assert_eq!(sorted(gt.keys), vec!["e"]);
+        let lt = manager.query_index("db", "n", QueryOperator::LessThan(10.0)).await.unwrap();
+        assert_eq!(sorted(lt.keys), vec!["a", "b"]);
+        let between = manager.query_index("db", "n", QueryOperator::Between(0.0, 10.0)).await.unwrap();
+        assert_eq!(sorted(between.keys), vec!["b", "c", "d"]);
+        // Numeric equality ignores formatting; text values still match exactly
+        let eq = manager.query_index("db", "n", QueryOperator::Equals("10".to_string())).await.unwrap();
+        assert_eq!(sorted(eq.keys), vec!["c", "d"]);
+        let text = manager.query_index("db", "n", QueryOperator::Equals("abc".to_string())).await.unwrap();
+        assert_eq!(text.keys, vec!["f"]);
+    }
 }
@@ -1057,7 +1057,7 @@ impl IrohNetwork {
         
                     match evt {
                         NetworkEvent::Message { peer, data } => {
-                            match serde_json::from_slice::<crate::sync::SyncMessage>(&data) {
+                            match crate::sync::SyncMessage::from_bytes(&data) {
                                 Ok(sync_msg) => {
                                     if let Some(manager) = sync_manager.as_ref() {
                                         // Add timeout to prevent sync handler from blocking forever
@@ -1069,7 +1069,7 @@ impl IrohNetwork {
                                         match handle_result {
                                             Ok(Ok(Some(response))) => {
                                                 if let Some(sender) = &sync_sender {
-                                                    if let Ok(payload) = serde_json::to_vec(&response) {
+                                                    if let Ok(payload) = response.to_bytes() {
                                                         if let Err(e) = sender.lock().await.broadcast(payload.into()).await {
                                                             tracing::error!("Failed to broadcast sync response to {}: {}", peer, e);
                                                         }
@@ -1987,7 +1987,7 @@ impl IrohNetwork {
         let Some(ref sender) = self.sync_sender else {
             anyhow::bail!("Network not started - call run() first");
         };
-        let payload = serde_json::to_vec(&sync_msg)?;
+        let payload = sync_msg.to_bytes()?;
         let len = payload.len();
         
         // Log operation details if it's an operation message
@@ -1998,6 +1998,12 @@ impl IrohNetwork {
                     operation.op_id, operation.db_name, operation.key, operation.store_type, len
                 );
             }
+            crate::sync::SyncMessage::Batch { operations } => {
+                tracing::info!("📤 Broadcasting batch of {} operations ({} bytes)", operations.len(), len);
+            }
+            crate::sync::SyncMessage::MerkleBatch { batch } => {
+                tracing::info!("📤 Broadcasting batch of {} operations ({} bytes)", batch.leaves.len(), len);
+            }
             crate::sync::SyncMessage::SyncRequest { requester, since_timestamp } => {
                 tracing::info!(
                     "📤 Broadcasting sync request from {} (since: {:?}, {} bytes)",
@@ -1,9 +1,18 @@
+pub mod admin;
+pub mod aggregation;
+pub mod batch;
+pub mod blob_encoding;
+pub mod changes;
 pub mod config;
 pub mod crdt;
 pub mod crypto;
+pub mod delegation;
+pub mod encryption;
 pub mod error;
 pub mod error_context;
 pub mod filters;
+pub mod fsck;
+pub mod fulltext;
 pub mod gossip_discovery;
 pub mod graphql;
 pub mod graphql_indexing;
@@ -16,20 +25,31 @@ pub mod mqtt_bridge;
 pub mod network_resilience;
 pub mod node_region;
 pub mod peer_registry;
+pub mod query_planner;
+pub mod rate_limit;
+pub mod read_access;
+pub mod replay;
 pub mod resource_manager;
 pub mod retry;
+pub mod sql;
 pub mod state_manager;
 pub mod storage;
+pub mod storage_backend;
 pub mod sync;
+pub mod vector;
+pub mod versioning;
 pub mod inference;
 
 // Re-export commonly used types for easier testing
+pub use crate::blob_encoding::{BlobEncoding, CompressionConfig};
 pub use crate::crdt::CrdtStore;
 pub use crate::crypto::{verify_signature, validate_timestamp, secure_hex_decode, verify_db_name_secure, constant_time_eq, generate_db_name, verify_db_name, extract_name_from_db};
 pub use crate::error::DbError;
-pub use crate::graphql::{QueryRoot, MutationRoot, SubscriptionRoot, ApiSchema, SignedData, StorageResult, QueryResult};
+pub use crate::fsck::{Fsck, FsckOptions, FsckReport, FsckIssue, FsckIssueKind};
+pub use crate::graphql::{Query, Mutation, QueryRoot, MutationRoot, SubscriptionRoot, ApiSchema, SignedData, StorageResult, QueryResult};
 pub use crate::indexing::{IndexManager, SecondaryIndex, IndexType, QueryOperator, QueryResult as IndexQueryResult};
-pub use crate::storage::{RedisStorage, StoreType, SignatureMetadata, StoredEntry, SortedSetEntry, BatchWriter, BatchWriterStats, TtlMetadata, TtlInfo};
+pub use crate::storage_backend::{StorageBackend, IndexStore, ValueStore, BackendKind, SledIrohBackend, RedbBackend, MemoryBackend};
+pub use crate::storage::{RedisStorage, StoreType, SignatureMetadata, StoredEntry, SortedSetEntry, BatchWriter, BatchWriterStats, TtlMetadata, TtlInfo, Durability, JournalRecovery, KeyCheck, MigrationStats};
 pub use crate::sync::{SyncStore, SyncManager, SignedOperation, SyncMessage};
 pub use crate::peer_registry::{PeerRegistry, PeerRegistryConfig, PeerMeta, PeerStatus, PeerCapabilities, PeerSummary};
 pub use crate::gossip_discovery::{GossipDiscoveryBuilder, DiscoverySender, DiscoveryReceiver, DiscoveryNode, PeerInfo, NodeCapabilities, NodeId as GossipNodeId};
@@ -1,10 +1,21 @@
+mod admin; // Admin role and audit log for node-control fields
+mod aggregation; // Aggregation pipelines over JSON documents
+mod batch; // Batched signed writes and Merkle root signatures
+mod blob_encoding; // Value blob header + zstd compression
+mod changes; // Change feed behind the watch subscriptions
 mod config;
 mod crdt;
 mod crypto;
+mod delegation; // Owner-signed write grants for databases
+mod encryption; // Client-side encrypted databases
 mod error;
 mod filters;
+mod fsck; // Storage consistency checker
+mod fulltext; // Inverted index and BM25 ranking for full-text search
 mod gossip_discovery; // Improved gossip-based peer discovery
 mod graphql;
+mod graphql_indexing; // Secondary index queries and mutations
+mod indexing; // Secondary indexes on JSON/Hash fields
 mod ipfs;
 mod iroh_network; // Iroh-based networking
 mod kadena; // Kadena blockchain integration
@@ -13,9 +24,17 @@ mod mqtt_bridge;
 mod network_resilience; // Circuit breaker, reputation, bandwidth throttling
 mod node_region; // Node region detection
 mod peer_registry; // Centralized peer lifecycle management
+mod query_planner; // Index selection for JSON filters
+mod rate_limit; // Per-IP/per-key token buckets for the API
+mod read_access; // Private databases and read tokens
+mod replay; // Seen-nonce window for signed writes
 mod retry; // Enhanced retry and circuit breaker mechanisms
+mod sql; // Read-only SQL over store types
 mod storage;
+mod storage_backend; // Pluggable index/value backends for BlobStorage
 mod sync; // Data synchronization with CRDT
+mod vector; // Vector store with HNSW search
+mod versioning; // Schema versions and migrations for persisted formats
 mod inference; // AI inference execution
 
 // Use jemalloc on Linux for better multi-threaded allocation performance
@@ -72,6 +91,85 @@ async fn metrics_handler() -> String {
     metrics::export_metrics()
 }
 
+/// Offline storage check; run while the node is stopped (sled is single-process).
+/// Repairs replay the local op log only, since there are no peers to ask.
+async fn run_fsck(config: &config::Config, args: &[String]) -> Result<()> {
+    let mut options = fsck::FsckOptions::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--repair" => options.repair = true,
+            "--db" => options.db_name = iter.next().cloned(),
+            other => return Err(anyhow::anyhow!("Unknown fsck argument: {}", other)),
+        }
+    }
+
+    let data_dir = std::path::PathBuf::from("./data/iroh");
+    let store = iroh_blobs::store::fs::FsStore::load(&data_dir).await?;
+    let backend = storage_backend::open_backend(config.storage_config.backend, store.clone(), &data_dir)?;
+    let mut storage = storage::BlobStorage::open(backend).await?;
+    storage.set_compression(config.storage_config.compression);
+
+    // Node identity is irrelevant offline; SyncManager only needs one for sync requests
+    let node_id = iroh::SecretKey::generate().public();
+    let sync_manager = sync::SyncManager::with_store(storage.clone(), node_id, store.clone());
+    let index_hash_path = data_dir.join("sync_index_hashes.json");
+    if let Ok(s) = tokio::fs::read_to_string(&index_hash_path).await {
+        let json: serde_json::Value = serde_json::from_str(&s)?;
+        if let Some(hash) = json.get("ops_index_hash").and_then(|v| v.as_str()).and_then(|h| h.parse().ok()) {
+            sync_manager.load_from_storage(hash).await?;
+        }
+        if let Some(hash) = json.get("applied_index_hash").and_then(|v| v.as_str()).and_then(|h| h.parse().ok()) {
+            sync_manager.load_applied_index(hash).await?;
+        }
+    }
+
+    let report = fsck::Fsck::new(&storage)
+        .with_sync_manager(&sync_manager)
+        .run(&options)
+        .await?;
+
+    for issue in &report.issues {
+        println!(
+            "{:?}\t{}\t{}{}",
+            issue.kind,
+            issue.key,
+            issue.detail,
+            if issue.repaired { " (repaired)" } else { "" }
+        );
+    }
+    println!(
+        "fsck: {} keys, {} operations, {} signatures verified ({} unverifiable), {} issues, {} repaired",
+        report.keys_scanned,
+        report.operations_scanned,
+        report.signatures_verified,
+        report.signatures_unverifiable,
+        report.issues.len(),
+        report.repaired
+    );
+
+    if options.repair {
+        storage.checkpoint_journal().await?;
+        // Replays mark ops as applied; record the new index hashes for the next start
+        let (ops_hash, applied_hash) = sync_manager.save_indexes_to_storage().await?;
+        let mut json = tokio::fs::read_to_string(&index_hash_path)
+            .await
+            .ok()
+            .and_then(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+            .unwrap_or_else(|| serde_json::json!({}));
+        json["ops_index_hash"] = serde_json::Value::String(ops_hash.to_string());
+        json["applied_index_hash"] = serde_json::Value::String(applied_hash.to_string());
+        tokio::fs::write(&index_hash_path, serde_json::to_vec_pretty(&json)?).await?;
+    }
+    store.shutdown().await?;
+
+    if report.is_clean() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("fsck found {} unrepaired issues", report.issues.len() - report.repaired))
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging with filters
@@ -118,6 +216,20 @@ async fn main() -> Result<()> {
 
     // Load configuration
     let config = config::Config::load()?;
+    crypto::set_legacy_signature_cutoff(config.security_config.legacy_signatures_until);
+    if crypto::legacy_signatures_accepted() {
+        let until = chrono::DateTime::from_timestamp_millis(crypto::legacy_signature_cutoff()).unwrap_or_default();
+        tracing::warn!("Accepting legacy signature formats until {} (LEGACY_SIGNATURES_UNTIL)", until.to_rfc3339());
+    }
+    if config.api_config.admin_api_tokens.is_empty() && config.api_config.admin_public_keys.is_empty() {
+        tracing::warn!("No ADMIN_API_TOKENS or ADMIN_PUBLIC_KEYS configured; node-control GraphQL fields are disabled");
+    }
+
+    // `fsck [--repair] [--db <name>]` checks storage consistency and exits
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("fsck") {
+        return run_fsck(&config, &args[2..]).await;
+    }
 
     // Initialize single shared Iroh node</parameter>
     // Initialize single shared Iroh node
@@ -251,11 +363,26 @@ async fn main() -> Result<()> {
 
     tracing::info!("Iroh router spawned with shared components");
 
-    // Initialize BlobStorage (Redis-like API on top of blob storage)
-    tracing::info!("🔧 Initializing BlobStorage with Sled DB...");
-    let sled_db_path = data_dir.join("sled_db");
-    let storage = storage::BlobStorage::new(store.clone(), Some(sled_db_path)).await?;
-    tracing::info!("✅ BlobStorage initialized (Redis-like API on blob store)");
+    // Initialize BlobStorage (Redis-like API on top of the selected storage backend)
+    tracing::info!("🔧 Initializing BlobStorage with {} backend...", config.storage_config.backend);
+    let storage_backend =
+        storage_backend::open_backend(config.storage_config.backend, store.clone(), &data_dir)?;
+    let mut storage = storage::BlobStorage::open(storage_backend).await?;
+    storage.set_compression(config.storage_config.compression);
+    storage.set_default_durability(config.storage_config.durability);
+    for (db_name, level) in &config.storage_config.durability_overrides {
+        storage.set_database_durability(db_name, *level);
+    }
+    tracing::info!(
+        "✅ BlobStorage initialized (Redis-like API on blob store, durability={})",
+        config.storage_config.durability
+    );
+
+    // Upgrade values stored with older schema versions while nothing else writes
+    storage.migrate_before_serving().await;
+
+    // Inference results are always written to the Iroh blob store, whatever the storage backend
+    let inference_store = store.clone();
 
     // Initialize IpfsStorage using shared Iroh components
     let ipfs = ipfs::IpfsStorage::from_components(router.clone(), blobs.clone(), store.clone());
@@ -440,7 +567,7 @@ async fn main() -> Result<()> {
         models_dir.clone(),
         node_id, // EndpointId is Copy
         signing_key,
-        storage.inner_store(),
+        inference_store,
     ));
 
     let worker_scheduler = inference_scheduler.clone();
@@ -670,6 +797,9 @@ async fn main() -> Result<()> {
     // Start TTL cleanup background task (runs every 60 seconds)
     storage::BlobStorage::start_ttl_cleanup_task(storage.clone(), Some(60));
     tracing::info!("TTL cleanup background task started (interval: 60s)");
+
+    // Start write-ahead journal checkpoints (runs every 5 seconds)
+    storage::BlobStorage::start_journal_checkpoint_task(storage.clone(), Some(5));
     
     // Network will be moved into its own task - no Arc<Mutex<>> needed
     // GraphQL uses the Endpoint directly, not IrohNetwork
@@ -696,6 +826,7 @@ async fn main() -> Result<()> {
             Some(message_broadcast_tx.clone()),
             Some(sync_out_tx.clone()),
             Some(inference_scheduler),
+            config.api_config.clone(),
         )
     ).await {
         Ok(Ok(server)) => {
@@ -802,7 +933,12 @@ async fn main() -> Result<()> {
     );
     tracing::info!("📊 GraphQL Playground: http://{}:{}/", api_public_ip, config.api_port);
     
-    axum::serve(listener, graphql_server.into_make_service()).await?;
+    // Client addresses feed the per-IP rate limit
+    axum::serve(
+        listener,
+        graphql_server.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
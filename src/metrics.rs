@@ -23,6 +23,45 @@ lazy_static! {
         "storage_deletes_total",
         "Total number of storage delete operations"
     ).unwrap();
+
+    pub static ref STORAGE_WRITES_BY_DURABILITY: IntCounterVec = IntCounterVec::new(
+        Opts::new("storage_writes_by_durability_total", "Storage writes by durability level"),
+        &["level"]
+    ).unwrap();
+
+    pub static ref STORAGE_JOURNAL_CHECKPOINTS: IntCounter = IntCounter::new(
+        "storage_journal_checkpoints_total",
+        "Total number of write-ahead journal checkpoints"
+    ).unwrap();
+
+    pub static ref STORAGE_JOURNAL_RECOVERED: IntCounterVec = IntCounterVec::new(
+        Opts::new("storage_journal_recovered_total", "Journal records replayed at startup"),
+        &["direction"]
+    ).unwrap();
+
+    // Value blob compression
+    pub static ref STORAGE_VALUE_BYTES_RAW: IntCounter = IntCounter::new(
+        "storage_value_bytes_raw_total",
+        "Serialized value bytes before blob encoding"
+    ).unwrap();
+
+    pub static ref STORAGE_VALUE_BYTES_STORED: IntCounter = IntCounter::new(
+        "storage_value_bytes_stored_total",
+        "Value blob bytes written after blob encoding"
+    ).unwrap();
+
+    pub static ref SCHEMA_MIGRATIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new("schema_migrations_total", "Encoding upgrades applied by format and source version"),
+        &["format", "from_version"]
+    ).unwrap();
+
+    pub static ref STORAGE_COMPRESSION_RATIO: Histogram = Histogram::with_opts(
+        HistogramOpts::new(
+            "storage_compression_ratio",
+            "Compressed/original size of zstd-compressed value blobs"
+        )
+        .buckets(vec![0.05, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0])
+    ).unwrap();
     
     // Cache metrics
     pub static ref CACHE_HITS: IntCounter = IntCounter::new(
@@ -132,6 +171,32 @@ lazy_static! {
         "Total number of CRDT merges"
     ).unwrap();
     
+    pub static ref SYNC_LEGACY_SIGNATURES: IntCounterVec = IntCounterVec::new(
+        Opts::new("sync_legacy_signatures_total", "Operations verified with a deprecated signature format"),
+        &["format"]
+    ).unwrap();
+    
+    pub static ref GRAPHQL_QUERIES_REJECTED: IntCounterVec = IntCounterVec::new(
+        Opts::new("graphql_queries_rejected_total", "Queries refused for exceeding the depth or complexity limit"),
+        &["limit"]
+    ).unwrap();
+    
+    pub static ref RATE_LIMITED_REQUESTS: IntCounterVec = IntCounterVec::new(
+        Opts::new("rate_limited_requests_total", "Requests refused by a rate limit"),
+        &["scope"]
+    ).unwrap();
+    
+    pub static ref CHANGE_FEED_MISSED: IntCounter = IntCounter::new(
+        "change_feed_missed_total",
+        "Change events dropped for subscribers that fell behind"
+    ).unwrap();
+    
+    // Admin metrics
+    pub static ref ADMIN_ACTIONS: IntCounterVec = IntCounterVec::new(
+        Opts::new("admin_actions_total", "Calls to admin-only GraphQL fields"),
+        &["action", "outcome"]
+    ).unwrap();
+    
     // Extended peer metrics
     pub static ref PEER_CONNECTIONS_TOTAL: IntCounter = IntCounter::new(
         "peer_connections_total",
@@ -280,6 +345,13 @@ pub fn init_metrics() {
     REGISTRY.register(Box::new(STORAGE_READS.clone())).unwrap();
     REGISTRY.register(Box::new(STORAGE_WRITES.clone())).unwrap();
     REGISTRY.register(Box::new(STORAGE_DELETES.clone())).unwrap();
+    REGISTRY.register(Box::new(STORAGE_WRITES_BY_DURABILITY.clone())).unwrap();
+    REGISTRY.register(Box::new(STORAGE_JOURNAL_CHECKPOINTS.clone())).unwrap();
+    REGISTRY.register(Box::new(STORAGE_JOURNAL_RECOVERED.clone())).unwrap();
+    REGISTRY.register(Box::new(STORAGE_VALUE_BYTES_RAW.clone())).unwrap();
+    REGISTRY.register(Box::new(STORAGE_VALUE_BYTES_STORED.clone())).unwrap();
+    REGISTRY.register(Box::new(STORAGE_COMPRESSION_RATIO.clone())).unwrap();
+    REGISTRY.register(Box::new(SCHEMA_MIGRATIONS.clone())).unwrap();
     
     // Register cache metrics
     REGISTRY.register(Box::new(CACHE_HITS.clone())).unwrap();
@@ -308,6 +380,11 @@ pub fn init_metrics() {
     REGISTRY.register(Box::new(SYNC_OPERATIONS.clone())).unwrap();
     REGISTRY.register(Box::new(SYNC_CONFLICTS.clone())).unwrap();
     REGISTRY.register(Box::new(SYNC_MERGES.clone())).unwrap();
+    REGISTRY.register(Box::new(SYNC_LEGACY_SIGNATURES.clone())).unwrap();
+    REGISTRY.register(Box::new(ADMIN_ACTIONS.clone())).unwrap();
+    REGISTRY.register(Box::new(CHANGE_FEED_MISSED.clone())).unwrap();
+    REGISTRY.register(Box::new(GRAPHQL_QUERIES_REJECTED.clone())).unwrap();
+    REGISTRY.register(Box::new(RATE_LIMITED_REQUESTS.clone())).unwrap();
     
     // Register peer metrics
     REGISTRY.register(Box::new(PEER_CONNECTIONS_TOTAL.clone())).unwrap();
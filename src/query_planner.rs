@@ -0,0 +1,368 @@
+//! Index selection for JSON filters
+//!
+//! `plan` picks indexes for the conditions of a `JsonFilterConditions` (its flat
+//! conditions and those its filter document requires at the top level): first
+//! the compound index matching the most leading equality conditions (plus a
+//! range on the field after them), then a single-field index for each remaining
+//! condition one can answer. The scans are intersected into a candidate key set
+//! that `JsonFilter` still checks against every condition, so an index only has
+//! to return a superset of the matches. With no usable index the filter falls
+//! back to scanning every key of the pattern.
+
+use anyhow::Result;
+use serde_json::Value as JsonValue;
+use std::collections::HashSet;
+
+use crate::filters::{FilterCondition, FilterOptions, JsonFilterConditions, SortOrder};
+use crate::indexing::{
+    scalar_text, IndexManager, IndexType, NumberBounds, QueryOperator, SecondaryIndex, COMPONENT_SEP,
+};
+
+/// One index lookup of a plan
+#[derive(Debug, Clone)]
+pub enum IndexScan {
+    /// Single-field index queried with an operator
+    Single {
+        index: String,
+        field: String,
+        operator: QueryOperator,
+    },
+    /// Compound index with fixed leading fields and an optional range on the next
+    Compound {
+        index: String,
+        fields: Vec<String>,
+        equals: Vec<String>,
+        range: NumberBounds,
+    },
+}
+
+/// How a filter will be answered
+#[derive(Debug, Clone, Default)]
+pub struct QueryPlan {
+    /// Database whose indexes are used; `None` when the pattern spans databases
+    pub db_name: Option<String>,
+    /// Index lookups whose results are intersected; empty means a full scan
+    pub scans: Vec<IndexScan>,
+    /// The first scan already returns keys in `sort_by` order
+    pub index_order: bool,
+    /// Number of conditions checked against each fetched document
+    pub conditions: usize,
+}
+
+impl QueryPlan {
+    /// Whether the filter has to scan every key of the pattern
+    pub fn is_full_scan(&self) -> bool {
+        self.scans.is_empty()
+    }
+
+    /// Candidate keys (without the `<db_name>:` prefix), or `None` for a full scan
+    pub async fn candidate_keys(&self, manager: &IndexManager) -> Result<Option<Vec<String>>> {
+        let Some(db_name) = &self.db_name else {
+            return Ok(None);
+        };
+        let mut candidates: Option<Vec<String>> = None;
+        for scan in &self.scans {
+            let keys = match scan {
+                IndexScan::Single { index, operator, .. } => {
+                    manager.query_index(db_name, index, operator.clone()).await?.keys
+                }
+                IndexScan::Compound { index, equals, range, .. } => {
+                    manager.scan_compound(db_name, index, equals.clone(), *range).await?
+                }
+            };
+            // Intersect while keeping the order of the first scan
+            candidates = Some(match candidates {
+                None => keys,
+                Some(current) => {
+                    let found: HashSet<String> = keys.into_iter().collect();
+                    current.into_iter().filter(|key| found.contains(key)).collect()
+                }
+            });
+            if candidates.as_ref().is_some_and(|keys| keys.is_empty()) {
+                break;
+            }
+        }
+        Ok(candidates)
+    }
+
+    /// Human-readable plan, one step per line
+    pub fn explain(&self, pattern: &str, options: &FilterOptions) -> Vec<String> {
+        let mut lines = Vec::new();
+        for scan in &self.scans {
+            lines.push(match scan {
+                IndexScan::Single { index, field, operator } => {
+                    format!("IXSCAN {} ({}): {}", index, field, describe_operator(field, operator))
+                }
+                IndexScan::Compound { index, fields, equals, range } => {
+                    let mut bounds: Vec<String> = fields
+                        .iter()
+                        .zip(equals)
+                        .map(|(field, value)| format!("{} = {}", field, value))
+                        .collect();
+                    if let Some(field) = fields.get(equals.len()) {
+                        if !range.is_unbounded() {
+                            bounds.push(describe_bounds(field, range));
+                        }
+                    }
+                    format!("IXSCAN {} ({}): {}", index, fields.join(", "), bounds.join(", "))
+                }
+            });
+        }
+        match self.scans.len() {
+            0 => lines.push(format!("COLLSCAN {}", pattern)),
+            1 => {}
+            n => lines.push(format!("INTERSECT {} scans", n)),
+        }
+        lines.push(format!("FETCH + FILTER {} conditions", self.conditions));
+        if let Some(sort_by) = &options.sort_by {
+            let direction = match options.sort_order {
+                SortOrder::Asc => "ASC",
+                SortOrder::Desc => "DESC",
+            };
+            let source = if self.index_order { " (index order)" } else { "" };
+            lines.push(format!("SORT {} {}{}", sort_by, direction, source));
+        }
+        if options.offset.is_some() || options.limit.is_some() {
+            lines.push(format!(
+                "SKIP {} LIMIT {}",
+                options.offset.unwrap_or(0),
+                options.limit.map(|l| l.to_string()).unwrap_or_else(|| "none".to_string())
+            ));
+        }
+        lines
+    }
+}
+
+/// Database named by a `<db_name>:...` key pattern, if the name has no wildcards
+pub fn pattern_db(pattern: &str) -> Option<&str> {
+    let (db_name, _) = pattern.split_once(':')?;
+    if db_name.is_empty() || db_name.contains(['*', '?']) {
+        return None;
+    }
+    Some(db_name)
+}
+
+/// Choose index scans for `conditions` among the indexes of `db_name`
+pub fn plan(
+    indexes: &[SecondaryIndex],
+    db_name: Option<&str>,
+    conditions: &JsonFilterConditions,
+    options: &FilterOptions,
+) -> QueryPlan {
+    let mut plan = QueryPlan {
+        db_name: db_name.map(str::to_string),
+        conditions: conditions.len(),
+        ..Default::default()
+    };
+    let conditions = conditions.index_conditions();
+    if db_name.is_none() || conditions.is_empty() {
+        return plan;
+    }
+
+    // Best compound index: two points per leading equality, one for a range
+    let mut covered: HashSet<String> = HashSet::new();
+    let mut best: Option<(usize, bool, IndexScan)> = None;
+    for index in indexes.iter().filter(|i| i.index_type == IndexType::Compound) {
+        let fields = index.fields();
+        let equals: Vec<String> = fields
+            .iter()
+            .map_while(|field| equality_text(&conditions, field))
+            .collect();
+        let next = fields.get(equals.len()).copied();
+        let range = next.map(|field| number_bounds(&conditions, field)).unwrap_or_default();
+        let score = equals.len() * 2 + usize::from(!range.is_unbounded());
+        if score == 0 {
+            continue;
+        }
+        let ordered = !range.is_unbounded() && next.is_some() && options.sort_by.as_deref() == next;
+        let better = match &best {
+            Some((best_score, best_ordered, _)) => (score, ordered) > (*best_score, *best_ordered),
+            None => true,
+        };
+        if better {
+            let scan = IndexScan::Compound {
+                index: index.name.clone(),
+                fields: fields.iter().map(|f| f.to_string()).collect(),
+                equals,
+                range,
+            };
+            best = Some((score, ordered, scan));
+        }
+    }
+    if let Some((_, ordered, scan)) = best {
+        if let IndexScan::Compound { fields, equals, range, .. } = &scan {
+            let used = equals.len() + usize::from(!range.is_unbounded());
+            covered.extend(fields.iter().take(used).cloned());
+        }
+        plan.index_order = ordered;
+        plan.scans.push(scan);
+    }
+
+    // Single-field indexes for the remaining conditions
+    for (field, condition) in &conditions {
+        if covered.contains(field) {
+            continue;
+        }
+        let scan = indexes
+            .iter()
+            .filter(|index| index.field == *field)
+            .find_map(|index| {
+                single_operator(&index.index_type, condition).map(|operator| IndexScan::Single {
+                    index: index.name.clone(),
+                    field: field.clone(),
+                    operator,
+                })
+            });
+        if let Some(scan) = scan {
+            plan.scans.push(scan);
+        }
+    }
+
+    plan
+}
+
+/// Text of the first usable equality condition on `field`
+fn equality_text(conditions: &[(String, FilterCondition)], field: &str) -> Option<String> {
+    conditions.iter().find_map(|(f, condition)| match condition {
+        FilterCondition::Eq(value) if f == field => indexable_text(value),
+        _ => None,
+    })
+}
+
+/// Combined numeric bounds of the range conditions on `field`
+fn number_bounds(conditions: &[(String, FilterCondition)], field: &str) -> NumberBounds {
+    conditions
+        .iter()
+        .filter(|(f, _)| f == field)
+        .fold(NumberBounds::default(), |bounds, (_, condition)| match condition {
+            FilterCondition::Gt(JsonValue::Number(n)) => bounds.with_min(n.as_f64().unwrap_or(0.0), false),
+            FilterCondition::Gte(JsonValue::Number(n)) => bounds.with_min(n.as_f64().unwrap_or(0.0), true),
+            FilterCondition::Lt(JsonValue::Number(n)) => bounds.with_max(n.as_f64().unwrap_or(0.0), false),
+            FilterCondition::Lte(JsonValue::Number(n)) => bounds.with_max(n.as_f64().unwrap_or(0.0), true),
+            _ => bounds,
+        })
+}
+
+/// Operator answering `condition` on a single-field index, if any
+fn single_operator(index_type: &IndexType, condition: &FilterCondition) -> Option<QueryOperator> {
+    let range = *index_type == IndexType::Range;
+    if !range && *index_type != IndexType::Exact {
+        return None;
+    }
+    let number = |value: &JsonValue| value.as_f64().filter(|_| range);
+    match condition {
+        FilterCondition::Eq(value) => indexable_text(value).map(QueryOperator::Equals),
+        FilterCondition::In(values) => values
+            .iter()
+            .map(indexable_text)
+            .collect::<Option<Vec<_>>>()
+            .map(QueryOperator::In),
+        FilterCondition::Contains(text) => Some(QueryOperator::Contains(text.clone())),
+        FilterCondition::Gt(value) => number(value).map(QueryOperator::GreaterThan),
+        FilterCondition::Gte(value) => number(value).map(|n| QueryOperator::Between(n, f64::INFINITY)),
+        FilterCondition::Lt(value) => number(value).map(QueryOperator::LessThan),
+        FilterCondition::Lte(value) => number(value).map(|n| QueryOperator::Between(f64::NEG_INFINITY, n)),
+        FilterCondition::Ne(_) => None,
+    }
+}
+
+/// Index text of a scalar, unless it holds characters entries cannot contain
+fn indexable_text(value: &JsonValue) -> Option<String> {
+    scalar_text(value).filter(|text| !text.contains(['\0', COMPONENT_SEP]))
+}
+
+fn describe_operator(field: &str, operator: &QueryOperator) -> String {
+    match operator {
+        QueryOperator::Equals(value) => format!("{} = {}", field, value),
+        QueryOperator::GreaterThan(n) => format!("{} > {}", field, n),
+        QueryOperator::LessThan(n) => format!("{} < {}", field, n),
+        QueryOperator::Between(min, max) if max.is_infinite() => format!("{} >= {}", field, min),
+        QueryOperator::Between(min, max) if min.is_infinite() => format!("{} <= {}", field, max),
+        QueryOperator::Between(min, max) => format!("{} BETWEEN {} AND {}", field, min, max),
+        QueryOperator::In(values) => format!("{} IN [{}]", field, values.join(", ")),
+        QueryOperator::Contains(text) => format!("{} CONTAINS {}", field, text),
+        QueryOperator::StartsWith(text) => format!("{} STARTS WITH {}", field, text),
+    }
+}
+
+fn describe_bounds(field: &str, range: &NumberBounds) -> String {
+    let mut parts = Vec::new();
+    if let Some((min, inclusive)) = range.min {
+        parts.push(format!("{} {} {}", field, if inclusive { ">=" } else { ">" }, min));
+    }
+    if let Some((max, inclusive)) = range.max {
+        parts.push(format!("{} {} {}", field, if inclusive { "<=" } else { "<" }, max));
+    }
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conditions(list: Vec<(&str, FilterCondition)>) -> JsonFilterConditions {
+        let mut conditions = JsonFilterConditions::new();
+        for (field, condition) in list {
+            conditions.add_condition(field.to_string(), condition);
+        }
+        conditions
+    }
+
+    fn index(name: &str, field: &str, index_type: IndexType) -> SecondaryIndex {
+        SecondaryIndex::new(name.to_string(), "db".to_string(), field.to_string(), index_type)
+    }
+
+    #[test]
+    fn test_prefers_compound_index_and_its_order() {
+        let indexes = vec![
+            index("status_idx", "status", IndexType::Exact),
+            index("status_created", "status,createdAt", IndexType::Compound),
+        ];
+        let conditions = conditions(vec![
+            ("status", FilterCondition::Eq(JsonValue::from("open"))),
+            ("createdAt", FilterCondition::Gt(JsonValue::from(100))),
+        ]);
+        let options = FilterOptions {
+            sort_by: Some("createdAt".to_string()),
+            ..Default::default()
+        };
+
+        let plan = plan(&indexes, Some("db"), &conditions, &options);
+        assert_eq!(plan.scans.len(), 1);
+        assert!(plan.index_order);
+        assert_eq!(
+            plan.explain("db:*", &options)[0],
+            "IXSCAN status_created (status, createdAt): status = open, createdAt > 100"
+        );
+    }
+
+    #[test]
+    fn test_intersects_single_field_indexes() {
+        let indexes = vec![
+            index("status_idx", "status", IndexType::Exact),
+            index("age_idx", "age", IndexType::Range),
+        ];
+        let conditions = conditions(vec![
+            ("status", FilterCondition::Eq(JsonValue::from("open"))),
+            ("age", FilterCondition::Gte(JsonValue::from(18))),
+            ("name", FilterCondition::Ne(JsonValue::from("bob"))),
+        ]);
+
+        let plan = plan(&indexes, Some("db"), &conditions, &FilterOptions::default());
+        let explain = plan.explain("db:*", &FilterOptions::default());
+        assert_eq!(explain[1], "IXSCAN age_idx (age): age >= 18");
+        assert_eq!(explain[2], "INTERSECT 2 scans");
+        assert_eq!(explain[3], "FETCH + FILTER 3 conditions");
+    }
+
+    #[test]
+    fn test_falls_back_to_scan() {
+        let indexes = vec![index("age_idx", "age", IndexType::Exact)];
+        let conditions = conditions(vec![("age", FilterCondition::Gt(JsonValue::from(18)))]);
+
+        // Exact indexes cannot answer ranges, and wildcard databases use no index
+        assert!(plan(&indexes, Some("db"), &conditions, &FilterOptions::default()).is_full_scan());
+        assert_eq!(pattern_db("*:users"), None);
+        assert_eq!(pattern_db("db:users:*"), Some("db"));
+    }
+}
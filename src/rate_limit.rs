@@ -0,0 +1,238 @@
+//! Rate limiting for the HTTP API
+//!
+//! Token buckets refill continuously at a fixed rate up to a burst size; each
+//! request takes one token. Two families of buckets are kept:
+//!
+//! - per client IP, charged by a tower layer in front of every route except
+//!   the health checks (`rate_limit_layer`)
+//! - per public key, charged by `submitData` (and by `submitBatch` for each
+//!   operation) once the signature checks out, so a key can't be throttled by
+//!   someone else claiming it
+//!
+//! A request over either limit gets `429 Too Many Requests` with a
+//! `Retry-After` header. GraphQL query depth and complexity limits, with
+//! extra cost for resolvers that scan a whole database, are set on the
+//! schema in `create_server`.
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{header::RETRY_AFTER, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::metrics;
+
+/// Buckets tracked per family; reaching it drops the least recently used
+/// down to `TRACKED_BUCKETS_LOW_WATER`
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+const TRACKED_BUCKETS_LOW_WATER: usize = 7_500;
+
+/// Complexity charged for a resolver that scans every key of a database,
+/// on top of its selections
+pub const SCAN_COST: usize = 50;
+
+/// Rate limits and query limits for the GraphQL API
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Sustained requests per second from one IP, and the burst allowed
+    pub ip_per_second: f64,
+    pub ip_burst: u32,
+    /// Sustained signed writes per second from one public key, and the burst allowed
+    pub key_per_second: f64,
+    pub key_burst: u32,
+    /// Take the client IP from the first `X-Forwarded-For` hop (only behind a
+    /// trusted proxy)
+    pub trust_forwarded_for: bool,
+    /// Deepest selection nesting a query may use
+    pub max_query_depth: usize,
+    /// Highest total field cost a query may have
+    pub max_query_complexity: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ip_per_second: 20.0,
+            ip_burst: 100,
+            key_per_second: 10.0,
+            key_burst: 50,
+            trust_forwarded_for: false,
+            max_query_depth: 32,
+            max_query_complexity: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, now: Instant, rate: f64, burst: f64) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.updated = now;
+    }
+}
+
+/// A family of token buckets sharing one rate and burst
+struct Buckets<K> {
+    buckets: DashMap<K, TokenBucket>,
+    rate: f64,
+    burst: f64,
+}
+
+impl<K: std::hash::Hash + Eq + Clone> Buckets<K> {
+    fn new(rate: f64, burst: u32) -> Self {
+        Self {
+            buckets: DashMap::new(),
+            rate,
+            burst: f64::from(burst.max(1)),
+        }
+    }
+
+    /// Take a token for `key`, or say how long until one is available
+    fn take(&self, key: &K) -> Result<(), Duration> {
+        let now = Instant::now();
+        if self.buckets.len() >= MAX_TRACKED_BUCKETS {
+            self.evict_least_recent();
+        }
+
+        let mut bucket = self.buckets.entry(key.clone()).or_insert(TokenBucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.refill(now, self.rate, self.burst);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        if self.rate <= 0.0 {
+            return Err(Duration::from_secs(60));
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+    }
+
+    /// Drop the buckets used longest ago, leaving `TRACKED_BUCKETS_LOW_WATER`,
+    /// so a flood of new clients costs one pass per few thousand of them
+    fn evict_least_recent(&self) {
+        let mut last_used: Vec<(Instant, K)> = self
+            .buckets
+            .iter()
+            .map(|entry| (entry.updated, entry.key().clone()))
+            .collect();
+        let excess = last_used.len().saturating_sub(TRACKED_BUCKETS_LOW_WATER);
+        if excess == 0 {
+            return;
+        }
+        last_used.select_nth_unstable_by_key(excess - 1, |(updated, _)| *updated);
+        for (_, key) in &last_used[..excess] {
+            self.buckets.remove(key);
+        }
+    }
+}
+
+/// Per-IP and per-public-key rate limiter
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    ips: Arc<Buckets<IpAddr>>,
+    keys: Arc<Buckets<String>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(RateLimitConfig::default())
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            ips: Arc::new(Buckets::new(config.ip_per_second, config.ip_burst)),
+            keys: Arc::new(Buckets::new(config.key_per_second, config.key_burst)),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Charge a request from `ip`; on refusal returns how long to wait
+    pub fn check_ip(&self, ip: IpAddr) -> Result<(), Duration> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        self.ips.take(&ip).inspect_err(|_| {
+            metrics::RATE_LIMITED_REQUESTS.with_label_values(&["ip"]).inc();
+        })
+    }
+
+    /// Charge a signed write by `public_key`; on refusal returns how long to wait
+    pub fn check_key(&self, public_key: &str) -> Result<(), Duration> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        self.keys.take(&public_key.to_string()).inspect_err(|_| {
+            metrics::RATE_LIMITED_REQUESTS.with_label_values(&["public_key"]).inc();
+        })
+    }
+
+    fn client_ip(&self, request: &Request) -> Option<IpAddr> {
+        if self.config.trust_forwarded_for {
+            let forwarded = request
+                .headers()
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .and_then(|hop| hop.trim().parse().ok());
+            if forwarded.is_some() {
+                return forwarded;
+            }
+        }
+        request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip())
+    }
+}
+
+/// Value for a `Retry-After` header: whole seconds, rounded up
+pub fn retry_after_header(wait: Duration) -> HeaderValue {
+    let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    HeaderValue::from(seconds.max(1))
+}
+
+/// `429 Too Many Requests` with a GraphQL-shaped error body
+pub fn too_many_requests(wait: Duration) -> Response {
+    let body = serde_json::json!({
+        "errors": [{ "message": "Rate limit exceeded", "extensions": { "code": "RATE_LIMITED" } }]
+    });
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, axum::Json(body)).into_response();
+    response.headers_mut().insert(RETRY_AFTER, retry_after_header(wait));
+    response
+}
+
+/// Middleware charging each request to its client IP (use with
+/// `axum::middleware::from_fn_with_state`). Requests whose IP is unknown,
+/// like those from in-process tests, pass through.
+pub async fn rate_limit_layer(State(limiter): State<RateLimiter>, request: Request, next: Next) -> Response {
+    let exempt = matches!(request.uri().path(), "/health" | "/ready");
+    if !exempt {
+        if let Some(ip) = limiter.client_ip(&request) {
+            if let Err(wait) = limiter.check_ip(ip) {
+                return too_many_requests(wait);
+            }
+        }
+    }
+    next.run(request).await
+}
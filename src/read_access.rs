@@ -0,0 +1,318 @@
+//! Read access control for private databases
+//!
+//! Databases are public by default: any client can query them. The owner can
+//! flag a database private with an ordinary signed operation, which is
+//! verified, logged and replicated like any other write:
+//!
+//! - `store_type: "Privacy"`, `key: "__privacy"`, `value: {"private": true}`
+//!   (`false` makes it public again; the latest operation wins)
+//!
+//! Queries and subscriptions on a private database are only served to its
+//! owner and to keys holding a live grant (see `delegation.rs`). They prove
+//! who they are with a short-lived bearer token they sign themselves, sent as
+//! `Authorization: Bearer <token>` (or as `authorization` in the websocket
+//! `connection_init` payload):
+//!
+//! - `<payload>.<signature>`, where payload is the unpadded base64url JSON
+//!   `{"public_key": <hex>, "expires_at": <ms>, "challenge": <optional>}` and
+//!   signature the hex Ed25519 signature of `cyberfly-read-token-v1`, a
+//!   newline, then the payload segment as sent
+//! - tokens may live at most an hour; with a `challenge` from this node's
+//!   `readChallenge` query they are also bound to that node and to the
+//!   challenge's five-minute window
+//!
+//! This is access control on the API, not confidentiality: peers replicate
+//! private databases like any other, so use an encrypted database (see
+//! `encryption.rs`) to keep values from node operators.
+//!
+//! ## Persistence
+//! Privacy flags live in the storage backend's `read_access` keyspace (a
+//! dedicated sled tree on the default backend), cached in memory, as JSON
+//! under `<db_name>`. Challenges are kept in memory only.
+
+use anyhow::{anyhow, bail, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use base64::Engine;
+use dashmap::DashMap;
+use ed25519_dalek::{Signer, SigningKey};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+use crate::crypto;
+use crate::delegation::Delegations;
+use crate::storage_backend::{MemoryBackend, StorageBackend};
+use crate::sync::SignedOperation;
+
+/// Reserved key that privacy operations are written to
+pub const PRIVACY_KEY: &str = "__privacy";
+
+/// Domain separator prefixed to every signed read token
+pub const READ_TOKEN_DOMAIN: &str = "cyberfly-read-token-v1";
+
+/// Longest a read token may be valid for
+pub const MAX_TOKEN_LIFETIME_MS: i64 = 3_600_000;
+
+/// How long a read challenge can be used
+pub const CHALLENGE_LIFETIME_MS: i64 = 300_000;
+
+/// Whether an operation changes a database's privacy rather than writing data
+pub fn is_privacy_op(op: &SignedOperation) -> bool {
+    op.store_type.eq_ignore_ascii_case("privacy")
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[derive(Deserialize)]
+struct PrivacyValue {
+    private: bool,
+}
+
+/// What a read token asserts about its signer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadTokenClaims {
+    /// Hex encoded Ed25519 public key of the reader
+    pub public_key: String,
+    /// Unix milliseconds after which the token is refused
+    pub expires_at: i64,
+    /// A challenge from `ReadAccess::issue_challenge`, if the token answers one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<String>,
+}
+
+impl ReadTokenClaims {
+    /// Encode and sign these claims as a bearer token
+    pub fn sign(&self, key: &SigningKey) -> String {
+        self.sign_for(READ_TOKEN_DOMAIN, key)
+    }
+
+    /// Decode a bearer token and check its signature (not its expiry)
+    pub fn verify(token: &str) -> Result<Self> {
+        Self::verify_for(READ_TOKEN_DOMAIN, token)
+    }
+
+    /// Sign these claims under another domain separator, for tokens that
+    /// must not double as read tokens (see `admin.rs`)
+    pub fn sign_for(&self, domain: &str, key: &SigningKey) -> String {
+        let payload = BASE64URL.encode(serde_json::to_vec(self).unwrap_or_default());
+        let signature = key.sign(token_message(domain, &payload).as_bytes());
+        format!("{}.{}", payload, hex::encode(signature.to_bytes()))
+    }
+
+    /// Decode a token signed with `sign_for` under `domain`
+    pub fn verify_for(domain: &str, token: &str) -> Result<Self> {
+        let (payload, signature) = token
+            .split_once('.')
+            .ok_or_else(|| anyhow!("Malformed token"))?;
+        let claims: Self = BASE64URL
+            .decode(payload)
+            .map_err(|_| anyhow!("Malformed token"))
+            .and_then(|json| serde_json::from_slice(&json).map_err(|e| anyhow!("Malformed token: {}", e)))?;
+        crypto::verify_signature(
+            &crypto::secure_hex_decode(&claims.public_key)?,
+            token_message(domain, payload).as_bytes(),
+            &crypto::secure_hex_decode(signature)?,
+        )?;
+        Ok(claims)
+    }
+
+    /// Check the token is live and not valid for longer than allowed
+    pub fn check_expiry(&self, now_ms: i64) -> Result<()> {
+        if self.expires_at <= now_ms {
+            bail!("Token expired");
+        }
+        if self.expires_at > now_ms + MAX_TOKEN_LIFETIME_MS {
+            bail!("Tokens may be valid for at most {} ms", MAX_TOKEN_LIFETIME_MS);
+        }
+        Ok(())
+    }
+}
+
+fn token_message(domain: &str, payload: &str) -> String {
+    format!("{}\n{}", domain, payload)
+}
+
+/// Credentials a request presents for reading private databases
+#[derive(Debug, Clone, Default)]
+pub struct ReadCredential {
+    token: Option<String>,
+}
+
+impl ReadCredential {
+    /// Credential from a bearer token
+    pub fn bearer(token: impl Into<String>) -> Self {
+        Self { token: Some(token.into()) }
+    }
+
+    /// Credential from an `Authorization` header value, if it holds a bearer token
+    pub fn from_authorization(header: Option<&str>) -> Self {
+        let token = header
+            .and_then(|value| value.trim().strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty());
+        Self { token }
+    }
+
+    /// Whether any token was presented
+    pub fn is_anonymous(&self) -> bool {
+        self.token.is_none()
+    }
+}
+
+/// The latest privacy setting of one database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DbPrivacy {
+    pub db_name: String,
+    pub private: bool,
+    /// Timestamp and ID of the operation that set it (for LWW)
+    pub issued_at: i64,
+    pub op_id: String,
+}
+
+/// Privacy registry over a storage backend
+#[derive(Clone)]
+pub struct ReadAccess {
+    databases: Arc<DashMap<String, DbPrivacy>>,
+    /// Outstanding challenges and when they expire
+    challenges: Arc<DashMap<String, i64>>,
+    backend: Arc<dyn StorageBackend>,
+    /// Serializes LWW compare-and-persist
+    lock: Arc<Mutex<()>>,
+}
+
+impl Default for ReadAccess {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReadAccess {
+    /// Create a registry with non-persistent storage
+    pub fn new() -> Self {
+        Self::with_backend(Arc::new(MemoryBackend::new()))
+    }
+
+    /// Create a registry persisting to `backend`; call `load` to pick up
+    /// settings recorded by earlier runs
+    pub fn with_backend(backend: Arc<dyn StorageBackend>) -> Self {
+        Self {
+            databases: Arc::new(DashMap::new()),
+            challenges: Arc::new(DashMap::new()),
+            backend,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Load persisted settings into memory
+    pub async fn load(&self) -> Result<usize> {
+        let backend = Arc::clone(&self.backend);
+        let databases = Arc::clone(&self.databases);
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut count = 0;
+            for entry in backend.read_access().keys_with_prefix("")? {
+                let Some(bytes) = backend.read_access().get(&entry)? else {
+                    continue;
+                };
+                match serde_json::from_slice::<DbPrivacy>(&bytes) {
+                    Ok(privacy) => {
+                        databases.insert(privacy.db_name.clone(), privacy);
+                        count += 1;
+                    }
+                    Err(e) => tracing::warn!("Skipping unreadable privacy setting {:?}: {}", entry, e),
+                }
+            }
+            Ok(count)
+        })
+        .await
+        .map_err(|e| anyhow!("Thread join error: {}", e))?
+    }
+
+    /// Record a verified privacy operation. Returns false when a later
+    /// setting for the database is already recorded.
+    pub async fn apply(&self, op: &SignedOperation) -> Result<bool> {
+        if op.key != PRIVACY_KEY {
+            bail!("Privacy operations must use key {}", PRIVACY_KEY);
+        }
+        let value: PrivacyValue = serde_json::from_str(&op.value)
+            .map_err(|e| anyhow!("Invalid privacy setting (expected {{\"private\": bool}}): {}", e))?;
+        let privacy = DbPrivacy {
+            db_name: op.db_name.clone(),
+            private: value.private,
+            issued_at: op.timestamp,
+            op_id: op.op_id.clone(),
+        };
+
+        let backend = Arc::clone(&self.backend);
+        let databases = Arc::clone(&self.databases);
+        let lock = Arc::clone(&self.lock);
+        tokio::task::spawn_blocking(move || -> Result<bool> {
+            let _guard = lock.lock().map_err(|_| anyhow!("Read access lock poisoned"))?;
+            if let Some(existing) = databases.get(&privacy.db_name) {
+                if (existing.issued_at, &existing.op_id) >= (privacy.issued_at, &privacy.op_id) {
+                    return Ok(false);
+                }
+            }
+            backend.read_access().insert(&privacy.db_name, serde_json::to_vec(&privacy)?)?;
+            databases.insert(privacy.db_name.clone(), privacy);
+            Ok(true)
+        })
+        .await
+        .map_err(|e| anyhow!("Thread join error: {}", e))?
+    }
+
+    /// Whether `db_name` is currently private
+    pub fn is_private(&self, db_name: &str) -> bool {
+        self.databases.get(db_name).is_some_and(|privacy| privacy.private)
+    }
+
+    /// Issue a random challenge for a read token, returning it and its expiry
+    pub fn issue_challenge(&self) -> (String, i64) {
+        let now = now_ms();
+        self.challenges.retain(|_, expires_at| *expires_at > now);
+        let challenge = hex::encode(rand::random::<[u8; 32]>());
+        let expires_at = now + CHALLENGE_LIFETIME_MS;
+        self.challenges.insert(challenge.clone(), expires_at);
+        (challenge, expires_at)
+    }
+
+    /// Verify a credential's token, returning the reader's public key
+    pub fn verify(&self, credential: &ReadCredential) -> Result<String> {
+        let token = credential
+            .token
+            .as_deref()
+            .ok_or_else(|| anyhow!("A read token is required"))?;
+        let claims = ReadTokenClaims::verify(token).map_err(|e| anyhow!("Invalid read token: {}", e))?;
+        let now = now_ms();
+        claims.check_expiry(now)?;
+        if let Some(challenge) = &claims.challenge {
+            let live = self.challenges.get(challenge).is_some_and(|expires_at| *expires_at > now);
+            if !live {
+                bail!("Read token answers an unknown or expired challenge");
+            }
+        }
+        Ok(claims.public_key)
+    }
+
+    /// Check that a credential may read `db_name`: anyone may read a public
+    /// database, only the owner and live delegates a private one
+    pub fn authorize(&self, db_name: &str, credential: &ReadCredential, delegations: &Delegations) -> Result<()> {
+        if !self.is_private(db_name) {
+            return Ok(());
+        }
+        let reader = self
+            .verify(credential)
+            .map_err(|e| anyhow!("Database {} is private: {}", db_name, e))?;
+        if crypto::verify_db_name_secure(db_name, &reader).is_ok()
+            || delegations.is_active(db_name, &reader, now_ms())
+        {
+            return Ok(());
+        }
+        bail!("Key {} may not read private database {}", reader, db_name)
+    }
+
+    /// Like `authorize`, as a yes/no answer for filtering listings
+    pub fn can_read(&self, db_name: &str, credential: &ReadCredential, delegations: &Delegations) -> bool {
+        self.authorize(db_name, credential, delegations).is_ok()
+    }
+}
@@ -0,0 +1,136 @@
+//! Replay protection for client-signed writes
+//!
+//! Every signed operation accepted through `submitData`, `submitBatch` or a
+//! gossiped `SyncMessage::Operation` or `Batch` is recorded here, and a second
+//! operation with the same nonce is rejected, so an observed submission can't
+//! be resent later to revert a key.
+//!
+//! Canonical, batch and legacy full-format signatures cover the operation ID
+//! and timestamp, so the ID is the nonce and only needs remembering for as long
+//! as `crypto::validate_timestamp` would still accept the timestamp. Legacy
+//! short-format signatures cover neither, so they could be resent with a fresh
+//! timestamp; the signature itself is recorded until the legacy formats stop
+//! being accepted (`crypto::legacy_signature_cutoff`), or for the freshness
+//! window if that ends later.
+//!
+//! An entry is recorded before the write it admits; callers `release` it if
+//! that write fails, so a failed write doesn't use up its nonce.
+//!
+//! ## Persistence
+//! Entries live in the storage backend's `nonces` keyspace (a dedicated sled
+//! tree on the default backend), so the window survives restarts. Each value is
+//! the entry's expiry in Unix milliseconds (big-endian i64):
+//!
+//! `n:<public_key>:<op_id>` or `s:<public_key>:<signature>`
+
+use anyhow::{anyhow, Result};
+use std::sync::{Arc, Mutex};
+
+use crate::crypto;
+use crate::storage_backend::StorageBackend;
+use crate::sync::{SignatureFormat, SignedOperation};
+
+/// Seen-nonce window over a storage backend
+#[derive(Clone)]
+pub struct ReplayGuard {
+    backend: Arc<dyn StorageBackend>,
+    /// Serializes check-and-record so concurrent duplicates can't both pass
+    lock: Arc<Mutex<()>>,
+}
+
+impl ReplayGuard {
+    pub fn with_backend(backend: Arc<dyn StorageBackend>) -> Self {
+        Self {
+            backend,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Record a verified operation, failing if its nonce was already used.
+    /// `format` is the signature format returned by `SignedOperation::verify`.
+    pub async fn check(&self, op: &SignedOperation, format: SignatureFormat) -> Result<()> {
+        let entry = entry(op, format);
+        let mut expires_at = op
+            .timestamp
+            .saturating_add(crypto::MAX_TIMESTAMP_TOLERANCE as i64 * 1000);
+        if format == SignatureFormat::LegacyShort {
+            expires_at = expires_at.max(crypto::legacy_signature_cutoff());
+        }
+
+        let backend = Arc::clone(&self.backend);
+        let lock = Arc::clone(&self.lock);
+        let fresh = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let _guard = lock.lock().map_err(|_| anyhow!("Replay guard lock poisoned"))?;
+            if backend.nonces().contains(&entry)? {
+                return Ok(false);
+            }
+            backend.nonces().insert(&entry, expires_at.to_be_bytes().to_vec())?;
+            Ok(true)
+        })
+        .await
+        .map_err(|e| anyhow!("Thread join error: {}", e))??;
+
+        if fresh {
+            Ok(())
+        } else if format == SignatureFormat::LegacyShort {
+            Err(anyhow!(
+                "Replayed signature: this {} signature was already used; sign the canonical envelope with a fresh nonce",
+                format
+            ))
+        } else {
+            Err(anyhow!("Replayed operation: nonce {} was already used", op.op_id))
+        }
+    }
+
+    /// Forget a nonce recorded by `check` whose write then failed, so the
+    /// operation can be resubmitted
+    pub async fn release(&self, op: &SignedOperation, format: SignatureFormat) -> Result<()> {
+        let entry = entry(op, format);
+        let backend = Arc::clone(&self.backend);
+        let lock = Arc::clone(&self.lock);
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let _guard = lock.lock().map_err(|_| anyhow!("Replay guard lock poisoned"))?;
+            backend.nonces().remove(&entry)?;
+            Ok(())
+        })
+        .await
+        .map_err(|e| anyhow!("Thread join error: {}", e))?
+    }
+
+    /// Drop entries that have expired. Returns the number removed.
+    pub async fn prune(&self) -> Result<usize> {
+        let backend = Arc::clone(&self.backend);
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut removed = 0;
+            let mut entries = backend.nonces().keys_with_prefix("n:")?;
+            entries.extend(backend.nonces().keys_with_prefix("s:")?);
+            for entry in entries {
+                let Some(bytes) = backend.nonces().get(&entry)? else {
+                    continue;
+                };
+                let expires_at = bytes
+                    .try_into()
+                    .map(i64::from_be_bytes)
+                    .unwrap_or(i64::MIN);
+                if expires_at < now_ms {
+                    backend.nonces().remove(&entry)?;
+                    removed += 1;
+                }
+            }
+            Ok(removed)
+        })
+        .await
+        .map_err(|e| anyhow!("Thread join error: {}", e))?
+    }
+}
+
+/// The nonces keyspace entry recording `op`
+fn entry(op: &SignedOperation, format: SignatureFormat) -> String {
+    match format {
+        SignatureFormat::Canonical | SignatureFormat::LegacyFull | SignatureFormat::MerkleBatch => {
+            format!("n:{}:{}", op.public_key, op.op_id)
+        }
+        SignatureFormat::LegacyShort => format!("s:{}:{}", op.public_key, op.signature),
+    }
+}
@@ -0,0 +1,922 @@
+//! Read-only SQL over a database's stores
+//!
+//! Each store type of a database is exposed as a virtual table:
+//!
+//! | table            | columns                      |
+//! |------------------|------------------------------|
+//! | `json_docs`      | `key, doc`                   |
+//! | `timeseries`     | `key, ts, value`             |
+//! | `stream_entries` | `key, id, field, value`      |
+//! | `geo`            | `key, member, lon, lat`      |
+//!
+//! `key` is the key within the database, without the `<db>:` prefix. Fields
+//! of a JSON document are addressed with dotted paths such as
+//! `doc.customer.tier`.
+//!
+//! Queries are a single `SELECT [DISTINCT] ... FROM <table> [WHERE ...]
+//! [GROUP BY ...] [HAVING ...] [ORDER BY ...] [LIMIT n] [OFFSET n]` with the
+//! aggregates `COUNT`, `SUM`, `AVG`, `MIN` and `MAX`. Anything that would
+//! modify data is rejected.
+//!
+//! WHERE conjuncts are pushed into the `filters` routines where possible:
+//! comparisons, `IN` and `LIKE` on `doc` paths become a filter document for
+//! `JsonFilter` (and so use secondary indexes, with its array semantics),
+//! `key = '...'` selects a single key, and bounds on `ts` and `value` become
+//! a `TimeSeriesFilter` range query.
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Value as JsonValue};
+use sqlparser::ast::{
+    BinaryOperator, Distinct, DuplicateTreatment, Expr, Function, FunctionArg, FunctionArgExpr,
+    FunctionArguments, GroupByExpr, OrderByExpr, SelectItem, SetExpr, Statement, TableFactor,
+    UnaryOperator, Value,
+};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use crate::aggregation::compare;
+use crate::filters::{
+    resolve_path, FilterExpr, FilterOptions, JsonFilter, JsonFilterConditions, StreamFilter,
+    TimeSeriesFilter, TimeSeriesOptions,
+};
+use crate::storage::{BlobStorage, StoreType};
+
+/// Virtual table over one store type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    JsonDocs,
+    TimeSeries,
+    StreamEntries,
+    Geo,
+}
+
+impl Table {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json_docs" => Some(Self::JsonDocs),
+            "timeseries" => Some(Self::TimeSeries),
+            "stream_entries" => Some(Self::StreamEntries),
+            "geo" => Some(Self::Geo),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::JsonDocs => "json_docs",
+            Self::TimeSeries => "timeseries",
+            Self::StreamEntries => "stream_entries",
+            Self::Geo => "geo",
+        }
+    }
+
+    pub fn columns(self) -> &'static [&'static str] {
+        match self {
+            Self::JsonDocs => &["key", "doc"],
+            Self::TimeSeries => &["key", "ts", "value"],
+            Self::StreamEntries => &["key", "id", "field", "value"],
+            Self::Geo => &["key", "member", "lon", "lat"],
+        }
+    }
+}
+
+/// Result set of a query
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SqlResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<JsonValue>>,
+}
+
+/// Runs SQL queries against a storage instance
+pub struct SqlEngine<'a> {
+    storage: &'a BlobStorage,
+}
+
+impl<'a> SqlEngine<'a> {
+    pub fn new(storage: &'a BlobStorage) -> Self {
+        Self { storage }
+    }
+
+    /// Run a SELECT over the virtual tables of `db_name`
+    pub async fn query(&self, db_name: &str, sql: &str) -> Result<SqlResult> {
+        let query = SelectQuery::parse(sql)?;
+        let mut conjuncts = Vec::new();
+        if let Some(selection) = &query.selection {
+            split_conjuncts(selection, &mut conjuncts);
+        }
+        let (rows, residual) = self.scan(db_name, &query.scope, conjuncts).await?;
+
+        let mut matching = Vec::new();
+        for row in rows {
+            if residual
+                .iter()
+                .map(|expr| query.scope.eval(expr, &Context::Row(&row)).map(|v| truth(&v)))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .all(|t| t == Some(true))
+            {
+                matching.push(row);
+            }
+        }
+        query.execute(matching)
+    }
+
+    /// Rows of the table, and the conjuncts still to be checked on them
+    async fn scan<'q>(
+        &self,
+        db_name: &str,
+        scope: &Scope,
+        conjuncts: Vec<&'q Expr>,
+    ) -> Result<(Vec<Vec<JsonValue>>, Vec<&'q Expr>)> {
+        let prefix = format!("{}:", db_name);
+        let short_key = |key: &str| JsonValue::String(key.strip_prefix(&prefix).unwrap_or(key).to_string());
+        let key_eq = conjuncts.iter().find_map(|expr| scope.key_equality(expr));
+        let full_key = key_eq.as_ref().map(|key| format!("{}{}", prefix, key));
+        let wanted = |key: &String| full_key.as_ref().is_none_or(|full_key| full_key == key);
+
+        let mut rows = Vec::new();
+        match scope.table {
+            Table::JsonDocs => {
+                let (pushed, residual): (Vec<_>, Vec<_>) = conjuncts
+                    .into_iter()
+                    .map(|expr| (scope.pushdown(expr), expr))
+                    .partition(|(clause, _)| clause.is_some());
+                let residual = residual.into_iter().map(|(_, expr)| expr).collect();
+
+                if pushed.is_empty() {
+                    let keys = self.storage.get_keys_by_type(db_name, StoreType::Json).await?;
+                    for key in keys.into_iter().filter(wanted) {
+                        if let Some(doc) = self.storage.get_json(&key, None).await? {
+                            rows.push(vec![short_key(&key), serde_json::from_str(&doc)?]);
+                        }
+                    }
+                } else {
+                    let clauses: Vec<JsonValue> = pushed.into_iter().filter_map(|(clause, _)| clause).collect();
+                    let conditions = JsonFilterConditions::from_filter(FilterExpr::parse(&json!({ "$and": clauses }))?);
+                    let pattern = match &key_eq {
+                        Some(key) if key.chars().all(|c| c.is_alphanumeric() || "_-:.".contains(c)) => {
+                            format!("{}{}", prefix, key)
+                        }
+                        _ => format!("{}*", prefix),
+                    };
+                    let docs = JsonFilter::new(self.storage)
+                        .filter_across_keys(&pattern, &conditions, &FilterOptions::default())
+                        .await?;
+                    for mut doc in docs {
+                        let key = doc.as_object_mut().and_then(|obj| obj.remove("_key"));
+                        if let Some(JsonValue::String(key)) = key {
+                            rows.push(vec![short_key(&key), doc]);
+                        }
+                    }
+                }
+                return Ok((rows, residual));
+            }
+            Table::TimeSeries => {
+                let (ts_min, ts_max) = scope.bounds(&conjuncts, "ts");
+                let (min_value, max_value) = scope.bounds(&conjuncts, "value");
+                let options = TimeSeriesOptions {
+                    min_value,
+                    max_value,
+                    ..Default::default()
+                };
+                let filter = TimeSeriesFilter::new(self.storage);
+                let from = ts_min.map_or(i64::MIN, |ts| ts.ceil() as i64);
+                let to = ts_max.map_or(i64::MAX, |ts| ts.floor() as i64);
+                let keys = self.storage.get_keys_by_type(db_name, StoreType::TimeSeries).await?;
+                for key in keys.into_iter().filter(wanted) {
+                    for (ts, value) in filter.query(&key, from, to, &options).await? {
+                        rows.push(vec![short_key(&key), JsonValue::from(ts), JsonValue::from(value)]);
+                    }
+                }
+            }
+            Table::StreamEntries => {
+                let filter = StreamFilter::new(self.storage);
+                let keys = self.storage.get_keys_by_type(db_name, StoreType::Stream).await?;
+                for key in keys.into_iter().filter(wanted) {
+                    let name = key.strip_prefix(&prefix).unwrap_or(&key);
+                    for (id, fields) in filter.get_entries(db_name, name, "-", "+").await? {
+                        for (field, value) in fields {
+                            rows.push(vec![
+                                short_key(&key),
+                                JsonValue::String(id.clone()),
+                                JsonValue::String(field),
+                                JsonValue::String(value),
+                            ]);
+                        }
+                    }
+                }
+            }
+            Table::Geo => {
+                for (key, members, _) in self.storage.get_all_geo(db_name).await? {
+                    if !wanted(&key) {
+                        continue;
+                    }
+                    for (member, lon, lat) in members {
+                        rows.push(vec![
+                            short_key(&key),
+                            JsonValue::String(member),
+                            JsonValue::from(lon),
+                            JsonValue::from(lat),
+                        ]);
+                    }
+                }
+            }
+        }
+        // Scalar columns have SQL semantics in the filter routines too, but
+        // bounds are pushed down inclusively, so everything is rechecked
+        Ok((rows, conjuncts))
+    }
+}
+
+/// The parts of a SELECT this engine runs
+struct SelectQuery {
+    scope: Scope,
+    distinct: bool,
+    projection: Vec<SelectItem>,
+    selection: Option<Expr>,
+    group_by: Vec<Expr>,
+    having: Option<Expr>,
+    order_by: Vec<OrderByExpr>,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+impl SelectQuery {
+    fn parse(sql: &str) -> Result<Self> {
+        let mut statements = Parser::parse_sql(&GenericDialect {}, sql)?;
+        if statements.len() != 1 {
+            bail!("Expected a single SELECT statement");
+        }
+        let Statement::Query(query) = statements.remove(0) else {
+            bail!("Only SELECT statements are supported");
+        };
+        let query = *query;
+        if query.with.is_some() || query.fetch.is_some() || !query.locks.is_empty() {
+            bail!("Unsupported query clause");
+        }
+        let SetExpr::Select(select) = *query.body else {
+            bail!("Only plain SELECT queries are supported");
+        };
+        let select = *select;
+
+        let [from] = select.from.as_slice() else {
+            bail!("Query exactly one table");
+        };
+        if !from.joins.is_empty() {
+            bail!("Joins are not supported");
+        }
+        let TableFactor::Table { name, alias, args: None, .. } = &from.relation else {
+            bail!("Unsupported FROM clause");
+        };
+        let table = match name.0.as_slice() {
+            [ident] => Table::from_name(&ident.value),
+            _ => None,
+        }
+        .ok_or_else(|| anyhow!("Unknown table {}; expected json_docs, timeseries, stream_entries or geo", name))?;
+
+        let distinct = match select.distinct {
+            None => false,
+            Some(Distinct::Distinct) => true,
+            Some(Distinct::On(_)) => bail!("DISTINCT ON is not supported"),
+        };
+        let group_by = match select.group_by {
+            GroupByExpr::Expressions(exprs, modifiers) if modifiers.is_empty() => exprs,
+            _ => bail!("Unsupported GROUP BY clause"),
+        };
+
+        Ok(Self {
+            scope: Scope {
+                table,
+                alias: alias.as_ref().map(|alias| alias.name.value.clone()),
+            },
+            distinct,
+            projection: select.projection,
+            selection: select.selection,
+            group_by,
+            having: select.having,
+            order_by: query.order_by.map(|order_by| order_by.exprs).unwrap_or_default(),
+            limit: query.limit.map(|limit| count_literal(&limit, "LIMIT")).transpose()?,
+            offset: query
+                .offset
+                .map(|offset| count_literal(&offset.value, "OFFSET"))
+                .transpose()?
+                .unwrap_or(0),
+        })
+    }
+
+    /// Project, group, sort and page the rows that passed WHERE
+    fn execute(&self, rows: Vec<Vec<JsonValue>>) -> Result<SqlResult> {
+        let scope = &self.scope;
+        let mut outputs = Vec::new();
+        for item in &self.projection {
+            match item {
+                SelectItem::UnnamedExpr(expr) => outputs.push((column_name(expr), expr.clone())),
+                SelectItem::ExprWithAlias { expr, alias } => outputs.push((alias.value.clone(), expr.clone())),
+                SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..) => {
+                    for column in scope.table.columns() {
+                        outputs.push((column.to_string(), Expr::Identifier((*column).into())));
+                    }
+                }
+            }
+        }
+
+        let grouped = !self.group_by.is_empty()
+            || self.having.is_some()
+            || outputs.iter().any(|(_, expr)| contains_aggregate(expr));
+        let contexts: Vec<Context> = if grouped {
+            // GROUP BY may name an output column or its position
+            let mut group_by = Vec::new();
+            for expr in &self.group_by {
+                group_by.push(match expr {
+                    Expr::Identifier(ident) if scope.reference(expr).is_none() => outputs
+                        .iter()
+                        .find(|(name, _)| *name == ident.value)
+                        .map(|(_, output)| output.clone())
+                        .ok_or_else(|| anyhow!("Unknown column {} in GROUP BY", ident))?,
+                    Expr::Value(Value::Number(n, _)) => n
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|p| outputs.get(p.wrapping_sub(1)))
+                        .map(|(_, output)| output.clone())
+                        .ok_or_else(|| anyhow!("GROUP BY position {} is out of range", n))?,
+                    expr => expr.clone(),
+                });
+            }
+            group_rows(scope, &group_by, &rows)?
+                .into_iter()
+                .map(Context::Group)
+                .collect()
+        } else {
+            rows.iter().map(|row| Context::Row(row)).collect()
+        };
+
+        // Output rows with their sort keys
+        let mut results = Vec::new();
+        for context in &contexts {
+            if let Some(having) = &self.having {
+                if truth(&scope.eval(having, context)?) != Some(true) {
+                    continue;
+                }
+            }
+            let row = outputs
+                .iter()
+                .map(|(_, expr)| scope.eval(expr, context))
+                .collect::<Result<Vec<_>>>()?;
+            let mut sort_keys = Vec::new();
+            for order in &self.order_by {
+                // ORDER BY may name an output column or its position
+                let alias = match &order.expr {
+                    Expr::Identifier(ident) => outputs.iter().position(|(name, _)| *name == ident.value),
+                    _ => None,
+                };
+                sort_keys.push(match (&order.expr, alias) {
+                    (_, Some(position)) => row[position].clone(),
+                    (Expr::Value(Value::Number(n, _)), None) => {
+                        let position = n
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|p| (1..=row.len()).contains(p))
+                            .ok_or_else(|| anyhow!("ORDER BY position {} is out of range", n))?;
+                        row[position - 1].clone()
+                    }
+                    (expr, None) => scope.eval(expr, context)?,
+                });
+            }
+            results.push((row, sort_keys));
+        }
+
+        if self.distinct {
+            let mut seen = HashSet::new();
+            results.retain(|(row, _)| seen.insert(JsonValue::Array(row.clone()).to_string()));
+        }
+        if !self.order_by.is_empty() {
+            results.sort_by(|(_, a), (_, b)| {
+                self.order_by
+                    .iter()
+                    .zip(a.iter().zip(b))
+                    .map(|(order, (a, b))| {
+                        let ordering = compare(a, b);
+                        if order.asc == Some(false) {
+                            ordering.reverse()
+                        } else {
+                            ordering
+                        }
+                    })
+                    .find(|ordering| *ordering != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+
+        Ok(SqlResult {
+            columns: outputs.into_iter().map(|(name, _)| name).collect(),
+            rows: results
+                .into_iter()
+                .skip(self.offset)
+                .take(self.limit.unwrap_or(usize::MAX))
+                .map(|(row, _)| row)
+                .collect(),
+        })
+    }
+}
+
+/// A row, or a group of rows after GROUP BY
+enum Context<'r> {
+    Row(&'r [JsonValue]),
+    Group(Vec<&'r [JsonValue]>),
+}
+
+/// The table a query reads, for resolving column references
+struct Scope {
+    table: Table,
+    alias: Option<String>,
+}
+
+impl Scope {
+    /// Column index and JSON path of a column reference such as `key`,
+    /// `doc.a.b` or `t.doc.a`
+    fn reference(&self, expr: &Expr) -> Option<(usize, Option<String>)> {
+        let parts: Vec<&str> = match expr {
+            Expr::Identifier(ident) => vec![ident.value.as_str()],
+            Expr::CompoundIdentifier(idents) => idents.iter().map(|ident| ident.value.as_str()).collect(),
+            _ => return None,
+        };
+        let qualified = parts.len() > 1
+            && (parts[0].eq_ignore_ascii_case(self.table.name())
+                || self.alias.as_deref().is_some_and(|alias| alias == parts[0]));
+        let parts = if qualified { &parts[1..] } else { &parts[..] };
+        let column = self
+            .table
+            .columns()
+            .iter()
+            .position(|column| column.eq_ignore_ascii_case(parts[0]))?;
+        Some((column, (parts.len() > 1).then(|| parts[1..].join("."))))
+    }
+
+    /// `key = '<literal>'`
+    fn key_equality(&self, expr: &Expr) -> Option<String> {
+        let Expr::BinaryOp { left, op: BinaryOperator::Eq, right } = expr else {
+            return None;
+        };
+        let (reference, literal) = match (self.reference(left), self.reference(right)) {
+            (Some(reference), None) => (reference, right),
+            (None, Some(reference)) => (reference, left),
+            _ => return None,
+        };
+        match (reference, literal_value(literal)?) {
+            ((0, None), JsonValue::String(key)) => Some(key),
+            _ => None,
+        }
+    }
+
+    /// Filter document clause for a conjunct on a `doc` path
+    fn pushdown(&self, expr: &Expr) -> Option<JsonValue> {
+        if self.table != Table::JsonDocs {
+            return None;
+        }
+        let doc_path = |expr: &Expr| match self.reference(expr) {
+            Some((1, Some(path))) => Some(path),
+            _ => None,
+        };
+        match expr {
+            Expr::BinaryOp { left, op, right } => {
+                let (path, literal, flipped) = match (doc_path(left), doc_path(right)) {
+                    (Some(path), None) => (path, right, false),
+                    (None, Some(path)) => (path, left, true),
+                    _ => return None,
+                };
+                let value = literal_value(literal).filter(|value| !value.is_null())?;
+                let operator = match (op, flipped) {
+                    (BinaryOperator::Eq, _) => "$eq",
+                    (BinaryOperator::Gt, false) | (BinaryOperator::Lt, true) => "$gt",
+                    (BinaryOperator::GtEq, false) | (BinaryOperator::LtEq, true) => "$gte",
+                    (BinaryOperator::Lt, false) | (BinaryOperator::Gt, true) => "$lt",
+                    (BinaryOperator::LtEq, false) | (BinaryOperator::GtEq, true) => "$lte",
+                    _ => return None,
+                };
+                Some(json!({ path: { operator: value } }))
+            }
+            Expr::InList { expr, list, negated: false } => {
+                let values = list
+                    .iter()
+                    .map(|item| literal_value(item).filter(|value| !value.is_null()))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(json!({ doc_path(expr)?: { "$in": values } }))
+            }
+            Expr::Like { negated: false, any: false, expr, pattern, escape_char: None } => {
+                let pattern = literal_value(pattern)?;
+                Some(json!({ doc_path(expr)?: { "$regex": like_regex(pattern.as_str()?) } }))
+            }
+            Expr::ILike { negated: false, any: false, expr, pattern, escape_char: None } => {
+                let pattern = literal_value(pattern)?;
+                Some(json!({ doc_path(expr)?: { "$regex": like_regex(pattern.as_str()?), "$options": "i" } }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Inclusive numeric bounds on a scalar column from the conjuncts
+    fn bounds(&self, conjuncts: &[&Expr], column: &str) -> (Option<f64>, Option<f64>) {
+        let target = self.table.columns().iter().position(|c| *c == column);
+        let is_column = |expr: &Expr| target.is_some() && self.reference(expr) == target.map(|t| (t, None));
+        let (mut low, mut high): (Option<f64>, Option<f64>) = (None, None);
+        let mut raise = |bound: f64| low = Some(low.map_or(bound, |low| low.max(bound)));
+        let mut lower = |bound: f64| high = Some(high.map_or(bound, |high| high.min(bound)));
+        for expr in conjuncts {
+            match expr {
+                Expr::BinaryOp { left, op, right } => {
+                    let (op, literal) = if is_column(left) {
+                        (op.clone(), right)
+                    } else if is_column(right) {
+                        (flip(op), left)
+                    } else {
+                        continue;
+                    };
+                    let Some(bound) = literal_value(literal).and_then(|v| v.as_f64()) else {
+                        continue;
+                    };
+                    match op {
+                        BinaryOperator::Gt | BinaryOperator::GtEq => raise(bound),
+                        BinaryOperator::Lt | BinaryOperator::LtEq => lower(bound),
+                        BinaryOperator::Eq => {
+                            raise(bound);
+                            lower(bound);
+                        }
+                        _ => {}
+                    }
+                }
+                Expr::Between { expr, negated: false, low: from, high: to } if is_column(expr) => {
+                    if let Some(bound) = literal_value(from).and_then(|v| v.as_f64()) {
+                        raise(bound);
+                    }
+                    if let Some(bound) = literal_value(to).and_then(|v| v.as_f64()) {
+                        lower(bound);
+                    }
+                }
+                _ => {}
+            }
+        }
+        (low, high)
+    }
+
+    fn eval(&self, expr: &Expr, context: &Context) -> Result<JsonValue> {
+        Ok(match expr {
+            Expr::Identifier(_) | Expr::CompoundIdentifier(_) => {
+                let (column, path) = self
+                    .reference(expr)
+                    .ok_or_else(|| anyhow!("Unknown column {} in {}", expr, self.table.name()))?;
+                let row = match context {
+                    Context::Row(row) => *row,
+                    // Bare columns in a group take the value of its first row
+                    Context::Group(rows) => match rows.first() {
+                        Some(row) => *row,
+                        None => return Ok(JsonValue::Null),
+                    },
+                };
+                match path {
+                    None => row[column].clone(),
+                    Some(path) => path_value(&row[column], &path),
+                }
+            }
+            Expr::Value(value) => literal(value)?,
+            Expr::Nested(inner) => self.eval(inner, context)?,
+            Expr::UnaryOp { op, expr } => {
+                let value = self.eval(expr, context)?;
+                match op {
+                    UnaryOperator::Not => truth(&value).map_or(JsonValue::Null, |t| JsonValue::Bool(!t)),
+                    UnaryOperator::Minus => arithmetic(&BinaryOperator::Minus, &JsonValue::from(0), &value),
+                    UnaryOperator::Plus => value,
+                    other => bail!("Unsupported operator {}", other),
+                }
+            }
+            Expr::BinaryOp { left, op, right } => {
+                let (left, right) = (self.eval(left, context)?, self.eval(right, context)?);
+                match op {
+                    BinaryOperator::And => match (truth(&left), truth(&right)) {
+                        (Some(false), _) | (_, Some(false)) => JsonValue::Bool(false),
+                        (Some(true), Some(true)) => JsonValue::Bool(true),
+                        _ => JsonValue::Null,
+                    },
+                    BinaryOperator::Or => match (truth(&left), truth(&right)) {
+                        (Some(true), _) | (_, Some(true)) => JsonValue::Bool(true),
+                        (Some(false), Some(false)) => JsonValue::Bool(false),
+                        _ => JsonValue::Null,
+                    },
+                    BinaryOperator::Eq
+                    | BinaryOperator::NotEq
+                    | BinaryOperator::Lt
+                    | BinaryOperator::LtEq
+                    | BinaryOperator::Gt
+                    | BinaryOperator::GtEq => comparison(op, &left, &right),
+                    BinaryOperator::StringConcat => match (&left, &right) {
+                        (JsonValue::Null, _) | (_, JsonValue::Null) => JsonValue::Null,
+                        _ => JsonValue::String(format!("{}{}", text(&left), text(&right))),
+                    },
+                    BinaryOperator::Plus
+                    | BinaryOperator::Minus
+                    | BinaryOperator::Multiply
+                    | BinaryOperator::Divide
+                    | BinaryOperator::Modulo => arithmetic(op, &left, &right),
+                    other => bail!("Unsupported operator {}", other),
+                }
+            }
+            Expr::IsNull(inner) => JsonValue::Bool(self.eval(inner, context)?.is_null()),
+            Expr::IsNotNull(inner) => JsonValue::Bool(!self.eval(inner, context)?.is_null()),
+            Expr::InList { expr, list, negated } => {
+                let value = self.eval(expr, context)?;
+                let mut result = Some(false);
+                for item in list {
+                    match truth(&comparison(&BinaryOperator::Eq, &value, &self.eval(item, context)?)) {
+                        Some(true) => {
+                            result = Some(true);
+                            break;
+                        }
+                        None => result = None,
+                        Some(false) => {}
+                    }
+                }
+                result.map_or(JsonValue::Null, |found| JsonValue::Bool(found != *negated))
+            }
+            Expr::Between { expr, negated, low, high } => {
+                let value = self.eval(expr, context)?;
+                let above = comparison(&BinaryOperator::GtEq, &value, &self.eval(low, context)?);
+                let below = comparison(&BinaryOperator::LtEq, &value, &self.eval(high, context)?);
+                match (truth(&above), truth(&below)) {
+                    (Some(false), _) | (_, Some(false)) => JsonValue::Bool(*negated),
+                    (Some(true), Some(true)) => JsonValue::Bool(!*negated),
+                    _ => JsonValue::Null,
+                }
+            }
+            Expr::Like { negated, any: false, expr, pattern, escape_char: None } => {
+                self.like(expr, pattern, *negated, false, context)?
+            }
+            Expr::ILike { negated, any: false, expr, pattern, escape_char: None } => {
+                self.like(expr, pattern, *negated, true, context)?
+            }
+            Expr::Function(function) => match context {
+                Context::Group(rows) if is_aggregate(function) => self.aggregate(function, rows)?,
+                _ if is_aggregate(function) => bail!("Aggregate {} is not allowed here", function),
+                _ => bail!("Unsupported function {}", function.name),
+            },
+            other => bail!("Unsupported expression {}", other),
+        })
+    }
+
+    fn like(&self, expr: &Expr, pattern: &Expr, negated: bool, case_insensitive: bool, context: &Context) -> Result<JsonValue> {
+        match (self.eval(expr, context)?, self.eval(pattern, context)?) {
+            (JsonValue::String(value), JsonValue::String(pattern)) => {
+                let re = regex::RegexBuilder::new(&like_regex(&pattern))
+                    .case_insensitive(case_insensitive)
+                    .build()?;
+                Ok(JsonValue::Bool(re.is_match(&value) != negated))
+            }
+            _ => Ok(JsonValue::Null),
+        }
+    }
+
+    fn aggregate(&self, function: &Function, rows: &[&[JsonValue]]) -> Result<JsonValue> {
+        let name = function.name.to_string().to_ascii_uppercase();
+        let FunctionArguments::List(arguments) = &function.args else {
+            bail!("{} needs an argument", name);
+        };
+        let arg = match arguments.args.as_slice() {
+            [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)] if name == "COUNT" => {
+                return Ok(JsonValue::from(rows.len()));
+            }
+            [FunctionArg::Unnamed(FunctionArgExpr::Expr(arg))] => arg,
+            _ => bail!("{} takes one argument", name),
+        };
+
+        let mut values = Vec::new();
+        for row in rows {
+            let value = self.eval(arg, &Context::Row(row))?;
+            if !value.is_null() {
+                values.push(value);
+            }
+        }
+        if matches!(arguments.duplicate_treatment, Some(DuplicateTreatment::Distinct)) {
+            let mut seen = HashSet::new();
+            values.retain(|value| seen.insert(value.to_string()));
+        }
+
+        let numbers: Vec<f64> = values.iter().filter_map(JsonValue::as_f64).collect();
+        Ok(match name.as_str() {
+            "COUNT" => JsonValue::from(values.len()),
+            "SUM" if numbers.is_empty() => JsonValue::Null,
+            "SUM" => number(numbers.iter().sum()),
+            "AVG" if numbers.is_empty() => JsonValue::Null,
+            "AVG" => number(numbers.iter().sum::<f64>() / numbers.len() as f64),
+            "MIN" => values.into_iter().min_by(compare).unwrap_or(JsonValue::Null),
+            "MAX" => values.into_iter().max_by(compare).unwrap_or(JsonValue::Null),
+            _ => unreachable!("checked by is_aggregate"),
+        })
+    }
+}
+
+fn count_literal(expr: &Expr, clause: &str) -> Result<usize> {
+    literal_value(expr)
+        .and_then(|value| value.as_u64())
+        .map(|n| n as usize)
+        .ok_or_else(|| anyhow!("{} needs a non-negative integer", clause))
+}
+
+/// Output column name of an unaliased expression
+fn column_name(expr: &Expr) -> String {
+    match expr {
+        Expr::Identifier(ident) => ident.value.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Top-level AND-ed parts of a WHERE clause
+fn split_conjuncts<'q>(expr: &'q Expr, out: &mut Vec<&'q Expr>) {
+    match expr {
+        Expr::BinaryOp { left, op: BinaryOperator::And, right } => {
+            split_conjuncts(left, out);
+            split_conjuncts(right, out);
+        }
+        Expr::Nested(inner) => split_conjuncts(inner, out),
+        other => out.push(other),
+    }
+}
+
+fn group_rows<'r>(scope: &Scope, group_by: &[Expr], rows: &'r [Vec<JsonValue>]) -> Result<Vec<Vec<&'r [JsonValue]>>> {
+    // Without GROUP BY, aggregates see one group, even over no rows
+    if group_by.is_empty() {
+        return Ok(vec![rows.iter().map(Vec::as_slice).collect()]);
+    }
+    // Groups in order of first appearance
+    let mut groups: Vec<Vec<&[JsonValue]>> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for row in rows {
+        let key = group_by
+            .iter()
+            .map(|expr| scope.eval(expr, &Context::Row(row)))
+            .collect::<Result<Vec<_>>>()?;
+        let position = *positions.entry(JsonValue::Array(key).to_string()).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[position].push(row);
+    }
+    Ok(groups)
+}
+
+fn is_aggregate(function: &Function) -> bool {
+    matches!(
+        function.name.to_string().to_ascii_uppercase().as_str(),
+        "COUNT" | "SUM" | "AVG" | "MIN" | "MAX"
+    )
+}
+
+fn contains_aggregate(expr: &Expr) -> bool {
+    match expr {
+        Expr::Function(function) => is_aggregate(function),
+        Expr::BinaryOp { left, right, .. } => contains_aggregate(left) || contains_aggregate(right),
+        Expr::UnaryOp { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::IsNull(expr)
+        | Expr::IsNotNull(expr) => contains_aggregate(expr),
+        Expr::InList { expr, list, .. } => contains_aggregate(expr) || list.iter().any(contains_aggregate),
+        Expr::Between { expr, low, high, .. } => {
+            contains_aggregate(expr) || contains_aggregate(low) || contains_aggregate(high)
+        }
+        Expr::Like { expr, pattern, .. } | Expr::ILike { expr, pattern, .. } => {
+            contains_aggregate(expr) || contains_aggregate(pattern)
+        }
+        _ => false,
+    }
+}
+
+/// Value of a literal expression, including negative numbers
+fn literal_value(expr: &Expr) -> Option<JsonValue> {
+    match expr {
+        Expr::Value(value) => literal(value).ok(),
+        Expr::Nested(inner) => literal_value(inner),
+        Expr::UnaryOp { op: UnaryOperator::Minus, expr } => {
+            Some(arithmetic(&BinaryOperator::Minus, &JsonValue::from(0), &literal_value(expr)?))
+        }
+        _ => None,
+    }
+}
+
+fn literal(value: &Value) -> Result<JsonValue> {
+    Ok(match value {
+        Value::Number(n, _) => match n.parse::<i64>() {
+            Ok(n) => JsonValue::from(n),
+            Err(_) => number(n.parse::<f64>()?),
+        },
+        Value::SingleQuotedString(s) | Value::DoubleQuotedString(s) => JsonValue::String(s.clone()),
+        Value::Boolean(b) => JsonValue::Bool(*b),
+        Value::Null => JsonValue::Null,
+        other => bail!("Unsupported literal {}", other),
+    })
+}
+
+/// Value at a dotted path; several values (through arrays) become an array
+fn path_value(value: &JsonValue, path: &str) -> JsonValue {
+    let mut found = Vec::new();
+    resolve_path(value, path, &mut found);
+    match found.len() {
+        0 => JsonValue::Null,
+        1 => found[0].clone(),
+        _ => JsonValue::Array(found.into_iter().cloned().collect()),
+    }
+}
+
+/// SQL truth value; anything but a boolean is unknown
+fn truth(value: &JsonValue) -> Option<bool> {
+    value.as_bool()
+}
+
+/// The operator with its operands swapped
+fn flip(op: &BinaryOperator) -> BinaryOperator {
+    match op {
+        BinaryOperator::Gt => BinaryOperator::Lt,
+        BinaryOperator::GtEq => BinaryOperator::LtEq,
+        BinaryOperator::Lt => BinaryOperator::Gt,
+        BinaryOperator::LtEq => BinaryOperator::GtEq,
+        other => other.clone(),
+    }
+}
+
+/// Comparisons are unknown with NULL; ordering needs operands of one type
+fn comparison(op: &BinaryOperator, left: &JsonValue, right: &JsonValue) -> JsonValue {
+    if left.is_null() || right.is_null() {
+        return JsonValue::Null;
+    }
+    let ordering = match (left, right) {
+        (JsonValue::Number(_), JsonValue::Number(_))
+        | (JsonValue::String(_), JsonValue::String(_))
+        | (JsonValue::Bool(_), JsonValue::Bool(_)) => Some(compare(left, right)),
+        _ => None,
+    };
+    let equal = ordering.map_or(left == right, |ordering| ordering == Ordering::Equal);
+    match (op, ordering) {
+        (BinaryOperator::Eq, _) => JsonValue::Bool(equal),
+        (BinaryOperator::NotEq, _) => JsonValue::Bool(!equal),
+        (_, None) => JsonValue::Null,
+        (BinaryOperator::Lt, Some(ordering)) => JsonValue::Bool(ordering == Ordering::Less),
+        (BinaryOperator::LtEq, Some(ordering)) => JsonValue::Bool(ordering != Ordering::Greater),
+        (BinaryOperator::Gt, Some(ordering)) => JsonValue::Bool(ordering == Ordering::Greater),
+        (BinaryOperator::GtEq, Some(ordering)) => JsonValue::Bool(ordering != Ordering::Less),
+        _ => JsonValue::Null,
+    }
+}
+
+/// Integer arithmetic when both operands are integers, otherwise floating point
+fn arithmetic(op: &BinaryOperator, left: &JsonValue, right: &JsonValue) -> JsonValue {
+    if let (Some(a), Some(b)) = (left.as_i64(), right.as_i64()) {
+        let result = match op {
+            BinaryOperator::Plus => a.checked_add(b),
+            BinaryOperator::Minus => a.checked_sub(b),
+            BinaryOperator::Multiply => a.checked_mul(b),
+            BinaryOperator::Divide => a.checked_div(b),
+            BinaryOperator::Modulo => a.checked_rem(b),
+            _ => None,
+        };
+        return result.map_or(JsonValue::Null, JsonValue::from);
+    }
+    let (Some(a), Some(b)) = (left.as_f64(), right.as_f64()) else {
+        return JsonValue::Null;
+    };
+    match op {
+        BinaryOperator::Plus => number(a + b),
+        BinaryOperator::Minus => number(a - b),
+        BinaryOperator::Multiply => number(a * b),
+        BinaryOperator::Divide if b != 0.0 => number(a / b),
+        BinaryOperator::Modulo if b != 0.0 => number(a % b),
+        _ => JsonValue::Null,
+    }
+}
+
+/// Integral results stay integers
+fn number(value: f64) -> JsonValue {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        JsonValue::from(value as i64)
+    } else {
+        serde_json::Number::from_f64(value).map(JsonValue::Number).unwrap_or(JsonValue::Null)
+    }
+}
+
+fn text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Anchored regex for a LIKE pattern (`%` any run, `_` one character)
+fn like_regex(pattern: &str) -> String {
+    let mut re = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '%' => re.push_str(".*"),
+            '_' => re.push('.'),
+            other => re.push_str(&regex::escape(&other.to_string())),
+        }
+    }
+    re.push('$');
+    re
+}
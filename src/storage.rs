@@ -1,18 +1,32 @@
 //! Storage Module
 //! 
 //! This module provides a distributed, content-addressed storage system
-//! using Sled (embedded B-tree DB) + Iroh Blobs (content-addressed storage).
+//! on top of a pluggable `StorageBackend` (default: Sled index + Iroh Blobs).
 //!
 //! ## Architecture
 //! - **BlobStorage**: Main storage interface (public API)
+//! - **StorageBackend**: Index + value persistence (see `storage_backend.rs`)
 //! - **TieredCache**: Two-tier LRU cache (hot/warm) with Arc for zero-copy reads
 //! - **BatchWriter**: Parallel write processing with semaphore-based concurrency control
+//! - **Write-ahead journal**: Makes each index update atomic with its value write
+//! - **Blob encoding**: Header + optional zstd compression for value blobs (see `blob_encoding.rs`)
+//! - **Versioning**: Schema version tag on every value body (see `versioning.rs`)
+//! - **Secondary indexes**: JSON/Hash field indexes kept current on every write (see `indexing.rs`)
+//! - **Vector store**: HNSW graph persisted with each vector value (see `vector.rs`)
 //!
 //! ## Components
-//! - Core storage: Sled index + Iroh blobs
+//! - Core storage: backend index + content-addressed values
 //! - Caching: Moka async cache with 55k total capacity
 //! - Metrics: Prometheus integration for observability
 //! - Concurrency: Tokio async + blocking thread pools for I/O
+//!
+//! ## Durability
+//! Every write first records a journal entry (`key -> new index entry + previous
+//! entry`), then writes the value, then the index. Journal entries are cleared once
+//! the value is known to be on disk: immediately for `Fsync` writes, otherwise by the
+//! periodic checkpoint task. On startup `recover_journal` rolls each leftover entry
+//! forward if its value survived the crash, or back to the previous entry if not,
+//! so the index never points at a missing value.
 
 use anyhow::Result;
 use async_graphql::SimpleObject;
@@ -21,13 +35,26 @@ use iroh_blobs::Hash;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use dashmap::DashMap;
 use moka::future::Cache as MokaCache;
-use sled::Db as SledDb;
+use crate::blob_encoding::{self, CompressionConfig};
+use crate::delegation::Delegations;
+use crate::encryption::EncryptedDatabases;
+use crate::indexing::{IndexManager, IndexType};
 use crate::metrics::{self, Timer};
-use tokio::sync::Semaphore;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::read_access::ReadAccess;
+use crate::changes::ChangeFeed;
+use crate::error::DbError;
+use crate::replay::ReplayGuard;
+use crate::vector::{DistanceMetric, HnswIndex, VectorEntry, VectorMatch};
+use crate::versioning;
+use crate::sync::SignedOperation;
+use crate::storage_backend::{BackendKind, MemoryBackend, SledIrohBackend, StorageBackend};
+use tokio::sync::{Mutex as AsyncMutex, RwLock as AsyncRwLock, Semaphore};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StoreType {
     String,
     Hash,
@@ -38,6 +65,48 @@ pub enum StoreType {
     Stream,
     TimeSeries,
     Geo,
+    Vector,
+}
+
+/// How far a write must reach before it is acknowledged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, async_graphql::Enum)]
+pub enum Durability {
+    /// Acknowledge once buffered; index flushed in the background (~1s)
+    #[default]
+    Fast,
+    /// Flush the index and journal to disk before acknowledging
+    FlushOnCommit,
+    /// Sync the value to disk before the index is written, then flush the index
+    Fsync,
+}
+
+impl Durability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Durability::Fast => "fast",
+            Durability::FlushOnCommit => "flush",
+            Durability::Fsync => "fsync",
+        }
+    }
+}
+
+impl std::str::FromStr for Durability {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "fast" | "none" => Ok(Durability::Fast),
+            "flush" | "flush-on-commit" | "flush_on_commit" => Ok(Durability::FlushOnCommit),
+            "fsync" | "sync" => Ok(Durability::Fsync),
+            other => Err(anyhow::anyhow!("Unknown durability level: {}", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for Durability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 // Metadata for signed data verification
@@ -163,6 +232,8 @@ pub struct StoredEntry {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct JsonValue {
+    // bincode can't decode a serde_json::Value directly, so it's kept as JSON text
+    #[serde(with = "json_text")]
     data: serde_json::Value,
     // Track _id for deduplication if present
     id: Option<String>,
@@ -182,6 +253,8 @@ struct TimeSeriesValue {
     points: BTreeMap<i64, f64>,
     metadata: Option<SignatureMetadata>,
     ttl: Option<TtlMetadata>,
+    /// Labels for selecting series in multi-series queries (stored value v2)
+    labels: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -191,6 +264,27 @@ struct GeoValue {
     ttl: Option<TtlMetadata>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VectorValue {
+    index: HnswIndex,
+    metadata: Option<SignatureMetadata>,
+    ttl: Option<TtlMetadata>,
+}
+
+/// Serde adapter storing a `serde_json::Value` as its JSON text
+pub(crate) mod json_text {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &serde_json::Value, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<serde_json::Value, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        serde_json::from_str(&text).map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 enum StoredValue {
     String(StringValue),
@@ -202,6 +296,7 @@ enum StoredValue {
     Stream(StreamValue),
     TimeSeries(TimeSeriesValue),
     Geo(GeoValue),
+    Vector(VectorValue),
 }
 
 /// Tiered cache for performance optimization
@@ -273,20 +368,75 @@ impl TieredCache {
     }
 }
 
+/// Write-ahead record for an index update whose value may not be on disk yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JournalRecord {
+    /// Write sequence number, used by checkpoints to clear only synced records
+    seq: u64,
+    /// Serialized index entry `(hash, StoreType)` being written
+    entry: Vec<u8>,
+    /// Index entry that was in place before the first un-synced write
+    prev: Option<Vec<u8>>,
+}
+
+/// Result of replaying the journal at startup
+#[derive(Debug, Clone, Default)]
+pub struct JournalRecovery {
+    /// Entries whose value survived and were (re)applied to the index
+    pub rolled_forward: usize,
+    /// Entries whose value was lost and were reverted to the previous entry
+    pub rolled_back: usize,
+}
+
+/// Number of locks keys are spread over for secondary index maintenance
+const INDEX_LOCK_STRIPES: usize = 64;
+
+/// Default and per-database durability levels shared by all clones of a storage
+#[derive(Default)]
+struct DurabilityPolicy {
+    default: RwLock<Durability>,
+    per_database: DashMap<String, Durability>,
+}
+
 pub struct BlobStorage {
-    store: FsStore,
-    sled_db: SledDb,
-    index_tree: sled::Tree,
+    backend: Arc<dyn StorageBackend>,
     cache: Arc<TieredCache>,
+    durability: Arc<DurabilityPolicy>,
+    /// Durability requested for writes made through this handle (see `with_durability`)
+    request_durability: Option<Durability>,
+    journal_seq: Arc<AtomicU64>,
+    /// Writers hold this shared; checkpoints take it exclusively to pick a cutoff
+    journal_gate: Arc<AsyncRwLock<()>>,
+    compression: CompressionConfig,
+    indexes: IndexManager,
+    /// Held (by key stripe) from reading a key's previous document until its
+    /// secondary index entries are moved, so concurrent writes to one key
+    /// can't leave entries for a document that was overwritten
+    index_locks: Arc<Vec<AsyncMutex<()>>>,
+    replay: ReplayGuard,
+    delegations: Delegations,
+    encryption: EncryptedDatabases,
+    read_access: ReadAccess,
+    changes: ChangeFeed,
 }
 
 impl Clone for BlobStorage {
     fn clone(&self) -> Self {
         Self {
-            store: self.store.clone(),
-            sled_db: self.sled_db.clone(),
-            index_tree: self.index_tree.clone(),
+            backend: Arc::clone(&self.backend),
             cache: Arc::clone(&self.cache),
+            durability: Arc::clone(&self.durability),
+            request_durability: self.request_durability,
+            journal_seq: Arc::clone(&self.journal_seq),
+            journal_gate: Arc::clone(&self.journal_gate),
+            compression: self.compression,
+            indexes: self.indexes.clone(),
+            index_locks: Arc::clone(&self.index_locks),
+            replay: self.replay.clone(),
+            delegations: self.delegations.clone(),
+            encryption: self.encryption.clone(),
+            read_access: self.read_access.clone(),
+            changes: self.changes.clone(),
         }
     }
 }
@@ -377,6 +527,74 @@ impl BatchWriter {
         results
     }
 
+    /// Write verified signed operations in parallel with bounded concurrency,
+    /// each with its signature as metadata and the given TTL. Operations on
+    /// the same key are written one after another in input order, so appends
+    /// and merges aren't lost; different keys are written concurrently.
+    ///
+    /// # Returns
+    /// Vector of Results, one per operation (in same order as input)
+    pub async fn write_operations(
+        &self,
+        ops: &[SignedOperation],
+        ttl_seconds: Option<u64>,
+    ) -> Vec<Result<()>> {
+        let mut by_key: HashMap<String, Vec<(usize, SignedOperation)>> = HashMap::new();
+        for (index, op) in ops.iter().enumerate() {
+            by_key
+                .entry(format!("{}:{}", op.db_name, op.key))
+                .or_default()
+                .push((index, op.clone()));
+        }
+        tracing::debug!("BatchWriter writing {} operations on {} keys with max_concurrent={}",
+            ops.len(), by_key.len(), self.max_concurrent);
+
+        let mut handles = Vec::with_capacity(by_key.len());
+        for (_, group) in by_key {
+            let storage = self.storage.clone();
+            let semaphore = Arc::clone(&self.semaphore);
+
+            handles.push(tokio::spawn(async move {
+                let mut results = Vec::with_capacity(group.len());
+                for (index, op) in group {
+                    let result = match semaphore.acquire().await {
+                        Ok(_permit) => {
+                            let sig_meta = SignatureMetadata {
+                                public_key: op.public_key.clone(),
+                                signature: op.signature.clone(),
+                                timestamp: chrono::Utc::now().timestamp_millis(),
+                            };
+                            crate::changes::attribute(
+                                storage.changes(),
+                                crate::changes::ChangeEvent::from_op(&op, None),
+                                storage.write_signed_operation(&op, Some(sig_meta), ttl_seconds),
+                            )
+                            .await
+                        }
+                        Err(e) => Err(anyhow::anyhow!("Semaphore acquire failed: {}", e)),
+                    };
+                    results.push((index, result));
+                }
+                results
+            }));
+        }
+
+        let mut results: Vec<Result<()>> = (0..ops.len())
+            .map(|_| Err(anyhow::anyhow!("Operation was not written")))
+            .collect();
+        for handle in handles {
+            match handle.await {
+                Ok(group) => {
+                    for (index, result) in group {
+                        results[index] = result;
+                    }
+                }
+                Err(e) => tracing::error!("BatchWriter task join error: {}", e),
+            }
+        }
+        results
+    }
+
     /// Get statistics about current batch writer state
     pub fn stats(&self) -> BatchWriterStats {
         BatchWriterStats {
@@ -393,10 +611,10 @@ pub struct BatchWriterStats {
     pub available_permits: usize,
 }
 
-// Helper methods for interacting with sled index
+// Helper methods for interacting with the backend index
 impl BlobStorage {
     fn index_get(&self, key: &str) -> Result<Option<(String, StoreType)>> {
-        if let Ok(Some(v)) = self.index_tree.get(key.as_bytes()) {
+        if let Ok(Some(v)) = self.backend.index().get(key) {
             let tuple: (String, StoreType) = bincode::deserialize(&v)?;
             Ok(Some(tuple))
         } else {
@@ -405,31 +623,26 @@ impl BlobStorage {
     }
 
     fn index_exists(&self, key: &str) -> Result<bool> {
-        Ok(self.index_tree.contains_key(key.as_bytes())?)
+        self.backend.index().contains(key)
     }
 
     fn index_remove(&self, key: &str) -> Result<()> {
-        self.index_tree.remove(key.as_bytes())?;
-        Ok(())
+        self.backend.index().remove(key)?;
+        // Drop any pending journal record so recovery can't resurrect the key
+        self.backend.journal().remove(key)
     }
 
     fn index_keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
-        let mut res = Vec::new();
-        let prefix_bytes = prefix.as_bytes();
-        for item in self.index_tree.scan_prefix(prefix_bytes) {
-            let (k, _v) = item?;
-            res.push(String::from_utf8(k.to_vec())?);
-        }
-        Ok(res)
+        self.backend.index().keys_with_prefix(prefix)
     }
     
     // Async version of index operations using blocking pool
     async fn index_get_async(&self, key: &str) -> Result<Option<(String, StoreType)>> {
-        let index_tree = self.index_tree.clone();
+        let backend = Arc::clone(&self.backend);
         let key_owned = key.to_string();
         
         tokio::task::spawn_blocking(move || {
-            if let Ok(Some(v)) = index_tree.get(key_owned.as_bytes()) {
+            if let Ok(Some(v)) = backend.index().get(&key_owned) {
                 let tuple: (String, StoreType) = bincode::deserialize(&v)?;
                 Ok(Some(tuple))
             } else {
@@ -441,78 +654,159 @@ impl BlobStorage {
     }
     
     async fn index_keys_with_prefix_async(&self, prefix: &str) -> Result<Vec<String>> {
-        let index_tree = self.index_tree.clone();
+        let backend = Arc::clone(&self.backend);
         let prefix_owned = prefix.to_string();
         
-        tokio::task::spawn_blocking(move || {
-            let mut res = Vec::new();
-            let prefix_bytes = prefix_owned.as_bytes();
-            for item in index_tree.scan_prefix(prefix_bytes) {
-                let (k, _v) = item?;
-                res.push(String::from_utf8(k.to_vec())?);
-            }
-            Ok::<Vec<String>, anyhow::Error>(res)
-        })
-        .await
-        .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))?
+        tokio::task::spawn_blocking(move || backend.index().keys_with_prefix(&prefix_owned))
+            .await
+            .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))?
     }
 }
 
 impl BlobStorage {
+    /// Create storage on the default Sled + Iroh backend
     pub async fn new(store: FsStore, sled_path: Option<PathBuf>) -> Result<Self> {
         tracing::info!("Initializing BlobStorage with FsStore");
+        let backend = SledIrohBackend::open(store, sled_path)?;
+        Self::open(Arc::new(backend)).await
+    }
+
+    /// Create storage on an opened backend, replay any leftover journal entries
+    /// and load persisted secondary indexes, grants, encryption metadata and
+    /// privacy flags
+    pub async fn open(backend: Arc<dyn StorageBackend>) -> Result<Self> {
+        let storage = Self::with_backend(backend);
+        storage.indexes.load().await?;
+        storage.delegations.load().await?;
+        storage.encryption.load().await?;
+        storage.read_access.load().await?;
+        let recovery = storage.recover_journal().await?;
+        if recovery.rolled_forward + recovery.rolled_back > 0 {
+            tracing::warn!(
+                "Storage journal recovery: {} rolled forward, {} rolled back",
+                recovery.rolled_forward,
+                recovery.rolled_back
+            );
+        }
+        Ok(storage)
+    }
 
-        // Determine sled DB path
-        let sled_path = sled_path.unwrap_or_else(|| PathBuf::from("./data/sled_db"));
-        
-        // Calculate Sled cache size from environment variable or use sensible default
-        // Default: 256MB (safe for Docker containers with 512MB-1GB RAM)
-        // Override with SLED_CACHE_MB environment variable
-        let cache_mb: u64 = std::env::var("SLED_CACHE_MB")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(256);
-        let cache_bytes = cache_mb * 1024 * 1024;
-        
-        tracing::info!("Configuring Sled with cache_mb={} (from SLED_CACHE_MB or default)", cache_mb);
-        let sled_config = sled::Config::new()
-            .path(&sled_path)
-            // Dynamic cache size (default 256MB, configurable via SLED_CACHE_MB)
-            .cache_capacity(cache_bytes)
-            // Batch writes for better throughput (flush every 1 second)
-            .flush_every_ms(Some(1000))
-            // Use high-throughput mode for write-heavy workloads
-            .mode(sled::Mode::HighThroughput)
-            // Enable compression to save disk space
-            .use_compression(true)
-            // Not temporary - this is production data
-            .temporary(false);
-        
-        let sled_db = sled_config.open()?;
-        let index_tree = sled_db.open_tree("storage_index")?;
-        
-        tracing::info!("Sled configured: cache={}MB, flush=1s, mode=HighThroughput, compression=enabled", cache_mb);
-
+    /// Create storage on top of an already opened backend (without loading
+    /// persisted indexes, grants, encryption metadata or privacy flags; see `open`)
+    pub fn with_backend(backend: Arc<dyn StorageBackend>) -> Self {
         // Create tiered cache with Arc for zero-copy reads
         // Hot tier: 5k entries, 5min TTL (most frequent)
         // Warm tier: 50k entries, 1hr TTL (less frequent)
         let cache = TieredCache::new();
         tracing::info!("Tiered cache configured: hot=5k/5min, warm=50k/1hr, Arc-based zero-copy");
 
-        let storage = Self {
-            store,
-            sled_db,
-            index_tree,
+        tracing::info!("BlobStorage initialized successfully with {} backend", backend.kind());
+        // Start sequence numbers at the current time so they keep increasing across restarts
+        let seq_start = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0);
+        Self {
+            indexes: IndexManager::with_backend(Arc::clone(&backend)),
+            index_locks: Arc::new((0..INDEX_LOCK_STRIPES).map(|_| AsyncMutex::new(())).collect()),
+            replay: ReplayGuard::with_backend(Arc::clone(&backend)),
+            delegations: Delegations::with_backend(Arc::clone(&backend)),
+            encryption: EncryptedDatabases::with_backend(Arc::clone(&backend)),
+            read_access: ReadAccess::with_backend(Arc::clone(&backend)),
+            changes: ChangeFeed::default(),
+            backend,
             cache: Arc::new(cache),
-        };
+            durability: Arc::new(DurabilityPolicy::default()),
+            request_durability: None,
+            journal_seq: Arc::new(AtomicU64::new(seq_start)),
+            journal_gate: Arc::new(AsyncRwLock::new(())),
+            compression: CompressionConfig::default(),
+        }
+    }
 
-        tracing::info!("BlobStorage initialized successfully with sled index at {:?}", sled_path);
-        Ok(storage)
+    /// Create ephemeral storage backed by in-memory maps (tests, throwaway nodes)
+    pub fn in_memory() -> Self {
+        Self::with_backend(Arc::new(MemoryBackend::new()))
+    }
+
+    /// Kind of backend this storage is running on
+    pub fn backend_kind(&self) -> BackendKind {
+        self.backend.kind()
+    }
+
+    /// Set value blob compression; applies to handles cloned after this call
+    pub fn set_compression(&mut self, config: CompressionConfig) {
+        self.compression = config;
+    }
+
+    /// Set the durability level used by databases without an override
+    pub fn set_default_durability(&self, level: Durability) {
+        if let Ok(mut default) = self.durability.default.write() {
+            *default = level;
+        }
+    }
+
+    /// Override the durability level for one database
+    pub fn set_database_durability(&self, db_name: &str, level: Durability) {
+        self.durability.per_database.insert(db_name.to_string(), level);
+    }
+
+    /// Handle whose writes use at least `level`; a request can strengthen its
+    /// database's durability but never weaken it
+    pub fn with_durability(&self, level: Durability) -> Self {
+        let mut storage = self.clone();
+        storage.request_durability = Some(level);
+        storage
+    }
+
+    /// Effective durability for a write to `key` (`<db_name>:<key>`)
+    pub fn durability_for(&self, key: &str) -> Durability {
+        let db_name = key.split(':').next().unwrap_or(key);
+        let base = self
+            .durability
+            .per_database
+            .get(db_name)
+            .map(|level| *level)
+            .unwrap_or_else(|| self.durability.default.read().map(|d| *d).unwrap_or_default());
+        match self.request_durability {
+            Some(requested) => requested.max(base),
+            None => base,
+        }
+    }
+
+    /// Secondary indexes maintained by this storage
+    pub fn index_manager(&self) -> &IndexManager {
+        &self.indexes
+    }
+
+    /// Seen-nonce window for signed writes
+    pub fn replay_guard(&self) -> &ReplayGuard {
+        &self.replay
+    }
+
+    /// Write access granted by database owners
+    pub fn delegations(&self) -> &Delegations {
+        &self.delegations
     }
 
-    /// Get reference to underlying FsStore
-    pub fn inner_store(&self) -> FsStore {
-        self.store.clone()
+    /// Databases whose values are sealed by clients
+    pub fn encryption(&self) -> &EncryptedDatabases {
+        &self.encryption
+    }
+
+    /// Which databases are private, and read tokens for them
+    pub fn read_access(&self) -> &ReadAccess {
+        &self.read_access
+    }
+
+    /// Feed of applied operations for live subscriptions
+    pub fn changes(&self) -> &ChangeFeed {
+        &self.changes
+    }
+
+    /// Get reference to underlying FsStore (only for backends that keep values in Iroh)
+    pub fn inner_store(&self) -> Option<FsStore> {
+        self.backend.blob_store()
     }
 
     /// Create a BatchWriter for parallel write processing
@@ -527,6 +821,128 @@ impl BlobStorage {
         BatchWriter::new(self.clone(), concurrency)
     }
 
+    /// Write a verified signed operation with its signature metadata and TTL,
+    /// dispatching on its store type. Invalid operations fail with
+    /// `DbError::InvalidData`.
+    pub async fn write_signed_operation(
+        &self,
+        op: &SignedOperation,
+        sig_meta: Option<SignatureMetadata>,
+        ttl_seconds: Option<u64>,
+    ) -> Result<()> {
+        let full_key = format!("{}:{}", op.db_name, op.key);
+
+        match op.store_type.to_lowercase().as_str() {
+            "string" => {
+                self.set_string_with_ttl(&full_key, &op.value, sig_meta, ttl_seconds).await?;
+            }
+            "hash" => {
+                let field = op.field.as_deref().ok_or_else(|| {
+                    DbError::InvalidData("Field required for Hash type".to_string())
+                })?;
+                self.set_hash_with_ttl(&full_key, field, &op.value, sig_meta, ttl_seconds).await?;
+            }
+            "list" => {
+                self.push_list_with_ttl(&full_key, &op.value, sig_meta, ttl_seconds).await?;
+            }
+            "set" => {
+                self.add_set_with_ttl(&full_key, &op.value, sig_meta, ttl_seconds).await?;
+            }
+            "sortedset" => {
+                let score = op.score.ok_or_else(|| {
+                    DbError::InvalidData("Score required for SortedSet type".to_string())
+                })?;
+                self.add_sorted_set_with_ttl(&full_key, score, &op.value, sig_meta, ttl_seconds)
+                    .await?;
+            }
+            "json" => {
+                let path = op.json_path.as_deref().unwrap_or("$");
+                self.set_json_with_ttl(&full_key, path, &op.value, sig_meta, ttl_seconds).await?;
+            }
+            "stream" => {
+                // stream_fields is a JSON array: [{"key": "field1", "value": "val1"}, ...]
+                let fields_json = op.stream_fields.as_deref().ok_or_else(|| {
+                    DbError::InvalidData("stream_fields required for Stream type".to_string())
+                })?;
+                let fields: Vec<serde_json::Value> = serde_json::from_str(fields_json).map_err(|e| {
+                    DbError::InvalidData(format!("Invalid stream_fields JSON: {}", e))
+                })?;
+                let field_pairs: Vec<(String, String)> = fields
+                    .iter()
+                    .filter_map(|field_obj| {
+                        let key = field_obj.get("key").and_then(|k| k.as_str())?;
+                        let value = field_obj.get("value").and_then(|v| v.as_str())?;
+                        Some((key.to_string(), value.to_string()))
+                    })
+                    .collect();
+                self.xadd_with_ttl(&full_key, "*", &field_pairs, sig_meta, ttl_seconds).await?;
+            }
+            "timeseries" => {
+                let timestamp = op
+                    .ts_timestamp
+                    .as_deref()
+                    .ok_or_else(|| DbError::InvalidData("timestamp required for TimeSeries type".to_string()))?
+                    .parse::<i64>()
+                    .map_err(|_| DbError::InvalidData("Invalid timestamp format".to_string()))?;
+                let value = op.value.parse::<f64>().map_err(|_| {
+                    DbError::InvalidData("Value must be a number for TimeSeries type".to_string())
+                })?;
+                let labels = op
+                    .labels
+                    .as_deref()
+                    .map(crate::filters::parse_labels)
+                    .transpose()
+                    .map_err(|e| DbError::InvalidData(format!("Invalid labels: {}", e)))?;
+                self.ts_add_with_ttl(&full_key, timestamp, value, sig_meta, ttl_seconds).await?;
+                if let Some(labels) = labels {
+                    self.ts_set_labels(&full_key, labels).await?;
+                }
+            }
+            "geo" => {
+                let longitude = op.longitude.ok_or_else(|| {
+                    DbError::InvalidData("longitude required for Geo type".to_string())
+                })?;
+                let latitude = op.latitude.ok_or_else(|| {
+                    DbError::InvalidData("latitude required for Geo type".to_string())
+                })?;
+                self.geoadd_with_ttl(&full_key, longitude, latitude, &op.value, sig_meta, ttl_seconds)
+                    .await?;
+            }
+            "vector" => {
+                let member = op.field.as_deref().ok_or_else(|| {
+                    DbError::InvalidData("field (member) required for Vector type".to_string())
+                })?;
+                let write = crate::vector::VectorWrite::parse(&op.value)
+                    .map_err(|e| DbError::InvalidData(format!("Invalid vector value: {}", e)))?;
+                self.vadd_with_ttl(&full_key, member, write.vector, write.metadata, write.metric, sig_meta, ttl_seconds)
+                    .await
+                    .map_err(|e| DbError::InvalidData(e.to_string()))?;
+            }
+            "grant" | "revoke" => {
+                self.delegations()
+                    .apply(op)
+                    .await
+                    .map_err(|e| DbError::InvalidData(e.to_string()))?;
+            }
+            "encryption" => {
+                self.encryption()
+                    .apply(op)
+                    .await
+                    .map_err(|e| DbError::InvalidData(e.to_string()))?;
+            }
+            "privacy" => {
+                self.read_access()
+                    .apply(op)
+                    .await
+                    .map_err(|e| DbError::InvalidData(e.to_string()))?;
+            }
+            _ => {
+                return Err(DbError::InvalidData(format!("Unknown store type: {}", op.store_type)).into());
+            }
+        }
+        Ok(())
+    }
+
     async fn load_index(&self) -> Result<()> {
         // Index is persisted in sled and is available on-disk; nothing to load into memory.
         Ok(())
@@ -556,33 +972,89 @@ impl BlobStorage {
         store_type: StoreType,
     ) -> Result<()> {
         let timer = Timer::new();
+        let op_type = format!("{:?}", store_type).to_lowercase();
+
+        // Previous document, so secondary index entries can be moved
+        let indexed = self.indexed_location(key, &store_type).await;
+        let _index_guard = match indexed {
+            Some(_) => Some(self.index_lock(key).lock().await),
+            None => None,
+        };
+        let old_document = match indexed {
+            Some(_) => self.read_value_raw(key).await.ok().flatten().and_then(|v| Self::indexable_document(&v)),
+            None => None,
+        };
         
         // OPTIMIZED: Use bincode instead of JSON for internal storage (3-5x faster, smaller)
         // Only use JSON for external APIs that require it
         let value_clone = value.clone();
+        let compression = self.compression;
         let value_bytes = tokio::task::spawn_blocking(move || {
-            bincode::serialize(&value_clone)
+            Self::encode_stored_value(&value_clone, &compression)
         })
         .await
         .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))??;
         
-        // Store in Iroh blobs (async I/O)
-        let blobs = self.store.blobs();
-        let tag = blobs.add_bytes(value_bytes).await?;
-        let hash_str = tag.hash.to_string();
+        let durability = self.durability_for(key);
+        let _gate = self.journal_gate.read().await;
+
+        // Write-ahead: journal the index update before touching the value store.
+        // Values are content-addressed, so the hash is known up front.
+        let hash = Hash::new(&value_bytes);
+        let hash_str = hash.to_string();
+        let entry = bincode::serialize(&(hash_str, store_type))?;
+        let seq = self.journal_seq.fetch_add(1, Ordering::SeqCst);
+        let backend = Arc::clone(&self.backend);
+        let key_owned = key.to_string();
+        let entry_clone = entry.clone();
+        tokio::task::spawn_blocking(move || {
+            // Keep the oldest `prev` so a rollback lands on a synced entry
+            let prev = match backend.journal().get(&key_owned)? {
+                Some(bytes) => bincode::deserialize::<JournalRecord>(&bytes)?.prev,
+                None => backend.index().get(&key_owned)?,
+            };
+            let record = JournalRecord { seq, entry: entry_clone, prev };
+            backend.journal().insert(&key_owned, bincode::serialize(&record)?)?;
+            Ok::<_, anyhow::Error>(())
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))??;
+
+        // Store value bytes in the backend value store (async I/O)
+        let stored_hash = self.backend.values().put(value_bytes).await?;
+        debug_assert_eq!(stored_hash, hash);
+        if durability == Durability::Fsync {
+            self.backend.values().sync().await?;
+        }
 
-        // Offload Sled write to blocking thread pool
-        let index_tree = self.index_tree.clone();
+        // Offload index write to blocking thread pool
+        let backend = Arc::clone(&self.backend);
         let key_owned = key.to_string();
-        let store_type_clone = store_type.clone();
-        
         tokio::task::spawn_blocking(move || {
-            let val = bincode::serialize(&(hash_str.clone(), store_type_clone))?;
-            index_tree.insert(key_owned.as_bytes(), val)?;
+            backend.index().insert(&key_owned, entry)?;
+            if durability >= Durability::FlushOnCommit {
+                backend.index().flush()?;
+            }
+            if durability == Durability::Fsync {
+                // Value is already on disk; nothing left for recovery to do
+                backend.journal().remove(&key_owned)?;
+            }
             Ok::<_, anyhow::Error>(())
         })
         .await
         .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))??;
+        metrics::STORAGE_WRITES_BY_DURABILITY
+            .with_label_values(&[durability.as_str()])
+            .inc();
+
+        if let Some((db_name, user_key)) = indexed {
+            let new_document = Self::indexable_document(&value);
+            self.indexes
+                .index_document(db_name, user_key, old_document.as_ref(), new_document.as_ref())
+                .await;
+        }
+
+        self.changes.publish_stored(key, &op_type, || Self::change_value(&value));
 
         // Update cache (fast, in-memory, Arc-based)
         self.cache.insert(key.to_string(), value).await;
@@ -590,11 +1062,123 @@ impl BlobStorage {
         timer.observe_duration_seconds(&metrics::WRITE_LATENCY);
         metrics::STORAGE_WRITES.inc();
         
-        tracing::debug!(key = %key, blob = %tag.hash, "Stored key in blob and updated index");
+        tracing::debug!(key = %key, blob = %hash, "Stored key in blob and updated index");
 
         Ok(())
     }
 
+    /// Lock serializing secondary index maintenance for `key`
+    fn index_lock(&self, key: &str) -> &AsyncMutex<()> {
+        use std::hash::{Hash as _, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.index_locks[hasher.finish() as usize % self.index_locks.len()]
+    }
+
+    /// Split `key` into `(db_name, user_key)` if a write of `store_type` to it
+    /// needs secondary index maintenance
+    async fn indexed_location<'k>(&self, key: &'k str, store_type: &StoreType) -> Option<(&'k str, &'k str)> {
+        if !matches!(store_type, StoreType::Json | StoreType::Hash) {
+            return None;
+        }
+        let (db_name, user_key) = key.split_once(':')?;
+        // Sealed values can't be indexed
+        if self.encryption.is_encrypted(db_name) {
+            return None;
+        }
+        self.indexes.has_indexes(db_name).await.then_some((db_name, user_key))
+    }
+
+    /// Value a change event carries when no operation made the change (see
+    /// `changes.rs`)
+    fn change_value(value: &StoredValue) -> String {
+        match value {
+            StoredValue::String(v) => v.value.clone(),
+            other => Self::indexable_document(other)
+                .map(|document| document.to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Document view of a value for secondary indexing (JSON documents and Hashes)
+    fn indexable_document(value: &StoredValue) -> Option<serde_json::Value> {
+        match value {
+            StoredValue::Json(v) => Some(v.data.clone()),
+            StoredValue::Hash(v) => Some(serde_json::json!(v.fields)),
+            _ => None,
+        }
+    }
+
+    /// Read a value without TTL handling or cache population
+    async fn read_value_raw(&self, key: &str) -> Result<Option<StoredValue>> {
+        if let Some(value) = self.cache.get(key).await {
+            return Ok(Some((*value).clone()));
+        }
+        let Some((hash_str, _)) = self.index_get_async(key).await? else {
+            return Ok(None);
+        };
+        let bytes = self.backend.values().get(hash_str.parse()?).await?;
+        Ok(Some(Self::decode_stored_value(&bytes)?.0))
+    }
+
+    /// Drop `key` from secondary indexes before it is removed
+    async fn unindex_key(&self, key: &str) {
+        let Some((db_name, user_key)) = key.split_once(':') else {
+            return;
+        };
+        if !self.indexes.has_indexes(db_name).await {
+            return;
+        }
+        if let Ok(Some(value)) = self.read_value_raw(key).await {
+            if let Some(doc) = Self::indexable_document(&value) {
+                self.indexes.index_document(db_name, user_key, Some(&doc), None).await;
+            }
+        }
+    }
+
+    /// Create a secondary index on a JSON/Hash field and backfill it from
+    /// existing keys in the database. Returns the number of keys indexed.
+    pub async fn create_index(
+        &self,
+        db_name: &str,
+        index_name: &str,
+        field: &str,
+        index_type: IndexType,
+    ) -> Result<usize> {
+        self.encryption.check_value_query(db_name)?;
+        self.indexes
+            .create_index(db_name.to_string(), index_name.to_string(), field.to_string(), index_type)
+            .await?;
+
+        let prefix = format!("{}:", db_name);
+        let mut indexed = 0;
+        for key in self.index_keys_with_prefix_async(&prefix).await? {
+            let value = self.get_value(&key).await.ok().flatten();
+            let Some(doc) = value.as_ref().and_then(Self::indexable_document) else {
+                continue;
+            };
+            let user_key = &key[prefix.len()..];
+            self.indexes.index_document(db_name, user_key, None, Some(&doc)).await;
+            indexed += 1;
+        }
+        tracing::info!("Backfilled index '{}' in '{}' from {} keys", index_name, db_name, indexed);
+        Ok(indexed)
+    }
+
+    /// Serialize a value into a versioned, possibly compressed blob
+    fn encode_stored_value(value: &StoredValue, compression: &CompressionConfig) -> Result<Vec<u8>> {
+        let body = bincode::serialize(value)?;
+        blob_encoding::encode(versioning::wrap_stored_value(body), compression)
+    }
+
+    /// Decode a blob written by any version of `encode_stored_value`.
+    /// Returns the value and the schema version it was stored with.
+    fn decode_stored_value(blob: &[u8]) -> Result<(StoredValue, u16)> {
+        let payload = blob_encoding::decode(blob)?;
+        let (version, body) = versioning::unwrap_stored_value(&payload)?;
+        Ok((bincode::deserialize(&body)?, version))
+    }
+
     /// Helper to extract TTL metadata from a StoredValue
     fn get_ttl_metadata(value: &StoredValue) -> Option<&TtlMetadata> {
         match value {
@@ -607,6 +1191,39 @@ impl BlobStorage {
             StoredValue::Stream(v) => v.ttl.as_ref(),
             StoredValue::TimeSeries(v) => v.ttl.as_ref(),
             StoredValue::Geo(v) => v.ttl.as_ref(),
+            StoredValue::Vector(v) => v.ttl.as_ref(),
+        }
+    }
+
+    /// Helper to extract signature metadata from a StoredValue
+    fn get_signature_metadata(value: &StoredValue) -> Option<&SignatureMetadata> {
+        match value {
+            StoredValue::String(v) => v.metadata.as_ref(),
+            StoredValue::Hash(v) => v.metadata.as_ref(),
+            StoredValue::List(v) => v.metadata.as_ref(),
+            StoredValue::Set(v) => v.metadata.as_ref(),
+            StoredValue::SortedSet(v) => v.metadata.as_ref(),
+            StoredValue::Json(v) => v.metadata.as_ref(),
+            StoredValue::Stream(v) => v.metadata.as_ref(),
+            StoredValue::TimeSeries(v) => v.metadata.as_ref(),
+            StoredValue::Geo(v) => v.metadata.as_ref(),
+            StoredValue::Vector(v) => v.metadata.as_ref(),
+        }
+    }
+
+    /// Store type matching a StoredValue variant
+    fn store_type_of(value: &StoredValue) -> StoreType {
+        match value {
+            StoredValue::String(_) => StoreType::String,
+            StoredValue::Hash(_) => StoreType::Hash,
+            StoredValue::List(_) => StoreType::List,
+            StoredValue::Set(_) => StoreType::Set,
+            StoredValue::SortedSet(_) => StoreType::SortedSet,
+            StoredValue::Json(_) => StoreType::Json,
+            StoredValue::Stream(_) => StoreType::Stream,
+            StoredValue::TimeSeries(_) => StoreType::TimeSeries,
+            StoredValue::Geo(_) => StoreType::Geo,
+            StoredValue::Vector(_) => StoreType::Vector,
         }
     }
 
@@ -639,12 +1256,12 @@ impl BlobStorage {
             return Ok(Some((*value).clone()));
         }
 
-        // Offload Sled lookup to blocking thread pool to prevent executor blocking
-        let index_tree = self.index_tree.clone();
+        // Offload index lookup to blocking thread pool to prevent executor blocking
+        let backend = Arc::clone(&self.backend);
         let key_owned = key.to_string();
         
         let index_result = tokio::task::spawn_blocking(move || {
-            index_tree.get(key_owned.as_bytes())
+            backend.index().get(&key_owned)
         })
         .await
         .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))??;
@@ -661,14 +1278,14 @@ impl BlobStorage {
             }
         };
 
-        // Fetch from Iroh blobs (already async)
+        // Fetch from the backend value store (already async)
         let hash: Hash = hash_str.parse()?;
-        let blobs = self.store.blobs();
-        let value_bytes = blobs.get_bytes(hash).await?.to_vec();
+        let value_bytes = self.backend.values().get(hash).await?;
         
         // OPTIMIZED: Use bincode instead of JSON for deserialization (matches store_value optimization)
-        let value = tokio::task::spawn_blocking(move || {
-            bincode::deserialize::<StoredValue>(&value_bytes)
+        // Older encodings are upgraded in memory; the migration pass rewrites them
+        let (value, _version) = tokio::task::spawn_blocking(move || {
+            Self::decode_stored_value(&value_bytes)
         })
         .await
         .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))??;
@@ -1170,14 +1787,26 @@ impl BlobStorage {
     }
 
     pub async fn delete(&self, key: &str) -> Result<()> {
+        self.remove_key(key, crate::changes::DELETE).await
+    }
+
+    /// Remove `key`, publishing the removal as `op_type`
+    async fn remove_key(&self, key: &str, op_type: &str) -> Result<()> {
         let timer = Timer::new();
         
+        let index_guard = self.index_lock(key).lock().await;
+        self.unindex_key(key).await;
+        let existed = self.index_exists(key)?;
         {
             self.index_remove(key)?;
         }
 
         // Invalidate cache entry in tiered cache
         self.cache.invalidate(key).await;
+        drop(index_guard);
+        if existed {
+            self.changes.publish_stored(key, op_type, String::new);
+        }
 
         self.save_index().await?;
         
@@ -1246,7 +1875,9 @@ impl BlobStorage {
         for key in keys_to_check {
             if let Ok(Some(StoredValue::Json(jv))) = self.get_value(&key).await {
                 if jv.id.as_deref() == Some(target_id) {
-                    // Remove from cache and index
+                    // Remove from secondary indexes, cache and index
+                    let _index_guard = self.index_lock(&key).lock().await;
+                    self.unindex_key(&key).await;
                     self.cache.invalidate(&key).await;
                     self.index_remove(&key)?;
                 }
@@ -1508,6 +2139,7 @@ impl BlobStorage {
                     points: BTreeMap::new(),
                     metadata: metadata.clone(),
                     ttl,
+                    labels: BTreeMap::new(),
                 }
             },
             _ => return Err(anyhow::anyhow!("Key is not a timeseries type")),
@@ -1525,6 +2157,37 @@ impl BlobStorage {
         .await
     }
 
+    /// Replace the labels of a time series, creating an empty series if needed
+    pub async fn ts_set_labels(&self, key: &str, labels: BTreeMap<String, String>) -> Result<()> {
+        let mut ts_value = match self.get_value(key).await? {
+            Some(StoredValue::TimeSeries(tsv)) => tsv,
+            None => TimeSeriesValue {
+                points: BTreeMap::new(),
+                metadata: None,
+                ttl: None,
+                labels: BTreeMap::new(),
+            },
+            _ => return Err(anyhow::anyhow!("Key is not a timeseries type")),
+        };
+
+        ts_value.labels = labels;
+        self.store_value(
+            key,
+            StoredValue::TimeSeries(ts_value),
+            StoreType::TimeSeries,
+        )
+        .await
+    }
+
+    /// Labels of a time series (empty if it has none or doesn't exist)
+    pub async fn ts_labels(&self, key: &str) -> Result<BTreeMap<String, String>> {
+        match self.get_value(key).await? {
+            Some(StoredValue::TimeSeries(tsv)) => Ok(tsv.labels),
+            None => Ok(BTreeMap::new()),
+            _ => Err(anyhow::anyhow!("Key is not a timeseries type")),
+        }
+    }
+
     pub async fn ts_range(
         &self,
         key: &str,
@@ -1817,6 +2480,129 @@ impl BlobStorage {
         EARTH_RADIUS_KM * c
     }
 
+    // Vector Operations
+    pub async fn vadd(
+        &self,
+        key: &str,
+        member: &str,
+        vector: Vec<f32>,
+        metadata: serde_json::Value,
+        metric: Option<DistanceMetric>,
+    ) -> Result<bool> {
+        self.vadd_with_ttl(key, member, vector, metadata, metric, None, None)
+            .await
+    }
+
+    /// Add or replace a vector with optional TTL (seconds). The first vector
+    /// of a key fixes its dimension and metric (default cosine). Returns true
+    /// when the member is new.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn vadd_with_ttl(
+        &self,
+        key: &str,
+        member: &str,
+        vector: Vec<f32>,
+        metadata: serde_json::Value,
+        metric: Option<DistanceMetric>,
+        sig_metadata: Option<SignatureMetadata>,
+        ttl_seconds: Option<u64>,
+    ) -> Result<bool> {
+        let mut vector_value = match self.get_value(key).await? {
+            Some(StoredValue::Vector(vv)) => {
+                if let Some(metric) = metric.filter(|metric| *metric != vv.index.metric()) {
+                    return Err(anyhow::anyhow!(
+                        "Key uses the {:?} metric, not {:?}",
+                        vv.index.metric(),
+                        metric
+                    ));
+                }
+                vv
+            }
+            None => {
+                let ttl = ttl_seconds.map(|s| TtlMetadata::new(Some(s)));
+                if ttl.is_some() {
+                    metrics::TTL_KEYS_TOTAL.inc();
+                }
+                VectorValue {
+                    index: HnswIndex::new(vector.len(), metric.unwrap_or_default()),
+                    metadata: sig_metadata.clone(),
+                    ttl,
+                }
+            }
+            _ => return Err(anyhow::anyhow!("Key is not a vector type")),
+        };
+
+        let added = vector_value.index.insert(member, vector, metadata)?;
+        if sig_metadata.is_some() {
+            vector_value.metadata = sig_metadata;
+        }
+        self.store_value(key, StoredValue::Vector(vector_value), StoreType::Vector)
+            .await?;
+        Ok(added)
+    }
+
+    /// Remove a member; returns true when it was present
+    pub async fn vrem(&self, key: &str, member: &str) -> Result<bool> {
+        match self.get_value(key).await? {
+            Some(StoredValue::Vector(mut vv)) => {
+                if !vv.index.remove(member) {
+                    return Ok(false);
+                }
+                self.store_value(key, StoredValue::Vector(vv), StoreType::Vector)
+                    .await?;
+                Ok(true)
+            }
+            None => Ok(false),
+            _ => Err(anyhow::anyhow!("Key is not a vector type")),
+        }
+    }
+
+    pub async fn vget(&self, key: &str, member: &str) -> Result<Option<VectorEntry>> {
+        match self.get_value(key).await? {
+            Some(StoredValue::Vector(vv)) => Ok(vv.index.get(member)),
+            None => Ok(None),
+            _ => Err(anyhow::anyhow!("Key is not a vector type")),
+        }
+    }
+
+    /// Dimension, metric and member count of a vector key
+    pub async fn vinfo(&self, key: &str) -> Result<Option<(usize, DistanceMetric, usize)>> {
+        match self.get_value(key).await? {
+            Some(StoredValue::Vector(vv)) => Ok(Some((
+                vv.index.dimension(),
+                vv.index.metric(),
+                vv.index.len(),
+            ))),
+            None => Ok(None),
+            _ => Err(anyhow::anyhow!("Key is not a vector type")),
+        }
+    }
+
+    /// The `k` nearest members to `query`, optionally only those whose
+    /// metadata matches a filter document
+    pub async fn vsearch(
+        &self,
+        key: &str,
+        query: &[f32],
+        k: usize,
+        ef: Option<usize>,
+        filter: Option<&crate::filters::FilterExpr>,
+    ) -> Result<Vec<VectorMatch>> {
+        match self.get_value(key).await? {
+            Some(StoredValue::Vector(vv)) => {
+                let matches = filter.map(|filter| move |metadata: &serde_json::Value| filter.matches(metadata));
+                vv.index.search(
+                    query,
+                    k,
+                    ef.unwrap_or(crate::vector::DEFAULT_EF_SEARCH),
+                    matches.as_ref().map(|f| f as &dyn Fn(&serde_json::Value) -> bool),
+                )
+            }
+            None => Ok(Vec::new()),
+            _ => Err(anyhow::anyhow!("Key is not a vector type")),
+        }
+    }
+
     // Filter Operations
     pub async fn filter_hash(
         &self,
@@ -2140,14 +2926,11 @@ impl BlobStorage {
 
         let re = regex::Regex::new(&regex_pattern)?;
 
-        let mut matching_keys = Vec::new();
-        for item in self.index_tree.iter() {
-            let (k, _v) = item?;
-            let key = String::from_utf8(k.to_vec())?;
-            if re.is_match(&key) {
-                matching_keys.push(key);
-            }
-        }
+        let matching_keys = self
+            .index_keys_with_prefix("")?
+            .into_iter()
+            .filter(|key| re.is_match(key))
+            .collect();
         Ok(matching_keys)
     }
 
@@ -2284,6 +3067,19 @@ impl BlobStorage {
                                 metadata: gv.metadata,
                             });
                         }
+                        StoredValue::Vector(vv) => {
+                            let members: Vec<serde_json::Value> = vv
+                                .index
+                                .entries()
+                                .map(|e| serde_json::json!({"member": e.member, "vector": e.vector, "metadata": e.metadata}))
+                                .collect();
+                            res.push(StoredEntry {
+                                key: key.clone(),
+                                store_type: StoreType::Vector,
+                                value: serde_json::Value::Array(members),
+                                metadata: vv.metadata,
+                            });
+                        }
                     }
                 }
             }
@@ -2369,19 +3165,13 @@ impl BlobStorage {
                 v.ttl = new_ttl;
                 StoredValue::Geo(v)
             }
+            StoredValue::Vector(mut v) => {
+                v.ttl = new_ttl;
+                StoredValue::Vector(v)
+            }
         };
 
-        let store_type = match &updated_value {
-            StoredValue::String(_) => StoreType::String,
-            StoredValue::Hash(_) => StoreType::Hash,
-            StoredValue::List(_) => StoreType::List,
-            StoredValue::Set(_) => StoreType::Set,
-            StoredValue::SortedSet(_) => StoreType::SortedSet,
-            StoredValue::Json(_) => StoreType::Json,
-            StoredValue::Stream(_) => StoreType::Stream,
-            StoredValue::TimeSeries(_) => StoreType::TimeSeries,
-            StoredValue::Geo(_) => StoreType::Geo,
-        };
+        let store_type = Self::store_type_of(&updated_value);
 
         self.store_value(key, updated_value, store_type).await?;
         metrics::TTL_KEYS_TOTAL.inc();
@@ -2460,6 +3250,13 @@ impl BlobStorage {
                 }
                 StoredValue::Geo(v)
             }
+            StoredValue::Vector(mut v) => {
+                if v.ttl.is_some() {
+                    v.ttl = None;
+                    metrics::TTL_KEYS_TOTAL.dec();
+                }
+                StoredValue::Vector(v)
+            }
         };
 
         let store_type = match &updated_value {
@@ -2472,6 +3269,7 @@ impl BlobStorage {
             StoredValue::Stream(_) => StoreType::Stream,
             StoredValue::TimeSeries(_) => StoreType::TimeSeries,
             StoredValue::Geo(_) => StoreType::Geo,
+            StoredValue::Vector(_) => StoreType::Vector,
         };
 
         self.store_value(key, updated_value, store_type).await?;
@@ -2480,7 +3278,7 @@ impl BlobStorage {
 
     /// Delete a key (internal helper for TTL cleanup)
     async fn delete_key(&self, key: &str) -> Result<()> {
-        self.delete(key).await
+        self.remove_key(key, crate::changes::EXPIRE).await
     }
 
     /// Run TTL cleanup - scans keys and removes expired ones
@@ -2491,17 +3289,7 @@ impl BlobStorage {
         let mut scanned_count = 0;
 
         // Get all keys from index
-        let all_keys: Vec<String> = {
-            let mut keys = Vec::new();
-            for item in self.index_tree.iter() {
-                if let Ok((k, _)) = item {
-                    if let Ok(key_str) = String::from_utf8(k.to_vec()) {
-                        keys.push(key_str);
-                    }
-                }
-            }
-            keys
-        };
+        let all_keys: Vec<String> = self.index_keys_with_prefix_async("").await.unwrap_or_default();
 
         scanned_count = all_keys.len();
         metrics::TTL_KEYS_SCANNED.set(scanned_count as i64);
@@ -2524,6 +3312,237 @@ impl BlobStorage {
         Ok(expired_count)
     }
 
+    /// All index keys starting with `prefix`, including expired ones
+    pub async fn index_keys(&self, prefix: &str) -> Result<Vec<String>> {
+        self.index_keys_with_prefix_async(prefix).await
+    }
+
+    /// Check one index entry against the value store, bypassing the cache.
+    /// Used by the consistency checker; never modifies storage.
+    pub async fn check_key(&self, key: &str) -> Result<KeyCheck> {
+        let backend = Arc::clone(&self.backend);
+        let key_owned = key.to_string();
+        let raw = tokio::task::spawn_blocking(move || backend.index().get(&key_owned))
+            .await
+            .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))??;
+
+        let Some(raw) = raw else {
+            return Ok(KeyCheck::Missing);
+        };
+        let (hash_str, indexed) = match bincode::deserialize::<(String, StoreType)>(&raw) {
+            Ok(entry) => entry,
+            Err(e) => return Ok(KeyCheck::CorruptIndex { error: e.to_string() }),
+        };
+        let hash: Hash = match hash_str.parse() {
+            Ok(hash) => hash,
+            Err(e) => return Ok(KeyCheck::CorruptIndex { error: format!("Invalid hash {}: {}", hash_str, e) }),
+        };
+
+        if !self.backend.values().contains(hash).await.unwrap_or(false) {
+            return Ok(KeyCheck::MissingValue { hash: hash_str });
+        }
+        let bytes = match self.backend.values().get(hash).await {
+            Ok(bytes) => bytes,
+            Err(e) => return Ok(KeyCheck::CorruptValue { hash: hash_str, error: e.to_string() }),
+        };
+        let value = match Self::decode_stored_value(&bytes) {
+            Ok((value, _version)) => value,
+            Err(e) => return Ok(KeyCheck::CorruptValue { hash: hash_str, error: e.to_string() }),
+        };
+
+        let actual = Self::store_type_of(&value);
+        if actual != indexed {
+            return Ok(KeyCheck::TypeMismatch { indexed, actual });
+        }
+        if Self::is_value_expired(&value) {
+            return Ok(KeyCheck::Expired);
+        }
+
+        let string_value = match &value {
+            StoredValue::String(v) => Some(v.value.clone()),
+            _ => None,
+        };
+        Ok(KeyCheck::Valid {
+            store_type: actual,
+            metadata: Self::get_signature_metadata(&value).cloned(),
+            string_value,
+        })
+    }
+
+    /// Rewrite every value stored with an older schema version at the current one.
+    /// Values that can't be upgraded are left in place and counted as failed.
+    ///
+    /// Each value is read and written back without coordinating with other
+    /// writes, so this must finish before the node takes any (see
+    /// `migrate_before_serving`).
+    pub async fn migrate_stored_values(&self) -> Result<MigrationStats> {
+        let mut stats = MigrationStats::default();
+        for key in self.index_keys_with_prefix_async("").await? {
+            stats.scanned += 1;
+            let Some((hash_str, store_type)) = self.index_get_async(&key).await? else {
+                continue;
+            };
+            let blob = match self.backend.values().get(hash_str.parse()?).await {
+                Ok(blob) => blob,
+                Err(_) => continue, // dangling entries are fsck's job
+            };
+            let version = blob_encoding::decode(&blob)
+                .map(|payload| versioning::stored_value_version(&payload))
+                .unwrap_or(versioning::STORED_VALUE_VERSION);
+            if version >= versioning::STORED_VALUE_VERSION {
+                continue;
+            }
+
+            match Self::decode_stored_value(&blob) {
+                Ok((value, _)) => {
+                    self.store_value(&key, value, store_type).await?;
+                    stats.upgraded += 1;
+                }
+                Err(e) => {
+                    tracing::warn!(key = %key, "Cannot migrate value from v{}: {}", version, e);
+                    stats.failed += 1;
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Upgrade old value encodings in one pass at startup, before the API,
+    /// sync or any background task can write
+    pub async fn migrate_before_serving(&self) {
+        match self.migrate_stored_values().await {
+            Ok(stats) if stats.upgraded + stats.failed > 0 => tracing::info!(
+                "Value migration: scanned {}, upgraded {}, failed {}",
+                stats.scanned,
+                stats.upgraded,
+                stats.failed
+            ),
+            Ok(_) => tracing::debug!("Value migration: all values current"),
+            Err(e) => tracing::warn!("Value migration error: {}", e),
+        }
+    }
+
+    /// Replay leftover journal records after an unclean shutdown.
+    ///
+    /// Records whose value is present are re-applied to the index; records whose
+    /// value was lost revert the index to the entry that preceded them.
+    pub async fn recover_journal(&self) -> Result<JournalRecovery> {
+        let backend = Arc::clone(&self.backend);
+        let pending = tokio::task::spawn_blocking(move || {
+            let mut pending = Vec::new();
+            for key in backend.journal().keys_with_prefix("")? {
+                if let Some(bytes) = backend.journal().get(&key)? {
+                    pending.push((key, bincode::deserialize::<JournalRecord>(&bytes)?));
+                }
+            }
+            Ok::<_, anyhow::Error>(pending)
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))??;
+
+        let mut recovery = JournalRecovery::default();
+        for (key, record) in pending {
+            let (hash_str, _store_type): (String, StoreType) = bincode::deserialize(&record.entry)?;
+            let hash: Hash = hash_str.parse()?;
+            let value_present = self.backend.values().contains(hash).await.unwrap_or(false);
+
+            let backend = Arc::clone(&self.backend);
+            tokio::task::spawn_blocking(move || {
+                if value_present {
+                    backend.index().insert(&key, record.entry)?;
+                } else if let Some(prev) = record.prev {
+                    backend.index().insert(&key, prev)?;
+                } else {
+                    backend.index().remove(&key)?;
+                }
+                backend.journal().remove(&key)?;
+                Ok::<_, anyhow::Error>(())
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))??;
+
+            if value_present {
+                recovery.rolled_forward += 1;
+                metrics::STORAGE_JOURNAL_RECOVERED.with_label_values(&["forward"]).inc();
+            } else {
+                recovery.rolled_back += 1;
+                metrics::STORAGE_JOURNAL_RECOVERED.with_label_values(&["back"]).inc();
+            }
+        }
+
+        let backend = Arc::clone(&self.backend);
+        tokio::task::spawn_blocking(move || backend.index().flush())
+            .await
+            .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))??;
+
+        Ok(recovery)
+    }
+
+    /// Sync the value store and clear journal records it now covers.
+    /// Returns the number of records cleared.
+    pub async fn checkpoint_journal(&self) -> Result<usize> {
+        // Every write with a lower sequence number has finished its value write
+        let cutoff = {
+            let _gate = self.journal_gate.write().await;
+            self.journal_seq.load(Ordering::SeqCst)
+        };
+
+        self.backend.values().sync().await?;
+        let backend = Arc::clone(&self.backend);
+        tokio::task::spawn_blocking(move || backend.index().flush())
+            .await
+            .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))??;
+
+        // Hold the gate so a newer write can't replace a record while we remove it
+        let _gate = self.journal_gate.write().await;
+        let backend = Arc::clone(&self.backend);
+        let cleared = tokio::task::spawn_blocking(move || {
+            let mut cleared = 0;
+            for key in backend.journal().keys_with_prefix("")? {
+                if let Some(bytes) = backend.journal().get(&key)? {
+                    let record: JournalRecord = bincode::deserialize(&bytes)?;
+                    if record.seq < cutoff {
+                        backend.journal().remove(&key)?;
+                        cleared += 1;
+                    }
+                }
+            }
+            Ok::<_, anyhow::Error>(cleared)
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Thread join error: {}", e))??;
+
+        metrics::STORAGE_JOURNAL_CHECKPOINTS.inc();
+        Ok(cleared)
+    }
+
+    /// Start background journal checkpoint task
+    /// Runs a checkpoint every `interval_seconds` (default: 5 seconds)
+    pub fn start_journal_checkpoint_task(storage: BlobStorage, interval_seconds: Option<u64>) {
+        let interval = std::time::Duration::from_secs(interval_seconds.unwrap_or(5));
+
+        tokio::spawn(async move {
+            let mut interval_timer = tokio::time::interval(interval);
+
+            loop {
+                interval_timer.tick().await;
+
+                match storage.checkpoint_journal().await {
+                    Ok(cleared) => {
+                        if cleared > 0 {
+                            tracing::debug!("Journal checkpoint: cleared {} records", cleared);
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("Journal checkpoint task error: {}", e);
+                    }
+                }
+            }
+        });
+
+        tracing::info!("Journal checkpoint background task started (interval: {}s)", interval.as_secs());
+    }
+
     /// Start background TTL cleanup task
     /// Runs cleanup every `interval_seconds` (default: 60 seconds)
     pub fn start_ttl_cleanup_task(storage: BlobStorage, interval_seconds: Option<u64>) {
@@ -2545,6 +3564,14 @@ impl BlobStorage {
                         tracing::warn!("TTL cleanup task error: {}", e);
                     }
                 }
+
+                match storage.replay_guard().prune().await {
+                    Ok(pruned) if pruned > 0 => {
+                        tracing::debug!("TTL cleanup task: pruned {} expired nonces", pruned);
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("Nonce pruning error: {}", e),
+                }
             }
         });
         
@@ -2552,6 +3579,38 @@ impl BlobStorage {
     }
 }
 
+/// Result of a value migration pass
+#[derive(Debug, Clone, Default)]
+pub struct MigrationStats {
+    pub scanned: usize,
+    pub upgraded: usize,
+    pub failed: usize,
+}
+
+/// Outcome of checking one index entry against its stored value (see `fsck.rs`)
+#[derive(Debug, Clone)]
+pub enum KeyCheck {
+    /// No index entry for this key
+    Missing,
+    /// Index entry points at a decodable value of the indexed type
+    Valid {
+        store_type: StoreType,
+        metadata: Option<SignatureMetadata>,
+        /// Stored value for String keys, whose signature covers the whole value
+        string_value: Option<String>,
+    },
+    /// Value is past its TTL and will be removed by cleanup
+    Expired,
+    /// Index entry itself could not be decoded
+    CorruptIndex { error: String },
+    /// Index entry points at a value missing from the value store
+    MissingValue { hash: String },
+    /// Value bytes are present but do not decode as a stored value
+    CorruptValue { hash: String, error: String },
+    /// Value decodes but its type disagrees with the index entry
+    TypeMismatch { indexed: StoreType, actual: StoreType },
+}
+
 /// TTL information for a key
 #[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
 pub struct TtlInfo {
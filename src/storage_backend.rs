@@ -0,0 +1,727 @@
+//! Storage Backend Module
+//!
+//! Pluggable persistence layer underneath `BlobStorage`. A backend is split
+//! into two independent halves:
+//! - **IndexStore**: ordered `key -> index entry` map used for lookups and prefix scans
+//! - **ValueStore**: content-addressed value bytes, addressed by BLAKE3 `Hash`
+//! - **Journal**: write-ahead records for index updates whose value is not yet
+//!   known to be durable (same interface as the index, separate keyspace)
+//! - **Secondary indexes**: definitions and entries for field indexes (see
+//!   `indexing.rs`), each in its own keyspace
+//!
+//! ## Backends
+//! - `SledIrohBackend`: Sled index + Iroh `FsStore` blobs (production default)
+//! - `RedbBackend`: single redb file holding both the index and the values
+//! - `MemoryBackend`: ephemeral in-process maps for tests and throwaway nodes
+//!
+//! The backend is selected at startup with `STORAGE_BACKEND` (see `config.rs`).
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use iroh_blobs::store::fs::FsStore;
+use iroh_blobs::Hash;
+use redb::TableDefinition;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Available storage backend implementations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackendKind {
+    /// Sled index + Iroh FsStore blobs
+    SledIroh,
+    /// Single-file redb database for index and values
+    Redb,
+    /// Non-persistent in-memory maps
+    Memory,
+}
+
+impl BackendKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackendKind::SledIroh => "sled",
+            BackendKind::Redb => "redb",
+            BackendKind::Memory => "memory",
+        }
+    }
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "sled" | "sled-iroh" | "sled_iroh" => Ok(BackendKind::SledIroh),
+            "redb" => Ok(BackendKind::Redb),
+            "memory" | "mem" | "in-memory" => Ok(BackendKind::Memory),
+            other => Err(anyhow!("Unknown storage backend: {}", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Ordered key index. Methods are blocking; async callers should run them
+/// on the blocking thread pool.
+pub trait IndexStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()>;
+    fn remove(&self, key: &str) -> Result<()>;
+    fn contains(&self, key: &str) -> Result<bool>;
+    /// Return all keys starting with `prefix` in ascending order
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>>;
+    /// Return all keys in `start..end` (end exclusive) in ascending order
+    fn keys_in_range(&self, start: &str, end: &str) -> Result<Vec<String>>;
+    /// Flush pending writes to durable storage
+    fn flush(&self) -> Result<()>;
+}
+
+/// Content-addressed value storage
+pub trait ValueStore: Send + Sync {
+    /// Store bytes and return their content hash
+    fn put(&self, bytes: Vec<u8>) -> BoxFuture<'_, Result<Hash>>;
+    /// Load bytes by content hash
+    fn get(&self, hash: Hash) -> BoxFuture<'_, Result<Vec<u8>>>;
+    /// Check whether a value with this hash is present
+    fn contains(&self, hash: Hash) -> BoxFuture<'_, Result<bool>>;
+    /// Make every value stored so far durable on disk
+    fn sync(&self) -> BoxFuture<'_, Result<()>>;
+}
+
+/// A complete storage backend: one index plus one value store
+pub trait StorageBackend: Send + Sync {
+    fn kind(&self) -> BackendKind;
+    fn index(&self) -> &dyn IndexStore;
+    fn values(&self) -> &dyn ValueStore;
+    /// Write-ahead journal; must share the index's write ordering
+    fn journal(&self) -> &dyn IndexStore;
+    /// Secondary index entries (`indexing.rs` key layout, empty values)
+    fn secondary_index(&self) -> &dyn IndexStore;
+    /// Secondary index definitions
+    fn index_definitions(&self) -> &dyn IndexStore;
+    /// Nonces of accepted signed writes (`replay.rs` key layout)
+    fn nonces(&self) -> &dyn IndexStore;
+    /// Write access granted by database owners (`delegation.rs` key layout)
+    fn delegations(&self) -> &dyn IndexStore;
+    /// Metadata of encrypted databases (`encryption.rs` key layout)
+    fn encryption(&self) -> &dyn IndexStore;
+    /// Privacy flags of databases (`read_access.rs` key layout)
+    fn read_access(&self) -> &dyn IndexStore;
+
+    /// Underlying Iroh blob store, if this backend keeps values in one
+    fn blob_store(&self) -> Option<FsStore> {
+        None
+    }
+}
+
+/// Open the backend selected by `kind`.
+///
+/// `data_dir` is the node data directory; sled and redb files are created inside it.
+pub fn open_backend(
+    kind: BackendKind,
+    store: FsStore,
+    data_dir: &Path,
+) -> Result<Arc<dyn StorageBackend>> {
+    let backend: Arc<dyn StorageBackend> = match kind {
+        BackendKind::SledIroh => Arc::new(SledIrohBackend::open(store, Some(data_dir.join("sled_db")))?),
+        BackendKind::Redb => Arc::new(RedbBackend::open(data_dir.join("storage.redb"))?),
+        BackendKind::Memory => Arc::new(MemoryBackend::new()),
+    };
+    tracing::info!("Storage backend selected: {}", kind);
+    Ok(backend)
+}
+
+// ============================================================================
+// Sled + Iroh
+// ============================================================================
+
+pub struct SledIrohIndex {
+    tree: sled::Tree,
+}
+
+impl IndexStore for SledIrohIndex {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.tree.get(key.as_bytes())?.map(|v| v.to_vec()))
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.tree.insert(key.as_bytes(), value)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        self.tree.remove(key.as_bytes())?;
+        Ok(())
+    }
+
+    fn contains(&self, key: &str) -> Result<bool> {
+        Ok(self.tree.contains_key(key.as_bytes())?)
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut res = Vec::new();
+        for item in self.tree.scan_prefix(prefix.as_bytes()) {
+            let (k, _v) = item?;
+            res.push(String::from_utf8(k.to_vec())?);
+        }
+        Ok(res)
+    }
+
+    fn keys_in_range(&self, start: &str, end: &str) -> Result<Vec<String>> {
+        let mut res = Vec::new();
+        if start >= end {
+            return Ok(res);
+        }
+        for item in self.tree.range(start.as_bytes()..end.as_bytes()) {
+            let (k, _v) = item?;
+            res.push(String::from_utf8(k.to_vec())?);
+        }
+        Ok(res)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.tree.flush()?;
+        Ok(())
+    }
+}
+
+pub struct IrohValues {
+    store: FsStore,
+}
+
+impl ValueStore for IrohValues {
+    fn put(&self, bytes: Vec<u8>) -> BoxFuture<'_, Result<Hash>> {
+        Box::pin(async move {
+            let tag = self.store.blobs().add_bytes(bytes).await?;
+            Ok(tag.hash)
+        })
+    }
+
+    fn get(&self, hash: Hash) -> BoxFuture<'_, Result<Vec<u8>>> {
+        Box::pin(async move { Ok(self.store.blobs().get_bytes(hash).await?.to_vec()) })
+    }
+
+    fn contains(&self, hash: Hash) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(async move { Ok(self.store.blobs().has(hash).await?) })
+    }
+
+    fn sync(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move { Ok(self.store.sync_db().await?) })
+    }
+}
+
+/// Production backend: Sled B-tree index + Iroh content-addressed blobs
+pub struct SledIrohBackend {
+    // Keep the Db handle alive for the lifetime of the tree
+    _sled_db: sled::Db,
+    index: SledIrohIndex,
+    journal: SledIrohIndex,
+    secondary_index: SledIrohIndex,
+    index_definitions: SledIrohIndex,
+    nonces: SledIrohIndex,
+    delegations: SledIrohIndex,
+    encryption: SledIrohIndex,
+    read_access: SledIrohIndex,
+    values: IrohValues,
+}
+
+impl SledIrohBackend {
+    pub fn open(store: FsStore, sled_path: Option<PathBuf>) -> Result<Self> {
+        // Determine sled DB path
+        let sled_path = sled_path.unwrap_or_else(|| PathBuf::from("./data/sled_db"));
+
+        // Calculate Sled cache size from environment variable or use sensible default
+        // Default: 256MB (safe for Docker containers with 512MB-1GB RAM)
+        // Override with SLED_CACHE_MB environment variable
+        let cache_mb: u64 = std::env::var("SLED_CACHE_MB")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(256);
+        let cache_bytes = cache_mb * 1024 * 1024;
+
+        tracing::info!("Configuring Sled with cache_mb={} (from SLED_CACHE_MB or default)", cache_mb);
+        let sled_config = sled::Config::new()
+            .path(&sled_path)
+            // Dynamic cache size (default 256MB, configurable via SLED_CACHE_MB)
+            .cache_capacity(cache_bytes)
+            // Batch writes for better throughput (flush every 1 second)
+            .flush_every_ms(Some(1000))
+            // Use high-throughput mode for write-heavy workloads
+            .mode(sled::Mode::HighThroughput)
+            // Enable compression to save disk space
+            .use_compression(true)
+            // Not temporary - this is production data
+            .temporary(false);
+
+        let sled_db = sled_config.open()?;
+        let tree = sled_db.open_tree("storage_index")?;
+        // Same Db as the index, so journal and index writes share one log
+        let journal = sled_db.open_tree("storage_wal")?;
+        let secondary_index = sled_db.open_tree("secondary_index_entries")?;
+        let index_definitions = sled_db.open_tree("secondary_index_defs")?;
+        let nonces = sled_db.open_tree("signed_nonces")?;
+        let delegations = sled_db.open_tree("delegations")?;
+        let encryption = sled_db.open_tree("encryption")?;
+        let read_access = sled_db.open_tree("read_access")?;
+
+        tracing::info!(
+            "Sled configured at {:?}: cache={}MB, flush=1s, mode=HighThroughput, compression=enabled",
+            sled_path,
+            cache_mb
+        );
+
+        Ok(Self {
+            _sled_db: sled_db,
+            index: SledIrohIndex { tree },
+            journal: SledIrohIndex { tree: journal },
+            secondary_index: SledIrohIndex { tree: secondary_index },
+            index_definitions: SledIrohIndex { tree: index_definitions },
+            nonces: SledIrohIndex { tree: nonces },
+            delegations: SledIrohIndex { tree: delegations },
+            encryption: SledIrohIndex { tree: encryption },
+            read_access: SledIrohIndex { tree: read_access },
+            values: IrohValues { store },
+        })
+    }
+}
+
+impl StorageBackend for SledIrohBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::SledIroh
+    }
+
+    fn index(&self) -> &dyn IndexStore {
+        &self.index
+    }
+
+    fn values(&self) -> &dyn ValueStore {
+        &self.values
+    }
+
+    fn journal(&self) -> &dyn IndexStore {
+        &self.journal
+    }
+
+    fn secondary_index(&self) -> &dyn IndexStore {
+        &self.secondary_index
+    }
+
+    fn index_definitions(&self) -> &dyn IndexStore {
+        &self.index_definitions
+    }
+
+    fn nonces(&self) -> &dyn IndexStore {
+        &self.nonces
+    }
+
+    fn delegations(&self) -> &dyn IndexStore {
+        &self.delegations
+    }
+
+    fn encryption(&self) -> &dyn IndexStore {
+        &self.encryption
+    }
+
+    fn read_access(&self) -> &dyn IndexStore {
+        &self.read_access
+    }
+
+    fn blob_store(&self) -> Option<FsStore> {
+        Some(self.values.store.clone())
+    }
+}
+
+// ============================================================================
+// redb
+// ============================================================================
+
+const REDB_INDEX_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("storage_index");
+const REDB_JOURNAL_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("storage_wal");
+const REDB_SECONDARY_INDEX_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("secondary_index_entries");
+const REDB_INDEX_DEFS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("secondary_index_defs");
+const REDB_NONCES_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("signed_nonces");
+const REDB_DELEGATIONS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("delegations");
+const REDB_ENCRYPTION_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("encryption");
+const REDB_READ_ACCESS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("read_access");
+const REDB_VALUES_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("values");
+
+pub struct RedbIndex {
+    db: Arc<redb::Database>,
+    table: TableDefinition<'static, &'static str, &'static [u8]>,
+}
+
+impl IndexStore for RedbIndex {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(self.table)?;
+        Ok(table.get(key)?.map(|v| v.value().to_vec()))
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(self.table)?;
+            table.insert(key, value.as_slice())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(self.table)?;
+            table.remove(key)?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    fn contains(&self, key: &str) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(self.table)?;
+        let mut res = Vec::new();
+        for item in table.range(prefix..)? {
+            let (k, _v) = item?;
+            let key = k.value();
+            if !key.starts_with(prefix) {
+                break;
+            }
+            res.push(key.to_string());
+        }
+        Ok(res)
+    }
+
+    fn keys_in_range(&self, start: &str, end: &str) -> Result<Vec<String>> {
+        let mut res = Vec::new();
+        if start >= end {
+            return Ok(res);
+        }
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(self.table)?;
+        for item in table.range(start..end)? {
+            let (k, _v) = item?;
+            res.push(k.value().to_string());
+        }
+        Ok(res)
+    }
+
+    fn flush(&self) -> Result<()> {
+        // Every redb write transaction is durable on commit
+        Ok(())
+    }
+}
+
+pub struct RedbValues {
+    db: Arc<redb::Database>,
+}
+
+impl ValueStore for RedbValues {
+    fn put(&self, bytes: Vec<u8>) -> BoxFuture<'_, Result<Hash>> {
+        let db = self.db.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let hash = Hash::new(&bytes);
+                let txn = db.begin_write()?;
+                {
+                    let mut table = txn.open_table(REDB_VALUES_TABLE)?;
+                    table.insert(hash.as_bytes().as_slice(), bytes.as_slice())?;
+                }
+                txn.commit()?;
+                Ok(hash)
+            })
+            .await
+            .map_err(|e| anyhow!("Thread join error: {}", e))?
+        })
+    }
+
+    fn get(&self, hash: Hash) -> BoxFuture<'_, Result<Vec<u8>>> {
+        let db = self.db.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let txn = db.begin_read()?;
+                let table = txn.open_table(REDB_VALUES_TABLE)?;
+                table
+                    .get(hash.as_bytes().as_slice())?
+                    .map(|v| v.value().to_vec())
+                    .ok_or_else(|| anyhow!("Value {} not found", hash))
+            })
+            .await
+            .map_err(|e| anyhow!("Thread join error: {}", e))?
+        })
+    }
+
+    fn contains(&self, hash: Hash) -> BoxFuture<'_, Result<bool>> {
+        let db = self.db.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let txn = db.begin_read()?;
+                let table = txn.open_table(REDB_VALUES_TABLE)?;
+                Ok(table.get(hash.as_bytes().as_slice())?.is_some())
+            })
+            .await
+            .map_err(|e| anyhow!("Thread join error: {}", e))?
+        })
+    }
+
+    fn sync(&self) -> BoxFuture<'_, Result<()>> {
+        // Values are committed with immediate durability in `put`
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Single-file redb backend holding both index and values
+pub struct RedbBackend {
+    index: RedbIndex,
+    journal: RedbIndex,
+    secondary_index: RedbIndex,
+    index_definitions: RedbIndex,
+    nonces: RedbIndex,
+    delegations: RedbIndex,
+    encryption: RedbIndex,
+    read_access: RedbIndex,
+    values: RedbValues,
+}
+
+impl RedbBackend {
+    pub fn open(path: PathBuf) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let db = Arc::new(redb::Database::create(&path)?);
+
+        // Create tables up front so read transactions never see a missing table
+        let txn = db.begin_write()?;
+        txn.open_table(REDB_INDEX_TABLE)?;
+        txn.open_table(REDB_JOURNAL_TABLE)?;
+        txn.open_table(REDB_SECONDARY_INDEX_TABLE)?;
+        txn.open_table(REDB_INDEX_DEFS_TABLE)?;
+        txn.open_table(REDB_NONCES_TABLE)?;
+        txn.open_table(REDB_DELEGATIONS_TABLE)?;
+        txn.open_table(REDB_ENCRYPTION_TABLE)?;
+        txn.open_table(REDB_READ_ACCESS_TABLE)?;
+        txn.open_table(REDB_VALUES_TABLE)?;
+        txn.commit()?;
+
+        tracing::info!("redb storage backend opened at {:?}", path);
+
+        Ok(Self {
+            index: RedbIndex { db: db.clone(), table: REDB_INDEX_TABLE },
+            journal: RedbIndex { db: db.clone(), table: REDB_JOURNAL_TABLE },
+            secondary_index: RedbIndex { db: db.clone(), table: REDB_SECONDARY_INDEX_TABLE },
+            index_definitions: RedbIndex { db: db.clone(), table: REDB_INDEX_DEFS_TABLE },
+            nonces: RedbIndex { db: db.clone(), table: REDB_NONCES_TABLE },
+            delegations: RedbIndex { db: db.clone(), table: REDB_DELEGATIONS_TABLE },
+            encryption: RedbIndex { db: db.clone(), table: REDB_ENCRYPTION_TABLE },
+            read_access: RedbIndex { db: db.clone(), table: REDB_READ_ACCESS_TABLE },
+            values: RedbValues { db },
+        })
+    }
+}
+
+impl StorageBackend for RedbBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Redb
+    }
+
+    fn index(&self) -> &dyn IndexStore {
+        &self.index
+    }
+
+    fn values(&self) -> &dyn ValueStore {
+        &self.values
+    }
+
+    fn journal(&self) -> &dyn IndexStore {
+        &self.journal
+    }
+
+    fn secondary_index(&self) -> &dyn IndexStore {
+        &self.secondary_index
+    }
+
+    fn index_definitions(&self) -> &dyn IndexStore {
+        &self.index_definitions
+    }
+
+    fn nonces(&self) -> &dyn IndexStore {
+        &self.nonces
+    }
+
+    fn delegations(&self) -> &dyn IndexStore {
+        &self.delegations
+    }
+
+    fn encryption(&self) -> &dyn IndexStore {
+        &self.encryption
+    }
+
+    fn read_access(&self) -> &dyn IndexStore {
+        &self.read_access
+    }
+}
+
+// ============================================================================
+// In-memory
+// ============================================================================
+
+#[derive(Default)]
+pub struct MemoryIndex {
+    entries: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl IndexStore for MemoryIndex {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let entries = self.entries.read().map_err(|_| anyhow!("Index lock poisoned"))?;
+        Ok(entries.get(key).cloned())
+    }
+
+    fn insert(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let mut entries = self.entries.write().map_err(|_| anyhow!("Index lock poisoned"))?;
+        entries.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        let mut entries = self.entries.write().map_err(|_| anyhow!("Index lock poisoned"))?;
+        entries.remove(key);
+        Ok(())
+    }
+
+    fn contains(&self, key: &str) -> Result<bool> {
+        let entries = self.entries.read().map_err(|_| anyhow!("Index lock poisoned"))?;
+        Ok(entries.contains_key(key))
+    }
+
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let entries = self.entries.read().map_err(|_| anyhow!("Index lock poisoned"))?;
+        Ok(entries
+            .range(prefix.to_string()..)
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .map(|(k, _)| k.clone())
+            .collect())
+    }
+
+    fn keys_in_range(&self, start: &str, end: &str) -> Result<Vec<String>> {
+        let entries = self.entries.read().map_err(|_| anyhow!("Index lock poisoned"))?;
+        if start >= end {
+            return Ok(Vec::new());
+        }
+        Ok(entries
+            .range(start.to_string()..end.to_string())
+            .map(|(k, _)| k.clone())
+            .collect())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryValues {
+    blobs: RwLock<HashMap<Hash, Vec<u8>>>,
+}
+
+impl ValueStore for MemoryValues {
+    fn put(&self, bytes: Vec<u8>) -> BoxFuture<'_, Result<Hash>> {
+        Box::pin(async move {
+            let hash = Hash::new(&bytes);
+            let mut blobs = self.blobs.write().map_err(|_| anyhow!("Value lock poisoned"))?;
+            blobs.insert(hash, bytes);
+            Ok(hash)
+        })
+    }
+
+    fn get(&self, hash: Hash) -> BoxFuture<'_, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let blobs = self.blobs.read().map_err(|_| anyhow!("Value lock poisoned"))?;
+            blobs
+                .get(&hash)
+                .cloned()
+                .ok_or_else(|| anyhow!("Value {} not found", hash))
+        })
+    }
+
+    fn contains(&self, hash: Hash) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(async move {
+            let blobs = self.blobs.read().map_err(|_| anyhow!("Value lock poisoned"))?;
+            Ok(blobs.contains_key(&hash))
+        })
+    }
+
+    fn sync(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Ephemeral backend; everything is lost when the process exits
+#[derive(Default)]
+pub struct MemoryBackend {
+    index: MemoryIndex,
+    journal: MemoryIndex,
+    secondary_index: MemoryIndex,
+    index_definitions: MemoryIndex,
+    nonces: MemoryIndex,
+    delegations: MemoryIndex,
+    encryption: MemoryIndex,
+    read_access: MemoryIndex,
+    values: MemoryValues,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Memory
+    }
+
+    fn index(&self) -> &dyn IndexStore {
+        &self.index
+    }
+
+    fn values(&self) -> &dyn ValueStore {
+        &self.values
+    }
+
+    fn journal(&self) -> &dyn IndexStore {
+        &self.journal
+    }
+
+    fn secondary_index(&self) -> &dyn IndexStore {
+        &self.secondary_index
+    }
+
+    fn index_definitions(&self) -> &dyn IndexStore {
+        &self.index_definitions
+    }
+
+    fn nonces(&self) -> &dyn IndexStore {
+        &self.nonces
+    }
+
+    fn delegations(&self) -> &dyn IndexStore {
+        &self.delegations
+    }
+
+    fn encryption(&self) -> &dyn IndexStore {
+        &self.encryption
+    }
+
+    fn read_access(&self) -> &dyn IndexStore {
+        &self.read_access
+    }
+}
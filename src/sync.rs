@@ -12,8 +12,13 @@ use tokio::sync::RwLock;
 // Chunk size for sync responses to avoid oversized payloads
 const MAX_OPS_PER_RESPONSE: usize = 128;
 
+use crate::batch;
 use crate::crypto;
+use crate::delegation::{self, Delegations};
+use crate::encryption;
+use crate::read_access;
 use crate::storage::RedisStorage;
+use crate::versioning;
 
 /// Sync message types
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,6 +38,44 @@ pub enum SyncMessage {
     },
     /// New operation to be replicated
     Operation { operation: SignedOperation },
+    /// Operations submitted together with `submitBatch`, replicated in one
+    /// message rather than one per operation
+    Batch { operations: Vec<SignedOperation> },
+    /// A `submitBatch` batch signed once over its Merkle root, with the
+    /// signer and root signature sent once (see `batch.rs`)
+    MerkleBatch { batch: batch::MerkleBatch },
+}
+
+impl SyncMessage {
+    /// Serialize for the wire, tagged with the lowest sync format version that
+    /// can represent it (see `versioning.rs`)
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut value = serde_json::to_value(self)?;
+        versioning::tag_sync_value(&mut value);
+        Ok(serde_json::to_vec(&value)?)
+    }
+
+    /// Parse a message from any sync format version, upgrading embedded operations
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut value: serde_json::Value = serde_json::from_slice(bytes)?;
+        let version = versioning::take_sync_version(&mut value)?;
+        if version != versioning::SYNC_FORMAT_VERSION {
+            if let Some(op) = value.get_mut("operation") {
+                *op = versioning::upgrade_sync_op(version, op.take())?;
+            }
+            if let Some(ops) = value.get_mut("operations").and_then(|ops| ops.as_array_mut()) {
+                for op in ops.iter_mut() {
+                    *op = versioning::upgrade_sync_op(version, op.take())?;
+                }
+            }
+            if let Some(leaves) = value.pointer_mut("/batch/leaves").and_then(|leaves| leaves.as_array_mut()) {
+                for op in leaves.iter_mut().filter_map(|leaf| leaf.get_mut("operation")) {
+                    *op = versioning::upgrade_sync_op(version, op.take())?;
+                }
+            }
+        }
+        Ok(serde_json::from_value(value)?)
+    }
 }
 
 /// A signed data operation that can be verified and merged
@@ -48,9 +91,11 @@ pub struct SignedOperation {
     pub key: String,
     /// The data value (JSON string)
     pub value: String,
-    /// Store type: String, Hash, List, Set, SortedSet, JSON, Stream, TimeSeries, Geo
+    /// Store type: String, Hash, List, Set, SortedSet, JSON, Stream, TimeSeries, Geo, Vector,
+    /// Grant/Revoke (see `delegation.rs`), Encryption (see `encryption.rs`) or
+    /// Privacy (see `read_access.rs`)
     pub store_type: String,
-    /// Optional field for Hash
+    /// Optional field for Hash, member for Vector, or grantee key for Grant/Revoke
     pub field: Option<String>,
     /// Optional score for SortedSet
     pub score: Option<f64>,
@@ -60,28 +105,170 @@ pub struct SignedOperation {
     pub stream_fields: Option<String>,
     /// Optional timestamp for TimeSeries
     pub ts_timestamp: Option<String>,
+    /// Optional labels replacing a TimeSeries' labels (JSON object of names to
+    /// values). Sync format v2; operations with labels can't replicate to v1
+    /// peers (see `versioning.rs`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<String>,
     /// Optional longitude for Geo
     pub longitude: Option<f64>,
     /// Optional latitude for Geo
     pub latitude: Option<f64>,
     /// Ed25519 public key (hex encoded)
     pub public_key: String,
-    /// Ed25519 signature (hex encoded) - signs: op_id:timestamp:db_name:key:value
+    /// Ed25519 signature (hex encoded) over `canonical_message()`
     pub signature: String,
 }
 
+/// Message formats a `SignedOperation` signature may cover
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureFormat {
+    /// Domain-separated JCS envelope of every field
+    Canonical,
+    /// Deprecated: op_id:timestamp:db_name:key:value
+    LegacyFull,
+    /// Deprecated: db_name:key:value
+    LegacyShort,
+    /// Canonical envelope as a leaf of a batch Merkle root signed once
+    /// (see `batch.rs`)
+    MerkleBatch,
+}
+
+impl std::fmt::Display for SignatureFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SignatureFormat::Canonical => "canonical",
+            SignatureFormat::LegacyFull => "legacy-full",
+            SignatureFormat::LegacyShort => "legacy-short",
+            SignatureFormat::MerkleBatch => "merkle-batch",
+        })
+    }
+}
+
 impl SignedOperation {
-    /// Verify the signature of this operation with enhanced security checks
-    /// Supports two formats:
-    /// 1. Full format: op_id:timestamp:db_name:key:value (for sync operations)
-    /// 2. Short format: db_name:key:value (for GraphQL submissions)
-    pub fn verify(&self) -> Result<()> {
-        // Enhanced database name verification with security checks
-        crypto::verify_db_name_secure(&self.db_name, &self.public_key)?;
+    /// Verify the signature of this operation with enhanced security checks,
+    /// returning the format it was signed in. Only the database owner's
+    /// writes are accepted. Legacy signature formats are only accepted during
+    /// their deprecation window (see `crypto::set_legacy_signature_cutoff`).
+    pub fn verify(&self) -> Result<SignatureFormat> {
+        self.verify_inner(None, None)
+    }
+
+    /// Like `verify`, but also accepts writes signed by a key the owner has
+    /// granted access to (see `delegation.rs`)
+    pub fn verify_with_delegations(&self, delegations: &Delegations) -> Result<SignatureFormat> {
+        self.verify_inner(Some(delegations), None)
+    }
+
+    /// Like `verify_with_delegations`, for writes submitted to this node's
+    /// API: a delegate's grant must also be unrevoked and unexpired by the
+    /// node's clock, not only at the timestamp the signer chose. Operations
+    /// replayed from the sync log use `verify_with_delegations`.
+    pub fn verify_submission(&self, delegations: &Delegations) -> Result<SignatureFormat> {
+        self.verify_inner(Some(delegations), Some(chrono::Utc::now().timestamp_millis()))
+    }
+
+    fn verify_inner(&self, delegations: Option<&Delegations>, now_ms: Option<i64>) -> Result<SignatureFormat> {
+        // Enhanced database name verification with security checks; a
+        // non-owner signer needs a grant from the owner
+        if let Err(e) = crypto::verify_db_name_secure(&self.db_name, &self.public_key) {
+            let (Some(delegations), Some(owner)) = (delegations, crypto::db_owner_key(&self.db_name)) else {
+                return Err(e);
+            };
+            crypto::verify_db_name_secure(&self.db_name, owner)?;
+            match now_ms {
+                Some(now_ms) => delegations.authorize_at(self, now_ms),
+                None => delegations.authorize(self),
+            }
+            .map_err(|denied| anyhow!("{}: {}", e, denied))?;
+        }
+
+        // Grants use a reserved key so they can't collide with data
+        if delegation::is_delegation_op(self) != (self.key == delegation::GRANTS_KEY) {
+            return Err(anyhow!(
+                "Grant and revoke operations must use key {}, which is reserved for them",
+                delegation::GRANTS_KEY
+            ));
+        }
+        if encryption::is_encryption_op(self) != (self.key == encryption::ENCRYPTION_KEY) {
+            return Err(anyhow!(
+                "Encryption operations must use key {}, which is reserved for them",
+                encryption::ENCRYPTION_KEY
+            ));
+        }
+        if read_access::is_privacy_op(self) != (self.key == read_access::PRIVACY_KEY) {
+            return Err(anyhow!(
+                "Privacy operations must use key {}, which is reserved for them",
+                read_access::PRIVACY_KEY
+            ));
+        }
 
         // Validate timestamp (allow some tolerance for network delays)
         crypto::validate_timestamp(self.timestamp, Some(crypto::MAX_TIMESTAMP_TOLERANCE))?;
 
+        let format = self.signature_format()?;
+        if matches!(format, SignatureFormat::LegacyFull | SignatureFormat::LegacyShort) {
+            if !crypto::legacy_signatures_accepted() {
+                return Err(anyhow!(
+                    "{} signatures are no longer accepted; sign the canonical envelope",
+                    format
+                ));
+            }
+            crate::metrics::SYNC_LEGACY_SIGNATURES
+                .with_label_values(&[&format.to_string()])
+                .inc();
+            tracing::warn!(
+                op_id = %self.op_id,
+                db_name = %self.db_name,
+                public_key = %self.public_key,
+                %format,
+                "Accepted an operation in a deprecated signature format"
+            );
+        }
+        Ok(format)
+    }
+
+    /// Verify only the signature, in any accepted format, without database
+    /// ownership, timestamp freshness or deprecation checks. Used when
+    /// re-checking operations already in the log.
+    pub fn verify_signature(&self) -> Result<()> {
+        self.signature_format().map(|_| ())
+    }
+
+    /// The canonical message to sign: the domain separator, a newline, then
+    /// the JCS encoding of every field except `signature` (absent optionals
+    /// as `null`), so no part of the operation can be altered in transit.
+    /// `labels` is left out when absent, so envelopes signed before it
+    /// existed still verify.
+    pub fn canonical_message(&self) -> String {
+        let mut envelope = serde_json::json!({
+            "db_name": self.db_name,
+            "field": self.field,
+            "json_path": self.json_path,
+            "key": self.key,
+            "latitude": self.latitude,
+            "longitude": self.longitude,
+            "op_id": self.op_id,
+            "public_key": self.public_key,
+            "score": self.score,
+            "store_type": self.store_type,
+            "stream_fields": self.stream_fields,
+            "timestamp": self.timestamp,
+            "ts_timestamp": self.ts_timestamp,
+            "value": self.value,
+        });
+        if let Some(labels) = &self.labels {
+            envelope["labels"] = serde_json::Value::from(labels.as_str());
+        }
+        format!("{}\n{}", crypto::SIGNED_OPERATION_DOMAIN, crypto::canonical_json(&envelope))
+    }
+
+    /// Find which message format the signature was made over
+    pub fn signature_format(&self) -> Result<SignatureFormat> {
+        if batch::is_merkle_signature(&self.signature) {
+            return batch::verify_merkle_signature(self).map(|()| SignatureFormat::MerkleBatch);
+        }
+
         // Securely decode public key and signature with validation
         let public_key_bytes = crypto::secure_hex_decode(&self.public_key)
             .map_err(|e| anyhow!("Invalid public key hex: {}", e))?;
@@ -90,32 +277,48 @@ impl SignedOperation {
 
         tracing::debug!(op_id = %self.op_id, "Verifying SignedOperation signature with enhanced security");
 
-        // Try full format first (op_id:timestamp:db_name:key:value)
-        let full_message = format!(
-            "{}:{}:{}:{}:{}",
-            self.op_id, self.timestamp, self.db_name, self.key, self.value
-        );
-
-        if crypto::verify_signature(&public_key_bytes, full_message.as_bytes(), &signature_bytes)
-            .is_ok()
-        {
-            tracing::debug!(op_id = %self.op_id, format = %full_message, "Signature verified with full format");
-            return Ok(());
-        }
-
-        // Try short format (db_name:key:value) - used by GraphQL client
-        let short_message = format!("{}:{}:{}", self.db_name, self.key, self.value);
-        match crypto::verify_signature(&public_key_bytes, short_message.as_bytes(), &signature_bytes)
-        {
-            Ok(_) => {
-                tracing::debug!(op_id = %self.op_id, format = %short_message, "Signature verified with short format");
-                Ok(())
-            }
-            Err(e) => {
-                tracing::warn!(op_id = %self.op_id, "Signature verification failed for both formats: {}", e);
-                Err(e)
+        let candidates = [
+            (SignatureFormat::Canonical, self.canonical_message()),
+            (
+                SignatureFormat::LegacyFull,
+                format!("{}:{}:{}:{}:{}", self.op_id, self.timestamp, self.db_name, self.key, self.value),
+            ),
+            (
+                SignatureFormat::LegacyShort,
+                format!("{}:{}:{}", self.db_name, self.key, self.value),
+            ),
+        ];
+
+        let mut last_error = None;
+        for (format, message) in candidates {
+            match crypto::verify_signature(&public_key_bytes, message.as_bytes(), &signature_bytes) {
+                Ok(()) => {
+                    tracing::debug!(op_id = %self.op_id, %format, "Signature verified");
+                    return Ok(format);
+                }
+                Err(e) => last_error = Some(e),
             }
         }
+
+        let e = last_error.unwrap_or_else(|| anyhow!("Signature verification failed"));
+        tracing::warn!(op_id = %self.op_id, "Signature verification failed for all formats: {}", e);
+        Err(e)
+    }
+
+    /// Serialize for the op log, tagged with the lowest sync format version
+    /// that can represent it
+    pub fn to_versioned_json(&self) -> Result<Vec<u8>> {
+        let mut value = serde_json::to_value(self)?;
+        versioning::tag_sync_value(&mut value);
+        Ok(serde_json::to_vec(&value)?)
+    }
+
+    /// Parse an op log entry from any sync format version
+    pub fn from_versioned_json(bytes: &[u8]) -> Result<Self> {
+        let mut value: serde_json::Value = serde_json::from_slice(bytes)?;
+        let version = versioning::take_sync_version(&mut value)?;
+        let value = versioning::upgrade_sync_op(version, value)?;
+        Ok(serde_json::from_value(value)?)
     }
 
     /// Get a comparable key for CRDT ordering (db_name:key:field)
@@ -141,6 +344,8 @@ pub struct SyncStore {
     operation_index: Arc<RwLock<HashMap<String, Hash>>>,
     /// Set of operation IDs that have been applied to storage (in-memory dedupe)
     applied_ops: Arc<RwLock<HashSet<String>>>,
+    /// Grants used to verify operations signed by delegates
+    delegations: Delegations,
 }
 
 impl SyncStore {
@@ -151,6 +356,7 @@ impl SyncStore {
             store: None,
             operation_index: Arc::new(RwLock::new(HashMap::new())),
             applied_ops: Arc::new(RwLock::new(HashSet::new())),
+            delegations: Delegations::new(),
         }
     }
 
@@ -162,9 +368,16 @@ impl SyncStore {
             store: Some(store),
             operation_index: Arc::new(RwLock::new(HashMap::new())),
             applied_ops: Arc::new(RwLock::new(HashSet::new())),
+            delegations: Delegations::new(),
         }
     }
 
+    /// Verify delegated writes against `delegations` (by default no grants are known)
+    pub fn with_delegations(mut self, delegations: Delegations) -> Self {
+        self.delegations = delegations;
+        self
+    }
+
     /// Check whether an operation has already been applied to storage
     pub async fn is_applied(&self, op_id: &str) -> bool {
         self.applied_ops.read().await.contains(op_id)
@@ -178,8 +391,8 @@ impl SyncStore {
     /// Persist an operation to Iroh blobs
     async fn persist_operation(&self, op: &SignedOperation) -> Result<Option<Hash>> {
         if let Some(ref store) = self.store {
-            // Serialize operation to JSON
-            let json = serde_json::to_vec(op)?;
+            // Serialize operation to versioned JSON
+            let json = op.to_versioned_json()?;
 
             // Store in Iroh blobs (add_bytes takes Vec<u8>)
             let blobs = store.blobs();
@@ -207,8 +420,8 @@ impl SyncStore {
             let blobs = store.blobs();
             let bytes = blobs.get_bytes(hash).await?.to_vec();
 
-            // Deserialize
-            let op: SignedOperation = serde_json::from_slice(&bytes)?;
+            // Deserialize (older format versions are upgraded)
+            let op = SignedOperation::from_versioned_json(&bytes)?;
 
             Ok(op)
         } else {
@@ -231,8 +444,14 @@ impl SyncStore {
         for (op_id, hash) in index.iter() {
             match self.load_operation(*hash).await {
                 Ok(op) => {
-                    // Add to memory (this will verify signature)
-                    if self.add_operation_to_memory(op).await? {
+                    // Logged ops were admitted when written and are usually
+                    // older than the freshness window, so only check that
+                    // the signature still holds; skip any that don't
+                    if let Err(e) = op.verify_signature() {
+                        tracing::warn!("Skipping logged operation {} with a bad signature: {}", op_id, e);
+                        continue;
+                    }
+                    if self.add_operation_to_memory_unverified(op).await? {
                         loaded += 1;
                     }
                 }
@@ -334,7 +553,7 @@ impl SyncStore {
     /// Add operation to memory only (used internally after loading from blobs)
     async fn add_operation_to_memory(&self, op: SignedOperation) -> Result<bool> {
         // Verify signature first
-        op.verify()?;
+        op.verify_with_delegations(&self.delegations)?;
 
         let crdt_key = op.crdt_key();
         let mut ops = self.operations.write().await;
@@ -543,7 +762,7 @@ impl Clone for SyncManager {
 impl SyncManager {
     pub fn new(storage: RedisStorage, local_node_id: EndpointId) -> Self {
         Self {
-            sync_store: Arc::new(SyncStore::new()),
+            sync_store: Arc::new(SyncStore::new().with_delegations(storage.delegations().clone())),
             storage,
             local_node_id,
         }
@@ -551,7 +770,9 @@ impl SyncManager {
 
     pub fn with_store(storage: RedisStorage, local_node_id: EndpointId, store: FsStore) -> Self {
         Self {
-            sync_store: Arc::new(SyncStore::with_store(store)),
+            sync_store: Arc::new(
+                SyncStore::with_store(store).with_delegations(storage.delegations().clone()),
+            ),
             storage,
             local_node_id,
         }
@@ -677,7 +898,7 @@ impl SyncManager {
                 // Merge and apply
                 let merged = self.sync_store.merge_operations(operations).await?;
                 tracing::info!("Merged {} new operations", merged);
-                self.apply_operations_to_storage().await?;
+                self.apply_operations_to_storage(&from_peer.to_string()).await?;
 
                 // If more data is available, immediately request the next chunk using continuation
                 if has_more {
@@ -702,136 +923,143 @@ impl SyncManager {
                 Ok(None)
             }
             SyncMessage::Operation { operation } => {
-                tracing::info!(
-                    "📥 Received operation {} from {} (db: {}, key: {}, type: {})",
-                    operation.op_id, from_peer, operation.db_name, operation.key, operation.store_type
-                );
-                
-                // First verify the operation signature
-                match operation.verify() {
-                    Ok(_) => {
-                        tracing::debug!(op_id = %operation.op_id, "✓ Signature verified");
-                    }
-                    Err(e) => {
-                        tracing::error!(
-                            op_id = %operation.op_id,
-                            "❌ Signature verification failed: {} - Rejecting operation",
-                            e
-                        );
-                        return Ok(None);
-                    }
+                self.receive_operation(operation, from_peer).await;
+                Ok(None)
+            }
+            SyncMessage::Batch { operations } => {
+                tracing::info!("📥 Received batch of {} operations from {}", operations.len(), from_peer);
+                for operation in operations {
+                    self.receive_operation(operation, from_peer).await;
                 }
-                
-                match self.sync_store.add_operation(operation.clone()).await {
-                    Ok(true) => {
-                        tracing::info!(
-                            op_id = %operation.op_id,
-                            "✓ Operation accepted into SyncStore, applying to storage"
-                        );
-                        if let Err(e) = self.apply_operation_to_storage(&operation).await {
-                            tracing::error!(
-                                op_id = %operation.op_id,
-                                "❌ Failed to apply operation to storage: {}",
-                                e
-                            );
-                        } else {
-                            tracing::info!(
-                                op_id = %operation.op_id,
-                                "✓ Operation successfully applied to storage"
-                            );
+                Ok(None)
+            }
+            SyncMessage::MerkleBatch { batch } => {
+                tracing::info!("📥 Received batch of {} operations from {}", batch.leaves.len(), from_peer);
+                match batch.unpack() {
+                    Ok(operations) => {
+                        for operation in operations {
+                            self.receive_operation(operation, from_peer).await;
                         }
                     }
-                    Ok(false) => {
-                        tracing::debug!(
-                            op_id = %operation.op_id,
-                            "⏭️  Operation rejected by SyncStore (duplicate or older)"
-                        );
-                    }
-                    Err(e) => {
-                        tracing::error!(
-                            op_id = %operation.op_id,
-                            "❌ Failed to add operation to SyncStore: {}",
-                            e
-                        );
-                    }
+                    Err(e) => tracing::warn!("Rejecting batch from {}: {}", from_peer, e),
                 }
                 Ok(None)
             }
         }
     }
 
-    /// Apply a single operation to Redis storage
-    async fn apply_operation_to_storage(&self, op: &SignedOperation) -> Result<()> {
-        // Avoid re-applying the same operation multiple times
-        if self.sync_store.is_applied(&op.op_id).await {
-            tracing::debug!(op_id = %op.op_id, "Skipping already-applied operation");
-            return Ok(());
-        }
-
-        let full_key = format!("{}:{}", op.db_name, op.key);
-
-        match op.store_type.to_lowercase().as_str() {
-            "string" => {
-                self.storage.set_string(&full_key, &op.value).await?;
-            }
-            "hash" => {
-                let field = op
-                    .field
-                    .as_ref()
-                    .ok_or_else(|| anyhow!("Field required for Hash type"))?;
-                self.storage.set_hash(&full_key, field, &op.value).await?;
-            }
-            "list" => {
-                self.storage.push_list(&full_key, &op.value).await?;
-            }
-            "set" => {
-                self.storage.add_set(&full_key, &op.value).await?;
-            }
-            "sortedset" => {
-                let score = op
-                    .score
-                    .ok_or_else(|| anyhow!("Score required for SortedSet type"))?;
-                self.storage
-                    .add_sorted_set(&full_key, score, &op.value)
-                    .await?;
+    /// Verify, log and apply one operation gossiped by `from_peer`; rejected
+    /// operations are logged and dropped
+    async fn receive_operation(&self, operation: SignedOperation, from_peer: EndpointId) {
+        tracing::info!(
+            "📥 Received operation {} from {} (db: {}, key: {}, type: {})",
+            operation.op_id, from_peer, operation.db_name, operation.key, operation.store_type
+        );
+        
+        // First verify the operation signature
+        let format = match operation.verify_with_delegations(self.storage.delegations()) {
+            Ok(format) => {
+                tracing::debug!(op_id = %operation.op_id, "✓ Signature verified");
+                format
             }
-            "json" => {
-                let path = op.json_path.as_deref().unwrap_or("$");
-                self.storage.set_json(&full_key, path, &op.value).await?;
+            Err(e) => {
+                tracing::error!(
+                    op_id = %operation.op_id,
+                    "❌ Signature verification failed: {} - Rejecting operation",
+                    e
+                );
+                return;
             }
-            "stream" => {
-                if let Some(ref fields_json) = op.stream_fields {
-                    let fields: Vec<serde_json::Value> = serde_json::from_str(fields_json)?;
-                    let mut field_pairs: Vec<(String, String)> = Vec::new();
-
-                    for field_obj in fields {
-                        if let (Some(k), Some(v)) = (
-                            field_obj.get("key").and_then(|k| k.as_str()),
-                            field_obj.get("value").and_then(|v| v.as_str()),
-                        ) {
-                            field_pairs.push((k.to_string(), v.to_string()));
-                        }
-                    }
+        };
 
-                    self.storage.xadd(&full_key, "*", &field_pairs).await?;
-                }
-            }
-            "timeseries" => {
-                if let Some(ref ts_str) = op.ts_timestamp {
-                    let timestamp = ts_str.parse::<i64>()?;
-                    let value = op.value.parse::<f64>()?;
-                    self.storage.ts_add(&full_key, timestamp, value).await?;
+        // Encrypted databases only take sealed values
+        if let Err(e) = self.storage.encryption().check_write(&operation) {
+            tracing::warn!(op_id = %operation.op_id, "Rejecting operation: {}", e);
+            return;
+        }
+
+        // Then reject nonces that were already used
+        if let Err(e) = self.storage.replay_guard().check(&operation, format).await {
+            tracing::warn!(op_id = %operation.op_id, "Rejecting operation: {}", e);
+            return;
+        }
+        
+        match self.sync_store.add_operation(operation.clone()).await {
+            Ok(true) => {
+                tracing::info!(
+                    op_id = %operation.op_id,
+                    "✓ Operation accepted into SyncStore, applying to storage"
+                );
+                if let Err(e) = self.apply_operation_to_storage(&operation, &from_peer.to_string()).await {
+                    self.release_nonce(&operation, format).await;
+                    tracing::error!(
+                        op_id = %operation.op_id,
+                        "❌ Failed to apply operation to storage: {}",
+                        e
+                    );
+                } else {
+                    tracing::info!(
+                        op_id = %operation.op_id,
+                        "✓ Operation successfully applied to storage"
+                    );
                 }
             }
-            "geo" => {
-                if let (Some(lon), Some(lat)) = (op.longitude, op.latitude) {
-                    self.storage.geoadd(&full_key, lon, lat, &op.value).await?;
-                }
+            Ok(false) => {
+                tracing::debug!(
+                    op_id = %operation.op_id,
+                    "⏭️  Operation rejected by SyncStore (duplicate or older)"
+                );
             }
-            _ => {
-                tracing::warn!("Unknown store type: {}", op.store_type);
+            Err(e) => {
+                self.release_nonce(&operation, format).await;
+                tracing::error!(
+                    op_id = %operation.op_id,
+                    "❌ Failed to add operation to SyncStore: {}",
+                    e
+                );
             }
         }
+    }
+
+    /// Give back the nonce recorded for an operation that couldn't be applied,
+    /// so a later copy of it is accepted
+    async fn release_nonce(&self, op: &SignedOperation, format: SignatureFormat) {
+        if let Err(e) = self.storage.replay_guard().release(op, format).await {
+            tracing::warn!(op_id = %op.op_id, "Failed to release nonce: {}", e);
+        }
+    }
+
+    /// Re-apply an operation from the log even if it was applied before.
+    /// Used by fsck to rebuild keys whose stored value is missing or corrupt.
+    pub async fn replay_operation(&self, op: &SignedOperation) -> Result<()> {
+        crate::changes::attribute(
+            self.storage.changes(),
+            crate::changes::ChangeEvent::from_op(op, None),
+            self.storage.write_signed_operation(op, None, None),
+        )
+        .await?;
+        self.sync_store.mark_applied(&op.op_id).await;
+        tracing::info!(op_id = %op.op_id, "Replayed operation from op log");
+        Ok(())
+    }
+
+    /// Apply a single operation to Redis storage, announcing it on the change
+    /// feed as received from `received_from`
+    async fn apply_operation_to_storage(&self, op: &SignedOperation, received_from: &str) -> Result<()> {
+        // Avoid re-applying the same operation multiple times
+        if self.sync_store.is_applied(&op.op_id).await {
+            tracing::debug!(op_id = %op.op_id, "Skipping already-applied operation");
+            return Ok(());
+        }
+
+        let full_key = format!("{}:{}", op.db_name, op.key);
+        crate::changes::attribute(
+            self.storage.changes(),
+            crate::changes::ChangeEvent::from_op(op, Some(received_from)),
+            self.storage.write_signed_operation(op, None, None),
+        )
+        .await?;
+
         // Mark as applied so we don't re-apply on duplicate sync messages
         self.sync_store.mark_applied(&op.op_id).await;
         // Persist applied_ops set so restarts won't re-apply already-applied operations.
@@ -847,13 +1075,13 @@ impl SyncManager {
     }
 
     /// Apply all operations in sync store to storage
-    async fn apply_operations_to_storage(&self) -> Result<()> {
+    async fn apply_operations_to_storage(&self, received_from: &str) -> Result<()> {
         let operations = self.sync_store.get_all_operations().await;
 
         tracing::info!("Applying {} operations to storage", operations.len());
 
         for op in operations {
-            if let Err(e) = self.apply_operation_to_storage(&op).await {
+            if let Err(e) = self.apply_operation_to_storage(&op, received_from).await {
                 tracing::error!("Failed to apply operation {}: {}", op.op_id, e);
             }
         }
@@ -935,6 +1163,7 @@ mod tests {
             json_path: None,
             stream_fields: None,
             ts_timestamp: None,
+            labels: None,
             longitude: None,
             latitude: None,
             public_key: public_key_hex,
@@ -964,6 +1193,7 @@ mod tests {
             json_path: None,
             stream_fields: None,
             ts_timestamp: None,
+            labels: None,
             longitude: None,
             latitude: None,
             public_key: public_key.clone(),
@@ -982,6 +1212,7 @@ mod tests {
             json_path: None,
             stream_fields: None,
             ts_timestamp: None,
+            labels: None,
             longitude: None,
             latitude: None,
             public_key: public_key.clone(),
@@ -0,0 +1,515 @@
+//! Vector store: fixed-dimension f32 vectors per member with an HNSW index
+//!
+//! Each vector key holds one `HnswIndex`, persisted with the value so a node
+//! restarts without rebuilding it. The first vector written fixes the key's
+//! dimension and distance metric. Levels are derived from a hash of the
+//! member name, so replicas applying the same writes in the same order build
+//! the same graph.
+//!
+//! Replacing or removing a member leaves a tombstone that search still walks
+//! through; the graph is rebuilt once tombstones outnumber live members.
+//!
+//! Writes carry the vector in the value as JSON, either a bare array or
+//! `{"vector": [...], "metadata": {...}, "metric": "cosine"}`.
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Links per node on the upper layers (twice as many on layer 0)
+const M: usize = 16;
+/// Candidates examined while linking a new node
+const EF_CONSTRUCTION: usize = 100;
+/// Candidates examined by a search unless told otherwise
+pub const DEFAULT_EF_SEARCH: usize = 64;
+/// Highest layer a node can be placed on
+const MAX_LEVEL: usize = 16;
+
+/// How vectors are compared; every metric yields a distance, lower is closer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, async_graphql::Enum)]
+pub enum DistanceMetric {
+    /// 1 - cosine similarity
+    #[default]
+    Cosine,
+    /// Euclidean distance
+    L2,
+    /// Negated dot product
+    Dot,
+}
+
+impl DistanceMetric {
+    pub fn distance(self, a: &[f32], b: &[f32]) -> f32 {
+        let dot = || a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+        match self {
+            DistanceMetric::Cosine => {
+                let norms = norm(a) * norm(b);
+                if norms == 0.0 {
+                    1.0
+                } else {
+                    1.0 - dot() / norms
+                }
+            }
+            DistanceMetric::L2 => a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f32>()
+                .sqrt(),
+            DistanceMetric::Dot => -dot(),
+        }
+    }
+}
+
+impl std::str::FromStr for DistanceMetric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_lowercase().as_str() {
+            "cosine" => DistanceMetric::Cosine,
+            "l2" | "euclidean" => DistanceMetric::L2,
+            "dot" => DistanceMetric::Dot,
+            other => bail!("Unknown distance metric: {}", other),
+        })
+    }
+}
+
+fn norm(v: &[f32]) -> f32 {
+    v.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+/// A vector write as carried in a value
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorWrite {
+    pub vector: Vec<f32>,
+    pub metadata: JsonValue,
+    /// Metric for a new key; must match an existing key's metric
+    pub metric: Option<DistanceMetric>,
+}
+
+impl VectorWrite {
+    /// Parse a value: a bare array of numbers, or an object with `vector`
+    /// and optional `metadata` and `metric`
+    pub fn parse(value: &str) -> Result<Self> {
+        let json: JsonValue = serde_json::from_str(value)?;
+        let (vector, metadata, metric) = match &json {
+            JsonValue::Array(_) => (&json, JsonValue::Null, None),
+            JsonValue::Object(map) => {
+                let vector = map.get("vector").ok_or_else(|| anyhow!("Missing 'vector'"))?;
+                let metric = match map.get("metric") {
+                    Some(JsonValue::String(name)) => Some(name.parse()?),
+                    Some(_) => bail!("'metric' must be a string"),
+                    None => None,
+                };
+                let metadata = map.get("metadata").cloned().unwrap_or(JsonValue::Null);
+                (vector, metadata, metric)
+            }
+            _ => bail!("Vector value must be an array or an object"),
+        };
+        let vector = vector
+            .as_array()
+            .ok_or_else(|| anyhow!("'vector' must be an array"))?
+            .iter()
+            .map(|x| x.as_f64().map(|x| x as f32).ok_or_else(|| anyhow!("Vector components must be numbers")))
+            .collect::<Result<Vec<f32>>>()?;
+        if vector.is_empty() {
+            bail!("Vector must not be empty");
+        }
+        if vector.iter().any(|x| !x.is_finite()) {
+            bail!("Vector components must be finite");
+        }
+        Ok(Self {
+            vector,
+            metadata,
+            metric,
+        })
+    }
+}
+
+/// A stored member
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorEntry {
+    pub member: String,
+    pub vector: Vec<f32>,
+    pub metadata: JsonValue,
+}
+
+/// A search hit
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorMatch {
+    pub member: String,
+    pub distance: f32,
+    pub metadata: JsonValue,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Node {
+    member: String,
+    vector: Vec<f32>,
+    #[serde(with = "crate::storage::json_text")]
+    metadata: JsonValue,
+    /// Neighbours per layer, from layer 0 up to the node's level
+    links: Vec<Vec<u32>>,
+    deleted: bool,
+}
+
+/// Hierarchical navigable small world graph over the vectors of one key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HnswIndex {
+    metric: DistanceMetric,
+    dimension: usize,
+    nodes: Vec<Node>,
+    /// Live node of each member
+    ids: HashMap<String, u32>,
+    entry: Option<u32>,
+    deleted: usize,
+}
+
+/// Distance and node id, ordered by distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Scored(f32, u32);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+impl HnswIndex {
+    pub fn new(dimension: usize, metric: DistanceMetric) -> Self {
+        Self {
+            metric,
+            dimension,
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+            entry: None,
+            deleted: 0,
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn metric(&self) -> DistanceMetric {
+        self.metric
+    }
+
+    /// Number of live members
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn get(&self, member: &str) -> Option<VectorEntry> {
+        let node = &self.nodes[*self.ids.get(member)? as usize];
+        Some(VectorEntry {
+            member: node.member.clone(),
+            vector: node.vector.clone(),
+            metadata: node.metadata.clone(),
+        })
+    }
+
+    /// Live members in insertion order
+    pub fn entries(&self) -> impl Iterator<Item = VectorEntry> + '_ {
+        self.nodes.iter().filter(|node| !node.deleted).map(|node| VectorEntry {
+            member: node.member.clone(),
+            vector: node.vector.clone(),
+            metadata: node.metadata.clone(),
+        })
+    }
+
+    fn check_dimension(&self, vector: &[f32]) -> Result<()> {
+        if vector.len() != self.dimension {
+            bail!(
+                "Vector has {} dimensions, the key holds {}-dimensional vectors",
+                vector.len(),
+                self.dimension
+            );
+        }
+        Ok(())
+    }
+
+    fn distance_to(&self, query: &[f32], id: u32) -> f32 {
+        self.metric.distance(query, &self.nodes[id as usize].vector)
+    }
+
+    /// Add or replace a member. Returns true when the member is new.
+    pub fn insert(&mut self, member: &str, vector: Vec<f32>, metadata: JsonValue) -> Result<bool> {
+        self.check_dimension(&vector)?;
+        let replaced = self.tombstone(member);
+
+        let id = self.nodes.len() as u32;
+        let level = level_for(member);
+        self.nodes.push(Node {
+            member: member.to_string(),
+            vector,
+            metadata,
+            links: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        self.ids.insert(member.to_string(), id);
+        self.link(id);
+        self.maybe_compact();
+        Ok(!replaced)
+    }
+
+    /// Remove a member. Returns true when it was present.
+    pub fn remove(&mut self, member: &str) -> bool {
+        let removed = self.tombstone(member);
+        self.maybe_compact();
+        removed
+    }
+
+    fn tombstone(&mut self, member: &str) -> bool {
+        match self.ids.remove(member) {
+            Some(id) => {
+                self.nodes[id as usize].deleted = true;
+                self.deleted += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Connect a freshly pushed node into the graph
+    fn link(&mut self, id: u32) {
+        let Some(entry) = self.entry else {
+            self.entry = Some(id);
+            return;
+        };
+        let query = self.nodes[id as usize].vector.clone();
+        let level = self.nodes[id as usize].links.len() - 1;
+        let top = self.nodes[entry as usize].links.len() - 1;
+
+        let mut nearest = vec![Scored(self.distance_to(&query, entry), entry)];
+        for layer in (level + 1..=top).rev() {
+            nearest = self.search_layer(&query, &nearest, 1, layer);
+        }
+        for layer in (0..=level.min(top)).rev() {
+            nearest = self.search_layer(&query, &nearest, EF_CONSTRUCTION, layer);
+            let neighbours: Vec<u32> = nearest.iter().take(M).map(|scored| scored.1).collect();
+            let max_links = if layer == 0 { 2 * M } else { M };
+            for &neighbour in &neighbours {
+                self.nodes[neighbour as usize].links[layer].push(id);
+                if self.nodes[neighbour as usize].links[layer].len() > max_links {
+                    self.prune(neighbour, layer, max_links);
+                }
+            }
+            self.nodes[id as usize].links[layer] = neighbours;
+        }
+        if level > top {
+            self.entry = Some(id);
+        }
+    }
+
+    /// Keep the closest `max_links` neighbours of a node on one layer
+    fn prune(&mut self, id: u32, layer: usize, max_links: usize) {
+        let node = &self.nodes[id as usize];
+        let mut scored: Vec<Scored> = node.links[layer]
+            .iter()
+            .map(|&other| Scored(self.distance_to(&node.vector, other), other))
+            .collect();
+        scored.sort();
+        scored.truncate(max_links);
+        self.nodes[id as usize].links[layer] = scored.into_iter().map(|scored| scored.1).collect();
+    }
+
+    /// Rebuild without tombstones once they outnumber live members
+    fn maybe_compact(&mut self) {
+        if self.deleted <= self.ids.len().max(M) {
+            return;
+        }
+        let live: Vec<Node> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .filter(|node| !node.deleted)
+            .collect();
+        self.ids.clear();
+        self.entry = None;
+        self.deleted = 0;
+        for mut node in live {
+            let id = self.nodes.len() as u32;
+            node.links = vec![Vec::new(); node.links.len()];
+            self.ids.insert(node.member.clone(), id);
+            self.nodes.push(node);
+            self.link(id);
+        }
+    }
+
+    /// Greedy best-first search of one layer; the `ef` closest nodes found,
+    /// closest first
+    fn search_layer(&self, query: &[f32], entries: &[Scored], ef: usize, layer: usize) -> Vec<Scored> {
+        let mut visited: HashSet<u32> = entries.iter().map(|scored| scored.1).collect();
+        let mut candidates: BinaryHeap<Reverse<Scored>> = entries.iter().copied().map(Reverse).collect();
+        let mut found: BinaryHeap<Scored> = entries.iter().copied().collect();
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(Reverse(closest)) = candidates.pop() {
+            if found.len() >= ef && found.peek().is_some_and(|worst| closest.0 > worst.0) {
+                break;
+            }
+            for &neighbour in &self.nodes[closest.1 as usize].links[layer] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let scored = Scored(self.distance_to(query, neighbour), neighbour);
+                if found.len() < ef || found.peek().is_some_and(|worst| scored.0 < worst.0) {
+                    candidates.push(Reverse(scored));
+                    found.push(scored);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec()
+    }
+
+    /// The `k` nearest live members whose metadata passes `filter`. Explores
+    /// `ef` candidates (at least `k`); when too few of them pass, falls back
+    /// to an exact scan so a selective filter still gets `k` hits.
+    pub fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef: usize,
+        filter: Option<&dyn Fn(&JsonValue) -> bool>,
+    ) -> Result<Vec<VectorMatch>> {
+        self.check_dimension(query)?;
+        let Some(entry) = self.entry else {
+            return Ok(Vec::new());
+        };
+        let wanted = |id: u32| {
+            let node = &self.nodes[id as usize];
+            !node.deleted && filter.is_none_or(|filter| filter(&node.metadata))
+        };
+
+        let top = self.nodes[entry as usize].links.len() - 1;
+        let mut nearest = vec![Scored(self.distance_to(query, entry), entry)];
+        for layer in (1..=top).rev() {
+            nearest = self.search_layer(query, &nearest, 1, layer);
+        }
+        let mut hits: Vec<Scored> = self
+            .search_layer(query, &nearest, ef.max(k), 0)
+            .into_iter()
+            .filter(|scored| wanted(scored.1))
+            .take(k)
+            .collect();
+
+        if hits.len() < k.min(self.len()) {
+            hits = (0..self.nodes.len() as u32)
+                .filter(|&id| wanted(id))
+                .map(|id| Scored(self.distance_to(query, id), id))
+                .collect();
+            hits.sort();
+            hits.truncate(k);
+        }
+
+        Ok(hits
+            .into_iter()
+            .map(|Scored(distance, id)| {
+                let node = &self.nodes[id as usize];
+                VectorMatch {
+                    member: node.member.clone(),
+                    distance,
+                    metadata: node.metadata.clone(),
+                }
+            })
+            .collect())
+    }
+}
+
+/// Layer of a member, geometrically distributed with ratio 1/M and derived
+/// from its name so every replica agrees
+fn level_for(member: &str) -> usize {
+    let digest = Sha256::digest(member.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    // Uniform in (0, 1]
+    let uniform = (u64::from_le_bytes(bytes) as f64 + 1.0) / (u64::MAX as f64 + 1.0);
+    let level = (-uniform.ln() / (M as f64).ln()).floor() as usize;
+    level.min(MAX_LEVEL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> HnswIndex {
+        let mut index = HnswIndex::new(2, DistanceMetric::L2);
+        for x in 0..20 {
+            for y in 0..20 {
+                let metadata = serde_json::json!({ "even": (x + y) % 2 == 0 });
+                index.insert(&format!("{}-{}", x, y), vec![x as f32, y as f32], metadata).unwrap();
+            }
+        }
+        index
+    }
+
+    #[test]
+    fn test_search_matches_exact_neighbours() {
+        let index = grid();
+        let hits = index.search(&[5.2, 7.1], 5, DEFAULT_EF_SEARCH, None).unwrap();
+        let members: Vec<&str> = hits.iter().map(|hit| hit.member.as_str()).collect();
+        assert_eq!(members[0], "5-7");
+        assert_eq!(members.len(), 5);
+        assert!(hits.windows(2).all(|pair| pair[0].distance <= pair[1].distance));
+        assert!(hits[4].distance <= 1.25);
+    }
+
+    #[test]
+    fn test_tombstones_and_compaction() {
+        let mut index = grid();
+        for x in 0..20 {
+            for y in 0..15 {
+                assert!(index.remove(&format!("{}-{}", x, y)));
+            }
+        }
+        assert_eq!(index.len(), 100);
+        assert!(index.deleted <= index.len().max(M));
+        let hits = index.search(&[0.0, 0.0], 3, DEFAULT_EF_SEARCH, None).unwrap();
+        assert_eq!(hits[0].member, "0-15");
+        assert!(!index.remove("0-0"));
+
+        // Replacing moves the member
+        assert!(!index.insert("0-15", vec![19.0, 19.0], JsonValue::Null).unwrap());
+        assert_eq!(index.search(&[0.0, 0.0], 1, DEFAULT_EF_SEARCH, None).unwrap()[0].member, "1-15");
+        assert_eq!(index.len(), 100);
+    }
+
+    #[test]
+    fn test_filtered_search_falls_back_to_scan() {
+        let index = grid();
+        let odd = |metadata: &JsonValue| metadata["even"] == false;
+        let hits = index.search(&[3.0, 3.0], 4, 4, Some(&odd)).unwrap();
+        assert_eq!(hits.len(), 4);
+        assert!(hits.iter().all(|hit| hit.metadata["even"] == false));
+        assert!(hits.iter().all(|hit| hit.distance == 1.0));
+    }
+
+    #[test]
+    fn test_parse_vector_write() {
+        let write = VectorWrite::parse(r#"{"vector": [1, 0.5], "metadata": {"tag": "a"}, "metric": "dot"}"#).unwrap();
+        assert_eq!(write.vector, vec![1.0, 0.5]);
+        assert_eq!(write.metric, Some(DistanceMetric::Dot));
+        assert_eq!(VectorWrite::parse("[3]").unwrap().metadata, JsonValue::Null);
+        for bad in ["[]", "{}", r#"["a"]"#, r#"{"vector": [1], "metric": "hamming"}"#, "1"] {
+            assert!(VectorWrite::parse(bad).is_err(), "{} should be rejected", bad);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! Schema Versioning Module
+//!
+//! Version tags and migrations for the two persisted formats:
+//! - **Stored values**: bincode `StoredValue` bodies inside value blobs
+//! - **Sync operations**: JSON `SignedOperation`s in op-log blobs and sync messages
+//!
+//! Encoders always write the current version. Decoders accept any older
+//! version and run it through the registered migrations, one step at a time,
+//! before deserializing. Data written before versioning existed is version 0.
+//!
+//! ## Stored value versions
+//! - v0: untagged bincode. JSON document bodies were bincode-encoded
+//!   `serde_json::Value`s, which bincode cannot decode
+//! - v1: `[0xCF, 0x56, version u16 LE, body]`; JSON document bodies are stored as JSON text
+//! - v2: time series values end with a label map
+//!
+//! ## Sync format versions
+//! - v0: bare `SignedOperation` / `SyncMessage` JSON
+//! - v1: same JSON with a top-level `"v"` field (ignored by v0 peers)
+//! - v2: operations may carry the time series `labels` field, which their
+//!   signatures cover. A v1 peer would drop it and fail to verify the
+//!   operation, so this is a breaking change for labelled operations only:
+//!   payloads are tagged with the lowest version that can represent them (see
+//!   `sync_version_for`), v1 while no operation has labels, and v1 peers
+//!   refuse v2 payloads as newer than they support
+//!
+//! ## Adding a version
+//! 1. Bump `STORED_VALUE_VERSION` or `SYNC_FORMAT_VERSION`
+//! 2. Register a step from the previous version in `stored_value_migrations()`
+//!    or `sync_op_migrations()`
+//! 3. Add a fixture for the previous version under `tests/fixtures/`
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use crate::metrics;
+
+/// Current encoding version for stored values
+pub const STORED_VALUE_VERSION: u16 = 2;
+/// Current encoding version for sync operations
+pub const SYNC_FORMAT_VERSION: u16 = 2;
+
+const VALUE_MAGIC: [u8; 2] = [0xCF, 0x56];
+const VALUE_HEADER_LEN: usize = 4;
+/// JSON field carrying the sync format version
+const SYNC_VERSION_FIELD: &str = "v";
+
+/// One upgrade step, from version `n` to `n + 1`
+pub type MigrationStep<T> = fn(T) -> Result<T>;
+
+/// Ordered set of upgrade steps for one format
+pub struct MigrationRegistry<T> {
+    name: &'static str,
+    current: u16,
+    steps: BTreeMap<u16, MigrationStep<T>>,
+}
+
+impl<T> MigrationRegistry<T> {
+    pub fn new(name: &'static str, current: u16) -> Self {
+        Self {
+            name,
+            current,
+            steps: BTreeMap::new(),
+        }
+    }
+
+    /// Register the step that upgrades `from` to `from + 1`
+    pub fn register(mut self, from: u16, step: MigrationStep<T>) -> Self {
+        self.steps.insert(from, step);
+        self
+    }
+
+    pub fn current(&self) -> u16 {
+        self.current
+    }
+
+    /// Upgrade data stored at version `from` to the current version
+    pub fn upgrade(&self, from: u16, mut data: T) -> Result<T> {
+        if from > self.current {
+            return Err(anyhow!(
+                "{} version {} is newer than supported version {}",
+                self.name,
+                from,
+                self.current
+            ));
+        }
+        for version in from..self.current {
+            let step = self
+                .steps
+                .get(&version)
+                .ok_or_else(|| anyhow!("No {} migration from version {}", self.name, version))?;
+            data = step(data)?;
+            metrics::SCHEMA_MIGRATIONS
+                .with_label_values(&[self.name, &version.to_string()])
+                .inc();
+        }
+        Ok(data)
+    }
+}
+
+// ============================================================================
+// Stored values
+// ============================================================================
+
+/// Migrations for bincode `StoredValue` bodies
+pub fn stored_value_migrations() -> &'static MigrationRegistry<Vec<u8>> {
+    static REGISTRY: OnceLock<MigrationRegistry<Vec<u8>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        MigrationRegistry::new("stored_value", STORED_VALUE_VERSION)
+            .register(0, stored_value_v0_to_v1)
+            .register(1, stored_value_v1_to_v2)
+    })
+}
+
+/// v0 -> v1: layouts match except JSON documents, whose v0 bodies can't be decoded
+fn stored_value_v0_to_v1(body: Vec<u8>) -> Result<Vec<u8>> {
+    // bincode enum tag: u32 LE variant index; 5 is StoredValue::Json
+    if body.get(..4) == Some(&[5, 0, 0, 0][..]) {
+        return Err(anyhow!("v0 JSON document blobs are not decodable; restore them from the op log"));
+    }
+    Ok(body)
+}
+
+/// v1 -> v2: `TimeSeriesValue` gained a trailing `labels` map
+fn stored_value_v1_to_v2(mut body: Vec<u8>) -> Result<Vec<u8>> {
+    // 7 is StoredValue::TimeSeries; its value ends the body, so the new
+    // field goes last. An empty map is just its u64 length
+    if body.get(..4) == Some(&[7, 0, 0, 0][..]) {
+        body.extend_from_slice(&0u64.to_le_bytes());
+    }
+    Ok(body)
+}
+
+/// Tag a bincode `StoredValue` body with the current version
+pub fn wrap_stored_value(body: Vec<u8>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(VALUE_HEADER_LEN + body.len());
+    out.extend_from_slice(&VALUE_MAGIC);
+    out.extend_from_slice(&STORED_VALUE_VERSION.to_le_bytes());
+    out.extend_from_slice(&body);
+    out
+}
+
+/// Version a stored value payload was written with (0 if untagged)
+pub fn stored_value_version(payload: &[u8]) -> u16 {
+    if payload.len() >= VALUE_HEADER_LEN && payload[..2] == VALUE_MAGIC {
+        u16::from_le_bytes([payload[2], payload[3]])
+    } else {
+        0
+    }
+}
+
+/// Strip the version tag and upgrade the body to the current version.
+/// Returns the version the payload was written with and the upgraded body.
+pub fn unwrap_stored_value(payload: &[u8]) -> Result<(u16, Cow<'_, [u8]>)> {
+    let version = stored_value_version(payload);
+    let body = if version == 0 { payload } else { &payload[VALUE_HEADER_LEN..] };
+    if version == STORED_VALUE_VERSION {
+        return Ok((version, Cow::Borrowed(body)));
+    }
+    let upgraded = stored_value_migrations().upgrade(version, body.to_vec())?;
+    Ok((version, Cow::Owned(upgraded)))
+}
+
+// ============================================================================
+// Sync operations
+// ============================================================================
+
+/// Migrations for `SignedOperation` JSON objects
+pub fn sync_op_migrations() -> &'static MigrationRegistry<Value> {
+    static REGISTRY: OnceLock<MigrationRegistry<Value>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        MigrationRegistry::new("sync_op", SYNC_FORMAT_VERSION)
+            .register(0, sync_op_v0_to_v1)
+            .register(1, sync_op_v1_to_v2)
+    })
+}
+
+/// v0 -> v1: fields unchanged, only the version tag was added
+fn sync_op_v0_to_v1(op: Value) -> Result<Value> {
+    Ok(op)
+}
+
+/// v1 -> v2: v1 operations have no `labels`, which v2 leaves out when absent
+fn sync_op_v1_to_v2(op: Value) -> Result<Value> {
+    Ok(op)
+}
+
+/// Lowest sync format version that can represent a serialized operation or
+/// message: v2 if any operation in it has labels, v1 otherwise
+pub fn sync_version_for(value: &Value) -> u16 {
+    fn has_labels(value: &Value) -> bool {
+        match value {
+            Value::Object(obj) => obj.contains_key("labels") || obj.values().any(has_labels),
+            Value::Array(items) => items.iter().any(has_labels),
+            _ => false,
+        }
+    }
+    if has_labels(value) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Tag a serialized operation or message with the lowest sync format version
+/// that can represent it
+pub fn tag_sync_value(value: &mut Value) {
+    let version = sync_version_for(value);
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert(SYNC_VERSION_FIELD.to_string(), Value::from(version));
+    }
+}
+
+/// Remove and return the sync format version (0 if untagged)
+pub fn take_sync_version(value: &mut Value) -> Result<u16> {
+    match value.as_object_mut().and_then(|obj| obj.remove(SYNC_VERSION_FIELD)) {
+        None => Ok(0),
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u16::try_from(v).ok())
+            .ok_or_else(|| anyhow!("Invalid sync format version: {}", v)),
+    }
+}
+
+/// Upgrade a single operation object written at `version`
+pub fn upgrade_sync_op(version: u16, op: Value) -> Result<Value> {
+    sync_op_migrations().upgrade(version, op)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_value_roundtrip() {
+        let body = vec![0, 0, 0, 0, 1, 2, 3];
+        let payload = wrap_stored_value(body.clone());
+        assert_eq!(stored_value_version(&payload), STORED_VALUE_VERSION);
+        let (version, unwrapped) = unwrap_stored_value(&payload).unwrap();
+        assert_eq!(version, STORED_VALUE_VERSION);
+        assert_eq!(unwrapped.as_ref(), body.as_slice());
+    }
+
+    #[test]
+    fn test_untagged_payload_is_v0() {
+        let legacy = vec![0, 0, 0, 0, 1, 2, 3];
+        assert_eq!(stored_value_version(&legacy), 0);
+        let (version, body) = unwrap_stored_value(&legacy).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(body.as_ref(), legacy.as_slice());
+
+        // v0 JSON documents can't be migrated
+        assert!(unwrap_stored_value(&[5, 0, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_v1_timeseries_gains_empty_labels() {
+        let mut payload = VALUE_MAGIC.to_vec();
+        payload.extend_from_slice(&1u16.to_le_bytes());
+        payload.extend_from_slice(&[7, 0, 0, 0, 9]);
+        let (version, body) = unwrap_stored_value(&payload).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(body.as_ref(), &[7, 0, 0, 0, 9, 0, 0, 0, 0, 0, 0, 0, 0][..]);
+    }
+
+    #[test]
+    fn test_newer_version_rejected() {
+        let mut payload = wrap_stored_value(vec![0, 0, 0, 0]);
+        payload[2] = 99;
+        assert!(unwrap_stored_value(&payload).is_err());
+    }
+
+    #[test]
+    fn test_registry_requires_every_step() {
+        let registry: MigrationRegistry<u32> = MigrationRegistry::new("test", 3)
+            .register(0, |n| Ok(n + 1))
+            .register(2, |n| Ok(n * 10));
+        assert!(registry.upgrade(0, 1).is_err());
+        assert_eq!(registry.upgrade(2, 1).unwrap(), 10);
+        assert_eq!(registry.upgrade(3, 1).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_sync_version_tag() {
+        let mut value = serde_json::json!({ "op_id": "a" });
+        assert_eq!(take_sync_version(&mut value.clone()).unwrap(), 0);
+        tag_sync_value(&mut value);
+        assert_eq!(take_sync_version(&mut value).unwrap(), 1);
+        assert!(value.get(SYNC_VERSION_FIELD).is_none());
+
+        // Labels need v2, wherever the operation sits in a message
+        let mut message = serde_json::json!({ "operations": [{ "op_id": "a" }, { "op_id": "b", "labels": "{}" }] });
+        tag_sync_value(&mut message);
+        assert_eq!(take_sync_version(&mut message).unwrap(), SYNC_FORMAT_VERSION);
+    }
+}
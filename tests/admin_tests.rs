@@ -0,0 +1,94 @@
+//! Admin role for node-control fields
+
+mod common;
+
+use async_graphql::{Request, Schema};
+use common::{now_ms, public_key};
+use cyberfly_rust_node::admin::{AdminAuth, AdminCredential, ADMIN_TOKEN_DOMAIN};
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::read_access::ReadTokenClaims;
+use cyberfly_rust_node::storage::BlobStorage;
+use ed25519_dalek::SigningKey;
+
+fn claims(key: &SigningKey, lifetime_ms: i64) -> ReadTokenClaims {
+    ReadTokenClaims {
+        public_key: public_key(key),
+        expires_at: now_ms() + lifetime_ms,
+        challenge: None,
+    }
+}
+
+#[tokio::test]
+async fn test_admin_guard_and_audit_log() {
+    let operator = SigningKey::from_bytes(&[51u8; 32]);
+    let stranger = SigningKey::from_bytes(&[52u8; 32]);
+    let admin = AdminAuth::new(&["s3cret".to_string()], &[public_key(&operator)]);
+    let storage = BlobStorage::in_memory();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage)
+        .data(admin.clone())
+        .finish();
+    let fsck = |credential: Option<AdminCredential>| {
+        let request = Request::new("mutation { fsck { keysScanned } }");
+        let request = match credential {
+            Some(credential) => request.data(credential),
+            None => request,
+        };
+        schema.execute(request)
+    };
+
+    let response = fsck(None).await;
+    assert!(response.errors[0].message.contains("required"), "{:?}", response.errors);
+
+    for credential in [
+        AdminCredential::token("s3cret"),
+        AdminCredential::token(claims(&operator, 60_000).sign_for(ADMIN_TOKEN_DOMAIN, &operator)),
+    ] {
+        let response = fsck(Some(credential)).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+    }
+
+    // Wrong token, a stranger's key, an expired token, and a read token from
+    // the operator are all refused
+    for credential in [
+        AdminCredential::token("guess"),
+        AdminCredential::token(claims(&stranger, 60_000).sign_for(ADMIN_TOKEN_DOMAIN, &stranger)),
+        AdminCredential::token(claims(&operator, -1).sign_for(ADMIN_TOKEN_DOMAIN, &operator)),
+        AdminCredential::token(claims(&operator, 60_000).sign(&operator)),
+    ] {
+        assert!(!fsck(Some(credential)).await.errors.is_empty());
+    }
+
+    // Every call is audited, newest first; the audit log is admin-only too
+    let request = Request::new("{ adminAuditLog(limit: 3) { actor action allowed reason } }")
+        .data(AdminCredential::token("s3cret"));
+    let data = schema.execute(request).await.data.into_json().unwrap();
+    let entries = data["adminAuditLog"].as_array().unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0]["action"], "adminAuditLog");
+    assert_eq!(entries[0]["allowed"], true);
+    assert_eq!(entries[1]["action"], "fsck");
+    assert_eq!(entries[1]["allowed"], false);
+    assert_eq!(entries[1]["actor"], "invalid");
+    assert_eq!(admin.audit_log(100).len(), 8);
+    assert_eq!(admin.audit_log(100).last().unwrap().actor, "anonymous");
+    assert!(admin.audit_log(100).iter().any(|entry| entry.actor == format!("key:{}", public_key(&operator))));
+}
+
+#[tokio::test]
+async fn test_admin_fields_disabled_without_credentials_configured() {
+    let storage = BlobStorage::in_memory();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage)
+        .data(AdminAuth::default())
+        .finish();
+
+    let request = Request::new("mutation { fsck { keysScanned } }").data(AdminCredential::token(""));
+    let response = schema.execute(request).await;
+    assert!(response.errors[0].message.contains("not configured"), "{:?}", response.errors);
+
+    // Data queries are unaffected
+    let response = schema.execute(r#"{ getAll(dbName: "notes-00") { key } }"#).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+}
@@ -0,0 +1,164 @@
+//! Aggregation pipelines over JSON documents
+
+use async_graphql::Schema;
+use cyberfly_rust_node::aggregation::{Aggregator, Pipeline};
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::indexing::IndexType;
+use cyberfly_rust_node::storage::BlobStorage;
+use serde_json::json;
+
+async fn seed_orders(storage: &BlobStorage) {
+    let orders = [
+        ("o1", json!({ "status": "shipped", "region": "eu", "total": 120, "items": [{ "sku": "A1", "qty": 2 }, { "sku": "B7", "qty": 1 }] })),
+        ("o2", json!({ "status": "pending", "region": "us", "total": 40, "items": [{ "sku": "A1", "qty": 1 }] })),
+        ("o3", json!({ "status": "shipped", "region": "us", "total": 15.5, "items": [] })),
+        ("o4", json!({ "status": "shipped", "region": "eu", "total": 300, "items": [{ "sku": "C3", "qty": 5 }] })),
+    ];
+    for (key, doc) in orders {
+        storage.set_json(&format!("shop:{}", key), "$", &doc.to_string()).await.unwrap();
+    }
+}
+
+async fn run(storage: &BlobStorage, pipeline: serde_json::Value) -> Vec<serde_json::Value> {
+    let pipeline = Pipeline::parse(&pipeline).unwrap();
+    Aggregator::new(storage).aggregate("shop", &pipeline).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_group_accumulators() {
+    let storage = BlobStorage::in_memory();
+    seed_orders(&storage).await;
+
+    let groups = run(
+        &storage,
+        json!([
+            { "$match": { "status": "shipped" } },
+            { "$group": {
+                "_id": "$region",
+                "revenue": { "$sum": "$total" },
+                "average": { "$avg": "$total" },
+                "smallest": { "$min": "$total" },
+                "largest": { "$max": "$total" },
+                "orders": { "$count": {} }
+            } },
+            { "$sort": { "_id": 1 } }
+        ]),
+    )
+    .await;
+
+    assert_eq!(
+        groups,
+        vec![
+            json!({ "_id": "eu", "revenue": 420, "average": 210, "smallest": 120, "largest": 300, "orders": 2 }),
+            json!({ "_id": "us", "revenue": 15.5, "average": 15.5, "smallest": 15.5, "largest": 15.5, "orders": 1 }),
+        ]
+    );
+
+    // A null _id groups every document together
+    let all = run(&storage, json!([{ "$group": { "_id": null, "n": { "$sum": 1 } } }])).await;
+    assert_eq!(all, vec![json!({ "_id": null, "n": 4 })]);
+}
+
+#[tokio::test]
+async fn test_unwind_project_sort_limit() {
+    let storage = BlobStorage::in_memory();
+    seed_orders(&storage).await;
+
+    let units = run(
+        &storage,
+        json!([
+            { "$unwind": "$items" },
+            { "$group": { "_id": "$items.sku", "units": { "$sum": "$items.qty" } } },
+            { "$sort": { "units": -1, "_id": 1 } },
+            { "$limit": 2 }
+        ]),
+    )
+    .await;
+    assert_eq!(units, vec![json!({ "_id": "C3", "units": 5 }), json!({ "_id": "A1", "units": 3 })]);
+
+    // Empty arrays are dropped unless preserved
+    let unwound = run(&storage, json!([{ "$unwind": "$items" }])).await;
+    assert_eq!(unwound.len(), 4);
+    let preserved = run(&storage, json!([{ "$unwind": { "path": "$items", "preserveNullAndEmptyArrays": true } }])).await;
+    assert_eq!(preserved.len(), 5);
+
+    let projected = run(
+        &storage,
+        json!([
+            { "$sort": { "total": -1 } },
+            { "$skip": 1 },
+            { "$limit": 1 },
+            { "$project": { "status": 1, "where": "$region" } }
+        ]),
+    )
+    .await;
+    assert_eq!(projected, vec![json!({ "_key": "shop:o1", "status": "shipped", "where": "eu" })]);
+}
+
+#[tokio::test]
+async fn test_match_uses_indexes() {
+    let storage = BlobStorage::in_memory();
+    seed_orders(&storage).await;
+    let pipeline = json!([
+        { "$match": { "status": "shipped" } },
+        { "$match": { "total": { "$gte": 100 } } },
+        { "$project": { "items": 0, "region": 0 } }
+    ]);
+
+    let scanned = run(&storage, pipeline.clone()).await;
+    storage.create_index("shop", "status_idx", "status", IndexType::Exact).await.unwrap();
+    let indexed = run(&storage, pipeline).await;
+
+    let mut keys: Vec<&str> = indexed.iter().map(|d| d["_key"].as_str().unwrap()).collect();
+    keys.sort();
+    assert_eq!(keys, vec!["shop:o1", "shop:o4"]);
+    assert_eq!(indexed.len(), scanned.len());
+    assert!(indexed.iter().all(|d| d.get("items").is_none() && d["status"] == "shipped"));
+}
+
+#[test]
+fn test_invalid_pipelines_are_rejected() {
+    for pipeline in [
+        json!({ "$match": {} }),
+        json!([{ "$lookup": {} }]),
+        json!([{ "$match": {}, "$limit": 1 }]),
+        json!([{ "$group": { "total": { "$sum": "$total" } } }]),
+        json!([{ "$group": { "_id": null, "x": { "$push": "$total" } } }]),
+        json!([{ "$sort": { "total": 2 } }]),
+        json!([{ "$limit": -1 }]),
+        json!([{ "$unwind": "items" }]),
+        json!([{ "$project": { "a": 1, "b": 0 } }]),
+    ] {
+        assert!(Pipeline::parse(&pipeline).is_err(), "{} should be rejected", pipeline);
+    }
+}
+
+#[tokio::test]
+async fn test_aggregate_query_in_graphql_schema() {
+    let storage = BlobStorage::in_memory();
+    seed_orders(&storage).await;
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+
+    let query = r#"query ($pipeline: JSON!) { aggregate(dbName: "shop", pipeline: $pipeline) }"#;
+    let request = |pipeline: serde_json::Value| {
+        async_graphql::Request::new(query)
+            .variables(async_graphql::Variables::from_json(json!({ "pipeline": pipeline })))
+    };
+
+    let response = schema
+        .execute(request(json!([
+            { "$group": { "_id": "$status", "n": { "$count": {} } } },
+            { "$sort": { "n": -1 } },
+            { "$limit": 1 }
+        ])))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["aggregate"], json!([{ "_id": "shipped", "n": 3 }]));
+
+    let response = schema.execute(request(json!([{ "$out": "other" }]))).await;
+    assert!(!response.errors.is_empty());
+}
@@ -0,0 +1,211 @@
+//! Batched signed writes
+
+mod common;
+
+use async_graphql::{Request, Schema, Variables};
+use common::{public_key, signed_data};
+use cyberfly_rust_node::batch;
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::storage::BlobStorage;
+use cyberfly_rust_node::sync::{SignatureFormat, SignedOperation, SyncManager, SyncMessage};
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::json;
+
+/// An unsigned operation on the owner's `gateway` database
+fn operation(owner: &SigningKey, store_type: &str, key: &str, value: &str) -> SignedOperation {
+    common::op(owner, "gateway", store_type, key).value(value).unsigned()
+}
+
+fn batch_request(input: serde_json::Value) -> Request {
+    Request::new(
+        r#"mutation ($input: BatchInput!) {
+            submitBatch(input: $input) { success accepted rejected results { index success opId message } }
+        }"#,
+    )
+    .variables(Variables::from_json(json!({ "input": input })))
+}
+
+#[test]
+fn test_merkle_proofs() {
+    let owner = SigningKey::from_bytes(&[81u8; 32]);
+    let ops: Vec<SignedOperation> = (0..7)
+        .map(|n| operation(&owner, "String", &format!("k{}", n), "v"))
+        .collect();
+    let leaves: Vec<[u8; 32]> = ops.iter().map(batch::leaf_hash).collect();
+    let root = batch::merkle_root(&leaves).unwrap();
+    for index in 0..leaves.len() {
+        let proof = batch::merkle_proof(&leaves, index);
+        assert_eq!(batch::fold_proof(leaves[index], &proof), root);
+    }
+    assert_eq!(batch::merkle_root(&leaves[..1]), Some(leaves[0]));
+    assert_eq!(batch::merkle_root(&[]), None);
+
+    // Each operation carries its own proof and verifies alone
+    let root_signature = hex::encode(owner.sign(batch::root_message(&root).as_bytes()).to_bytes());
+    let mut sealed = ops.clone();
+    assert_eq!(batch::attach_root_signature(&mut sealed, &root_signature).unwrap(), root);
+    for op in &sealed {
+        assert_eq!(op.signature_format().unwrap(), SignatureFormat::MerkleBatch);
+    }
+
+    // Changing an operation, or moving its proof to another, breaks it
+    let mut altered = sealed[3].clone();
+    altered.value = "forged".to_string();
+    assert!(altered.verify().is_err());
+    let mut moved = sealed[3].clone();
+    moved.signature = sealed[4].signature.clone();
+    assert!(moved.verify().is_err());
+
+    // Packed for sync, any subset of the batch unpacks to the same signatures
+    let subset = vec![(2, sealed[2].clone()), (5, sealed[5].clone()), (6, sealed[6].clone())];
+    let packed = batch::MerkleBatch::pack(sealed.len(), subset).unwrap();
+    assert!(packed.leaves.iter().all(|leaf| leaf.operation.signature.is_empty()));
+    let unpacked = packed.clone().unpack().unwrap();
+    assert_eq!(
+        unpacked.iter().map(|op| &op.signature).collect::<Vec<_>>(),
+        [&sealed[2].signature, &sealed[5].signature, &sealed[6].signature]
+    );
+    let mut misplaced = packed;
+    misplaced.leaves[0].index = 3;
+    assert!(misplaced.unpack().unwrap()[0].verify().is_err());
+
+    // A root signed by someone else doesn't verify
+    let stranger = SigningKey::from_bytes(&[82u8; 32]);
+    let forged = hex::encode(stranger.sign(batch::root_message(&root).as_bytes()).to_bytes());
+    let mut sealed = ops;
+    batch::attach_root_signature(&mut sealed, &forged).unwrap();
+    assert!(sealed[0].verify().is_err());
+}
+
+#[tokio::test]
+async fn test_submit_batch_reports_each_operation() {
+    let storage = BlobStorage::in_memory();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+    let owner = SigningKey::from_bytes(&[83u8; 32]);
+    let stranger = SigningKey::from_bytes(&[84u8; 32]);
+    let db_name = format!("gateway-{}", public_key(&owner));
+
+    let readings: Vec<SignedOperation> = (0..20)
+        .map(|n| common::op(&owner, "gateway", "List", "readings").value(&n.to_string()).sign())
+        .collect();
+    let mut forged = operation(&owner, "String", "status", "pwned");
+    common::sign(&stranger, &mut forged);
+    let hash_without_field = common::op(&owner, "gateway", "Hash", "profile").value("x").sign();
+
+    let mut items: Vec<serde_json::Value> = readings.iter().map(signed_data).collect();
+    items.insert(5, signed_data(&forged));
+    items.push(signed_data(&hash_without_field));
+    items.push(signed_data(&readings[0]));
+
+    let response = schema.execute(batch_request(json!({ "operations": items }))).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let result = response.data.into_json().unwrap()["submitBatch"].clone();
+    assert_eq!(result["success"], false);
+    assert_eq!(result["accepted"], 20);
+    assert_eq!(result["rejected"], 3);
+    let results = result["results"].as_array().unwrap();
+    assert_eq!(results.len(), 23);
+    assert_eq!(results[5]["success"], false);
+    assert!(results[5]["message"].as_str().unwrap().contains("Signature"), "{}", results[5]);
+    assert_eq!(results[6]["opId"], readings[5].op_id);
+    assert!(results[21]["message"].as_str().unwrap().contains("Field required"), "{}", results[21]);
+    assert!(results[22]["message"].as_str().unwrap().contains("Replayed"), "{}", results[22]);
+
+    // Appends to one key keep the batch's order
+    let list = storage.get_list(&format!("{}:readings", db_name), 0, -1).await.unwrap();
+    let expected: Vec<String> = (0..20).map(|n| n.to_string()).collect();
+    assert_eq!(list, expected);
+    assert!(storage.get_string(&format!("{}:status", db_name)).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_batch_signed_over_merkle_root_replicates_as_one_message() {
+    let storage = BlobStorage::in_memory();
+    let local = iroh::SecretKey::generate().public();
+    let sync_manager = SyncManager::new(storage.clone(), local);
+    let (sync_out_tx, mut sync_out_rx) = tokio::sync::mpsc::unbounded_channel::<SyncMessage>();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .data(sync_manager)
+        .data(sync_out_tx)
+        .finish();
+    let owner = SigningKey::from_bytes(&[85u8; 32]);
+    let ops: Vec<SignedOperation> = (0..50)
+        .map(|n| operation(&owner, "String", &format!("sensor:{}", n), &n.to_string()))
+        .collect();
+    let leaves: Vec<[u8; 32]> = ops.iter().map(batch::leaf_hash).collect();
+    let root = batch::merkle_root(&leaves).unwrap();
+    let root_signature = hex::encode(owner.sign(batch::root_message(&root).as_bytes()).to_bytes());
+    let items: Vec<serde_json::Value> = ops.iter().map(signed_data).collect();
+
+    // A root signature that doesn't match the operations fails the batch
+    let wrong = hex::encode(owner.sign(b"something else").to_bytes());
+    let response = schema
+        .execute(batch_request(json!({ "operations": items, "rootSignature": wrong })))
+        .await;
+    assert!(response.errors[0].message.contains("batch root"), "{:?}", response.errors);
+
+    let response = schema
+        .execute(batch_request(json!({ "operations": items, "rootSignature": root_signature })))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let result = response.data.into_json().unwrap()["submitBatch"].clone();
+    assert_eq!(result["accepted"], 50, "{}", result);
+
+    let message = sync_out_rx.try_recv().unwrap();
+    let SyncMessage::MerkleBatch { batch } = &message else {
+        panic!("expected a Merkle batch sync message");
+    };
+    assert_eq!(batch.leaves.len(), 50);
+    assert!(sync_out_rx.try_recv().is_err());
+
+    // The root signature and signer go over the wire once
+    let bytes = message.to_bytes().unwrap();
+    let wire = String::from_utf8_lossy(&bytes);
+    assert_eq!(wire.matches(&root_signature).count(), 1);
+    assert_eq!(wire.matches(&public_key(&owner)).count(), 1 + 50);
+
+    // A peer verifies and applies every operation from the one message
+    let peer_storage = BlobStorage::in_memory();
+    let peer = SyncManager::new(peer_storage.clone(), iroh::SecretKey::generate().public());
+    peer.handle_sync_message(SyncMessage::from_bytes(&bytes).unwrap(), local).await.unwrap();
+    let db_name = format!("gateway-{}", public_key(&owner));
+    for n in [0, 17, 49] {
+        let value = peer_storage.get_string(&format!("{}:sensor:{}", db_name, n)).await.unwrap();
+        assert_eq!(value, Some(n.to_string()));
+    }
+}
+
+#[tokio::test]
+async fn test_synced_operations_are_checked_like_local_writes() {
+    let storage = BlobStorage::in_memory();
+    let peer = iroh::SecretKey::generate().public();
+    let sync_manager = SyncManager::new(storage.clone(), peer);
+    let owner = SigningKey::from_bytes(&[86u8; 32]);
+    let sample = |ts: &str, labels: &str| {
+        common::op(&owner, "gateway", "TimeSeries", "temp")
+            .value("21.5")
+            .with(|op| {
+                op.ts_timestamp = Some(ts.to_string());
+                op.labels = Some(labels.to_string());
+            })
+            .sign()
+    };
+    let operations = vec![
+        sample("1700000000000", r#"{"room":"lab"}"#),
+        sample("1700000060000", r#"{"room":["lab"]}"#),
+    ];
+    sync_manager
+        .handle_sync_message(SyncMessage::Batch { operations }, peer)
+        .await
+        .unwrap();
+
+    // The sample with unparseable labels is refused whole
+    let key = format!("gateway-{}:temp", public_key(&owner));
+    assert_eq!(storage.ts_range(&key, 0, i64::MAX).await.unwrap(), [(1_700_000_000_000, 21.5)]);
+    assert_eq!(storage.ts_labels(&key).await.unwrap().get("room").map(String::as_str), Some("lab"));
+}
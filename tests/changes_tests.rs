@@ -0,0 +1,221 @@
+//! Live change subscriptions
+
+mod common;
+
+use async_graphql::{Request, Response, Schema, Variables};
+use common::{now_ms, public_key, submit_data};
+use cyberfly_rust_node::changes::{ChangeEvent, ChangeFeed, ChangeFilter, ChangeNotice};
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::read_access::{ReadCredential, ReadTokenClaims};
+use cyberfly_rust_node::storage::BlobStorage;
+use cyberfly_rust_node::sync::{SignedOperation, SyncManager, SyncMessage};
+use ed25519_dalek::SigningKey;
+use futures::{Stream, StreamExt};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// An operation on the owner's `feed` database
+fn signed_op(owner: &SigningKey, store_type: &str, key: &str, value: &str) -> SignedOperation {
+    common::op(owner, "feed", store_type, key).value(value).sign()
+}
+
+const CHANGE_FIELDS: &str =
+    "... on DataChange { opId key opType value signer receivedFrom local } ... on ChangesMissed { missed }";
+
+/// Poll a subscription once so it registers with the change feed
+async fn start(stream: &mut (impl Stream<Item = Response> + Unpin)) {
+    assert!(tokio::time::timeout(Duration::from_millis(50), stream.next()).await.is_err());
+}
+
+async fn next_json(stream: &mut (impl Stream<Item = Response> + Unpin), field: &str) -> serde_json::Value {
+    let response = tokio::time::timeout(Duration::from_secs(5), stream.next())
+        .await
+        .expect("no change event")
+        .unwrap();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    response.data.into_json().unwrap()[field].clone()
+}
+
+async fn next_change(stream: &mut (impl Stream<Item = ChangeNotice> + Unpin)) -> Arc<ChangeEvent> {
+    match tokio::time::timeout(Duration::from_secs(5), stream.next()).await {
+        Ok(Some(ChangeNotice::Change(event))) => event,
+        other => panic!("expected a change, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_watch_key_and_query_see_local_writes() {
+    let storage = BlobStorage::in_memory();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+    let owner = SigningKey::from_bytes(&[71u8; 32]);
+    let db_name = format!("feed-{}", public_key(&owner));
+
+    let mut key_stream = schema.execute_stream(format!(
+        r#"subscription {{ watchKey(dbName: "{}", key: "status") {{ {} }} }}"#,
+        db_name, CHANGE_FIELDS
+    ));
+    let query = Request::new(format!(
+        r#"subscription ($filter: WatchFilter!) {{ watchQuery(filter: $filter) {{ {} }} }}"#,
+        CHANGE_FIELDS
+    ))
+    .variables(Variables::from_json(json!({
+        "filter": { "dbName": db_name, "keyPrefix": "order:", "storeTypes": ["Json"], "valueFilter": { "total": { "$gt": 100 } } }
+    })));
+    let mut query_stream = schema.execute_stream(query);
+    start(&mut key_stream).await;
+    start(&mut query_stream).await;
+
+    for op in [
+        signed_op(&owner, "String", "other", "ignored"),
+        signed_op(&owner, "String", "status", "online"),
+        signed_op(&owner, "Json", "order:1", r#"{"total": 20}"#),
+        signed_op(&owner, "Json", "order:2", r#"{"total": 250}"#),
+    ] {
+        let response = schema.execute(submit_data(&op)).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+    }
+
+    let change = next_json(&mut key_stream, "watchKey").await;
+    assert_eq!(change["key"], "status");
+    assert_eq!(change["opType"], "string");
+    assert_eq!(change["value"], "online");
+    assert_eq!(change["signer"], public_key(&owner));
+    assert_eq!(change["local"], true);
+    assert_eq!(change["receivedFrom"], serde_json::Value::Null);
+
+    let change = next_json(&mut query_stream, "watchQuery").await;
+    assert_eq!(change["key"], "order:2");
+}
+
+#[tokio::test]
+async fn test_watch_database_sees_synced_writes() {
+    let storage = BlobStorage::in_memory();
+    let peer = iroh::SecretKey::generate().public();
+    let sync_manager = SyncManager::new(storage.clone(), peer);
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.clone())
+        .finish();
+    let owner = SigningKey::from_bytes(&[72u8; 32]);
+    let db_name = format!("feed-{}", public_key(&owner));
+
+    let mut stream = schema.execute_stream(format!(
+        r#"subscription {{ watchDatabase(dbName: "{}", storeType: "Hash") {{ {} }} }}"#,
+        db_name, CHANGE_FIELDS
+    ));
+    start(&mut stream).await;
+
+    let hash = common::op(&owner, "feed", "Hash", "profile").field(Some("name")).value("Ada").sign();
+    for operation in [signed_op(&owner, "String", "greeting", "hi"), hash] {
+        sync_manager
+            .handle_sync_message(SyncMessage::Operation { operation }, peer)
+            .await
+            .unwrap();
+    }
+
+    let change = next_json(&mut stream, "watchDatabase").await;
+    assert_eq!(change["key"], "profile");
+    assert_eq!(change["opType"], "hash");
+    assert_eq!(change["local"], false);
+    assert_eq!(change["receivedFrom"], peer.to_string());
+}
+
+#[tokio::test]
+async fn test_watch_sees_changes_storage_makes_itself() {
+    let storage = BlobStorage::in_memory();
+    let owner = SigningKey::from_bytes(&[75u8; 32]);
+    let db_name = format!("feed-{}", public_key(&owner));
+    let mut stream = Box::pin(storage.changes().watch(ChangeFilter::database(&db_name)));
+
+    let key = format!("{}:session", db_name);
+    storage.set_string(&key, "open").await.unwrap();
+    let event = next_change(&mut stream).await;
+    assert_eq!((event.key.as_str(), event.op_type.as_str(), event.value.as_str()), ("session", "string", "open"));
+    assert_eq!(event.op_id, None);
+
+    storage.set_key_ttl(&key, 60).await.unwrap();
+    assert_eq!(next_change(&mut stream).await.op_type, "string");
+    storage.delete(&key).await.unwrap();
+    assert_eq!(next_change(&mut stream).await.op_type, "delete");
+
+    // Expiry, whether found by a read or by the cleanup pass
+    storage.set_json(&format!("{}:doc", db_name), "$", r#"{"a":1}"#).await.unwrap();
+    assert_eq!(next_change(&mut stream).await.value, r#"{"a":1}"#);
+    storage.set_key_ttl(&format!("{}:doc", db_name), 0).await.unwrap();
+    next_change(&mut stream).await;
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    storage.cleanup_expired_keys().await.unwrap();
+    let event = next_change(&mut stream).await;
+    assert_eq!((event.key.as_str(), event.op_type.as_str()), ("doc", "expire"));
+
+    // A replayed operation is announced as that operation, once
+    let sync_manager = SyncManager::new(storage.clone(), iroh::SecretKey::generate().public());
+    let op = signed_op(&owner, "String", "replayed", "again");
+    sync_manager.replay_operation(&op).await.unwrap();
+    let event = next_change(&mut stream).await;
+    assert_eq!(event.op_id.as_deref(), Some(op.op_id.as_str()));
+    assert!(tokio::time::timeout(Duration::from_millis(50), stream.next()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_slow_subscriber_is_told_what_it_missed() {
+    let owner = SigningKey::from_bytes(&[73u8; 32]);
+    let db_name = format!("feed-{}", public_key(&owner));
+    let feed = ChangeFeed::new(4);
+    let mut stream = Box::pin(feed.watch(ChangeFilter::database(&db_name)));
+
+    for n in 0..10 {
+        let op = signed_op(&owner, "String", &format!("k{}", n), "v");
+        feed.publish(ChangeEvent::from_op(&op, None));
+    }
+
+    match stream.next().await.unwrap() {
+        ChangeNotice::Missed(missed) => assert_eq!(missed, 6),
+        other => panic!("expected a missed notice, got {:?}", other),
+    }
+    let keys: Vec<String> = stream
+        .take(4)
+        .map(|notice| match notice {
+            ChangeNotice::Change(event) => event.key.clone(),
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect()
+        .await;
+    assert_eq!(keys, ["k6", "k7", "k8", "k9"]);
+}
+
+#[tokio::test]
+async fn test_watching_a_private_database_needs_a_read_token() {
+    let storage = BlobStorage::in_memory();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.clone())
+        .finish();
+    let owner = SigningKey::from_bytes(&[74u8; 32]);
+    let db_name = format!("feed-{}", public_key(&owner));
+    let response = schema
+        .execute(submit_data(&signed_op(&owner, "Privacy", "__privacy", r#"{"private": true}"#)))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let subscription = format!(
+        r#"subscription {{ watchDatabase(dbName: "{}") {{ {} }} }}"#,
+        db_name, CHANGE_FIELDS
+    );
+    let response = schema.execute_stream(subscription.clone()).next().await.unwrap();
+    assert!(response.errors[0].message.contains("private"));
+
+    let token = ReadTokenClaims {
+        public_key: public_key(&owner),
+        expires_at: now_ms() + 60_000,
+        challenge: None,
+    }
+    .sign(&owner);
+    let mut stream = schema.execute_stream(Request::new(subscription).data(ReadCredential::bearer(token)));
+    start(&mut stream).await;
+    let response = schema.execute(submit_data(&signed_op(&owner, "String", "secret", "x"))).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(next_json(&mut stream, "watchDatabase").await["key"], "secret");
+}
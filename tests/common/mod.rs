@@ -0,0 +1,114 @@
+//! Fixtures shared by the integration tests
+
+#![allow(dead_code)]
+
+use async_graphql::{Request, Variables};
+use cyberfly_rust_node::sync::SignedOperation;
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::json;
+
+pub fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+pub fn public_key(key: &SigningKey) -> String {
+    hex::encode(key.verifying_key().to_bytes())
+}
+
+/// Canonically sign `op` with `key`, replacing any signature it had
+pub fn sign(key: &SigningKey, op: &mut SignedOperation) {
+    op.signature = hex::encode(key.sign(op.canonical_message().as_bytes()).to_bytes());
+}
+
+/// Builds an operation on the owner's `<db>-<owner key>` database, written
+/// now by the owner with a fresh op ID, until told otherwise
+pub struct OpBuilder {
+    op: SignedOperation,
+    signer: SigningKey,
+}
+
+/// Start an operation of `store_type` on `key` in the owner's `db` database
+pub fn op(owner: &SigningKey, db: &str, store_type: &str, key: &str) -> OpBuilder {
+    OpBuilder {
+        op: SignedOperation {
+            op_id: uuid::Uuid::new_v4().to_string(),
+            timestamp: now_ms(),
+            db_name: format!("{}-{}", db, public_key(owner)),
+            key: key.to_string(),
+            value: String::new(),
+            store_type: store_type.to_string(),
+            field: None,
+            score: None,
+            json_path: None,
+            stream_fields: None,
+            ts_timestamp: None,
+            labels: None,
+            longitude: None,
+            latitude: None,
+            public_key: public_key(owner),
+            signature: String::new(),
+        },
+        signer: owner.clone(),
+    }
+}
+
+impl OpBuilder {
+    pub fn value(mut self, value: &str) -> Self {
+        self.op.value = value.to_string();
+        self
+    }
+
+    pub fn field(mut self, field: Option<&str>) -> Self {
+        self.op.field = field.map(str::to_string);
+        self
+    }
+
+    pub fn op_id(mut self, op_id: &str) -> Self {
+        self.op.op_id = op_id.to_string();
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.op.timestamp = timestamp;
+        self
+    }
+
+    /// Sign with `signer`'s key instead of the owner's
+    pub fn signed_by(mut self, signer: &SigningKey) -> Self {
+        self.op.public_key = public_key(signer);
+        self.signer = signer.clone();
+        self
+    }
+
+    /// Set any other fields
+    pub fn with(mut self, fill: impl FnOnce(&mut SignedOperation)) -> Self {
+        fill(&mut self.op);
+        self
+    }
+
+    /// The operation with an empty signature
+    pub fn unsigned(self) -> SignedOperation {
+        self.op
+    }
+
+    /// The operation, canonically signed
+    pub fn sign(mut self) -> SignedOperation {
+        sign(&self.signer, &mut self.op);
+        self.op
+    }
+}
+
+/// `SignedData` input for `submitData` carrying a canonically signed operation
+pub fn signed_data(op: &SignedOperation) -> serde_json::Value {
+    json!({
+        "dbName": op.db_name, "key": op.key, "value": op.value, "publicKey": op.public_key,
+        "signature": op.signature, "storeType": op.store_type, "field": op.field,
+        "nonce": op.op_id, "signedAt": op.timestamp.to_string(),
+    })
+}
+
+/// A `submitData` mutation for `op`, selecting `success`
+pub fn submit_data(op: &SignedOperation) -> Request {
+    Request::new(r#"mutation ($input: SignedData!) { submitData(input: $input) { success } }"#)
+        .variables(Variables::from_json(json!({ "input": signed_data(op) })))
+}
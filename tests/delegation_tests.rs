@@ -0,0 +1,177 @@
+//! Delegated write access via owner-signed grants
+
+mod common;
+
+use async_graphql::Schema;
+use common::{now_ms, public_key, submit_data};
+use cyberfly_rust_node::delegation::Delegations;
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::storage::BlobStorage;
+use cyberfly_rust_node::storage_backend::MemoryBackend;
+use cyberfly_rust_node::sync::{SignedOperation, SyncManager, SyncMessage};
+use ed25519_dalek::SigningKey;
+use serde_json::json;
+use std::sync::Arc;
+
+/// An operation on the owner's `team` database, canonically signed by `signer`
+fn signed_op(
+    signer: &SigningKey,
+    owner: &SigningKey,
+    store_type: &str,
+    key: &str,
+    field: Option<&str>,
+    value: &str,
+    timestamp: i64,
+) -> SignedOperation {
+    common::op(owner, "team", store_type, key)
+        .signed_by(signer)
+        .field(field)
+        .value(value)
+        .timestamp(timestamp)
+        .sign()
+}
+
+#[tokio::test]
+async fn test_grant_scope_expiry_and_revocation() {
+    let owner = SigningKey::from_bytes(&[21u8; 32]);
+    let member = SigningKey::from_bytes(&[22u8; 32]);
+    let delegations = Delegations::new();
+    let now = now_ms();
+
+    let capability = json!({ "store_types": ["Hash"], "key_prefixes": ["orders/"], "expires_at": now + 60_000 });
+    let grant = signed_op(&owner, &owner, "Grant", "__grants", Some(&public_key(&member)), &capability.to_string(), now - 10);
+    assert!(grant.verify().is_ok());
+    assert!(delegations.apply(&grant).await.unwrap());
+
+    let write = signed_op(&member, &owner, "Hash", "orders/42", Some("status"), "shipped", now);
+    assert!(write.verify().is_err());
+    assert!(write.verify_with_delegations(&delegations).is_ok());
+
+    let denied = [
+        signed_op(&member, &owner, "Hash", "invoices/42", Some("status"), "paid", now),
+        signed_op(&member, &owner, "String", "orders/43", None, "new", now),
+        signed_op(&member, &owner, "Hash", "orders/44", Some("status"), "late", now + 61_000),
+        signed_op(&member, &owner, "Grant", "__grants", Some(&public_key(&member)), "", now),
+        signed_op(&owner, &owner, "Hash", "__grants", Some("x"), "y", now),
+    ];
+    for op in &denied {
+        assert!(op.verify_with_delegations(&delegations).is_err(), "{} {} accepted", op.store_type, op.key);
+    }
+
+    // The later operation wins, whichever order they arrive in
+    let revoke = signed_op(&owner, &owner, "Revoke", "__grants", Some(&public_key(&member)), "", now);
+    assert!(delegations.apply(&revoke).await.unwrap());
+    assert!(!delegations.apply(&grant).await.unwrap());
+    let error = write.verify_with_delegations(&delegations).unwrap_err().to_string();
+    assert!(error.contains("revoked"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_revocation_only_refuses_later_writes() {
+    let owner = SigningKey::from_bytes(&[26u8; 32]);
+    let member = SigningKey::from_bytes(&[27u8; 32]);
+    let now = now_ms();
+    let grant = signed_op(&owner, &owner, "Grant", "__grants", Some(&public_key(&member)), r#"{"store_types":["String"]}"#, now - 100);
+    let revoke = signed_op(&owner, &owner, "Revoke", "__grants", Some(&public_key(&member)), "", now);
+    let before = signed_op(&member, &owner, "String", "status", None, "ok", now - 50);
+    let after = signed_op(&member, &owner, "String", "status", None, "late", now + 50);
+    let outside = signed_op(&member, &owner, "Hash", "status", Some("f"), "v", now - 50);
+
+    // Replicas that saw grant and revocation in either order agree
+    for order in [[&grant, &revoke], [&revoke, &grant]] {
+        let delegations = Delegations::new();
+        for op in order {
+            delegations.apply(op).await.unwrap();
+        }
+        assert!(before.verify_with_delegations(&delegations).is_ok());
+        assert!(after.verify_with_delegations(&delegations).is_err());
+        assert!(outside.verify_with_delegations(&delegations).is_err());
+        assert!(!delegations.is_active(&grant.db_name, &public_key(&member), now - 50));
+    }
+
+    // A revocation with no grant behind it allows nothing
+    let delegations = Delegations::new();
+    delegations.apply(&revoke).await.unwrap();
+    assert!(before.verify_with_delegations(&delegations).is_err());
+}
+
+#[tokio::test]
+async fn test_delegated_writes_through_graphql() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = BlobStorage::open(backend.clone()).await.unwrap();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+    let submit = |op: &SignedOperation| schema.execute(submit_data(op));
+
+    let owner = SigningKey::from_bytes(&[23u8; 32]);
+    let member = SigningKey::from_bytes(&[24u8; 32]);
+    let db_name = format!("team-{}", public_key(&owner));
+    let write = signed_op(&member, &owner, "Hash", "board", Some("todo"), "ship it", now_ms());
+    assert!(!submit(&write).await.errors.is_empty());
+
+    let grant = signed_op(&owner, &owner, "Grant", "__grants", Some(&public_key(&member)), "", now_ms());
+    let response = submit(&grant).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let response = submit(&write).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        storage.get_hash(&format!("{}:board", db_name), "todo").await.unwrap(),
+        Some("ship it".to_string())
+    );
+
+    let query = format!(r#"{{ grants(dbName: "{}") {{ grantee storeTypes revoked }} }}"#, db_name);
+    let data = schema.execute(query).await.data.into_json().unwrap();
+    assert_eq!(data["grants"], json!([{ "grantee": public_key(&member), "storeTypes": [], "revoked": false }]));
+
+    // Grants survive a restart
+    let reopened = BlobStorage::open(backend).await.unwrap();
+    assert!(write.verify_with_delegations(reopened.delegations()).is_ok());
+
+    let revoke = signed_op(&owner, &owner, "Revoke", "__grants", Some(&public_key(&member)), "", now_ms() + 1);
+    assert!(submit(&revoke).await.errors.is_empty());
+    let again = signed_op(&member, &owner, "Hash", "board", Some("todo"), "undo", now_ms() + 2);
+    assert!(submit(&again).await.errors[0].message.contains("revoked"));
+
+    // Backdating under the revocation still replays from the log, but the
+    // API checks the grant against the node's clock
+    let backdated = signed_op(&member, &owner, "Hash", "board", Some("todo"), "undo", revoke.timestamp - 60_000);
+    assert!(backdated.verify_with_delegations(storage.delegations()).is_ok());
+    let response = submit(&backdated).await;
+    assert!(response.errors[0].message.contains("no longer active"), "{:?}", response.errors);
+    assert_eq!(
+        storage.get_hash(&format!("{}:board", db_name), "todo").await.unwrap(),
+        Some("ship it".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_grants_replicate_through_sync() {
+    let storage = BlobStorage::in_memory();
+    let peer = iroh::SecretKey::generate().public();
+    let sync_manager = SyncManager::new(storage.clone(), peer);
+
+    let owner = SigningKey::from_bytes(&[25u8; 32]);
+    let member = SigningKey::from_bytes(&[26u8; 32]);
+    let grant = signed_op(&owner, &owner, "Grant", "__grants", Some(&public_key(&member)), "", now_ms());
+    let write = signed_op(&member, &owner, "String", "motd", None, "hello team", now_ms() + 1);
+
+    // Without the grant the delegate's write is dropped
+    sync_manager
+        .handle_sync_message(SyncMessage::Operation { operation: write.clone() }, peer)
+        .await
+        .unwrap();
+    let full_key = format!("{}:motd", write.db_name);
+    assert_eq!(storage.get_string(&full_key).await.unwrap(), None);
+
+    // A rejected operation doesn't use up its nonce
+    for operation in [grant, write] {
+        sync_manager
+            .handle_sync_message(SyncMessage::Operation { operation }, peer)
+            .await
+            .unwrap();
+    }
+    assert_eq!(storage.get_string(&full_key).await.unwrap(), Some("hello team".to_string()));
+    assert_eq!(sync_manager.sync_store().operation_count().await, 2);
+}
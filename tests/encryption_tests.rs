@@ -0,0 +1,152 @@
+//! Encrypted databases with client-side keys
+
+mod common;
+
+use async_graphql::Schema;
+use common::{public_key, submit_data};
+use cyberfly_rust_node::encryption::{self, EncryptionConfig, SealedValue, WrappedKey};
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::storage::BlobStorage;
+use cyberfly_rust_node::storage_backend::MemoryBackend;
+use cyberfly_rust_node::sync::{SignedOperation, SyncManager, SyncMessage};
+use ed25519_dalek::SigningKey;
+use std::sync::Arc;
+
+/// An operation on the owner's `vault` database, canonically signed by `signer`
+fn signed_op(signer: &SigningKey, owner: &SigningKey, store_type: &str, key: &str, field: Option<&str>, value: &str) -> SignedOperation {
+    common::op(owner, "vault", store_type, key)
+        .signed_by(signer)
+        .field(field)
+        .value(value)
+        .sign()
+}
+
+#[test]
+fn test_seal_and_wrap_round_trip() {
+    let owner = SigningKey::from_bytes(&[31u8; 32]);
+    let member = SigningKey::from_bytes(&[32u8; 32]);
+    let outsider = SigningKey::from_bytes(&[33u8; 32]);
+    let db_name = format!("vault-{}", public_key(&owner));
+    let db_key = encryption::generate_db_key();
+
+    let sealed = SealedValue::seal(&db_key, "k1", &db_name, "diary", Some("monday"), b"dear diary").unwrap();
+    let stored = sealed.to_json();
+    assert!(!stored.contains("dear diary"));
+    let parsed = SealedValue::parse(&stored).unwrap();
+    assert_eq!(parsed.open(&db_key, &db_name, "diary", Some("monday")).unwrap(), b"dear diary");
+    // Bound to its location
+    assert!(parsed.open(&db_key, &db_name, "diary", Some("tuesday")).is_err());
+    assert!(parsed.open(&db_key, &db_name, "notes", Some("monday")).is_err());
+
+    let config = EncryptionConfig::new(&db_key, "k1", &db_name, &[&public_key(&owner), &public_key(&member)]).unwrap();
+    let config = EncryptionConfig::parse(&serde_json::to_string(&config).unwrap()).unwrap();
+    for key in [&owner, &member] {
+        let wrapped = config.wrapped_key(&public_key(key)).unwrap();
+        assert_eq!(wrapped.unwrap(&db_name, key).unwrap(), db_key);
+    }
+    let wrapped: &WrappedKey = config.wrapped_key(&public_key(&member)).unwrap();
+    assert!(wrapped.unwrap(&db_name, &outsider).is_err());
+    assert!(wrapped.unwrap("other-db", &member).is_err());
+    assert!(config.wrapped_key(&public_key(&outsider)).is_none());
+
+    for invalid in [r#"{"ct": "x"}"#, "plaintext", r#"{"v":1,"alg":"aes","kid":"k1","nonce":"","ct":""}"#] {
+        assert!(SealedValue::parse(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[tokio::test]
+async fn test_encrypted_database_through_graphql() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = BlobStorage::open(backend.clone()).await.unwrap();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+    let submit = |op: &SignedOperation| schema.execute(submit_data(op));
+
+    let owner = SigningKey::from_bytes(&[34u8; 32]);
+    let member = SigningKey::from_bytes(&[35u8; 32]);
+    let db_name = format!("vault-{}", public_key(&owner));
+    let db_key = encryption::generate_db_key();
+    let config = EncryptionConfig::new(&db_key, "k1", &db_name, &[&public_key(&owner), &public_key(&member)]).unwrap();
+    let metadata = signed_op(&owner, &owner, "Encryption", "__encryption", None, &serde_json::to_string(&config).unwrap());
+
+    // Only the owner can mark a database encrypted, even with a grant
+    let grant = signed_op(&owner, &owner, "Grant", "__grants", Some(&public_key(&member)), "");
+    assert!(submit(&grant).await.errors.is_empty());
+    let hijack = signed_op(&member, &owner, "Encryption", "__encryption", None, &metadata.value);
+    assert!(submit(&hijack).await.errors[0].message.contains("owner"));
+
+    let response = submit(&metadata).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let plaintext = signed_op(&owner, &owner, "String", "secret", None, "hunter2");
+    assert!(submit(&plaintext).await.errors[0].message.contains("encrypted"));
+    let json_write = signed_op(&owner, &owner, "Json", "doc", None, r#"{"a": 1}"#);
+    assert!(submit(&json_write).await.errors[0].message.contains("not supported"));
+
+    // A member writes a sealed value; nodes only ever see the envelope
+    let sealed = SealedValue::seal(&db_key, "k1", &db_name, "secret", None, b"hunter2").unwrap();
+    let write = signed_op(&member, &owner, "String", "secret", None, &sealed.to_json());
+    let response = submit(&write).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let query = format!(r#"{{ getString(dbName: "{}", key: "secret") {{ value }} }}"#, db_name);
+    let data = schema.execute(query).await.data.into_json().unwrap();
+    let stored = SealedValue::parse(data["getString"]["value"].as_str().unwrap()).unwrap();
+    assert_eq!(stored.open(&db_key, &db_name, "secret", None).unwrap(), b"hunter2");
+
+    // Members find their wrapped key in the metadata
+    let query = format!(r#"{{ encryption(dbName: "{}") {{ keyId members {{ member epk nonce ct }} }} }}"#, db_name);
+    let data = schema.execute(query).await.data.into_json().unwrap();
+    assert_eq!(data["encryption"]["keyId"], "k1");
+    let entry = data["encryption"]["members"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|m| m["member"] == public_key(&member))
+        .unwrap();
+    let wrapped: WrappedKey = serde_json::from_value(entry.clone()).unwrap();
+    assert_eq!(wrapped.unwrap(&db_name, &member).unwrap(), db_key);
+
+    // Value-reading queries are refused; key-level ones keep working
+    let query = format!(r#"{{ filterList(dbName: "{}", key: "secret", valuePattern: "*") }}"#, db_name);
+    assert!(schema.execute(query).await.errors[0].message.contains("key-level"));
+    let query = format!(r#"{{ getAll(dbName: "{}") {{ key }} }}"#, db_name);
+    assert!(schema.execute(query).await.errors.is_empty());
+    assert!(storage.create_index(&db_name, "by_a", "a", cyberfly_rust_node::IndexType::Exact).await.is_err());
+
+    // Metadata survives a restart
+    let reopened = BlobStorage::open(backend).await.unwrap();
+    assert!(reopened.encryption().check_write(&plaintext).is_err());
+    assert!(reopened.encryption().check_write(&write).is_ok());
+}
+
+#[tokio::test]
+async fn test_encryption_replicates_through_sync() {
+    let storage = BlobStorage::in_memory();
+    let peer = iroh::SecretKey::generate().public();
+    let sync_manager = SyncManager::new(storage.clone(), peer);
+
+    let owner = SigningKey::from_bytes(&[36u8; 32]);
+    let db_name = format!("vault-{}", public_key(&owner));
+    let db_key = encryption::generate_db_key();
+    let config = EncryptionConfig::new(&db_key, "k1", &db_name, &[&public_key(&owner)]).unwrap();
+    let metadata = signed_op(&owner, &owner, "Encryption", "__encryption", None, &serde_json::to_string(&config).unwrap());
+    let plaintext = signed_op(&owner, &owner, "String", "pin", None, "1234");
+    let sealed = SealedValue::seal(&db_key, "k1", &db_name, "pin", None, b"1234").unwrap();
+    let write = signed_op(&owner, &owner, "String", "pin", None, &sealed.to_json());
+
+    for operation in [metadata, plaintext, write.clone()] {
+        sync_manager
+            .handle_sync_message(SyncMessage::Operation { operation }, peer)
+            .await
+            .unwrap();
+    }
+    assert!(storage.encryption().is_encrypted(&db_name));
+    assert_eq!(
+        storage.get_string(&format!("{}:pin", db_name)).await.unwrap(),
+        Some(write.value.clone())
+    );
+    assert_eq!(sync_manager.sync_store().operation_count().await, 2);
+}
@@ -0,0 +1,142 @@
+//! Canonical signed-operation envelope
+
+mod common;
+
+use async_graphql::{Request, Schema, Variables};
+use common::{now_ms};
+use cyberfly_rust_node::crypto;
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::storage::BlobStorage;
+use cyberfly_rust_node::sync::{SignatureFormat, SignedOperation};
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::json;
+
+fn operation(key: &SigningKey) -> SignedOperation {
+    common::op(key, "scores", "SortedSet", "leaderboard")
+        .op_id("op-1")
+        .value("alice")
+        .with(|op| op.score = Some(42.5))
+        .unsigned()
+}
+
+/// Sign a message other than the canonical envelope
+fn sign(key: &SigningKey, message: &str) -> String {
+    hex::encode(key.sign(message.as_bytes()).to_bytes())
+}
+
+#[test]
+fn test_canonical_json_encoding() {
+    let value = json!({
+        "b": [1.0, 1e21, 1.5e-7, 0.000001, -123.456, -0.0, 5e-324],
+        "a": { "z": null, "é": "\u{1}\"\\", "Z": true },
+        "": 9007199254740993i64,
+    });
+    assert_eq!(
+        crypto::canonical_json(&value),
+        r#"{"":9007199254740993,"a":{"Z":true,"z":null,"é":"\u0001\"\\"},"b":[1,1e+21,1.5e-7,0.000001,-123.456,0,5e-324]}"#
+    );
+}
+
+#[test]
+fn test_signature_covers_every_field() {
+    let key = SigningKey::from_bytes(&[3u8; 32]);
+    let mut op = operation(&key);
+    op.timestamp = 1_700_000_000_000;
+    assert_eq!(
+        op.canonical_message(),
+        format!(
+            "cyberfly-signed-operation-v1\n{{\"db_name\":\"{}\",\"field\":null,\"json_path\":null,\"key\":\"leaderboard\",\
+             \"latitude\":null,\"longitude\":null,\"op_id\":\"op-1\",\"public_key\":\"{}\",\"score\":42.5,\
+             \"store_type\":\"SortedSet\",\"stream_fields\":null,\"timestamp\":1700000000000,\"ts_timestamp\":null,\
+             \"value\":\"alice\"}}",
+            op.db_name, op.public_key
+        )
+    );
+
+    common::sign(&key, &mut op);
+    assert_eq!(op.signature_format().unwrap(), SignatureFormat::Canonical);
+
+    let tampered: [fn(&mut SignedOperation); 6] = [
+        |op| op.store_type = "Set".to_string(),
+        |op| op.labels = Some("{}".to_string()),
+        |op| op.score = Some(1000.0),
+        |op| op.field = Some("member".to_string()),
+        |op| op.latitude = Some(0.0),
+        |op| op.timestamp += 1,
+    ];
+    for tamper in tampered {
+        let mut relayed = op.clone();
+        tamper(&mut relayed);
+        assert!(relayed.verify_signature().is_err());
+    }
+
+    // A legacy short-form signature says nothing about the score
+    op.signature = sign(&key, &format!("{}:leaderboard:alice", op.db_name));
+    op.score = Some(1000.0);
+    assert_eq!(op.signature_format().unwrap(), SignatureFormat::LegacyShort);
+}
+
+#[test]
+fn test_legacy_signatures_rejected_after_cutoff() {
+    let key = SigningKey::from_bytes(&[4u8; 32]);
+    let mut legacy = operation(&key);
+    legacy.signature = sign(&key, &format!("{}:leaderboard:alice", legacy.db_name));
+    let mut canonical = operation(&key);
+    common::sign(&key, &mut canonical);
+
+    assert!(legacy.verify().is_ok());
+
+    crypto::set_legacy_signature_cutoff(now_ms() - 1);
+    let result = legacy.verify();
+    crypto::set_legacy_signature_cutoff(crypto::DEFAULT_LEGACY_SIGNATURES_UNTIL);
+
+    assert!(result.unwrap_err().to_string().contains("legacy-short"));
+    assert!(canonical.verify().is_ok());
+    // The op log re-check still recognises old entries
+    assert!(legacy.verify_signature().is_ok());
+}
+
+#[tokio::test]
+async fn test_submit_data_with_canonical_envelope() {
+    let storage = BlobStorage::in_memory();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+    let submit = r#"mutation ($input: SignedData!) { submitData(input: $input) { success } }"#;
+
+    let key = SigningKey::from_bytes(&[5u8; 32]);
+    let mut op = operation(&key);
+    op.op_id = "3f1c1d2e-nonce".to_string();
+    common::sign(&key, &mut op);
+    let input = json!({
+        "dbName": op.db_name, "key": op.key, "value": op.value, "publicKey": op.public_key,
+        "signature": op.signature, "storeType": op.store_type, "score": op.score,
+        "nonce": op.op_id, "signedAt": op.timestamp.to_string(),
+    });
+
+    let response = schema
+        .execute(Request::new(submit).variables(Variables::from_json(json!({ "input": input.clone() }))))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let members = storage
+        .get_sorted_set_with_scores(&format!("{}:leaderboard", op.db_name), 0, -1)
+        .await
+        .unwrap();
+    assert_eq!(members, vec![("alice".to_string(), 42.5)]);
+
+    // Changing any signed field, or dropping half of the envelope, is rejected
+    for (field, value) in [
+        ("score", json!(1000.0)),
+        ("storeType", json!("Set")),
+        ("signedAt", json!((op.timestamp + 1).to_string())),
+        ("signedAt", json!(null)),
+    ] {
+        let mut forged = input.clone();
+        forged[field] = value;
+        let response = schema
+            .execute(Request::new(submit).variables(Variables::from_json(json!({ "input": forged }))))
+            .await;
+        assert_eq!(response.errors.len(), 1, "{} was not covered", field);
+    }
+}
@@ -0,0 +1,144 @@
+//! MongoDB-style filter documents evaluated by `JsonFilter`
+
+use async_graphql::Schema;
+use cyberfly_rust_node::filters::{FilterExpr, FilterOptions, JsonFilter, JsonFilterConditions};
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::indexing::IndexType;
+use cyberfly_rust_node::storage::BlobStorage;
+use serde_json::json;
+
+fn matches(filter: serde_json::Value, doc: &serde_json::Value) -> bool {
+    FilterExpr::parse(&filter).unwrap().matches(doc)
+}
+
+fn order() -> serde_json::Value {
+    json!({
+        "status": "shipped",
+        "total": 120,
+        "customer": { "name": "Alice Smith", "tier": "gold" },
+        "tags": ["gift", "express"],
+        "items": [
+            { "sku": "A1", "qty": 2, "price": 10 },
+            { "sku": "B7", "qty": 1, "price": 100 }
+        ]
+    })
+}
+
+#[test]
+fn test_logical_operators_and_nested_paths() {
+    let doc = order();
+    assert!(matches(json!({ "customer.tier": "gold", "total": { "$gte": 100, "$lt": 200 } }), &doc));
+    assert!(!matches(json!({ "customer.tier": "silver" }), &doc));
+    assert!(matches(json!({ "$or": [{ "status": "pending" }, { "customer.name": { "$regex": "^alice", "$options": "i" } }] }), &doc));
+    assert!(matches(json!({ "$and": [{ "status": "shipped" }, { "$not": { "total": { "$gt": 500 } } }] }), &doc));
+    assert!(!matches(json!({ "$not": { "status": "shipped" } }), &doc));
+    assert!(matches(json!({ "total": { "$not": { "$lt": 100 } } }), &doc));
+
+    // Ordering comparisons only match values of the same type
+    assert!(!matches(json!({ "status": { "$gt": 5 } }), &doc));
+}
+
+#[test]
+fn test_array_matching() {
+    let doc = order();
+    // Equality against an array matches any element, or the whole array
+    assert!(matches(json!({ "tags": "gift" }), &doc));
+    assert!(matches(json!({ "tags": ["gift", "express"] }), &doc));
+    assert!(matches(json!({ "tags": { "$nin": ["fragile"] } }), &doc));
+    assert!(!matches(json!({ "tags": { "$ne": "express" } }), &doc));
+
+    // Paths descend into arrays of objects
+    assert!(matches(json!({ "items.sku": "B7" }), &doc));
+    assert!(matches(json!({ "items.1.sku": "B7" }), &doc));
+
+    // $elemMatch needs one element to satisfy every condition
+    assert!(matches(json!({ "items": { "$elemMatch": { "sku": "A1", "qty": { "$gte": 2 } } } }), &doc));
+    assert!(!matches(json!({ "items": { "$elemMatch": { "sku": "A1", "price": 100 } } }), &doc));
+    assert!(matches(json!({ "items.sku": "A1", "items.price": 100 }), &doc));
+    assert!(matches(json!({ "items.qty": { "$elemMatch": { "$gt": 1, "$lt": 3 } } }), &json!({ "items": { "qty": [1, 2] } })));
+
+    assert!(matches(json!({ "tags": { "$size": 2 }, "customer.email": { "$exists": false } }), &doc));
+    assert!(!matches(json!({ "items": { "$size": 3 } }), &doc));
+    assert!(matches(json!({ "customer": { "$exists": true } }), &doc));
+}
+
+#[test]
+fn test_invalid_filters_are_rejected() {
+    for filter in [
+        json!({ "$xor": [] }),
+        json!({ "total": { "$near": 5 } }),
+        json!({ "$or": [] }),
+        json!({ "tags": { "$size": -1 } }),
+        json!({ "name": { "$regex": "(" } }),
+        json!({ "name": { "$regex": "a", "$options": "g" } }),
+        json!(["not", "an", "object"]),
+    ] {
+        assert!(FilterExpr::parse(&filter).is_err(), "{} should be rejected", filter);
+    }
+}
+
+async fn seed_orders(storage: &BlobStorage) {
+    let orders = [
+        ("o1", json!({ "status": "shipped", "total": 120, "items": [{ "sku": "A1" }] })),
+        ("o2", json!({ "status": "pending", "total": 40, "items": [{ "sku": "B7" }, { "sku": "A1" }] })),
+        ("o3", json!({ "status": "shipped", "total": 15, "items": [] })),
+        ("o4", json!({ "status": "cancelled", "total": 300 })),
+    ];
+    for (key, doc) in orders {
+        storage.set_json(&format!("shop:{}", key), "$", &doc.to_string()).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_filter_documents_with_index_pushdown() {
+    let storage = BlobStorage::in_memory();
+    seed_orders(&storage).await;
+    let filter = JsonFilter::new(&storage);
+    let options = FilterOptions {
+        sort_by: Some("total".to_string()),
+        ..Default::default()
+    };
+    let conditions = JsonFilterConditions::from_filter(
+        FilterExpr::parse(&json!({ "items.sku": "A1", "$or": [{ "total": { "$gt": 100 } }, { "status": "pending" }] }))
+            .unwrap(),
+    );
+
+    let scanned = filter.filter_across_keys("shop:*", &conditions, &options).await.unwrap();
+    let keys: Vec<&str> = scanned.iter().map(|d| d["_key"].as_str().unwrap()).collect();
+    assert_eq!(keys, vec!["shop:o2", "shop:o1"]);
+
+    // The top-level path condition can use an index; the $or cannot
+    storage.create_index("shop", "sku_idx", "items.sku", IndexType::Exact).await.unwrap();
+    storage.create_index("shop", "status_idx", "status", IndexType::Exact).await.unwrap();
+    let plan = filter.explain("shop:*", &conditions, &options).await;
+    assert_eq!(plan.scans.len(), 1);
+    assert_eq!(plan.explain("shop:*", &options)[0], "IXSCAN sku_idx (items.sku): items.sku = A1");
+    assert_eq!(filter.filter_across_keys("shop:*", &conditions, &options).await.unwrap(), scanned);
+}
+
+#[tokio::test]
+async fn test_filter_argument_in_graphql_schema() {
+    let storage = BlobStorage::in_memory();
+    seed_orders(&storage).await;
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+
+    // `$` keys are not valid GraphQL names, so filters travel as variables
+    let query = r#"query ($filter: JSON!) { filterDocuments(input: { dbName: "shop", filter: $filter }) { count documents } }"#;
+    let request = |filter: serde_json::Value| {
+        async_graphql::Request::new(query).variables(async_graphql::Variables::from_json(json!({ "filter": filter })))
+    };
+
+    let response = schema
+        .execute(request(json!({ "status": "shipped", "total": { "$gte": 100 } })))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["filterDocuments"]["count"], 1);
+    assert_eq!(data["filterDocuments"]["documents"][0]["_key"], "shop:o1");
+
+    let response = schema.execute(request(json!({ "$where": "this.total > 1" }))).await;
+    assert!(!response.errors.is_empty());
+}
@@ -0,0 +1,194 @@
+//! Consistency checker tests on the in-memory backend
+
+mod common;
+
+use cyberfly_rust_node::fsck::{Fsck, FsckIssueKind, FsckOptions};
+use cyberfly_rust_node::storage::{BlobStorage, SignatureMetadata, StoreType};
+use cyberfly_rust_node::storage_backend::{IndexStore, MemoryBackend, StorageBackend, ValueStore};
+use cyberfly_rust_node::sync::{SignedOperation, SyncManager};
+use ed25519_dalek::{Signer, SigningKey};
+use iroh_blobs::Hash;
+use std::sync::Arc;
+
+/// A String write on the signer's `mydb` database, signed in the legacy
+/// full `op_id:timestamp:db_name:key:value` form
+fn signed_string_op(signing_key: &SigningKey, key: &str, value: &str) -> SignedOperation {
+    let mut op = common::op(signing_key, "mydb", "String", key).value(value).unsigned();
+    let message = format!("{}:{}:{}:{}:{}", op.op_id, op.timestamp, op.db_name, key, value);
+    op.signature = hex::encode(signing_key.sign(message.as_bytes()).to_bytes());
+    op
+}
+
+#[tokio::test]
+async fn test_fsck_clean_storage() {
+    let storage = BlobStorage::in_memory();
+    storage.set_string("db:a", "1").await.unwrap();
+    storage.set_hash("db:h", "f", "v").await.unwrap();
+
+    let report = Fsck::new(&storage).run(&FsckOptions::default()).await.unwrap();
+    assert_eq!(report.keys_scanned, 2);
+    assert!(report.issues.is_empty());
+    assert!(report.is_clean());
+}
+
+#[tokio::test]
+async fn test_fsck_detects_and_drops_broken_entries() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = BlobStorage::open(backend.clone()).await.unwrap();
+    storage.set_string("db:ok", "fine").await.unwrap();
+
+    // Index entry pointing at a value that was never written
+    let missing = Hash::new(b"never stored").to_string();
+    let entry = bincode::serialize(&(missing, StoreType::String)).unwrap();
+    backend.index().insert("db:dangling", entry).unwrap();
+
+    // Index entry pointing at bytes that aren't a stored value
+    let garbage = backend.values().put(vec![0xff; 3]).await.unwrap();
+    let entry = bincode::serialize(&(garbage.to_string(), StoreType::String)).unwrap();
+    backend.index().insert("db:corrupt", entry).unwrap();
+
+    let report = Fsck::new(&storage).run(&FsckOptions::default()).await.unwrap();
+    let mut kinds: Vec<_> = report.issues.iter().map(|i| (i.key.as_str(), i.kind)).collect();
+    kinds.sort_by_key(|(key, _)| *key);
+    assert_eq!(
+        kinds,
+        vec![("db:corrupt", FsckIssueKind::CorruptValue), ("db:dangling", FsckIssueKind::MissingValue)]
+    );
+
+    // Nothing to replay them from, so repair drops them from the index
+    let options = FsckOptions { repair: true, db_name: Some("db".to_string()) };
+    let report = Fsck::new(&storage).run(&options).await.unwrap();
+    assert_eq!(report.issues.len(), 2);
+    assert!(!backend.index().contains("db:dangling").unwrap());
+    assert!(!backend.index().contains("db:corrupt").unwrap());
+    assert_eq!(storage.get_string("db:ok").await.unwrap(), Some("fine".to_string()));
+}
+
+#[tokio::test]
+async fn test_fsck_signatures_and_op_log_replay() {
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+    let public_key = hex::encode(signing_key.verifying_key().as_bytes());
+    let db_name = format!("mydb-{}", public_key);
+
+    let storage = BlobStorage::in_memory();
+    let sync_manager = SyncManager::new(storage.clone(), iroh::SecretKey::generate().public());
+
+    // Valid signature in the short (GraphQL) format
+    let signature = signing_key.sign(format!("{}:good:hello", db_name).as_bytes());
+    let meta = SignatureMetadata {
+        public_key: public_key.clone(),
+        signature: hex::encode(signature.to_bytes()),
+        timestamp: 0,
+    };
+    storage
+        .set_string_with_metadata(&format!("{}:good", db_name), "hello", Some(meta.clone()))
+        .await
+        .unwrap();
+    // Same signature on a different value, with no logged operation
+    storage
+        .set_string_with_metadata(&format!("{}:tampered", db_name), "hello", Some(meta.clone()))
+        .await
+        .unwrap();
+    // No signature to check
+    let unsigned = SignatureMetadata { signature: String::new(), ..meta };
+    storage
+        .set_string_with_metadata(&format!("{}:unsigned", db_name), "hello", Some(unsigned))
+        .await
+        .unwrap();
+    // Signed by a key that doesn't own the database
+    let stranger = SigningKey::from_bytes(&[17u8; 32]);
+    let signature = stranger.sign(format!("{}:foreign:hello", db_name).as_bytes());
+    let foreign = SignatureMetadata {
+        public_key: hex::encode(stranger.verifying_key().as_bytes()),
+        signature: hex::encode(signature.to_bytes()),
+        timestamp: 0,
+    };
+    storage
+        .set_string_with_metadata(&format!("{}:foreign", db_name), "hello", Some(foreign))
+        .await
+        .unwrap();
+
+    // Logged but never applied, as after a crash between logging and apply
+    let op = signed_string_op(&signing_key, "pending", "from-log");
+    sync_manager.sync_store().add_operation(op).await.unwrap();
+
+    let fsck = Fsck::new(&storage).with_sync_manager(&sync_manager);
+    let report = fsck.run(&FsckOptions::default()).await.unwrap();
+    assert_eq!(report.signatures_verified, 1);
+    assert_eq!(report.signatures_unverifiable, 1);
+    assert_eq!(report.operations_scanned, 1);
+    let invalid: Vec<_> = report
+        .issues
+        .iter()
+        .filter(|i| i.kind == FsckIssueKind::InvalidSignature)
+        .map(|i| i.key.as_str())
+        .collect();
+    assert_eq!(invalid, [format!("{}:foreign", db_name), format!("{}:tampered", db_name)]);
+    let kinds: Vec<_> = report.issues.iter().map(|i| i.kind).collect();
+    assert!(kinds.contains(&FsckIssueKind::OrphanOperation));
+
+    let options = FsckOptions { repair: true, db_name: None };
+    let report = fsck.run(&options).await.unwrap();
+    assert!(report.issues.iter().any(|i| i.kind == FsckIssueKind::OrphanOperation && i.repaired));
+    assert_eq!(
+        storage.get_string(&format!("{}:pending", db_name)).await.unwrap(),
+        Some("from-log".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_fsck_verifies_canonical_string_signatures() {
+    let signing_key = SigningKey::from_bytes(&[8u8; 32]);
+    let public_key = hex::encode(signing_key.verifying_key().as_bytes());
+    let db_name = format!("mydb-{}", public_key);
+
+    let storage = BlobStorage::in_memory();
+    let sync_manager = SyncManager::new(storage.clone(), iroh::SecretKey::generate().public());
+
+    let mut op = signed_string_op(&signing_key, "greeting", "hi");
+    common::sign(&signing_key, &mut op);
+    let meta = SignatureMetadata {
+        public_key,
+        signature: op.signature.clone(),
+        timestamp: op.timestamp,
+    };
+    storage
+        .set_string_with_metadata(&format!("{}:greeting", db_name), "hi", Some(meta))
+        .await
+        .unwrap();
+    sync_manager.sync_store().add_operation(op.clone()).await.unwrap();
+    sync_manager.sync_store().mark_applied(&op.op_id).await;
+
+    let report = Fsck::new(&storage).with_sync_manager(&sync_manager).run(&FsckOptions::default()).await.unwrap();
+    assert_eq!(report.signatures_verified, 1);
+    assert!(report.issues.is_empty(), "{:?}", report.issues);
+}
+
+#[tokio::test]
+async fn test_logged_operations_reload_whatever_their_age() {
+    let signing_key = SigningKey::from_bytes(&[9u8; 32]);
+    let dir = tempfile::TempDir::new().unwrap();
+    let store = iroh_blobs::store::fs::FsStore::load(dir.path()).await.unwrap();
+
+    // A day-old operation, and one whose signature no longer matches
+    let mut old = signed_string_op(&signing_key, "old", "kept");
+    old.timestamp -= 86_400_000;
+    common::sign(&signing_key, &mut old);
+    let mut forged = signed_string_op(&signing_key, "forged", "x");
+    forged.value = "changed".to_string();
+
+    let mut index = std::collections::HashMap::new();
+    for op in [&old, &forged] {
+        let hash = store.blobs().add_bytes(op.to_versioned_json().unwrap()).await.unwrap().hash;
+        index.insert(op.op_id.clone(), hash);
+    }
+    let index_hash = store.blobs().add_bytes(serde_json::to_vec(&index).unwrap()).await.unwrap().hash;
+
+    let storage = BlobStorage::in_memory();
+    let sync_manager = SyncManager::with_store(storage, iroh::SecretKey::generate().public(), store.clone());
+    assert_eq!(sync_manager.load_from_storage(index_hash).await.unwrap(), 1);
+    let logged = sync_manager.sync_store().get_all_operations().await;
+    assert_eq!(logged.len(), 1);
+    assert_eq!(logged[0].op_id, old.op_id);
+    store.shutdown().await.unwrap();
+}
@@ -0,0 +1,384 @@
+//! Secondary indexes maintained by storage writes and exposed through GraphQL
+
+use async_graphql::{Request, Schema};
+use cyberfly_rust_node::filters::{FilterCondition, FilterOptions, JsonFilter, JsonFilterConditions, SortOrder};
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::indexing::{IndexType, QueryOperator};
+use cyberfly_rust_node::read_access::{ReadCredential, ReadTokenClaims};
+use cyberfly_rust_node::storage::BlobStorage;
+use cyberfly_rust_node::storage_backend::RedbBackend;
+use ed25519_dalek::SigningKey;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// A bearer token proving `key`, good for a minute
+fn token(key: &SigningKey) -> String {
+    ReadTokenClaims {
+        public_key: hex::encode(key.verifying_key().to_bytes()),
+        expires_at: chrono::Utc::now().timestamp_millis() + 60_000,
+        challenge: None,
+    }
+    .sign(key)
+}
+
+async fn query_keys(storage: &BlobStorage, index: &str, operator: QueryOperator) -> Vec<String> {
+    let mut keys = storage
+        .index_manager()
+        .query_index("db", index, operator)
+        .await
+        .unwrap()
+        .keys;
+    keys.sort();
+    keys
+}
+
+#[tokio::test]
+async fn test_create_index_backfills_existing_keys() {
+    let storage = BlobStorage::in_memory();
+    storage.set_json("db:u1", "$", r#"{"name":"alice","age":30}"#).await.unwrap();
+    storage.set_json("db:u2", "$", r#"{"name":"bob","age":17}"#).await.unwrap();
+    storage.set_hash("db:h1", "age", "42").await.unwrap();
+    storage.set_string("db:s1", "not indexed").await.unwrap();
+    storage.set_json("other:u1", "$", r#"{"age":99}"#).await.unwrap();
+
+    let indexed = storage.create_index("db", "age_idx", "age", IndexType::Range).await.unwrap();
+    assert_eq!(indexed, 3);
+    assert_eq!(
+        query_keys(&storage, "age_idx", QueryOperator::GreaterThan(18.0)).await,
+        vec!["h1", "u1"]
+    );
+}
+
+#[tokio::test]
+async fn test_writes_and_deletes_maintain_indexes() {
+    let storage = BlobStorage::in_memory();
+    storage.create_index("db", "status_idx", "status", IndexType::Exact).await.unwrap();
+
+    storage.set_json("db:a", "$", r#"{"status":"open"}"#).await.unwrap();
+    storage.set_hash("db:b", "status", "open").await.unwrap();
+    assert_eq!(
+        query_keys(&storage, "status_idx", QueryOperator::Equals("open".to_string())).await,
+        vec!["a", "b"]
+    );
+
+    // Overwrites move the key to its new value
+    storage.set_json("db:a", "$", r#"{"status":"closed"}"#).await.unwrap();
+    storage.set_hash("db:b", "status", "closed").await.unwrap();
+    assert!(query_keys(&storage, "status_idx", QueryOperator::Equals("open".to_string())).await.is_empty());
+    assert_eq!(
+        query_keys(&storage, "status_idx", QueryOperator::Equals("closed".to_string())).await,
+        vec!["a", "b"]
+    );
+
+    storage.delete("db:a").await.unwrap();
+    assert_eq!(
+        query_keys(&storage, "status_idx", QueryOperator::Equals("closed".to_string())).await,
+        vec!["b"]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_writes_to_one_key_leave_one_index_entry() {
+    let storage = BlobStorage::in_memory();
+    storage.create_index("db", "status_idx", "status", IndexType::Exact).await.unwrap();
+
+    let writes: Vec<_> = (0..32)
+        .map(|n| {
+            let storage = storage.clone();
+            tokio::spawn(async move {
+                let document = format!(r#"{{"status":"s{}"}}"#, n);
+                storage.set_json("db:doc", "$", &document).await.unwrap();
+            })
+        })
+        .collect();
+    for write in writes {
+        write.await.unwrap();
+    }
+
+    let stored: serde_json::Value =
+        serde_json::from_str(&storage.get_json("db:doc", None).await.unwrap().unwrap()).unwrap();
+    let mut indexed = Vec::new();
+    for n in 0..32 {
+        let status = format!("s{}", n);
+        if !query_keys(&storage, "status_idx", QueryOperator::Equals(status.clone())).await.is_empty() {
+            indexed.push(status);
+        }
+    }
+    assert_eq!(indexed, [stored["status"].as_str().unwrap()]);
+}
+
+#[tokio::test]
+async fn test_indexes_persist_across_reopen() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("storage.redb");
+
+    {
+        let storage = BlobStorage::open(Arc::new(RedbBackend::open(path.clone()).unwrap())).await.unwrap();
+        storage.create_index("db", "age_idx", "age", IndexType::Range).await.unwrap();
+        for (key, age) in [("a", 5), ("b", 50), ("c", 500)] {
+            storage.set_json(&format!("db:{}", key), "$", &format!(r#"{{"age":{}}}"#, age)).await.unwrap();
+        }
+    }
+
+    // Definitions and entries come back from disk without a backfill
+    let storage = BlobStorage::open(Arc::new(RedbBackend::open(path).unwrap())).await.unwrap();
+    assert_eq!(storage.index_manager().list_indexes("db").await, vec!["age_idx"]);
+    assert_eq!(
+        query_keys(&storage, "age_idx", QueryOperator::Between(10.0, 500.0)).await,
+        vec!["b", "c"]
+    );
+    let stats = storage.index_manager().get_index_stats("db", "age_idx").await.unwrap();
+    assert_eq!(stats.total_keys, 3);
+    assert_eq!(stats.unique_values, 3);
+
+    // Dropping removes the definition and its entries
+    storage.index_manager().drop_index("db", "age_idx").await.unwrap();
+    storage.create_index("db", "age_idx", "age", IndexType::Range).await.unwrap();
+    assert_eq!(storage.index_manager().get_index_stats("db", "age_idx").await.unwrap().total_keys, 3);
+}
+
+#[tokio::test]
+async fn test_index_api_in_graphql_schema() {
+    let storage = BlobStorage::in_memory();
+    let owner = SigningKey::from_bytes(&[21u8; 32]);
+    let db_name = format!("users-{}", hex::encode(owner.verifying_key().to_bytes()));
+    storage.set_json(&format!("{}:u1", db_name), "$", r#"{"email":"a@example.com"}"#).await.unwrap();
+
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+    let create = format!(
+        r#"mutation {{ createIndex(dbName: "{}", indexName: "email_idx", field: "email", indexType: "exact") }}"#,
+        db_name
+    );
+    let drop = format!(r#"mutation {{ dropIndex(dbName: "{}", indexName: "email_idx") }}"#, db_name);
+
+    // Only the owner may create or drop indexes
+    let stranger = SigningKey::from_bytes(&[22u8; 32]);
+    for credential in [ReadCredential::default(), ReadCredential::bearer(token(&stranger))] {
+        for mutation in [&create, &drop] {
+            let response = schema.execute(Request::new(mutation.as_str()).data(credential.clone())).await;
+            assert!(!response.errors.is_empty(), "{} was allowed", mutation);
+        }
+    }
+    assert!(storage.index_manager().list_indexes(&db_name).await.is_empty());
+
+    let owner_credential = ReadCredential::bearer(token(&owner));
+    let response = schema.execute(Request::new(create.as_str()).data(owner_credential.clone())).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let response = schema
+        .execute(format!(
+            r#"{{ queryIndex(dbName: "{}", indexName: "email_idx", operator: "equals", value: "a@example.com") {{ keys count }} }}"#,
+            db_name
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["queryIndex"]["keys"], serde_json::json!(["u1"]));
+    assert_eq!(data["queryIndex"]["count"], 1);
+
+    let response = schema.execute(Request::new(drop.as_str()).data(owner_credential)).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert!(storage.index_manager().list_indexes(&db_name).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_fulltext_search_ranks_and_highlights() {
+    let storage = BlobStorage::in_memory();
+    storage
+        .set_json("db:p1", "$", r#"{"body":"Rust makes systems programming safe and fast"}"#)
+        .await
+        .unwrap();
+    storage
+        .set_json("db:p2", "$", r#"{"body":"Programming in rust: rust ownership explained for rust programmers"}"#)
+        .await
+        .unwrap();
+    storage.set_hash("db:p3", "body", "Gardening tips for the spring").await.unwrap();
+    storage.create_index("db", "body_ft", "body", IndexType::FullText).await.unwrap();
+
+    // Stemming matches "programmers"/"programming" and ranks the denser document first
+    let hits = storage.index_manager().search("db", "rust program", 10).await.unwrap();
+    let keys: Vec<&str> = hits.iter().map(|hit| hit.key.as_str()).collect();
+    assert_eq!(keys, vec!["p2", "p1"]);
+    assert!(hits[0].score > hits[1].score);
+    assert!(hits[1].highlights[0].contains("<em>Rust</em>"));
+
+    let hits = storage.index_manager().search("db", "\"systems programming\"", 10).await.unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].key, "p1");
+
+    let hits = storage.index_manager().search("db", "garden*", 10).await.unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].key, "p3");
+
+    // Updates and deletes keep the inverted index in sync
+    storage.set_hash("db:p3", "body", "Rust on old bicycles").await.unwrap();
+    storage.delete("db:p2").await.unwrap();
+    let mut keys: Vec<String> = storage
+        .index_manager()
+        .search("db", "rust", 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|hit| hit.key)
+        .collect();
+    keys.sort();
+    assert_eq!(keys, vec!["p1", "p3"]);
+    assert!(storage.index_manager().search("db", "garden*", 10).await.unwrap().is_empty());
+    assert!(storage.index_manager().search("other", "rust", 10).await.is_err());
+}
+
+#[tokio::test]
+async fn test_fulltext_search_in_graphql_schema() {
+    let storage = BlobStorage::in_memory();
+    storage.set_json("db:a", "$", r#"{"title":"Quick brown fox","tags":["animals"]}"#).await.unwrap();
+    storage.set_json("db:b", "$", r#"{"title":"Lazy dog"}"#).await.unwrap();
+    storage.create_index("db", "all_ft", "*", IndexType::FullText).await.unwrap();
+
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+
+    let response = schema
+        .execute(r#"{ search(dbName: "db", query: "animal fox", limit: 5) { key score highlights } }"#)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["search"].as_array().unwrap().len(), 1);
+    assert_eq!(data["search"][0]["key"], "a");
+    assert!(data["search"][0]["score"].as_f64().unwrap() > 0.0);
+}
+
+async fn seed_tickets(storage: &BlobStorage) {
+    let tickets = [
+        ("t1", "open", 100, 3),
+        ("t2", "open", 300, 1),
+        ("t3", "closed", 200, 2),
+        ("t4", "open", 200, 5),
+        ("t5", "open", 50, 4),
+    ];
+    for (key, status, created, priority) in tickets {
+        let doc = serde_json::json!({ "status": status, "createdAt": created, "priority": priority });
+        storage.set_json(&format!("db:{}", key), "$", &doc.to_string()).await.unwrap();
+    }
+    // Missing the second field; still indexed under its status
+    storage.set_json("db:t6", "$", r#"{"status":"open"}"#).await.unwrap();
+}
+
+fn doc_keys(docs: &[serde_json::Value]) -> Vec<&str> {
+    docs.iter().map(|doc| doc["_key"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn test_filter_uses_compound_index_order() {
+    let storage = BlobStorage::in_memory();
+    seed_tickets(&storage).await;
+    storage
+        .create_index("db", "status_created", "status,createdAt", IndexType::Compound)
+        .await
+        .unwrap();
+
+    let mut conditions = JsonFilterConditions::new();
+    conditions.add_condition("status".to_string(), FilterCondition::Eq(serde_json::json!("open")));
+    conditions.add_condition("createdAt".to_string(), FilterCondition::Gt(serde_json::json!(60)));
+    let mut options = FilterOptions {
+        sort_by: Some("createdAt".to_string()),
+        ..Default::default()
+    };
+
+    let filter = JsonFilter::new(&storage);
+    let plan = filter.explain("db:*", &conditions, &options).await;
+    assert!(plan.index_order);
+    assert_eq!(
+        plan.explain("db:*", &options),
+        vec![
+            "IXSCAN status_created (status, createdAt): status = open, createdAt > 60",
+            "FETCH + FILTER 2 conditions",
+            "SORT createdAt ASC (index order)",
+        ]
+    );
+
+    let docs = filter.filter_across_keys("db:*", &conditions, &options).await.unwrap();
+    assert_eq!(doc_keys(&docs), vec!["db:t1", "db:t4", "db:t2"]);
+
+    options.sort_order = SortOrder::Desc;
+    options.offset = Some(1);
+    options.limit = Some(1);
+    let docs = filter.filter_across_keys("db:*", &conditions, &options).await.unwrap();
+    assert_eq!(doc_keys(&docs), vec!["db:t4"]);
+
+    // Equality on the leading field alone still uses the index
+    let mut conditions = JsonFilterConditions::new();
+    conditions.add_condition("status".to_string(), FilterCondition::Eq(serde_json::json!("open")));
+    let docs = filter.filter_across_keys("db:*", &conditions, &FilterOptions::default()).await.unwrap();
+    let mut keys = doc_keys(&docs);
+    keys.sort();
+    assert_eq!(keys, vec!["db:t1", "db:t2", "db:t4", "db:t5", "db:t6"]);
+}
+
+#[tokio::test]
+async fn test_filter_intersects_indexes_and_matches_full_scan() {
+    let storage = BlobStorage::in_memory();
+    seed_tickets(&storage).await;
+
+    let mut conditions = JsonFilterConditions::new();
+    conditions.add_condition("status".to_string(), FilterCondition::Eq(serde_json::json!("open")));
+    conditions.add_condition("priority".to_string(), FilterCondition::Lte(serde_json::json!(3)));
+    conditions.add_condition("createdAt".to_string(), FilterCondition::Ne(serde_json::json!(300)));
+    let options = FilterOptions {
+        sort_by: Some("priority".to_string()),
+        ..Default::default()
+    };
+
+    let filter = JsonFilter::new(&storage);
+    assert!(filter.explain("db:*", &conditions, &options).await.is_full_scan());
+    let scanned = filter.filter_across_keys("db:*", &conditions, &options).await.unwrap();
+    assert_eq!(doc_keys(&scanned), vec!["db:t1"]);
+
+    storage.create_index("db", "status_idx", "status", IndexType::Exact).await.unwrap();
+    storage.create_index("db", "priority_idx", "priority", IndexType::Range).await.unwrap();
+    let plan = filter.explain("db:*", &conditions, &options).await;
+    assert_eq!(plan.scans.len(), 2);
+    assert_eq!(plan.explain("db:*", &options)[2], "INTERSECT 2 scans");
+    let indexed = filter.filter_across_keys("db:*", &conditions, &options).await.unwrap();
+    assert_eq!(indexed, scanned);
+}
+
+#[tokio::test]
+async fn test_filter_documents_in_graphql_schema() {
+    let storage = BlobStorage::in_memory();
+    seed_tickets(&storage).await;
+    storage
+        .create_index("db", "status_created", "status,createdAt", IndexType::Compound)
+        .await
+        .unwrap();
+
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+
+    let query = r#"{ filterDocuments(input: { dbName: "db", conditions: [
+            { field: "status", op: "eq", value: "open" },
+            { field: "createdAt", op: "gte", value: 200 }
+        ], sortBy: "createdAt", sortDesc: true, explain: EXPLAIN }) { documents count indexUsed plan } }"#;
+
+    let response = schema.execute(query.replace("EXPLAIN", "false")).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["filterDocuments"]["count"], 2);
+    assert_eq!(data["filterDocuments"]["documents"][0]["_key"], "db:t2");
+    assert_eq!(data["filterDocuments"]["indexUsed"], true);
+
+    let response = schema.execute(query.replace("EXPLAIN", "true")).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["filterDocuments"]["count"], 0);
+    assert_eq!(
+        data["filterDocuments"]["plan"][0],
+        "IXSCAN status_created (status, createdAt): status = open, createdAt >= 200"
+    );
+}
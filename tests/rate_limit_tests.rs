@@ -0,0 +1,140 @@
+//! Rate limits and query cost limits on the API
+
+mod common;
+
+use async_graphql::Schema;
+use axum::{routing::get, Router};
+use common::{submit_data};
+use cyberfly_rust_node::graphql::{graphql_http_response, Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::rate_limit::{rate_limit_layer, RateLimitConfig, RateLimiter};
+use cyberfly_rust_node::storage::BlobStorage;
+use ed25519_dalek::SigningKey;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+fn config(burst: u32) -> RateLimitConfig {
+    RateLimitConfig {
+        ip_per_second: 1.0,
+        ip_burst: burst,
+        key_per_second: 1.0,
+        key_burst: burst,
+        ..RateLimitConfig::default()
+    }
+}
+
+#[test]
+fn test_token_buckets() {
+    let limiter = RateLimiter::new(config(3));
+    let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    for _ in 0..3 {
+        assert!(limiter.check_ip(ip).is_ok());
+    }
+    let wait = limiter.check_ip(ip).unwrap_err();
+    assert!(wait.as_secs_f64() > 0.0 && wait.as_secs_f64() <= 1.0, "{:?}", wait);
+
+    // Buckets are independent per IP and per key
+    assert!(limiter.check_ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))).is_ok());
+    assert!(limiter.check_key("abc").is_ok());
+
+    let disabled = RateLimiter::new(RateLimitConfig { enabled: false, ..config(0) });
+    for _ in 0..10 {
+        assert!(disabled.check_ip(ip).is_ok());
+    }
+}
+
+#[test]
+fn test_least_recently_used_buckets_are_evicted() {
+    let limiter = RateLimiter::new(RateLimitConfig { ip_per_second: 0.001, ..config(1) });
+    let first = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    assert!(limiter.check_ip(first).is_ok());
+    assert!(limiter.check_ip(first).is_err());
+
+    // A flood of new clients pushes out the one seen longest ago; recent
+    // ones keep their buckets
+    let flood: Vec<IpAddr> = (0..10_000u32).map(|n| IpAddr::V4(Ipv4Addr::from(0x0b00_0000 + n))).collect();
+    for ip in &flood {
+        assert!(limiter.check_ip(*ip).is_ok());
+    }
+    assert!(limiter.check_ip(first).is_ok());
+    assert!(limiter.check_ip(flood[9_999]).is_err());
+}
+
+#[tokio::test]
+async fn test_layer_answers_429_with_retry_after() {
+    let limiter = RateLimiter::new(config(2));
+    let app = Router::new()
+        .route("/graphql", get(|| async { "ok" }))
+        .route("/health", get(|| async { "OK" }))
+        .layer(axum::middleware::from_fn_with_state(limiter, rate_limit_layer));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .unwrap();
+    });
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/graphql", addr);
+    for _ in 0..2 {
+        assert_eq!(client.get(&url).send().await.unwrap().status(), 200);
+    }
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers()["retry-after"], "1");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["extensions"]["code"], "RATE_LIMITED");
+
+    // Health checks are never throttled
+    let health = client.get(format!("http://{}/health", addr)).send().await.unwrap();
+    assert_eq!(health.status(), 200);
+}
+
+#[tokio::test]
+async fn test_query_limits_and_per_key_writes() {
+    let storage = BlobStorage::in_memory();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage)
+        .data(RateLimiter::new(config(2)))
+        .limit_depth(RateLimitConfig::default().max_query_depth)
+        .limit_complexity(RateLimitConfig::default().max_query_complexity)
+        .finish();
+
+    // Scans are weighted: one is fine, a query full of them is not
+    let scan = r#"getAll(dbName: "notes-00") { key }"#;
+    assert!(schema.execute(format!("{{ {} }}", scan)).await.errors.is_empty());
+    let many: Vec<String> = (0..25).map(|i| format!("s{}: {}", i, scan)).collect();
+    let response = schema.execute(format!("{{ {} }}", many.join(" "))).await;
+    assert!(response.errors[0].message.contains("too complex"), "{:?}", response.errors);
+
+    // Introspection stays within the limits
+    let introspection = include_str!("fixtures/introspection.graphql");
+    let response = schema.execute(introspection).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    // Signed writes are budgeted per key, with Retry-After for the HTTP layer
+    let key = SigningKey::from_bytes(&[61u8; 32]);
+    let submit = |n: usize| {
+        let op = common::op(&key, "notes", "String", &format!("k{}", n)).value("v").sign();
+        schema.execute(submit_data(&op))
+    };
+    for n in 0..2 {
+        let response = submit(n).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert!(!response.http_headers.contains_key("retry-after"));
+    }
+    let response = submit(2).await;
+    assert!(response.errors[0].message.contains("Rate limit"), "{:?}", response.errors);
+    assert_eq!(response.http_headers["retry-after"], "1");
+
+    // Which the HTTP layer answers with 429
+    let response = graphql_http_response(response);
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers()["retry-after"], "1");
+    let mut response = submit(3).await;
+    response.http_headers.clear();
+    assert_eq!(graphql_http_response(response).status(), 429);
+    // Other errors stay 200, as GraphQL errors do
+    let unsigned = common::op(&key, "notes", "String", "k4").value("v").unsigned();
+    assert_eq!(graphql_http_response(schema.execute(submit_data(&unsigned)).await).status(), 200);
+}
@@ -0,0 +1,162 @@
+//! Private databases and signed read tokens
+
+mod common;
+
+use async_graphql::{Request, Schema};
+use common::{now_ms, public_key, submit_data};
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::read_access::{ReadCredential, ReadTokenClaims, MAX_TOKEN_LIFETIME_MS};
+use cyberfly_rust_node::storage::BlobStorage;
+use cyberfly_rust_node::storage_backend::MemoryBackend;
+use cyberfly_rust_node::sync::{SignedOperation, SyncManager, SyncMessage};
+use ed25519_dalek::{Signer, SigningKey};
+use std::sync::Arc;
+
+/// An operation on the owner's `diary` database, canonically signed by `signer`
+fn signed_op(signer: &SigningKey, owner: &SigningKey, store_type: &str, key: &str, field: Option<&str>, value: &str) -> SignedOperation {
+    common::op(owner, "diary", store_type, key)
+        .signed_by(signer)
+        .field(field)
+        .value(value)
+        .sign()
+}
+
+fn token(key: &SigningKey, lifetime_ms: i64, challenge: Option<&str>) -> String {
+    ReadTokenClaims {
+        public_key: public_key(key),
+        expires_at: now_ms() + lifetime_ms,
+        challenge: challenge.map(str::to_string),
+    }
+    .sign(key)
+}
+
+#[tokio::test]
+async fn test_private_database_through_graphql() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = BlobStorage::open(backend.clone()).await.unwrap();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+    let submit = |op: &SignedOperation| schema.execute(submit_data(op));
+
+    let owner = SigningKey::from_bytes(&[41u8; 32]);
+    let reader = SigningKey::from_bytes(&[42u8; 32]);
+    let outsider = SigningKey::from_bytes(&[43u8; 32]);
+    let db_name = format!("diary-{}", public_key(&owner));
+    let read = |credential: Option<ReadCredential>| {
+        let request = Request::new(format!(r#"{{ getString(dbName: "{}", key: "entry") {{ value }} }}"#, db_name));
+        let request = match credential {
+            Some(credential) => request.data(credential),
+            None => request,
+        };
+        schema.execute(request)
+    };
+
+    let write = signed_op(&owner, &owner, "String", "entry", None, "dear diary");
+    assert!(submit(&write).await.errors.is_empty());
+    // Public by default
+    assert!(read(None).await.errors.is_empty());
+
+    // Only the owner can make a database private, even with a grant
+    let grant = signed_op(&owner, &owner, "Grant", "__grants", Some(&public_key(&reader)), "");
+    assert!(submit(&grant).await.errors.is_empty());
+    let hijack = signed_op(&reader, &owner, "Privacy", "__privacy", None, r#"{"private": false}"#);
+    assert!(submit(&hijack).await.errors[0].message.contains("owner"));
+    let private = signed_op(&owner, &owner, "Privacy", "__privacy", None, r#"{"private": true}"#);
+    let response = submit(&private).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let query = format!(r#"{{ isPrivate(dbName: "{}") }}"#, db_name);
+    assert_eq!(schema.execute(query).await.data.into_json().unwrap()["isPrivate"], true);
+
+    // Anonymous and outsider reads are refused
+    assert!(read(None).await.errors[0].message.contains("private"));
+    let response = read(Some(ReadCredential::bearer(token(&outsider, 60_000, None)))).await;
+    assert!(response.errors[0].message.contains("may not read"));
+
+    // The owner and a delegate read with their own tokens
+    for key in [&owner, &reader] {
+        let response = read(Some(ReadCredential::bearer(token(key, 60_000, None)))).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(response.data.into_json().unwrap()["getString"]["value"], "dear diary");
+    }
+
+    // Expired, over-long, forged and unknown-challenge tokens are refused
+    let forged = {
+        let token = token(&owner, 60_000, None);
+        let (payload, _) = token.split_once('.').unwrap();
+        format!("{}.{}", payload, hex::encode(outsider.sign(b"x").to_bytes()))
+    };
+    for bad in [
+        token(&owner, -1, None),
+        token(&owner, MAX_TOKEN_LIFETIME_MS + 60_000, None),
+        token(&owner, 60_000, Some("not-issued")),
+        forged,
+        "garbage".to_string(),
+    ] {
+        assert!(!read(Some(ReadCredential::bearer(bad))).await.errors.is_empty());
+    }
+
+    // A token answering this node's challenge works
+    let data = schema.execute("{ readChallenge { challenge expiresAt } }").await.data.into_json().unwrap();
+    let challenge = data["readChallenge"]["challenge"].as_str().unwrap();
+    let response = read(Some(ReadCredential::bearer(token(&reader, 60_000, Some(challenge))))).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    // Revoking the grant takes read access with it
+    let revoke = signed_op(&owner, &owner, "Revoke", "__grants", Some(&public_key(&reader)), "");
+    assert!(submit(&revoke).await.errors.is_empty());
+    assert!(!read(Some(ReadCredential::bearer(token(&reader, 60_000, None)))).await.errors.is_empty());
+
+    // The setting survives a restart
+    let reopened = BlobStorage::open(backend).await.unwrap();
+    assert!(reopened.read_access().is_private(&db_name));
+}
+
+#[test]
+fn test_authorization_header_parsing() {
+    assert!(ReadCredential::from_authorization(None).is_anonymous());
+    assert!(ReadCredential::from_authorization(Some("Basic abc")).is_anonymous());
+    assert!(ReadCredential::from_authorization(Some("Bearer ")).is_anonymous());
+    assert!(!ReadCredential::from_authorization(Some("Bearer abc.def")).is_anonymous());
+}
+
+#[tokio::test]
+async fn test_privacy_replicates_through_sync() {
+    let storage = BlobStorage::in_memory();
+    let peer = iroh::SecretKey::generate().public();
+    let sync_manager = SyncManager::new(storage.clone(), peer);
+
+    let owner = SigningKey::from_bytes(&[44u8; 32]);
+    let db_name = format!("diary-{}", public_key(&owner));
+    let private = signed_op(&owner, &owner, "Privacy", "__privacy", None, r#"{"private": true}"#);
+    sync_manager
+        .handle_sync_message(SyncMessage::Operation { operation: private }, peer)
+        .await
+        .unwrap();
+    assert!(storage.read_access().is_private(&db_name));
+
+    // The op log listing leaves the private database out for anonymous readers
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.clone())
+        .data(sync_manager.clone())
+        .finish();
+    let list = |credential: ReadCredential| async {
+        let request = Request::new("{ getAllBlobOperations(limit: 100) { dbName } }").data(credential);
+        let data = schema.execute(request).await.data.into_json().unwrap();
+        data["getAllBlobOperations"].as_array().unwrap().len()
+    };
+    assert_eq!(list(ReadCredential::default()).await, 0);
+    assert_eq!(list(ReadCredential::bearer(token(&owner, 60_000, None))).await, 1);
+
+    // A later public setting wins; an older one replayed afterwards doesn't
+    let mut public = signed_op(&owner, &owner, "Privacy", "__privacy", None, r#"{"private": false}"#);
+    public.timestamp += 1;
+    common::sign(&owner, &mut public);
+    sync_manager
+        .handle_sync_message(SyncMessage::Operation { operation: public }, peer)
+        .await
+        .unwrap();
+    assert!(!storage.read_access().is_private(&db_name));
+}
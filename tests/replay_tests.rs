@@ -0,0 +1,133 @@
+//! Replay protection for signed writes
+
+mod common;
+
+use async_graphql::{Request, Schema, Variables};
+use common::{now_ms, signed_data};
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::storage::BlobStorage;
+use cyberfly_rust_node::storage_backend::MemoryBackend;
+use cyberfly_rust_node::sync::{SignatureFormat, SignedOperation, SyncManager, SyncMessage};
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::json;
+use std::sync::Arc;
+
+/// A canonically signed String write
+fn signed_op(key: &SigningKey, nonce: &str, value: &str, timestamp: i64) -> SignedOperation {
+    common::op(key, "prefs", "String", "theme")
+        .op_id(nonce)
+        .value(value)
+        .timestamp(timestamp)
+        .sign()
+}
+
+#[tokio::test]
+async fn test_submit_data_rejects_replays() {
+    let storage = BlobStorage::in_memory();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+    let submit = |input: serde_json::Value| {
+        let request = Request::new(r#"mutation ($input: SignedData!) { submitData(input: $input) { success } }"#)
+            .variables(Variables::from_json(json!({ "input": input })));
+        schema.execute(request)
+    };
+
+    let key = SigningKey::from_bytes(&[11u8; 32]);
+    let dark = signed_op(&key, "n-1", "dark", now_ms());
+    let light = signed_op(&key, "n-2", "light", now_ms());
+    assert!(submit(signed_data(&dark)).await.errors.is_empty());
+    assert!(submit(signed_data(&light)).await.errors.is_empty());
+
+    // Resending the first write would revert the key
+    let response = submit(signed_data(&dark)).await;
+    assert!(response.errors[0].message.contains("already used"), "{:?}", response.errors);
+    let full_key = format!("{}:theme", dark.db_name);
+    assert_eq!(storage.get_string(&full_key).await.unwrap(), Some("light".to_string()));
+
+    // Stale signing times fail the freshness check
+    let stale = signed_op(&key, "n-3", "dark", now_ms() - 2 * 3600 * 1000);
+    assert!(submit(signed_data(&stale)).await.errors[0].message.contains("too old"));
+
+    // Short-form signatures can be used once
+    let signature = key.sign(format!("{}:theme:sepia", dark.db_name).as_bytes());
+    let legacy = json!({
+        "dbName": dark.db_name, "key": "theme", "value": "sepia", "publicKey": dark.public_key,
+        "signature": hex::encode(signature.to_bytes()), "storeType": "String",
+    });
+    assert!(submit(legacy.clone()).await.errors.is_empty());
+    assert!(submit(legacy).await.errors[0].message.contains("already used"));
+}
+
+#[tokio::test]
+async fn test_gossiped_operation_replay_is_ignored() {
+    let storage = BlobStorage::in_memory();
+    let key = SigningKey::from_bytes(&[12u8; 32]);
+    let dark = signed_op(&key, "g-1", "dark", now_ms());
+    let light = signed_op(&key, "g-2", "light", now_ms());
+    let peer = iroh::SecretKey::generate().public();
+
+    let sync_manager = SyncManager::new(storage.clone(), peer);
+    for op in [&dark, &light] {
+        sync_manager
+            .handle_sync_message(SyncMessage::Operation { operation: op.clone() }, peer)
+            .await
+            .unwrap();
+    }
+    let full_key = format!("{}:theme", dark.db_name);
+    assert_eq!(storage.get_string(&full_key).await.unwrap(), Some("light".to_string()));
+
+    // A restarted node has no in-memory op log, only the persisted nonces
+    let restarted = SyncManager::new(storage.clone(), peer);
+    restarted
+        .handle_sync_message(SyncMessage::Operation { operation: dark }, peer)
+        .await
+        .unwrap();
+    assert_eq!(storage.get_string(&full_key).await.unwrap(), Some("light".to_string()));
+}
+
+#[tokio::test]
+async fn test_nonce_window_persists_and_prunes() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = BlobStorage::open(backend.clone()).await.unwrap();
+    let key = SigningKey::from_bytes(&[13u8; 32]);
+    let current = signed_op(&key, "p-1", "dark", now_ms());
+    let expired = signed_op(&key, "p-2", "dark", now_ms() - 2 * 3600 * 1000);
+    let guard = storage.replay_guard();
+    guard.check(&current, SignatureFormat::Canonical).await.unwrap();
+    guard.check(&expired, SignatureFormat::Canonical).await.unwrap();
+    guard.check(&expired, SignatureFormat::LegacyShort).await.unwrap();
+    drop(storage);
+
+    let storage = BlobStorage::open(backend).await.unwrap();
+    let guard = storage.replay_guard();
+    assert!(guard.check(&current, SignatureFormat::Canonical).await.is_err());
+    assert!(guard.check(&current, SignatureFormat::LegacyFull).await.is_err());
+
+    // Only aged-out nonces are dropped; short-form signatures are kept while
+    // the legacy formats are accepted, since they could be resent with a
+    // fresh timestamp
+    guard.check(&current, SignatureFormat::LegacyShort).await.unwrap();
+    assert_eq!(guard.prune().await.unwrap(), 1);
+    assert!(guard.check(&current, SignatureFormat::Canonical).await.is_err());
+    assert!(guard.check(&current, SignatureFormat::LegacyShort).await.is_err());
+    assert!(guard.check(&expired, SignatureFormat::LegacyShort).await.is_err());
+    guard.check(&expired, SignatureFormat::Canonical).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_failed_write_releases_nonce() {
+    let storage = BlobStorage::in_memory();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+
+    // A Hash write without a field passes verification but fails to write
+    let key = SigningKey::from_bytes(&[14u8; 32]);
+    let op = common::op(&key, "prefs", "Hash", "theme").op_id("f-1").value("dark").sign();
+    let response = schema.execute(common::submit_data(&op)).await;
+    assert!(response.errors[0].message.contains("Field required"), "{:?}", response.errors);
+    storage.replay_guard().check(&op, SignatureFormat::Canonical).await.unwrap();
+}
@@ -0,0 +1,172 @@
+//! Read-only SQL over the store types of a database
+
+use async_graphql::Schema;
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::indexing::IndexType;
+use cyberfly_rust_node::sql::SqlEngine;
+use cyberfly_rust_node::storage::BlobStorage;
+use serde_json::{json, Value};
+
+async fn seed(storage: &BlobStorage) {
+    let orders = [
+        ("o1", json!({ "status": "shipped", "region": "eu", "total": 120, "tags": ["gift"] })),
+        ("o2", json!({ "status": "pending", "region": "us", "total": 40 })),
+        ("o3", json!({ "status": "shipped", "region": "us", "total": 15 })),
+        ("o4", json!({ "status": "shipped", "region": "eu", "total": 300 })),
+    ];
+    for (key, doc) in orders {
+        storage.set_json(&format!("shop:{}", key), "$", &doc.to_string()).await.unwrap();
+    }
+
+    for (ts, value) in [(1_000, 20.5), (61_000, 21.0), (62_000, 23.0), (125_000, 19.0)] {
+        storage.ts_add("shop:temp", ts, value).await.unwrap();
+    }
+    storage.ts_add("shop:humidity", 1_000, 40.0).await.unwrap();
+
+    storage
+        .xadd("shop:events", "1-0", &[("type".to_string(), "login".to_string()), ("user".to_string(), "ann".to_string())])
+        .await
+        .unwrap();
+    storage
+        .xadd("shop:events", "2-0", &[("type".to_string(), "logout".to_string())])
+        .await
+        .unwrap();
+
+    storage.geoadd("shop:stores", 13.4, 52.5, "berlin").await.unwrap();
+    storage.geoadd("shop:stores", 2.35, 48.85, "paris").await.unwrap();
+    storage.geoadd("shop:warehouses", 4.9, 52.37, "amsterdam").await.unwrap();
+}
+
+async fn rows(storage: &BlobStorage, sql: &str) -> Vec<Vec<Value>> {
+    SqlEngine::new(storage).query("shop", sql).await.unwrap().rows
+}
+
+#[tokio::test]
+async fn test_json_docs_where_group_order() {
+    let storage = BlobStorage::in_memory();
+    seed(&storage).await;
+
+    let result = SqlEngine::new(&storage)
+        .query(
+            "shop",
+            "SELECT doc.region AS region, COUNT(*) AS orders, SUM(doc.total) AS revenue, AVG(doc.total) \
+             FROM json_docs WHERE doc.status = 'shipped' GROUP BY doc.region ORDER BY revenue DESC",
+        )
+        .await
+        .unwrap();
+    assert_eq!(result.columns, vec!["region", "orders", "revenue", "AVG(doc.total)"]);
+    assert_eq!(result.rows, vec![vec![json!("eu"), json!(2), json!(420), json!(210)], vec![json!("us"), json!(1), json!(15), json!(15)]]);
+
+    assert_eq!(
+        rows(&storage, "SELECT key, doc.total FROM json_docs WHERE doc.total BETWEEN 20 AND 200 OR key = 'o3' ORDER BY 2 LIMIT 2 OFFSET 1").await,
+        vec![vec![json!("o2"), json!(40)], vec![json!("o1"), json!(120)]]
+    );
+    assert_eq!(
+        rows(&storage, "SELECT key FROM json_docs WHERE doc.status LIKE 'ship%' AND doc.region IN ('us') AND key <> 'o9'").await,
+        vec![vec![json!("o3")]]
+    );
+
+    // Missing fields are NULL
+    assert_eq!(
+        rows(&storage, "SELECT key, doc.tags FROM json_docs WHERE doc.tags IS NOT NULL").await,
+        vec![vec![json!("o1"), json!(["gift"])]]
+    );
+    assert_eq!(rows(&storage, "SELECT doc FROM json_docs WHERE key = 'o2'").await[0][0]["region"], "us");
+    assert_eq!(
+        rows(&storage, "SELECT DISTINCT doc.status FROM json_docs ORDER BY doc.status").await,
+        vec![vec![json!("pending")], vec![json!("shipped")]]
+    );
+}
+
+#[tokio::test]
+async fn test_pushed_filters_use_indexes() {
+    let storage = BlobStorage::in_memory();
+    seed(&storage).await;
+    let sql = "SELECT key FROM json_docs WHERE doc.status = 'shipped' AND doc.total >= 100 ORDER BY key";
+
+    let scanned = rows(&storage, sql).await;
+    storage.create_index("shop", "status_idx", "status", IndexType::Exact).await.unwrap();
+    storage.create_index("shop", "total_idx", "total", IndexType::Range).await.unwrap();
+    assert_eq!(rows(&storage, sql).await, scanned);
+    assert_eq!(scanned, vec![vec![json!("o1")], vec![json!("o4")]]);
+}
+
+#[tokio::test]
+async fn test_timeseries_streams_and_geo() {
+    let storage = BlobStorage::in_memory();
+    seed(&storage).await;
+
+    // One-minute buckets through integer division
+    assert_eq!(
+        rows(
+            &storage,
+            "SELECT ts / 60000 * 60000 AS bucket, MAX(value), COUNT(*) FROM timeseries \
+             WHERE key = 'temp' AND ts >= 1000 AND ts < 125000 GROUP BY bucket ORDER BY bucket",
+        )
+        .await,
+        vec![vec![json!(0), json!(20.5), json!(1)], vec![json!(60000), json!(23.0), json!(2)]]
+    );
+    assert_eq!(
+        rows(&storage, "SELECT key, COUNT(*) FROM timeseries WHERE value > 20 GROUP BY key ORDER BY key").await,
+        vec![vec![json!("humidity"), json!(1)], vec![json!("temp"), json!(3)]]
+    );
+
+    assert_eq!(
+        rows(&storage, "SELECT id, value FROM stream_entries WHERE key = 'events' AND field = 'type' ORDER BY id DESC").await,
+        vec![vec![json!("2-0"), json!("logout")], vec![json!("1-0"), json!("login")]]
+    );
+
+    assert_eq!(
+        rows(&storage, "SELECT g.member FROM geo g WHERE g.lat > 50 ORDER BY g.lon").await,
+        vec![vec![json!("amsterdam")], vec![json!("berlin")]]
+    );
+    assert_eq!(
+        rows(&storage, "SELECT key, COUNT(member) AS n FROM geo GROUP BY key HAVING COUNT(*) > 1").await,
+        vec![vec![json!("stores"), json!(2)]]
+    );
+}
+
+#[tokio::test]
+async fn test_rejected_statements() {
+    let storage = BlobStorage::in_memory();
+    seed(&storage).await;
+    let engine = SqlEngine::new(&storage);
+
+    for sql in [
+        "DELETE FROM json_docs",
+        "UPDATE json_docs SET doc = '{}'",
+        "INSERT INTO timeseries VALUES ('temp', 1, 2)",
+        "DROP TABLE geo",
+        "SELECT * FROM users",
+        "SELECT * FROM json_docs a JOIN geo b ON a.key = b.key",
+        "SELECT 1 FROM geo; SELECT 2 FROM geo",
+        "SELECT missing FROM geo",
+        "SELECT key FROM geo WHERE COUNT(*) > 1",
+        "SELECT NOW() FROM geo",
+    ] {
+        assert!(engine.query("shop", sql).await.is_err(), "{} should be rejected", sql);
+    }
+}
+
+#[tokio::test]
+async fn test_sql_query_in_graphql_schema() {
+    let storage = BlobStorage::in_memory();
+    seed(&storage).await;
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+
+    let response = schema
+        .execute(r#"{ sql(dbName: "shop", query: "SELECT * FROM geo WHERE member = 'paris'") { columns rows } }"#)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["sql"]["columns"], json!(["key", "member", "lon", "lat"]));
+    assert_eq!(data["sql"]["rows"], json!([["stores", "paris", 2.35, 48.85]]));
+
+    let response = schema
+        .execute(r#"{ sql(dbName: "shop", query: "DELETE FROM json_docs") { columns } }"#)
+        .await;
+    assert!(!response.errors.is_empty());
+}
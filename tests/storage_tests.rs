@@ -0,0 +1,233 @@
+//! Storage tests against the non-Iroh backends
+//!
+//! Exercises `BlobStorage` on the in-memory and redb backends so storage
+//! behaviour can be tested without a running Iroh node.
+
+use cyberfly_rust_node::blob_encoding::{self, BlobEncoding};
+use cyberfly_rust_node::storage::{BlobStorage, Durability, StoreType};
+use cyberfly_rust_node::storage_backend::{
+    BackendKind, IndexStore, MemoryBackend, MemoryValues, RedbBackend, StorageBackend, ValueStore,
+};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_memory_backend_string_roundtrip() {
+    let storage = BlobStorage::in_memory();
+    assert_eq!(storage.backend_kind(), BackendKind::Memory);
+
+    storage.set_string("db:key1", "hello").await.unwrap();
+    assert_eq!(storage.get_string("db:key1").await.unwrap(), Some("hello".to_string()));
+    assert!(storage.exists("db:key1").await.unwrap());
+
+    storage.delete("db:key1").await.unwrap();
+    assert!(!storage.exists("db:key1").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_memory_backend_prefix_scan() {
+    let storage = BlobStorage::in_memory();
+
+    storage.set_string("db1:a", "1").await.unwrap();
+    storage.set_string("db1:b", "2").await.unwrap();
+    storage.set_string("db2:a", "3").await.unwrap();
+    storage.set_json("db1:doc", "$", r#"{"name":"x"}"#).await.unwrap();
+
+    let strings = storage.get_all_strings("db1").await.unwrap();
+    assert_eq!(strings.len(), 2);
+
+    let docs = storage.get_all_jsons("db1").await.unwrap();
+    assert_eq!(docs.len(), 1);
+
+    let keys = storage.scan_keys("db2:*").await.unwrap();
+    assert_eq!(keys, vec!["db2:a".to_string()]);
+}
+
+#[tokio::test]
+async fn test_redb_backend_persists_across_reopen() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("storage.redb");
+
+    {
+        let backend = RedbBackend::open(path.clone()).unwrap();
+        let storage = BlobStorage::with_backend(Arc::new(backend));
+        storage.set_hash("db:user", "name", "alice").await.unwrap();
+        storage.ts_add("db:temp", 1000, 21.5).await.unwrap();
+    }
+
+    let backend = RedbBackend::open(path).unwrap();
+    assert_eq!(backend.kind(), BackendKind::Redb);
+    let storage = BlobStorage::with_backend(Arc::new(backend));
+
+    assert_eq!(
+        storage.get_hash("db:user", "name").await.unwrap(),
+        Some("alice".to_string())
+    );
+    assert_eq!(storage.ts_get("db:temp").await.unwrap(), Some((1000, 21.5)));
+}
+
+#[tokio::test]
+async fn test_value_store_is_content_addressed() {
+    let backend = MemoryBackend::new();
+    let h1 = backend.values().put(b"same".to_vec()).await.unwrap();
+    let h2 = backend.values().put(b"same".to_vec()).await.unwrap();
+    assert_eq!(h1, h2);
+    assert!(backend.values().contains(h1).await.unwrap());
+    assert_eq!(backend.values().get(h1).await.unwrap(), b"same".to_vec());
+}
+
+#[test]
+fn test_backend_kind_parse() {
+    assert_eq!("sled".parse::<BackendKind>().unwrap(), BackendKind::SledIroh);
+    assert_eq!("REDB".parse::<BackendKind>().unwrap(), BackendKind::Redb);
+    assert_eq!("memory".parse::<BackendKind>().unwrap(), BackendKind::Memory);
+    assert!("rocksdb".parse::<BackendKind>().is_err());
+}
+
+/// Shares index and journal with a live backend but has lost every value,
+/// as if the node crashed before the value store reached disk
+struct CrashedBackend {
+    inner: Arc<MemoryBackend>,
+    values: MemoryValues,
+}
+
+impl StorageBackend for CrashedBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Memory
+    }
+
+    fn index(&self) -> &dyn IndexStore {
+        self.inner.index()
+    }
+
+    fn values(&self) -> &dyn ValueStore {
+        &self.values
+    }
+
+    fn journal(&self) -> &dyn IndexStore {
+        self.inner.journal()
+    }
+
+    fn secondary_index(&self) -> &dyn IndexStore {
+        self.inner.secondary_index()
+    }
+
+    fn index_definitions(&self) -> &dyn IndexStore {
+        self.inner.index_definitions()
+    }
+
+    fn nonces(&self) -> &dyn IndexStore {
+        self.inner.nonces()
+    }
+
+    fn delegations(&self) -> &dyn IndexStore {
+        self.inner.delegations()
+    }
+
+    fn encryption(&self) -> &dyn IndexStore {
+        self.inner.encryption()
+    }
+
+    fn read_access(&self) -> &dyn IndexStore {
+        self.inner.read_access()
+    }
+}
+
+#[test]
+fn test_durability_resolution() {
+    let storage = BlobStorage::in_memory();
+    assert_eq!(storage.durability_for("db:key"), Durability::Fast);
+
+    storage.set_database_durability("ledger", Durability::Fsync);
+    assert_eq!(storage.durability_for("ledger:key"), Durability::Fsync);
+
+    // Per-request level can strengthen but not weaken
+    let flushed = storage.with_durability(Durability::FlushOnCommit);
+    assert_eq!(flushed.durability_for("db:key"), Durability::FlushOnCommit);
+    assert_eq!(flushed.durability_for("ledger:key"), Durability::Fsync);
+
+    assert_eq!("flush".parse::<Durability>().unwrap(), Durability::FlushOnCommit);
+    assert!("eventually".parse::<Durability>().is_err());
+}
+
+#[tokio::test]
+async fn test_journal_cleared_by_fsync_and_checkpoint() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = BlobStorage::open(backend.clone()).await.unwrap();
+
+    storage.set_string("db:fast", "1").await.unwrap();
+    assert!(backend.journal().contains("db:fast").unwrap());
+
+    storage.with_durability(Durability::Fsync).set_string("db:sync", "2").await.unwrap();
+    assert!(!backend.journal().contains("db:sync").unwrap());
+
+    assert_eq!(storage.checkpoint_journal().await.unwrap(), 1);
+    assert!(backend.journal().keys_with_prefix("").unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_journal_recovery_rolls_forward_and_back() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = BlobStorage::open(backend.clone()).await.unwrap();
+    storage.set_string("db:old", "v1").await.unwrap();
+    storage.checkpoint_journal().await.unwrap();
+    storage.set_string("db:old", "v2").await.unwrap();
+
+    // Values survived: pending writes are kept
+    let recovered = BlobStorage::open(backend.clone()).await.unwrap();
+    assert_eq!(recovered.get_string("db:old").await.unwrap(), Some("v2".to_string()));
+    let v2_entry = backend.index().get("db:old").unwrap();
+
+    // Values lost: new keys disappear, overwritten keys revert to their previous entry
+    storage.set_string("db:old", "v3").await.unwrap();
+    storage.set_string("db:new", "v1").await.unwrap();
+    let crashed = Arc::new(CrashedBackend {
+        inner: backend.clone(),
+        values: MemoryValues::default(),
+    });
+    BlobStorage::open(crashed).await.unwrap();
+    assert!(!backend.index().contains("db:new").unwrap());
+    assert_eq!(backend.index().get("db:old").unwrap(), v2_entry);
+    assert!(backend.journal().keys_with_prefix("").unwrap().is_empty());
+}
+
+/// Raw value blob currently indexed for `key`
+async fn stored_blob(backend: &MemoryBackend, key: &str) -> Vec<u8> {
+    let entry = backend.index().get(key).unwrap().unwrap();
+    let (hash, _): (String, StoreType) = bincode::deserialize(&entry).unwrap();
+    backend.values().get(hash.parse().unwrap()).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_large_values_compressed_transparently() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = BlobStorage::open(backend.clone()).await.unwrap();
+
+    let large = "lorem ipsum ".repeat(500);
+    storage.set_string("db:large", &large).await.unwrap();
+    storage.set_string("db:small", "x").await.unwrap();
+
+    let blob = stored_blob(&backend, "db:large").await;
+    assert_eq!(blob_encoding::encoding_of(&blob).unwrap(), BlobEncoding::Zstd);
+    assert!(blob.len() < large.len() / 4);
+    let blob = stored_blob(&backend, "db:small").await;
+    assert_eq!(blob_encoding::encoding_of(&blob).unwrap(), BlobEncoding::Raw);
+
+    // Fresh handle on the same backend: no cache, decoded from the blob
+    let reopened = BlobStorage::open(backend).await.unwrap();
+    assert_eq!(reopened.get_string("db:large").await.unwrap(), Some(large));
+}
+
+#[tokio::test]
+async fn test_legacy_uncompressed_blob_still_readable() {
+    let backend = Arc::new(MemoryBackend::new());
+
+    // Pre-header layout: bincode StoredValue::String { value, metadata: None, ttl: None }
+    let legacy = bincode::serialize(&(0u32, "old value".to_string(), None::<u8>, None::<u8>)).unwrap();
+    let hash = backend.values().put(legacy).await.unwrap();
+    let entry = bincode::serialize(&(hash.to_string(), StoreType::String)).unwrap();
+    backend.index().insert("db:legacy", entry).unwrap();
+
+    let storage = BlobStorage::open(backend).await.unwrap();
+    assert_eq!(storage.get_string("db:legacy").await.unwrap(), Some("old value".to_string()));
+}
@@ -27,6 +27,7 @@ fn create_test_operation(signing_key: &SigningKey, db_name: &str, key: &str, val
         json_path: None,
         stream_fields: None,
         ts_timestamp: None,
+        labels: None,
         longitude: None,
         latitude: None,
         public_key: public_key_hex,
@@ -54,6 +55,7 @@ fn create_test_operation_with_timestamp(signing_key: &SigningKey, db_name: &str,
         json_path: None,
         stream_fields: None,
         ts_timestamp: None,
+        labels: None,
         longitude: None,
         latitude: None,
         public_key: public_key_hex,
@@ -131,6 +133,7 @@ async fn test_signed_operation_short_format() {
         json_path: None,
         stream_fields: None,
         ts_timestamp: None,
+        labels: None,
         longitude: None,
         latitude: None,
         public_key: public_key_hex,
@@ -0,0 +1,309 @@
+//! Multi-series time series queries
+
+use async_graphql::Schema;
+use cyberfly_rust_node::filters::{
+    Aggregation, AggregationType, BucketAlignment, GapFill, MRangeOptions, SeriesSelector,
+    TimeSeriesFilter, TimeSeriesOptions,
+};
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::storage::BlobStorage;
+use futures::StreamExt;
+use serde_json::json;
+
+/// Three temperature sensors in two rooms, plus an unrelated series
+async fn seed(storage: &BlobStorage) {
+    let sensors = [
+        ("sensor:1", "kitchen", [(0, 20.0), (30_000, 22.0), (60_000, 24.0)]),
+        ("sensor:2", "kitchen", [(10_000, 18.0), (70_000, 20.0), (130_000, 21.0)]),
+        ("sensor:3", "garage", [(5_000, 10.0), (65_000, 12.0), (125_000, 11.0)]),
+    ];
+    for (key, room, points) in sensors {
+        let full_key = format!("fleet:{}", key);
+        for (ts, value) in points {
+            storage.ts_add(&full_key, ts, value).await.unwrap();
+        }
+        let labels = [("room".to_string(), room.to_string()), ("kind".to_string(), "temp".to_string())];
+        storage.ts_set_labels(&full_key, labels.into_iter().collect()).await.unwrap();
+    }
+    storage.ts_add("fleet:power", 0, 500.0).await.unwrap();
+}
+
+fn per_minute(agg_type: AggregationType) -> Option<Aggregation> {
+    Some(Aggregation {
+        agg_type,
+        time_bucket: 60_000,
+    })
+}
+
+#[tokio::test]
+async fn test_select_series_by_pattern_and_labels() {
+    let storage = BlobStorage::in_memory();
+    seed(&storage).await;
+    let filter = TimeSeriesFilter::new(&storage);
+    let keys = |series: Vec<(String, _)>| series.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+
+    let all = SeriesSelector::default();
+    assert_eq!(filter.select_series("fleet", &all).await.unwrap().len(), 4);
+
+    let by_pattern = SeriesSelector {
+        key_pattern: Some("sensor:*".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        keys(filter.select_series("fleet", &by_pattern).await.unwrap()),
+        vec!["fleet:sensor:1", "fleet:sensor:2", "fleet:sensor:3"]
+    );
+
+    let by_label = SeriesSelector {
+        key_pattern: None,
+        labels: vec![("room".to_string(), "kitchen".to_string())],
+    };
+    assert_eq!(
+        keys(filter.select_series("fleet", &by_label).await.unwrap()),
+        vec!["fleet:sensor:1", "fleet:sensor:2"]
+    );
+}
+
+#[tokio::test]
+async fn test_mrange_aligns_and_reduces() {
+    let storage = BlobStorage::in_memory();
+    seed(&storage).await;
+    let filter = TimeSeriesFilter::new(&storage);
+    let sensors = SeriesSelector {
+        key_pattern: None,
+        labels: vec![("kind".to_string(), "temp".to_string())],
+    };
+
+    // Each series on its own, in common one-minute buckets
+    let options = MRangeOptions {
+        from_timestamp: 0,
+        to_timestamp: 119_999,
+        aggregation: per_minute(AggregationType::Avg),
+        ..Default::default()
+    };
+    let ranges = filter.mrange("fleet", &sensors, &options).await.unwrap();
+    assert_eq!(ranges.len(), 3);
+    assert_eq!(ranges[0].points, vec![(0, 21.0), (60_000, 24.0)]);
+    assert_eq!(ranges[2].labels.get("room").map(String::as_str), Some("garage"));
+
+    // Reduced across all series
+    let options = MRangeOptions {
+        reduce: Some(AggregationType::Max),
+        ..options
+    };
+    let ranges = filter.mrange("fleet", &sensors, &options).await.unwrap();
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0].key, "*");
+    assert_eq!(ranges[0].points, vec![(0, 21.0), (60_000, 24.0)]);
+    assert_eq!(
+        ranges[0].labels.get("__source__").map(String::as_str),
+        Some("fleet:sensor:1,fleet:sensor:2,fleet:sensor:3")
+    );
+
+    // Reduced per room
+    let options = MRangeOptions {
+        aggregation: per_minute(AggregationType::Max),
+        reduce: Some(AggregationType::Sum),
+        group_by: Some("room".to_string()),
+        ..options
+    };
+    let ranges = filter.mrange("fleet", &sensors, &options).await.unwrap();
+    let summary: Vec<(&str, &Vec<(i64, f64)>)> = ranges.iter().map(|r| (r.key.as_str(), &r.points)).collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("room=garage", &vec![(0, 10.0), (60_000, 12.0)]),
+            ("room=kitchen", &vec![(0, 40.0), (60_000, 44.0)]),
+        ]
+    );
+}
+
+#[test]
+fn test_bucket_aggregations() {
+    let points = [(0, 2.0), (1_000, 4.0), (2_000, 4.0), (3_000, 4.0), (4_000, 5.0), (5_000, 5.0), (6_000, 7.0), (8_000, 9.0)];
+    let apply = |name: &str| name.parse::<AggregationType>().unwrap().apply(&points).unwrap();
+
+    assert_eq!(apply("range"), 7.0);
+    assert_eq!(apply("var.p"), 4.0);
+    assert_eq!(apply("std.p"), 2.0);
+    assert_eq!(apply("var.s"), 32.0 / 7.0);
+    assert_eq!(apply("p50"), 4.5);
+    assert_eq!(apply("p100"), 9.0);
+    assert!((apply("p95") - 8.3).abs() < 1e-9);
+    assert_eq!(apply("rate"), 7.0 / 8.0);
+    assert_eq!(apply("derivative"), 7.0 / 8.0);
+    // Trapezoids: 3 + 4 + 4 + 4.5 + 5 + 6 + 16
+    assert_eq!(apply("integral"), 42.5);
+    assert_eq!(apply("twa"), 42.5 / 8.0);
+
+    // Undefined over a single point
+    let single = [(0, 1.0)];
+    assert_eq!(AggregationType::Rate.apply(&single), None);
+    assert_eq!(AggregationType::StdDevS.apply(&single), None);
+    assert_eq!(AggregationType::TimeWeightedAvg.apply(&single), Some(1.0));
+
+    for name in ["p101", "median", "px"] {
+        assert!(name.parse::<AggregationType>().is_err(), "{} should be rejected", name);
+    }
+    assert_eq!(AggregationType::Percentile(99.0).to_string(), "p99");
+}
+
+#[tokio::test]
+async fn test_gap_filling_and_alignment() {
+    let storage = BlobStorage::in_memory();
+    for (ts, value) in [(5_000, 1.0), (15_000, 3.0), (45_000, 9.0), (52_000, 2.0)] {
+        storage.ts_add("db:cpu", ts, value).await.unwrap();
+    }
+    let filter = TimeSeriesFilter::new(&storage);
+    let options = |fill: Option<GapFill>, alignment: BucketAlignment| TimeSeriesOptions {
+        aggregation: Some(Aggregation {
+            agg_type: AggregationType::Max,
+            time_bucket: 10_000,
+        }),
+        fill,
+        alignment,
+        ..Default::default()
+    };
+    let query = |fill, alignment| {
+        let options = options(fill, alignment);
+        let filter = &filter;
+        async move { filter.query_buckets("db:cpu", 0, 59_999, &options).await.unwrap() }
+    };
+
+    assert_eq!(
+        query(None, BucketAlignment::Epoch).await,
+        vec![(0, Some(1.0)), (10_000, Some(3.0)), (40_000, Some(9.0)), (50_000, Some(2.0))]
+    );
+    assert_eq!(
+        query(Some(GapFill::Null), BucketAlignment::Epoch).await,
+        vec![(0, Some(1.0)), (10_000, Some(3.0)), (20_000, None), (30_000, None), (40_000, Some(9.0)), (50_000, Some(2.0))]
+    );
+    assert_eq!(
+        query(Some(GapFill::Previous), BucketAlignment::Epoch).await[2..4],
+        [(20_000, Some(3.0)), (30_000, Some(3.0))]
+    );
+    assert_eq!(
+        query(Some(GapFill::Linear), BucketAlignment::Epoch).await[2..4],
+        [(20_000, Some(5.0)), (30_000, Some(7.0))]
+    );
+
+    assert_eq!(
+        query(None, BucketAlignment::At(5_000)).await,
+        vec![(5_000, Some(1.0)), (15_000, Some(3.0)), (45_000, Some(9.0))]
+    );
+    // The last bucket ends at the end of the range
+    let options = options(None, BucketAlignment::End);
+    assert_eq!(
+        filter.query_buckets("db:cpu", 0, 57_999, &options).await.unwrap(),
+        vec![(-2_000, Some(1.0)), (8_000, Some(3.0)), (38_000, Some(9.0)), (48_000, Some(2.0))]
+    );
+
+    // Rates over single points are gaps too
+    let rate = TimeSeriesOptions {
+        aggregation: Some(Aggregation {
+            agg_type: AggregationType::Rate,
+            time_bucket: 60_000,
+        }),
+        ..Default::default()
+    };
+    assert_eq!(filter.query("db:cpu", 0, 59_999, &rate).await.unwrap(), vec![(0, 1.0 / 47.0)]);
+    assert!(filter.query("db:cpu", 0, 9_999, &rate).await.unwrap().is_empty());
+
+    // Too many buckets to fill
+    storage.ts_add("db:cpu", 500_000, 4.0).await.unwrap();
+    let options = TimeSeriesOptions {
+        aggregation: Some(Aggregation {
+            agg_type: AggregationType::Avg,
+            time_bucket: 1,
+        }),
+        fill: Some(GapFill::Null),
+        ..Default::default()
+    };
+    assert!(filter.query_buckets("db:cpu", 0, 599_999, &options).await.is_err());
+}
+
+#[tokio::test]
+async fn test_mrange_stream_matches_single_query() {
+    let storage = BlobStorage::in_memory();
+    seed(&storage).await;
+    let filter = TimeSeriesFilter::new(&storage);
+    let options = MRangeOptions {
+        from_timestamp: 0,
+        to_timestamp: 179_999,
+        aggregation: per_minute(AggregationType::Sum),
+        reduce: Some(AggregationType::Avg),
+        group_by: None,
+    };
+    let selector = SeriesSelector {
+        key_pattern: Some("sensor:?".to_string()),
+        ..Default::default()
+    };
+    let whole = filter.mrange("fleet", &selector, &options).await.unwrap();
+
+    // A 90s chunk is rounded up to whole minute buckets
+    let chunks: Vec<_> = filter
+        .mrange_stream("fleet".to_string(), selector, options, 90_000)
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    let windows: Vec<(i64, i64)> = chunks.iter().map(|c| (c.from_timestamp, c.to_timestamp)).collect();
+    assert_eq!(windows, vec![(0, 119_999), (120_000, 179_999)]);
+
+    let streamed: Vec<(i64, f64)> = chunks.iter().flat_map(|c| c.series[0].points.clone()).collect();
+    assert_eq!(streamed, whole[0].points);
+}
+
+#[tokio::test]
+async fn test_mrange_in_graphql_schema() {
+    let storage = BlobStorage::in_memory();
+    seed(&storage).await;
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+    let input = r#"{ dbName: "fleet", labels: [{ name: "room", value: "kitchen" }], fromTimestamp: "0",
+        toTimestamp: "119999", aggregation: "avg", timeBucket: 60000, reduce: "sum" }"#;
+
+    let response = schema
+        .execute(format!("{{ timeseriesMrange(input: {}) {{ key points {{ timestamp value }} }} }}", input))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(
+        data["timeseriesMrange"],
+        json!([{ "key": "*", "points": [{ "timestamp": "0", "value": 39.0 }, { "timestamp": "60000", "value": 44.0 }] }])
+    );
+
+    let mut stream = schema.execute_stream(format!(
+        "subscription {{ timeseriesMrange(input: {}, chunkSize: 60000) {{ fromTimestamp series {{ points {{ value }} }} }} }}",
+        input
+    ));
+    let mut windows = Vec::new();
+    while let Some(response) = stream.next().await {
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        windows.push(response.data.into_json().unwrap()["timeseriesMrange"]["fromTimestamp"].clone());
+    }
+    assert_eq!(windows, vec![json!("0"), json!("60000")]);
+
+    let response = schema
+        .execute(r#"{ timeseriesMrange(input: { dbName: "fleet", fromTimestamp: "0", toTimestamp: "1", aggregation: "median", timeBucket: 10 }) { key } }"#)
+        .await;
+    assert!(!response.errors.is_empty());
+
+    // Time-based aggregations cannot reduce across series
+    let response = schema
+        .execute(r#"{ timeseriesMrange(input: { dbName: "fleet", fromTimestamp: "0", toTimestamp: "1", reduce: "rate" }) { key } }"#)
+        .await;
+    assert!(!response.errors.is_empty());
+
+    let response = schema
+        .execute(
+            r#"{ timeseriesAggregate(input: { dbName: "fleet", key: "sensor:2", fromTimestamp: "0", toTimestamp: "179999",
+                aggregation: "p50", timeBucket: 30000, fill: "linear", align: "start" }) { timestamp value } }"#,
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    let values: Vec<_> = data["timeseriesAggregate"].as_array().unwrap().iter().map(|b| b["value"].clone()).collect();
+    assert_eq!(values, vec![json!(18.0), json!(19.0), json!(20.0), json!(20.5), json!(21.0)]);
+}
@@ -0,0 +1,149 @@
+//! Vector store and similarity search
+
+use async_graphql::{Request, Schema, Variables};
+use cyberfly_rust_node::filters::FilterExpr;
+use cyberfly_rust_node::graphql::{Mutation, Query, SubscriptionRoot};
+use cyberfly_rust_node::storage::BlobStorage;
+use cyberfly_rust_node::storage_backend::MemoryBackend;
+use cyberfly_rust_node::vector::DistanceMetric;
+use ed25519_dalek::{Signer, SigningKey};
+use serde_json::json;
+use std::sync::Arc;
+
+/// Embeddings of a few images, roughly clustered by label
+async fn seed(storage: &BlobStorage) {
+    let images = [
+        ("cat-1", [0.9, 0.1, 0.0], "cat"),
+        ("cat-2", [0.8, 0.2, 0.1], "cat"),
+        ("dog-1", [0.1, 0.9, 0.1], "dog"),
+        ("dog-2", [0.2, 0.8, 0.0], "dog"),
+        ("car-1", [0.0, 0.1, 0.9], "car"),
+    ];
+    for (member, vector, label) in images {
+        storage
+            .vadd("db:images", member, vector.to_vec(), json!({ "label": label }), None)
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_search_with_metadata_filter() {
+    let storage = BlobStorage::in_memory();
+    seed(&storage).await;
+
+    let hits = storage.vsearch("db:images", &[1.0, 0.0, 0.0], 2, None, None).await.unwrap();
+    let members: Vec<&str> = hits.iter().map(|hit| hit.member.as_str()).collect();
+    assert_eq!(members, vec!["cat-1", "cat-2"]);
+    assert!(hits[0].distance < 0.01);
+
+    let dogs = FilterExpr::parse(&json!({ "label": { "$in": ["dog", "car"] } })).unwrap();
+    let hits = storage.vsearch("db:images", &[1.0, 0.0, 0.0], 2, None, Some(&dogs)).await.unwrap();
+    let members: Vec<&str> = hits.iter().map(|hit| hit.member.as_str()).collect();
+    assert_eq!(members, vec!["dog-2", "dog-1"]);
+
+    assert_eq!(storage.vinfo("db:images").await.unwrap(), Some((3, DistanceMetric::Cosine, 5)));
+    assert!(storage.vsearch("db:missing", &[1.0], 3, None, None).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_dimension_metric_and_removal() {
+    let storage = BlobStorage::in_memory();
+    seed(&storage).await;
+
+    assert!(storage.vadd("db:images", "bad", vec![1.0, 2.0], json!(null), None).await.is_err());
+    assert!(storage
+        .vadd("db:images", "bad", vec![1.0, 2.0, 3.0], json!(null), Some(DistanceMetric::L2))
+        .await
+        .is_err());
+    assert!(storage.vsearch("db:images", &[1.0, 0.0], 1, None, None).await.is_err());
+
+    // Replacing keeps the count, removing drops it
+    assert!(!storage.vadd("db:images", "cat-1", vec![0.0, 0.0, 1.0], json!({ "label": "car" }), None).await.unwrap());
+    assert_eq!(storage.vget("db:images", "cat-1").await.unwrap().unwrap().vector, vec![0.0, 0.0, 1.0]);
+    assert!(storage.vrem("db:images", "car-1").await.unwrap());
+    assert!(!storage.vrem("db:images", "car-1").await.unwrap());
+    assert_eq!(storage.vinfo("db:images").await.unwrap().unwrap().2, 4);
+
+    let hits = storage.vsearch("db:images", &[0.0, 0.0, 1.0], 1, None, None).await.unwrap();
+    assert_eq!(hits[0].member, "cat-1");
+
+    // Dot product ranks by magnitude too
+    storage.vadd("db:dot", "small", vec![1.0, 0.0], json!(null), Some(DistanceMetric::Dot)).await.unwrap();
+    storage.vadd("db:dot", "large", vec![3.0, 1.0], json!(null), None).await.unwrap();
+    let hits = storage.vsearch("db:dot", &[1.0, 0.0], 2, None, None).await.unwrap();
+    assert_eq!((hits[0].member.as_str(), hits[0].distance), ("large", -3.0));
+}
+
+#[tokio::test]
+async fn test_index_survives_reopen() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = BlobStorage::open(backend.clone()).await.unwrap();
+    for i in 0..200 {
+        let angle = i as f32 / 200.0 * std::f32::consts::PI;
+        storage
+            .vadd("db:ring", &format!("p{}", i), vec![angle.cos(), angle.sin()], json!({ "i": i }), None)
+            .await
+            .unwrap();
+    }
+    let before = storage.vsearch("db:ring", &[0.0, 1.0], 5, None, None).await.unwrap();
+    drop(storage);
+
+    let storage = BlobStorage::open(backend).await.unwrap();
+    let after = storage.vsearch("db:ring", &[0.0, 1.0], 5, None, None).await.unwrap();
+    assert_eq!(after, before);
+    assert_eq!(after[0].member, "p100");
+}
+
+#[tokio::test]
+async fn test_vectors_in_graphql_schema() {
+    let storage = BlobStorage::in_memory();
+    let schema = Schema::build(Query::default(), Mutation::default(), SubscriptionRoot)
+        .data(storage.index_manager().clone())
+        .data(storage.clone())
+        .finish();
+
+    let key = SigningKey::from_bytes(&[9u8; 32]);
+    let public_key = hex::encode(key.verifying_key().to_bytes());
+    let db_name = format!("embeddings-{}", public_key);
+    let submit = r#"mutation ($input: SignedData!) { submitData(input: $input) { success } }"#;
+    for (member, value) in [
+        ("a", r#"{"vector": [1, 0], "metadata": {"source": "cam-1"}, "metric": "l2"}"#),
+        ("b", r#"{"vector": [0, 1], "metadata": {"source": "cam-2"}}"#),
+        ("c", "[0.9, 0.3]"),
+    ] {
+        let signature = key.sign(format!("{}:{}:{}", db_name, "frames", value).as_bytes());
+        let input = json!({
+            "dbName": db_name, "key": "frames", "value": value, "publicKey": public_key,
+            "signature": hex::encode(signature.to_bytes()), "storeType": "Vector", "field": member,
+        });
+        let response = schema
+            .execute(Request::new(submit).variables(Variables::from_json(json!({ "input": input }))))
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+    }
+
+    let query = format!(
+        r#"{{ vectorSearch(input: {{ dbName: "{db}", key: "frames", vector: [1, 0.1], k: 2 }}) {{ member metadata }}
+             vectorInfo(dbName: "{db}", key: "frames") {{ dimension metric count }}
+             getVector(dbName: "{db}", key: "frames", member: "b") {{ vector }} }}"#,
+        db = db_name
+    );
+    let response = schema.execute(query).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(
+        data["vectorSearch"],
+        json!([{ "member": "a", "metadata": { "source": "cam-1" } }, { "member": "c", "metadata": null }])
+    );
+    assert_eq!(data["vectorInfo"], json!({ "dimension": 2, "metric": "L2", "count": 3 }));
+    assert_eq!(data["getVector"]["vector"], json!([0.0, 1.0]));
+
+    let filtered = r#"query ($input: VectorSearchInput!) { vectorSearch(input: $input) { member } }"#;
+    let input = json!({ "dbName": db_name, "key": "frames", "vector": [1, 0], "filter": { "source": { "$regex": "^cam-2" } } });
+    let response = schema
+        .execute(Request::new(filtered).variables(Variables::from_json(json!({ "input": input }))))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data.into_json().unwrap()["vectorSearch"], json!([{ "member": "b" }]));
+}